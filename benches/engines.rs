@@ -0,0 +1,65 @@
+//! Compares the tree-walking interpreter with the bytecode VM.
+//!
+//! Run with `cargo bench --bench engines`. Each workload is parsed once and
+//! then executed `RUNS` times per engine; the best time is reported.
+
+use std::time::{Duration, Instant};
+
+use interpeter::ast::Program;
+use interpeter::envoriment::Environment;
+use interpeter::lexer::Lexer;
+use interpeter::parser::Parser;
+use interpeter::vm;
+
+const RUNS: usize = 5;
+
+const WORKLOADS: &[(&str, &str)] = &[
+    ("fib(22)", "let fib = fn(n) { if (n < 2) { n } else { fib(n - 1) + fib(n - 2) } }; fib(22)"),
+    (
+        "sum loop",
+        "let sum = fn(n, acc) { if (n == 0) { acc } else { sum(n - 1, acc + n) } };
+         let repeat = fn(times, total) { if (times == 0) { total } else { repeat(times - 1, total + sum(500, 0)) } };
+         repeat(200, 0)",
+    ),
+    (
+        "array map",
+        "let map = fn(arr, f) { let iter = fn(arr, acc) { if (len(arr) == 0) { acc } else { iter(rest(arr), push(acc, f(first(arr)))) } }; iter(arr, []) };
+         let range = fn(n, acc) { if (n == 0) { acc } else { range(n - 1, push(acc, n)) } };
+         len(map(range(300, []), fn(x) { x * 2 }))",
+    ),
+];
+
+fn parse(source: &str) -> Program {
+    let mut parser = Parser::new(Lexer::new(source.to_string()));
+    let program = parser.parse_program();
+    assert!(parser.errors.is_empty(), "benchmark failed to parse: {:?}", parser.errors);
+    program
+}
+
+fn best_of<F: FnMut() -> String>(mut run: F) -> (Duration, String) {
+    let mut best = Duration::MAX;
+    let mut result = String::new();
+    for _ in 0..RUNS {
+        let start = Instant::now();
+        result = run();
+        best = best.min(start.elapsed());
+    }
+    (best, result)
+}
+
+fn main() {
+    println!("{:<12} {:>14} {:>14} {:>9}", "workload", "tree-walker", "vm", "speedup");
+    for (name, source) in WORKLOADS {
+        let program = parse(source);
+        let (walker, walker_result) = best_of(|| Environment::new().eval(&program).inspect());
+        let (vm, vm_result) = best_of(|| vm::run_program(&program).unwrap().inspect());
+        assert_eq!(walker_result, vm_result, "engines disagree on {}", name);
+        println!(
+            "{:<12} {:>12.2}ms {:>12.2}ms {:>8.1}x",
+            name,
+            walker.as_secs_f64() * 1000.0,
+            vm.as_secs_f64() * 1000.0,
+            walker.as_secs_f64() / vm.as_secs_f64()
+        );
+    }
+}
@@ -1,15 +1,19 @@
 use std::rc::Rc;
 
-use crate::token::Token;
+use crate::token::{Span, Token, TokenType};
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
 pub enum NodeType {
     Program,
     Statement,
     Expression,
     Identifier,
     LetStatement,
+    /// A `LetStatement` declared with `const`.
+    ConstStatement,
     ReturnStatement,
     ExpressionStatement,
     IntegerLiteral,
+    FloatLiteral,
     PrefixExpression,
     InfixExpression,
     Boolean,
@@ -18,6 +22,18 @@ pub enum NodeType {
     FunctionLiteral,
     CallExpression,
     StringLiteral,
+    InterpolatedString,
+    ArrayLiteral,
+    IndexExpression,
+    HashLiteral,
+    WhileStatement,
+    BreakStatement,
+    ContinueStatement,
+    ForStatement,
+    AssignExpression,
+    MatchExpression,
+    MatchArm,
+    RestPattern,
 }
 
 
@@ -26,6 +42,8 @@ pub trait Node  {
     fn string(&self) -> String;
     fn as_any(&self) -> &dyn std::any::Any;
     fn node_type(&self) -> NodeType;
+    /// Source range covered by the whole node, from its first token to its last.
+    fn span(&self) -> Span;
 }
 
 pub trait Statement: Node + std::fmt::Debug + std::any::Any    {
@@ -44,7 +62,8 @@ pub trait Expression: Node + std::fmt::Debug + std::any::Any  {
 
 #[derive(Debug)]
 pub struct Program {
-    pub statements: Vec<Box<dyn Statement>>
+    pub statements: Vec<Box<dyn Statement>>,
+    pub span: Span,
 }
 
 impl Node for Program {
@@ -68,12 +87,20 @@ impl Node for Program {
     fn node_type(&self) -> NodeType {
         NodeType::Program
     }
+    fn span(&self) -> Span {
+        self.span
+    }
 }
+/// `let name = value;`, or a constant binding when `token` is `const`.
 #[derive(Debug)]
 pub struct LetStatement {
     pub token: Token,
+    pub span: Span,
     pub name: Identifier,
-    pub value: Box<dyn Expression >
+    pub value: Box<dyn Expression >,
+    /// Text of the `///` doc comment directly above the statement, one line
+    /// per comment line, without the slashes.
+    pub doc: Option<String>,
 }
 impl Statement for  LetStatement {
     fn statement_node(&self) {
@@ -94,13 +121,13 @@ impl Node for LetStatement {
     fn string(&self) -> String {
         let mut out = String::new();
         out.push_str(&self.token_literal());
-        out.push_str(" ");
+        out.push(' ');
         out.push_str(&self.name.string());
         out.push_str(" = ");
         if self.value.string() != "" {
             out.push_str(&self.value.string());
         }  
-        out.push_str(";");
+        out.push(';');
         out
     }
 
@@ -109,13 +136,20 @@ impl Node for LetStatement {
     }
     
     fn node_type(&self) -> NodeType {
-        NodeType::LetStatement
+        match self.token.type_ {
+            TokenType::CONST => NodeType::ConstStatement,
+            _ => NodeType::LetStatement,
+        }
+    }
+    fn span(&self) -> Span {
+        self.span
     }
 
 }
 #[derive(Debug)]
 pub struct ReturnStatement {
     pub token: Token,
+    pub span: Span,
     pub return_value: Box<dyn Expression>
 }
 
@@ -136,11 +170,11 @@ impl Node for ReturnStatement {
     fn string(&self) -> String {
         let mut out = String::new();
         out.push_str(&self.token_literal());
-        out.push_str(" ");
+        out.push(' ');
         if self.return_value.string() != "" {
             out.push_str(&self.return_value.string());
         }  
-        out.push_str(";");
+        out.push(';');
         out
     }
     fn as_any(&self) -> &dyn std::any::Any {
@@ -150,6 +184,9 @@ impl Node for ReturnStatement {
     fn node_type(&self) -> NodeType {
         NodeType::ReturnStatement
     }
+    fn span(&self) -> Span {
+        self.span
+    }
 
 }
 
@@ -157,6 +194,7 @@ impl Node for ReturnStatement {
 #[derive(Debug,Clone)]
 pub struct Identifier {
     pub token: Token,
+    pub span: Span,
     pub value: String
 }
 
@@ -186,10 +224,14 @@ impl Node for Identifier {
     fn node_type(&self) -> NodeType {
         NodeType::Identifier
     }
+    fn span(&self) -> Span {
+        self.span
+    }
 }
 #[derive(Debug)]
 pub struct ExpressionStatement {
     pub token: Token,
+    pub span: Span,
     pub expression: Box<dyn Expression>
 }
 
@@ -223,11 +265,15 @@ impl Node for ExpressionStatement {
     fn node_type(&self) -> NodeType {
         NodeType::ExpressionStatement
     }
+    fn span(&self) -> Span {
+        self.span
+    }
 }
 
 #[derive(Debug)]
 pub struct IntegerLiteral {
     pub token: Token,
+    pub span: Span,
     pub value: i64
 }
 
@@ -257,12 +303,55 @@ impl Node for IntegerLiteral {
     fn node_type(&self) -> NodeType {
         NodeType::IntegerLiteral
     }
+    fn span(&self) -> Span {
+        self.span
+    }
+    
+}
+
+#[derive(Debug)]
+pub struct FloatLiteral {
+    pub token: Token,
+    pub span: Span,
+    pub value: f64
+}
+
+impl Expression for FloatLiteral {
+    fn expression_node(&self) {
+        
+    }
+
+    fn as_node(&self) -> &dyn Node {
+        self
+    }
+    // fn as_any(&self) -> &dyn std::any::Any {
+    //     self
+    // }
+}
+
+impl Node for FloatLiteral {
+    fn token_literal(&self) -> String {
+        self.token.literal.clone()
+    }
+    fn string(&self) -> String {
+        self.token.literal.clone()
+    }
+    fn as_any(&self) -> &dyn std::any::Any {
+        self
+    }
+    fn node_type(&self) -> NodeType {
+        NodeType::FloatLiteral
+    }
+    fn span(&self) -> Span {
+        self.span
+    }
     
 }
 
 #[derive(Debug)]
 pub struct PrefixExpression {
     pub token: Token,
+    pub span: Span,
     pub operator: String,
     pub right: Box<dyn Expression>
 }
@@ -301,11 +390,15 @@ impl Node for PrefixExpression {
     fn node_type(&self) -> NodeType {
         NodeType::PrefixExpression
     }
+    fn span(&self) -> Span {
+        self.span
+    }
     
 }
 #[derive(Debug)]
 pub struct InfixExpression {
     pub token: Token,
+    pub span: Span,
     pub left: Box<dyn Expression>,
     pub operator: String,
     pub right: Box<dyn Expression>
@@ -346,11 +439,15 @@ impl Node for InfixExpression {
     fn node_type(&self) -> NodeType {
         NodeType::InfixExpression
     }
+    fn span(&self) -> Span {
+        self.span
+    }
     
 }
 #[derive(Debug)]
 pub struct Boolean {
     pub token: Token,
+    pub span: Span,
     pub value: bool
 }
 impl Expression for Boolean {
@@ -381,12 +478,16 @@ impl Node for Boolean {
     fn node_type(&self) -> NodeType {
         NodeType::Boolean
     }
+    fn span(&self) -> Span {
+        self.span
+    }
     
 }
 
 #[derive(Debug)]
 pub struct IfExpression {
     pub token: Token,
+    pub span: Span,
     pub condition: Box<dyn Expression>,
     pub consequence: Box <dyn Statement>,
     pub alternative: Option<Box <dyn Statement>>
@@ -414,7 +515,7 @@ impl Node for IfExpression {
         let mut out = String::new();
         out.push_str("if");
         out.push_str(&self.condition.string());
-        out.push_str(" ");
+        out.push(' ');
         out.push_str(&self.consequence.string());
         if let Some(alt) = &self.alternative {
             out.push_str("else ");
@@ -429,12 +530,16 @@ impl Node for IfExpression {
     fn node_type(&self) -> NodeType {
         NodeType::IfExpression
     }
+    fn span(&self) -> Span {
+        self.span
+    }
     
 }
 
 #[derive(Debug)]
 pub struct BlockStatement {
     pub token: Token,
+    pub span: Span,
     pub statements: Vec<Box<dyn Statement>>
 }
 impl Statement for BlockStatement {
@@ -470,12 +575,16 @@ impl Node for BlockStatement {
     fn node_type(&self) -> NodeType {
         NodeType::BlockStatement
     }
+    fn span(&self) -> Span {
+        self.span
+    }
     
 }
 
 #[derive(Debug)]
 pub struct FunctionLiteral {
     pub token: Token,
+    pub span: Span,
     pub parameters: Vec<Identifier>,
     pub body: Rc<Box<dyn Statement>>
 }
@@ -525,12 +634,16 @@ impl Node for FunctionLiteral {
     fn node_type(&self) -> NodeType {
         NodeType::FunctionLiteral
     }
+    fn span(&self) -> Span {
+        self.span
+    }
     
 }
 
 #[derive(Debug)]
 pub struct CallExpression {
     pub token: Token,
+    pub span: Span,
     pub function: Box<dyn Expression>,
     pub arguments: Vec<Box<dyn Expression>>
 }
@@ -572,12 +685,16 @@ impl Node for CallExpression {
     fn node_type(&self) -> NodeType {
         NodeType::CallExpression
     }
+    fn span(&self) -> Span {
+        self.span
+    }
     
 }
 
 #[derive(Debug)]
 pub struct StringLiteral {
     pub token: Token,
+    pub span: Span,
     pub value: String
 }
 
@@ -609,16 +726,692 @@ impl Node for StringLiteral {
     fn node_type(&self) -> NodeType {
         NodeType::StringLiteral
     }
+    fn span(&self) -> Span {
+        self.span
+    }
     
 }
 
+/// A piece of an `InterpolatedString`.
+#[derive(Debug)]
+pub enum InterpolationPart {
+    Text(String),
+    Expression(Box<dyn Expression>),
+}
+
+/// A string such as `"hello ${name}"`, whose expressions are evaluated and
+/// spliced into the text.
+#[derive(Debug)]
+pub struct InterpolatedString {
+    pub token: Token,
+    pub span: Span,
+    pub parts: Vec<InterpolationPart>,
+}
+
+impl Expression for InterpolatedString {
+    fn expression_node(&self) {
+        
+    }
+
+    fn as_node(&self) -> &dyn Node {
+        self
+    }
+}
+
+impl Node for InterpolatedString {
+    fn token_literal(&self) -> String {
+        self.token.literal.clone()
+    }
+    fn string(&self) -> String {
+        let mut out = String::from("\"");
+        for part in &self.parts {
+            match part {
+                InterpolationPart::Text(text) => {
+                    for ch in text.chars() {
+                        match ch {
+                            '"' => out.push_str("\\\""),
+                            '\\' => out.push_str("\\\\"),
+                            '$' => out.push_str("\\$"),
+                            '\n' => out.push_str("\\n"),
+                            '\t' => out.push_str("\\t"),
+                            '\r' => out.push_str("\\r"),
+                            '\0' => out.push_str("\\0"),
+                            ch => out.push(ch),
+                        }
+                    }
+                }
+                InterpolationPart::Expression(expression) => {
+                    out.push_str("${");
+                    out.push_str(&expression.string());
+                    out.push('}');
+                }
+            }
+        }
+        out.push('"');
+        out
+    }
+    fn as_any(&self) -> &dyn std::any::Any {
+        self
+    }
+
+    fn node_type(&self) -> NodeType {
+        NodeType::InterpolatedString
+    }
+    fn span(&self) -> Span {
+        self.span
+    }
+}
+
+#[derive(Debug)]
+pub struct ArrayLiteral {
+    pub token: Token,
+    pub span: Span,
+    pub elements: Vec<Box<dyn Expression>>
+}
 
+impl Expression for ArrayLiteral {
+    fn expression_node(&self) {
+        
+    }
 
+    fn as_node(&self) -> &dyn Node {
+        self
+    }
+}
 
+impl Node for ArrayLiteral {
+    fn token_literal(&self) -> String {
+        self.token.literal.clone()
+    }
+    fn string(&self) -> String {
+        let mut out = String::new();
+        let mut elements = Vec::new();
+        for e in &self.elements {
+            elements.push(e.string());
+        }
+        out.push('[');
+        out.push_str(&elements.join(", "));
+        out.push(']');
+        out
+    }
+    fn as_any(&self) -> &dyn std::any::Any {
+        self
+    }
 
+    fn node_type(&self) -> NodeType {
+        NodeType::ArrayLiteral
+    }
+    fn span(&self) -> Span {
+        self.span
+    }
+}
 
-#[cfg(test)]
+#[derive(Debug)]
+pub struct IndexExpression {
+    pub token: Token,
+    pub span: Span,
+    pub left: Box<dyn Expression>,
+    pub index: Box<dyn Expression>
+}
+
+impl Expression for IndexExpression {
+    fn expression_node(&self) {
+        
+    }
+
+    fn as_node(&self) -> &dyn Node {
+        self
+    }
+}
+
+impl Node for IndexExpression {
+    fn token_literal(&self) -> String {
+        self.token.literal.clone()
+    }
+    fn string(&self) -> String {
+        let mut out = String::new();
+        out.push('(');
+        out.push_str(&self.left.string());
+        out.push('[');
+        out.push_str(&self.index.string());
+        out.push_str("])");
+        out
+    }
+    fn as_any(&self) -> &dyn std::any::Any {
+        self
+    }
+
+    fn node_type(&self) -> NodeType {
+        NodeType::IndexExpression
+    }
+    fn span(&self) -> Span {
+        self.span
+    }
+}
+
+#[derive(Debug)]
+pub struct HashLiteral {
+    pub token: Token,
+    pub span: Span,
+    pub pairs: Vec<(Box<dyn Expression>, Box<dyn Expression>)>
+}
+
+impl Expression for HashLiteral {
+    fn expression_node(&self) {
+        
+    }
+
+    fn as_node(&self) -> &dyn Node {
+        self
+    }
+}
+
+impl Node for HashLiteral {
+    fn token_literal(&self) -> String {
+        self.token.literal.clone()
+    }
+    fn string(&self) -> String {
+        let mut out = String::new();
+        let mut pairs = Vec::new();
+        for (key, value) in &self.pairs {
+            pairs.push(format!("{}: {}", key.string(), value.string()));
+        }
+        out.push('{');
+        out.push_str(&pairs.join(", "));
+        out.push('}');
+        out
+    }
+    fn as_any(&self) -> &dyn std::any::Any {
+        self
+    }
+
+    fn node_type(&self) -> NodeType {
+        NodeType::HashLiteral
+    }
+    fn span(&self) -> Span {
+        self.span
+    }
+}
+
+/// `while (condition) { ... }`, run for as long as the condition is truthy.
+#[derive(Debug)]
+pub struct WhileStatement {
+    pub token: Token,
+    pub span: Span,
+    pub condition: Box<dyn Expression>,
+    pub body: Box<dyn Statement>,
+}
+
+impl Statement for WhileStatement {
+    fn statement_node(&self) {
+        
+    }
+
+    fn as_node(&self) -> &dyn Node {
+        self
+    }
+}
+
+impl Node for WhileStatement {
+    fn token_literal(&self) -> String {
+        self.token.literal.clone()
+    }
+    fn string(&self) -> String {
+        let mut out = String::new();
+        out.push_str("while");
+        out.push_str(&self.condition.string());
+        out.push(' ');
+        out.push_str(&self.body.string());
+        out
+    }
+    fn as_any(&self) -> &dyn std::any::Any {
+        self
+    }
 
+    fn node_type(&self) -> NodeType {
+        NodeType::WhileStatement
+    }
+    fn span(&self) -> Span {
+        self.span
+    }
+}
+
+/// `for (variable in iterable) { ... }`, run once for each element of the
+/// iterable with the element bound to `variable`.
+#[derive(Debug)]
+pub struct ForStatement {
+    pub token: Token,
+    pub span: Span,
+    pub variable: Identifier,
+    pub iterable: Box<dyn Expression>,
+    pub body: Box<dyn Statement>,
+}
+
+impl Statement for ForStatement {
+    fn statement_node(&self) {
+        
+    }
+
+    fn as_node(&self) -> &dyn Node {
+        self
+    }
+}
+
+impl Node for ForStatement {
+    fn token_literal(&self) -> String {
+        self.token.literal.clone()
+    }
+    fn string(&self) -> String {
+        let mut out = String::new();
+        out.push_str("for(");
+        out.push_str(&self.variable.string());
+        out.push_str(" in ");
+        out.push_str(&self.iterable.string());
+        out.push_str(") ");
+        out.push_str(&self.body.string());
+        out
+    }
+    fn as_any(&self) -> &dyn std::any::Any {
+        self
+    }
+
+    fn node_type(&self) -> NodeType {
+        NodeType::ForStatement
+    }
+    fn span(&self) -> Span {
+        self.span
+    }
+}
+
+/// `break`, which leaves the innermost loop.
+#[derive(Debug)]
+pub struct BreakStatement {
+    pub token: Token,
+    pub span: Span,
+}
+
+impl Statement for BreakStatement {
+    fn statement_node(&self) {
+        
+    }
+
+    fn as_node(&self) -> &dyn Node {
+        self
+    }
+}
+
+impl Node for BreakStatement {
+    fn token_literal(&self) -> String {
+        self.token.literal.clone()
+    }
+    fn string(&self) -> String {
+        String::from("break;")
+    }
+    fn as_any(&self) -> &dyn std::any::Any {
+        self
+    }
+
+    fn node_type(&self) -> NodeType {
+        NodeType::BreakStatement
+    }
+    fn span(&self) -> Span {
+        self.span
+    }
+}
+
+/// `continue`, which skips to the next iteration of the innermost loop.
+#[derive(Debug)]
+pub struct ContinueStatement {
+    pub token: Token,
+    pub span: Span,
+}
+
+impl Statement for ContinueStatement {
+    fn statement_node(&self) {
+        
+    }
+
+    fn as_node(&self) -> &dyn Node {
+        self
+    }
+}
+
+impl Node for ContinueStatement {
+    fn token_literal(&self) -> String {
+        self.token.literal.clone()
+    }
+    fn string(&self) -> String {
+        String::from("continue;")
+    }
+    fn as_any(&self) -> &dyn std::any::Any {
+        self
+    }
+
+    fn node_type(&self) -> NodeType {
+        NodeType::ContinueStatement
+    }
+    fn span(&self) -> Span {
+        self.span
+    }
+}
+
+/// `target = value`, or a compound assignment such as `target += value`.
+/// The target is a name or an index into one, as checked by `place`.
+#[derive(Debug)]
+pub struct AssignExpression {
+    pub token: Token,
+    pub span: Span,
+    pub target: Box<dyn Expression>,
+    pub operator: String,
+    pub value: Box<dyn Expression>,
+}
+
+impl Expression for AssignExpression {
+    fn expression_node(&self) {
+        
+    }
+
+    fn as_node(&self) -> &dyn Node {
+        self
+    }
+}
+
+impl Node for AssignExpression {
+    fn token_literal(&self) -> String {
+        self.token.literal.clone()
+    }
+    fn string(&self) -> String {
+        let mut out = String::new();
+        out.push('(');
+        out.push_str(&self.target.string());
+        out.push(' ');
+        out.push_str(&self.operator);
+        out.push(' ');
+        out.push_str(&self.value.string());
+        out.push(')');
+        out
+    }
+    fn as_any(&self) -> &dyn std::any::Any {
+        self
+    }
+
+    fn node_type(&self) -> NodeType {
+        NodeType::AssignExpression
+    }
+    fn span(&self) -> Span {
+        self.span
+    }
+}
+
+/// Splits an assignment target into the variable it rebinds and the indexes
+/// leading to the element it replaces, outermost first: `xs[i][j]` gives `xs`
+/// and `[i, j]`. `None` if `target` is neither a name nor an index into one.
+pub fn place(target: &dyn Node) -> Option<(&Identifier, Vec<&dyn Node>)> {
+    let mut path = Vec::new();
+    let mut node = target;
+    while let Some(index) = node.as_any().downcast_ref::<IndexExpression>() {
+        path.push(index.index.as_node());
+        node = index.left.as_node();
+    }
+    path.reverse();
+    node.as_any().downcast_ref::<Identifier>().map(|name| (name, path))
+}
+
+/// `match (subject) { pattern => body, ... }`: the body of the first arm
+/// whose pattern matches the subject and whose guard, if any, holds.
+#[derive(Debug)]
+pub struct MatchExpression {
+    pub token: Token,
+    pub span: Span,
+    pub subject: Box<dyn Expression>,
+    pub arms: Vec<MatchArm>,
+}
+
+impl Expression for MatchExpression {
+    fn expression_node(&self) {
+        
+    }
+
+    fn as_node(&self) -> &dyn Node {
+        self
+    }
+}
+
+impl Node for MatchExpression {
+    fn token_literal(&self) -> String {
+        self.token.literal.clone()
+    }
+    fn string(&self) -> String {
+        let arms: Vec<String> = self.arms.iter().map(|arm| arm.string()).collect();
+        format!("match ({}) {{{}}}", self.subject.string(), arms.join(", "))
+    }
+    fn as_any(&self) -> &dyn std::any::Any {
+        self
+    }
+
+    fn node_type(&self) -> NodeType {
+        NodeType::MatchExpression
+    }
+    fn span(&self) -> Span {
+        self.span
+    }
+}
+
+/// `pattern if guard => body` in a `match`, with `token` the `=>`. An arm
+/// written `pattern => expression` gets a block holding just that
+/// expression, spanning the same text.
+#[derive(Debug)]
+pub struct MatchArm {
+    pub token: Token,
+    pub span: Span,
+    pub pattern: Box<dyn Expression>,
+    pub guard: Option<Box<dyn Expression>>,
+    pub body: Box<dyn Statement>,
+}
+
+impl MatchArm {
+    /// Whether the body was written as a `{ ... }` block.
+    pub fn braced(&self) -> bool {
+        match self.body.as_any().downcast_ref::<BlockStatement>() {
+            Some(block) => !matches!(&block.statements[..], [statement]
+                if statement.node_type() == NodeType::ExpressionStatement && statement.span() == block.span),
+            None => true,
+        }
+    }
+}
+
+impl Node for MatchArm {
+    fn token_literal(&self) -> String {
+        self.token.literal.clone()
+    }
+    fn string(&self) -> String {
+        let mut out = self.pattern.string();
+        if let Some(guard) = &self.guard {
+            out.push_str(" if ");
+            out.push_str(&guard.string());
+        }
+        out.push_str(" => ");
+        out.push_str(&self.body.string());
+        out
+    }
+    fn as_any(&self) -> &dyn std::any::Any {
+        self
+    }
+
+    fn node_type(&self) -> NodeType {
+        NodeType::MatchArm
+    }
+    fn span(&self) -> Span {
+        self.span
+    }
+}
+
+/// `..rest` or `..` as the last element of an array pattern, matching the
+/// remaining elements and binding them to `name` as an array.
+#[derive(Debug)]
+pub struct RestPattern {
+    pub token: Token,
+    pub span: Span,
+    pub name: Option<Identifier>,
+}
+
+impl Expression for RestPattern {
+    fn expression_node(&self) {
+        
+    }
+
+    fn as_node(&self) -> &dyn Node {
+        self
+    }
+}
+
+impl Node for RestPattern {
+    fn token_literal(&self) -> String {
+        self.token.literal.clone()
+    }
+    fn string(&self) -> String {
+        let mut out = String::from("..");
+        if let Some(name) = &self.name {
+            out.push_str(&name.value);
+        }
+        out
+    }
+    fn as_any(&self) -> &dyn std::any::Any {
+        self
+    }
+
+    fn node_type(&self) -> NodeType {
+        NodeType::RestPattern
+    }
+    fn span(&self) -> Span {
+        self.span
+    }
+}
+
+/// The names a `match` pattern binds, in source order, or `None` if `pattern`
+/// is not a pattern. A pattern is `_`, a name, a literal (a number that may be
+/// negated, a plain string or a boolean), an array of patterns that may end
+/// in a `RestPattern`, or a hash from integer, string or boolean literals to
+/// patterns. `_` matches anything and binds nothing.
+pub fn pattern_bindings(pattern: &dyn Node) -> Option<Vec<&Identifier>> {
+    let mut names = Vec::new();
+    collect_bindings(pattern, &mut names).then_some(names)
+}
+
+fn collect_bindings<'a>(pattern: &'a dyn Node, names: &mut Vec<&'a Identifier>) -> bool {
+    let any = pattern.as_any();
+    match pattern.node_type() {
+        NodeType::Identifier => {
+            names.extend(any.downcast_ref::<Identifier>().filter(|name| name.value != "_"));
+            true
+        }
+        NodeType::FloatLiteral => true,
+        NodeType::ArrayLiteral => {
+            let elements = &any.downcast_ref::<ArrayLiteral>().unwrap().elements;
+            elements.iter().enumerate().all(|(i, element)| match element.as_any().downcast_ref::<RestPattern>() {
+                Some(rest) => {
+                    names.extend(rest.name.iter().filter(|name| name.value != "_"));
+                    i + 1 == elements.len()
+                }
+                None => collect_bindings(element.as_node(), names),
+            })
+        }
+        NodeType::HashLiteral => any.downcast_ref::<HashLiteral>().unwrap().pairs.iter()
+            .all(|(key, value)| is_key_pattern(key.as_node()) && collect_bindings(value.as_node(), names)),
+        NodeType::PrefixExpression => {
+            let prefix = any.downcast_ref::<PrefixExpression>().unwrap();
+            prefix.operator == "-" && matches!(prefix.right.node_type(), NodeType::IntegerLiteral | NodeType::FloatLiteral)
+        }
+        _ => is_key_pattern(pattern),
+    }
+}
+
+/// Whether `pattern` is a literal that can be a hash key.
+pub fn is_key_pattern(pattern: &dyn Node) -> bool {
+    match pattern.as_any().downcast_ref::<PrefixExpression>() {
+        Some(prefix) => prefix.operator == "-" && prefix.right.node_type() == NodeType::IntegerLiteral,
+        None => matches!(pattern.node_type(), NodeType::IntegerLiteral | NodeType::StringLiteral | NodeType::Boolean),
+    }
+}
+
+/// The nodes directly below `node`, in source order. The text inside an
+/// `InterpolatedString` belongs to one token, so its expressions are not
+/// listed.
+pub fn children(node: &dyn Node) -> Vec<&dyn Node> {
+    let any = node.as_any();
+    match node.node_type() {
+        NodeType::Program => any.downcast_ref::<Program>().unwrap().statements.iter().map(|s| s.as_node()).collect(),
+        NodeType::LetStatement | NodeType::ConstStatement => {
+            let statement = any.downcast_ref::<LetStatement>().unwrap();
+            vec![&statement.name, statement.value.as_node()]
+        }
+        NodeType::ReturnStatement => vec![any.downcast_ref::<ReturnStatement>().unwrap().return_value.as_node()],
+        NodeType::ExpressionStatement => vec![any.downcast_ref::<ExpressionStatement>().unwrap().expression.as_node()],
+        NodeType::PrefixExpression => vec![any.downcast_ref::<PrefixExpression>().unwrap().right.as_node()],
+        NodeType::InfixExpression => {
+            let infix = any.downcast_ref::<InfixExpression>().unwrap();
+            vec![infix.left.as_node(), infix.right.as_node()]
+        }
+        NodeType::IfExpression => {
+            let if_expression = any.downcast_ref::<IfExpression>().unwrap();
+            let mut nodes = vec![if_expression.condition.as_node(), if_expression.consequence.as_node()];
+            nodes.extend(if_expression.alternative.iter().map(|a| a.as_node()));
+            nodes
+        }
+        NodeType::BlockStatement => any.downcast_ref::<BlockStatement>().unwrap().statements.iter().map(|s| s.as_node()).collect(),
+        NodeType::FunctionLiteral => {
+            let function = any.downcast_ref::<FunctionLiteral>().unwrap();
+            let mut nodes: Vec<&dyn Node> = function.parameters.iter().map(|p| p as &dyn Node).collect();
+            nodes.push(function.body.as_node());
+            nodes
+        }
+        NodeType::CallExpression => {
+            let call = any.downcast_ref::<CallExpression>().unwrap();
+            let mut nodes = vec![call.function.as_node()];
+            nodes.extend(call.arguments.iter().map(|a| a.as_node()));
+            nodes
+        }
+        NodeType::ArrayLiteral => any.downcast_ref::<ArrayLiteral>().unwrap().elements.iter().map(|e| e.as_node()).collect(),
+        NodeType::IndexExpression => {
+            let index = any.downcast_ref::<IndexExpression>().unwrap();
+            vec![index.left.as_node(), index.index.as_node()]
+        }
+        NodeType::HashLiteral => any.downcast_ref::<HashLiteral>().unwrap().pairs.iter()
+            .flat_map(|(key, value)| [key.as_node(), value.as_node()])
+            .collect(),
+        NodeType::WhileStatement => {
+            let statement = any.downcast_ref::<WhileStatement>().unwrap();
+            vec![statement.condition.as_node(), statement.body.as_node()]
+        }
+        NodeType::ForStatement => {
+            let statement = any.downcast_ref::<ForStatement>().unwrap();
+            vec![&statement.variable, statement.iterable.as_node(), statement.body.as_node()]
+        }
+        NodeType::AssignExpression => {
+            let assign = any.downcast_ref::<AssignExpression>().unwrap();
+            vec![assign.target.as_node(), assign.value.as_node()]
+        }
+        NodeType::MatchExpression => {
+            let expression = any.downcast_ref::<MatchExpression>().unwrap();
+            let mut nodes = vec![expression.subject.as_node()];
+            nodes.extend(expression.arms.iter().map(|arm| arm as &dyn Node));
+            nodes
+        }
+        NodeType::MatchArm => {
+            let arm = any.downcast_ref::<MatchArm>().unwrap();
+            let mut nodes = vec![arm.pattern.as_node()];
+            nodes.extend(arm.guard.iter().map(|guard| guard.as_node()));
+            nodes.push(arm.body.as_node());
+            nodes
+        }
+        NodeType::RestPattern => any.downcast_ref::<RestPattern>().unwrap().name.iter().map(|name| name as &dyn Node).collect(),
+        NodeType::Identifier | NodeType::IntegerLiteral | NodeType::FloatLiteral | NodeType::Boolean
+        | NodeType::StringLiteral | NodeType::InterpolatedString | NodeType::BreakStatement | NodeType::ContinueStatement
+        | NodeType::Statement | NodeType::Expression => Vec::new(),
+    }
+}
+
+#[cfg(test)]
 mod test {
     use super::*;
     use crate:: token::TokenType;
@@ -628,16 +1421,21 @@ mod test {
            statements: vec![
                Box::new(LetStatement {
                    token: Token::new(TokenType::LET, "let"),
+                   span: Span::default(),
                    name: Identifier {
                        token: Token::new(TokenType::IDENT, "myVar"),
+                       span: Span::default(),
                        value: String::from("myVar")
                    },
                    value: Box::new(Identifier {
                        token: Token::new(TokenType::IDENT, "anotherVar"),
+                       span: Span::default(),
                        value: String::from("anotherVar")
-                   })
+                   }),
+                   doc: None,
                })
-           ]
+           ],
+           span: Span::default(),
        };
          assert_eq!(program.string(), "let myVar = anotherVar;");
 
@@ -1,14 +1,316 @@
+use std::cell::RefCell;
+use std::io::Write;
 use std::rc::Rc;
 
+use crate::diagnostic::Diagnostic;
 use crate::object;
 
+thread_local! {
+    static SCRIPT_ARGS: RefCell<Vec<String>> = const { RefCell::new(Vec::new()) };
+    static OUTPUT: RefCell<Option<Output>> = const { RefCell::new(None) };
+}
+
+/// A handle to a writer shared by `puts` and the code that set it up, such as
+/// the CLI writing results to the same place.
+#[derive(Clone)]
+pub struct Output(pub Rc<RefCell<dyn Write>>);
+
+impl Output {
+    pub fn new(writer: impl Write + 'static) -> Self {
+        Output(Rc::new(RefCell::new(writer)))
+    }
+}
+
+impl Write for Output {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        self.0.borrow_mut().write(buf)
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        self.0.borrow_mut().flush()
+    }
+}
+
+/// Sets the command line arguments a script sees through `argc` and `argv`.
+pub fn set_args(args: Vec<String>) {
+    SCRIPT_ARGS.with(|a| *a.borrow_mut() = args);
+}
+
+/// Sends what `puts` prints to `output`, flushing it after each call. Until
+/// this is called, `puts` prints to stdout.
+pub fn set_output(output: Output) {
+    OUTPUT.with(|o| *o.borrow_mut() = Some(output));
+}
+
+/// Every builtin, in the order the bytecode compiler numbers them. Both the
+/// tree-walker and the `vm` resolve builtins from this table.
+pub const BUILTINS: &[(&str, object::BuiltinFunction)] = &[
+    ("len", len),
+    ("first", first),
+    ("last", last),
+    ("rest", rest),
+    ("push", push),
+    ("keys", keys),
+    ("values", values),
+    ("has_key", has_key),
+    ("delete", delete),
+    ("puts", puts),
+    ("argc", argc),
+    ("argv", argv),
+    ("int", int),
+    ("float", float),
+    ("floor", floor),
+    ("ceil", ceil),
+    ("round", round),
+];
+
+fn new_error(code: &'static str, message: &str) -> Rc<Box<dyn object::Object>> {
+    Rc::new(Box::new(object::Error{diagnostic: Diagnostic::error(code, message)}))
+}
+
+fn wrong_arguments(got: usize, want: usize) -> Rc<Box<dyn object::Object>> {
+    new_error("E0104", &format!("wrong number of arguments: want={}, got={}", want, got))
+}
+
+fn unsupported_argument(name: &str, arg: &Rc<Box<dyn object::Object>>) -> Rc<Box<dyn object::Object>> {
+    new_error("E0105", &format!("argument to `{}` not supported, got {}", name, arg.object_type()))
+}
+
+/// Length of an array, or of a string in Unicode scalar values (not bytes),
+/// so `len("é")` is 1, matching how strings are indexed.
 pub fn len(args: Vec<Rc<Box<dyn object::Object>>>) -> Rc<Box<dyn object::Object>> {
     if args.len() != 1 {
-        return Rc::new(Box::new(object::Null{}));
+        return wrong_arguments(args.len(), 1);
+    }
+
+    if let Some(s) = args[0].as_any().downcast_ref::<object::StringValue>() {
+        return Rc::new(Box::new(object::Integer{value: s.value.chars().count() as i64}));
+    }
+    match args[0].as_any().downcast_ref::<object::Array>() {
+        Some(a) => Rc::new(Box::new(object::Integer{value: a.elements.len() as i64})),
+        None => unsupported_argument("len", &args[0]),
+    }
+}
+
+pub fn first(args: Vec<Rc<Box<dyn object::Object>>>) -> Rc<Box<dyn object::Object>> {
+    if args.len() != 1 {
+        return wrong_arguments(args.len(), 1);
+    }
+    match args[0].as_any().downcast_ref::<object::Array>() {
+        Some(a) => match a.elements.first() {
+            Some(e) => e.clone(),
+            None => Rc::new(Box::new(object::Null{})),
+        },
+        None => unsupported_argument("first", &args[0]),
+    }
+}
+
+pub fn last(args: Vec<Rc<Box<dyn object::Object>>>) -> Rc<Box<dyn object::Object>> {
+    if args.len() != 1 {
+        return wrong_arguments(args.len(), 1);
+    }
+    match args[0].as_any().downcast_ref::<object::Array>() {
+        Some(a) => match a.elements.last() {
+            Some(e) => e.clone(),
+            None => Rc::new(Box::new(object::Null{})),
+        },
+        None => unsupported_argument("last", &args[0]),
+    }
+}
+
+/// Returns a new array holding every element but the first, or null for an
+/// empty array.
+pub fn rest(args: Vec<Rc<Box<dyn object::Object>>>) -> Rc<Box<dyn object::Object>> {
+    if args.len() != 1 {
+        return wrong_arguments(args.len(), 1);
+    }
+    match args[0].as_any().downcast_ref::<object::Array>() {
+        Some(a) if a.elements.is_empty() => Rc::new(Box::new(object::Null{})),
+        Some(a) => Rc::new(Box::new(object::Array{elements: a.elements[1..].to_vec()})),
+        None => unsupported_argument("rest", &args[0]),
+    }
+}
+
+/// Returns a new array with the second argument appended; the original array
+/// is left untouched.
+pub fn push(args: Vec<Rc<Box<dyn object::Object>>>) -> Rc<Box<dyn object::Object>> {
+    if args.len() != 2 {
+        return wrong_arguments(args.len(), 2);
+    }
+    match args[0].as_any().downcast_ref::<object::Array>() {
+        Some(a) => {
+            let mut elements = a.elements.clone();
+            elements.push(args[1].clone());
+            Rc::new(Box::new(object::Array{elements}))
+        }
+        None => unsupported_argument("push", &args[0]),
+    }
+}
+
+pub fn keys(args: Vec<Rc<Box<dyn object::Object>>>) -> Rc<Box<dyn object::Object>> {
+    if args.len() != 1 {
+        return wrong_arguments(args.len(), 1);
+    }
+    match args[0].as_any().downcast_ref::<object::Hash>() {
+        Some(h) => Rc::new(Box::new(object::Array{elements: h.pairs.iter().map(|p| p.key.clone()).collect()})),
+        None => unsupported_argument("keys", &args[0]),
+    }
+}
+
+pub fn values(args: Vec<Rc<Box<dyn object::Object>>>) -> Rc<Box<dyn object::Object>> {
+    if args.len() != 1 {
+        return wrong_arguments(args.len(), 1);
+    }
+    match args[0].as_any().downcast_ref::<object::Hash>() {
+        Some(h) => Rc::new(Box::new(object::Array{elements: h.pairs.iter().map(|p| p.value.clone()).collect()})),
+        None => unsupported_argument("values", &args[0]),
+    }
+}
+
+pub fn has_key(args: Vec<Rc<Box<dyn object::Object>>>) -> Rc<Box<dyn object::Object>> {
+    if args.len() != 2 {
+        return wrong_arguments(args.len(), 2);
+    }
+    let hash = match args[0].as_any().downcast_ref::<object::Hash>() {
+        Some(h) => h,
+        None => return unsupported_argument("has_key", &args[0]),
+    };
+    match args[1].hash_key() {
+        Some(key) => Rc::new(Box::new(object::Boolean{value: hash.get(&key).is_some()})),
+        None => new_error("E0106", &format!("unusable as hash key: {}", args[1].object_type())),
+    }
+}
+
+/// Returns a new hash without the given key; the original hash is left
+/// untouched.
+pub fn delete(args: Vec<Rc<Box<dyn object::Object>>>) -> Rc<Box<dyn object::Object>> {
+    if args.len() != 2 {
+        return wrong_arguments(args.len(), 2);
+    }
+    let hash = match args[0].as_any().downcast_ref::<object::Hash>() {
+        Some(h) => h,
+        None => return unsupported_argument("delete", &args[0]),
+    };
+    match args[1].hash_key() {
+        Some(key) => Rc::new(Box::new(hash.without(&key))),
+        None => new_error("E0106", &format!("unusable as hash key: {}", args[1].object_type())),
+    }
+}
+
+pub fn argc(args: Vec<Rc<Box<dyn object::Object>>>) -> Rc<Box<dyn object::Object>> {
+    if !args.is_empty() {
+        return wrong_arguments(args.len(), 0);
+    }
+    let count = SCRIPT_ARGS.with(|a| a.borrow().len());
+    Rc::new(Box::new(object::Integer{value: count as i64}))
+}
+
+/// `argv()` returns every script argument as an array of strings, `argv(i)`
+/// the i-th one or null when there are fewer arguments.
+pub fn argv(args: Vec<Rc<Box<dyn object::Object>>>) -> Rc<Box<dyn object::Object>> {
+    if args.is_empty() {
+        let elements = SCRIPT_ARGS.with(|a| a.borrow().clone());
+        let elements = elements.into_iter().map(|value| Rc::new(Box::new(object::StringValue{value}) as Box<dyn object::Object>)).collect();
+        return Rc::new(Box::new(object::Array{elements}));
+    }
+    if args.len() != 1 {
+        return wrong_arguments(args.len(), 1);
+    }
+    let index = match args[0].as_any().downcast_ref::<object::Integer>() {
+        Some(i) => i.value,
+        None => return unsupported_argument("argv", &args[0]),
+    };
+    let arg = SCRIPT_ARGS.with(|a| usize::try_from(index).ok().and_then(|i| a.borrow().get(i).cloned()));
+    match arg {
+        Some(value) => Rc::new(Box::new(object::StringValue{value})),
+        None => Rc::new(Box::new(object::Null{})),
+    }
+}
+
+pub fn puts(args: Vec<Rc<Box<dyn object::Object>>>) -> Rc<Box<dyn object::Object>> {
+    let printed: String = args.iter().map(|arg| format!("{}\n", arg.inspect())).collect();
+    match OUTPUT.with(|o| o.borrow().clone()) {
+        Some(mut output) => output.write_all(printed.as_bytes()).and_then(|_| output.flush()).unwrap(),
+        None => print!("{}", printed),
+    }
+    Rc::new(Box::new(object::Null{}))
+}
+
+/// Converts a float to an integer after `rounding` it, failing for NaN,
+/// infinities and values outside the INTEGER range.
+fn float_to_integer(name: &str, value: f64, rounding: fn(f64) -> f64) -> Rc<Box<dyn object::Object>> {
+    let rounded = rounding(value);
+    // -2^63 is exactly representable; 2^63 is the first float past i64::MAX.
+    // NaN is outside every range.
+    if !(-9223372036854775808.0..9223372036854775808.0).contains(&rounded) {
+        return new_error("E0110", &format!("cannot convert {:?} to INTEGER in `{}`", value, name));
+    }
+    Rc::new(Box::new(object::Integer{value: rounded as i64}))
+}
+
+/// `int(x)` truncates a float toward zero and parses a string like `"42"`.
+pub fn int(args: Vec<Rc<Box<dyn object::Object>>>) -> Rc<Box<dyn object::Object>> {
+    if args.len() != 1 {
+        return wrong_arguments(args.len(), 1);
+    }
+    if args[0].object_type() == object::ObjectType::INTEGER {
+        return args[0].clone();
+    }
+    if let Some(f) = args[0].as_any().downcast_ref::<object::Float>() {
+        return float_to_integer("int", f.value, f64::trunc);
     }
+    match args[0].as_any().downcast_ref::<object::StringValue>() {
+        Some(s) => match s.value.trim().parse::<i64>() {
+            Ok(value) => Rc::new(Box::new(object::Integer{value})),
+            Err(_) => new_error("E0110", &format!("cannot convert {:?} to INTEGER in `int`", s.value)),
+        },
+        None => unsupported_argument("int", &args[0]),
+    }
+}
 
+/// `float(x)` widens an integer and parses a string like `"2.5"`.
+pub fn float(args: Vec<Rc<Box<dyn object::Object>>>) -> Rc<Box<dyn object::Object>> {
+    if args.len() != 1 {
+        return wrong_arguments(args.len(), 1);
+    }
+    if args[0].object_type() == object::ObjectType::FLOAT {
+        return args[0].clone();
+    }
+    if let Some(i) = args[0].as_any().downcast_ref::<object::Integer>() {
+        return Rc::new(Box::new(object::Float{value: i.value as f64}));
+    }
     match args[0].as_any().downcast_ref::<object::StringValue>() {
-        Some(s) => Rc::new(Box::new(object::Integer{value: s.value.len() as i64})),
-        None => Rc::new(Box::new(object::Null{}))
+        Some(s) => match s.value.trim().parse::<f64>() {
+            Ok(value) => Rc::new(Box::new(object::Float{value})),
+            Err(_) => new_error("E0110", &format!("cannot convert {:?} to FLOAT in `float`", s.value)),
+        },
+        None => unsupported_argument("float", &args[0]),
+    }
+}
+
+/// Shared body of `floor`, `ceil` and `round`, which return INTEGERs.
+fn rounding_builtin(name: &str, args: Vec<Rc<Box<dyn object::Object>>>, rounding: fn(f64) -> f64) -> Rc<Box<dyn object::Object>> {
+    if args.len() != 1 {
+        return wrong_arguments(args.len(), 1);
+    }
+    if args[0].object_type() == object::ObjectType::INTEGER {
+        return args[0].clone();
     }
-}
\ No newline at end of file
+    match args[0].as_any().downcast_ref::<object::Float>() {
+        Some(f) => float_to_integer(name, f.value, rounding),
+        None => unsupported_argument(name, &args[0]),
+    }
+}
+
+pub fn floor(args: Vec<Rc<Box<dyn object::Object>>>) -> Rc<Box<dyn object::Object>> {
+    rounding_builtin("floor", args, f64::floor)
+}
+
+pub fn ceil(args: Vec<Rc<Box<dyn object::Object>>>) -> Rc<Box<dyn object::Object>> {
+    rounding_builtin("ceil", args, f64::ceil)
+}
+
+/// Rounds half away from zero: `round(2.5)` is 3 and `round(-2.5)` is -3.
+pub fn round(args: Vec<Rc<Box<dyn object::Object>>>) -> Rc<Box<dyn object::Object>> {
+    rounding_builtin("round", args, f64::round)
+}
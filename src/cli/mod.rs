@@ -0,0 +1,440 @@
+use std::io::{BufRead, Write};
+use std::rc::Rc;
+
+use crate::ast;
+use crate::builtins::{self, Output};
+use crate::envoriment::Environment;
+use crate::fmt;
+use crate::lexer::Lexer;
+use crate::object;
+use crate::parser::Parser;
+use crate::repl;
+use crate::serialize;
+use crate::vm;
+
+const USAGE: &str = "usage:
+    interpeter                      start the REPL (or run a script piped on stdin)
+    interpeter run <file> [args]    run a script file
+    interpeter -e <source> [args]   evaluate source and print the result
+    interpeter - [args]             run a script read from stdin
+    interpeter tokens <file|->      print the tokens of a script with their spans
+    interpeter fmt [--write] <file|->
+                                    print a script formatted, or rewrite the file
+    interpeter ast [--sexp] <file|->
+                                    print the syntax tree of a script as JSON, or
+                                    as an S-expression
+    interpeter run-ast <file|-> [args]
+                                    run a syntax tree in either form
+
+Put `--vm` before a command to run the script on the bytecode VM instead of
+the tree-walking interpreter, e.g. `interpeter --vm run fib.mk`.
+
+Arguments after the script are available to it through `argc()`, `argv(i)`
+and `argv()`, which returns them all as an array.
+
+In the REPL, `:tokens` toggles printing the tokens of each line before it is
+evaluated.
+";
+
+/// The engine that executes a script.
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum Engine {
+    TreeWalker,
+    Vm,
+}
+
+/// Entry point of the command line interface. `args` excludes the program
+/// name; the returned value is the process exit code: 0 on success, 1 when
+/// the script fails to parse or evaluates to an error, 2 on bad usage.
+pub fn run<R: BufRead, E: Write>(args: &[String], input: &mut R, interactive: bool, out: &mut Output, err: &mut E) -> i32 {
+    let (engine, args) = match args.split_first() {
+        Some((flag, rest)) if flag == "--vm" => (Engine::Vm, rest),
+        _ => (Engine::TreeWalker, args),
+    };
+    match args.first().map(String::as_str) {
+        None if interactive && engine == Engine::Vm => usage(err),
+        None if interactive => {
+            writeln!(out, "Feel Free to type in commands").unwrap();
+            repl::start(input, out);
+            0
+        }
+        None | Some("-") => {
+            let mut source = String::new();
+            if let Err(e) = input.read_to_string(&mut source) {
+                writeln!(err, "error: could not read stdin: {}", e).unwrap();
+                return 1;
+            }
+            builtins::set_args(script_args(args.get(1..)));
+            execute("<stdin>", &source, false, engine, out, err)
+        }
+        Some("run") => {
+            let path = match args.get(1) {
+                Some(path) => path,
+                None => return usage(err),
+            };
+            let source = match std::fs::read_to_string(path) {
+                Ok(source) => source,
+                Err(e) => {
+                    writeln!(err, "error: could not read {}: {}", path, e).unwrap();
+                    return 1;
+                }
+            };
+            builtins::set_args(script_args(args.get(2..)));
+            execute(path, &source, false, engine, out, err)
+        }
+        Some("-e") => {
+            let source = match args.get(1) {
+                Some(source) => source,
+                None => return usage(err),
+            };
+            builtins::set_args(script_args(args.get(2..)));
+            execute("<expr>", source, true, engine, out, err)
+        }
+        Some("tokens") => match args.get(1) {
+            Some(path) => match read_source(path, input, err) {
+                Some((name, source)) => dump_tokens(name, &source, out, err),
+                None => 1,
+            },
+            None => usage(err),
+        },
+        Some("fmt") => {
+            let (write, path) = match (args.get(1).map(String::as_str), args.get(2)) {
+                (Some("--write"), Some(path)) if path != "-" => (true, path),
+                (Some("--write"), _) => return usage(err),
+                (Some(_), None) => (false, &args[1]),
+                _ => return usage(err),
+            };
+            let (name, source) = match read_source(path, input, err) {
+                Some(read) => read,
+                None => return 1,
+            };
+            let formatted = match fmt::format_source(&source) {
+                Ok(formatted) => formatted,
+                Err(errors) => {
+                    for error in errors {
+                        write!(err, "{}", error.render_in(name, &source)).unwrap();
+                    }
+                    return 1;
+                }
+            };
+            if !write {
+                write!(out, "{}", formatted).unwrap();
+            } else if let Err(e) = std::fs::write(path, formatted) {
+                writeln!(err, "error: could not write {}: {}", path, e).unwrap();
+                return 1;
+            }
+            0
+        }
+        Some("ast") => {
+            let (sexp, path) = match (args.get(1).map(String::as_str), args.get(2)) {
+                (Some("--sexp"), Some(path)) => (true, path),
+                (Some("--sexp"), None) => return usage(err),
+                (Some(_), None) => (false, &args[1]),
+                _ => return usage(err),
+            };
+            let (name, source) = match read_source(path, input, err) {
+                Some(read) => read,
+                None => return 1,
+            };
+            let mut parser = Parser::new(Lexer::new(source.clone()));
+            let program = parser.parse_program();
+            if !parser.errors.is_empty() {
+                for error in parser.errors {
+                    write!(err, "{}", error.render_in(name, &source)).unwrap();
+                }
+                return 1;
+            }
+            match sexp {
+                true => writeln!(out, "{}", serialize::to_sexp(&program, true)).unwrap(),
+                false => writeln!(out, "{}", serialize::to_json(&program)).unwrap(),
+            }
+            0
+        }
+        Some("run-ast") => {
+            let (name, text) = match args.get(1).map(|path| read_source(path, input, err)) {
+                Some(Some(read)) => read,
+                Some(None) => return 1,
+                None => return usage(err),
+            };
+            builtins::set_args(script_args(args.get(2..)));
+            let program = match text.trim_start().starts_with('(') {
+                true => serialize::from_sexp(&text),
+                false => serialize::from_json(&text),
+            };
+            match program {
+                // Spans in the tree point into a source we do not have.
+                Ok(program) => execute_program(name, "", &program, false, engine, out, err),
+                Err(error) => {
+                    write!(err, "{}", error.render_in(name, &text)).unwrap();
+                    1
+                }
+            }
+        }
+        Some("-h") | Some("--help") => {
+            write!(out, "{}", USAGE).unwrap();
+            0
+        }
+        Some(_) => usage(err),
+    }
+}
+
+/// Parses and evaluates `source` in a fresh environment on `engine`, writing
+/// diagnostics to `err`. With `print_result` set, a non-null final value is
+/// written to `out`.
+pub fn execute<E: Write>(name: &str, source: &str, print_result: bool, engine: Engine, out: &mut Output, err: &mut E) -> i32 {
+    let mut parser = Parser::new(Lexer::new(source.to_string()));
+    let program = parser.parse_program();
+    if !parser.errors.is_empty() {
+        for error in parser.errors {
+            write!(err, "{}", error.render_in(name, source)).unwrap();
+        }
+        return 1;
+    }
+    execute_program(name, source, &program, print_result, engine, out, err)
+}
+
+/// Evaluates an already parsed `program` like `execute`; `source` is what
+/// its spans refer to when rendering a runtime error. What the program
+/// prints with `puts` goes to `out` as it runs.
+pub fn execute_program<E: Write>(name: &str, source: &str, program: &ast::Program, print_result: bool, engine: Engine, out: &mut Output, err: &mut E) -> i32 {
+    builtins::set_output(out.clone());
+    let evaluated: Rc<Box<dyn object::Object>> = match engine {
+        Engine::TreeWalker => Environment::new().eval(program),
+        Engine::Vm => match vm::run_program(program) {
+            Ok(evaluated) => evaluated,
+            Err(diagnostic) => Rc::new(Box::new(object::Error{diagnostic: *diagnostic})),
+        },
+    };
+    if let Some(error) = evaluated.as_any().downcast_ref::<object::Error>() {
+        write!(err, "{}", error.diagnostic.render_in(name, source)).unwrap();
+        return 1;
+    }
+    if print_result && evaluated.object_type() != object::ObjectType::NULL {
+        writeln!(out, "{}", evaluated.inspect()).unwrap();
+    }
+    0
+}
+
+/// Reads a script from the file at `path`, or from `input` if it is `-`,
+/// returning the name to report it under with its text.
+fn read_source<'a, R: BufRead, E: Write>(path: &'a str, input: &mut R, err: &mut E) -> Option<(&'a str, String)> {
+    let mut source = String::new();
+    if path == "-" {
+        if let Err(e) = input.read_to_string(&mut source) {
+            writeln!(err, "error: could not read stdin: {}", e).unwrap();
+            return None;
+        }
+        return Some(("<stdin>", source));
+    }
+    match std::fs::read_to_string(path) {
+        Ok(source) => Some((path, source)),
+        Err(e) => {
+            writeln!(err, "error: could not read {}: {}", path, e).unwrap();
+            None
+        }
+    }
+}
+
+/// Writes one line per token of `source` to `out`, and any lexer errors to
+/// `err`. Returns 1 if there were errors.
+pub fn dump_tokens<W: Write, E: Write>(name: &str, source: &str, out: &mut W, err: &mut E) -> i32 {
+    let mut lexer = Lexer::new(source.to_string());
+    for token in lexer.by_ref() {
+        writeln!(out, "{}", token).unwrap();
+    }
+    for error in lexer.errors.iter() {
+        write!(err, "{}", error.render_in(name, source)).unwrap();
+    }
+    if lexer.errors.is_empty() { 0 } else { 1 }
+}
+
+fn script_args(rest: Option<&[String]>) -> Vec<String> {
+    match rest {
+        Some([separator, rest @ ..]) if separator == "--" => rest.to_vec(),
+        Some(rest) => rest.to_vec(),
+        None => Vec::new(),
+    }
+}
+
+fn usage<E: Write>(err: &mut E) -> i32 {
+    write!(err, "{}", USAGE).unwrap();
+    2
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::cell::RefCell;
+
+    fn run_cli(args: &[&str], stdin: &str) -> (i32, String, String) {
+        let args: Vec<String> = args.iter().map(|a| a.to_string()).collect();
+        let out = Rc::new(RefCell::new(Vec::new()));
+        let mut err = Vec::new();
+        let code = run(&args, &mut stdin.as_bytes(), false, &mut Output(out.clone()), &mut err);
+        let out = out.borrow().clone();
+        (code, String::from_utf8(out).unwrap(), String::from_utf8(err).unwrap())
+    }
+
+    #[test]
+    fn test_eval_expression() {
+        assert_eq!(run_cli(&["-e", "1 + 2 * 3"], ""), (0, String::from("7\n"), String::new()));
+        assert_eq!(run_cli(&["-e", "let x = 1;"], ""), (0, String::new(), String::new()));
+        assert_eq!(run_cli(&["-e", "puts(\"a\", [1]); puts(); 2"], ""), (0, String::from("a\n[1]\n2\n"), String::new()));
+        assert_eq!(run_cli(&["--vm", "-e", "puts(\"a\", [1]); puts(); 2"], ""), (0, String::from("a\n[1]\n2\n"), String::new()));
+        assert_eq!(run_cli(&["-e", "puts(1); 1 + true"], "").1, "1\n");
+    }
+
+    #[test]
+    fn test_puts_flushes_as_it_goes() {
+        // Records what had been written at each flush.
+        struct Flushes(Vec<u8>, Vec<String>);
+        impl Write for Flushes {
+            fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+                self.0.write(buf)
+            }
+            fn flush(&mut self) -> std::io::Result<()> {
+                self.1.push(String::from_utf8(self.0.clone()).unwrap());
+                Ok(())
+            }
+        }
+        for engine in [Engine::TreeWalker, Engine::Vm] {
+            let flushes = Rc::new(RefCell::new(Flushes(Vec::new(), Vec::new())));
+            let code = execute("<expr>", "puts(1); puts(\"a\", 2); 3", true, engine, &mut Output(flushes.clone()), &mut Vec::new());
+            assert_eq!(code, 0);
+            assert_eq!(flushes.borrow().1, vec!["1\n", "1\na\n2\n"]);
+            assert_eq!(flushes.borrow().0, b"1\na\n2\n3\n");
+        }
+    }
+
+    #[test]
+    fn test_script_from_stdin_spans_lines() {
+        let script = "let add = fn(a, b) {\n  a + b\n};\nadd(1, 2);\n";
+        assert_eq!(run_cli(&[], script).0, 0);
+        assert_eq!(run_cli(&["-"], script).0, 0);
+    }
+
+    #[test]
+    fn test_errors_exit_non_zero() {
+        let (code, _, err) = run_cli(&["-e", "let = 1;"], "");
+        assert_eq!(code, 1);
+        assert!(err.starts_with("error[E0001]: expected next token to be IDENT, got ASSIGN instead\n --> <expr>:1:5\n"));
+
+        let (code, _, err) = run_cli(&[], "1;\n2 + true;\n");
+        assert_eq!(code, 1);
+        assert!(err.contains("--> <stdin>:2:1"));
+
+        let (code, _, err) = run_cli(&["-e", "len(\"abc)"], "");
+        assert_eq!(code, 1);
+        assert!(err.starts_with("error[E0006]: unterminated string literal\n --> <expr>:1:5\n"));
+    }
+
+    #[test]
+    fn test_run_file() {
+        let path = std::env::temp_dir().join(format!("interpeter-cli-{}.mk", std::process::id()));
+        std::fs::write(&path, "if (argc() == 2) { argv(1) } else { missing }").unwrap();
+        let path = path.to_str().unwrap();
+        assert_eq!(run_cli(&["run", path, "a", "b"], "").0, 0);
+        assert_eq!(run_cli(&["run", path, "--", "a", "b"], "").0, 0);
+        assert_eq!(run_cli(&["run", path, "a"], "").0, 1);
+        std::fs::remove_file(path).unwrap();
+
+        assert_eq!(run_cli(&["run", path], "").0, 1);
+    }
+
+    #[test]
+    fn test_script_args() {
+        assert_eq!(run_cli(&["-e", "argv(0) + argv(1)", "x", "y"], ""), (0, String::from("xy\n"), String::new()));
+        assert_eq!(run_cli(&["-e", "argc()"], ""), (0, String::from("0\n"), String::new()));
+        assert_eq!(run_cli(&["-e", "len(argv())", "x", "y"], ""), (0, String::from("2\n"), String::new()));
+    }
+
+    #[test]
+    fn test_bad_usage() {
+        assert_eq!(run_cli(&["run"], "").0, 2);
+        assert_eq!(run_cli(&["-e"], "").0, 2);
+        assert_eq!(run_cli(&["--bogus"], "").0, 2);
+        assert_eq!(run_cli(&["--vm", "run"], "").0, 2);
+    }
+
+    #[test]
+    fn test_dump_tokens() {
+        let (code, out, err) = run_cli(&["tokens", "-"], "let x = 1;\n\"é\" <= x");
+        assert_eq!(code, 0, "{}", err);
+        assert_eq!(out, "\
+1:1 0..3 LET \"let\"
+1:5 4..5 IDENT \"x\"
+1:7 6..7 ASSIGN \"=\"
+1:9 8..9 INT \"1\"
+1:10 9..10 SEMICOLON \";\"
+2:1 11..15 STRING \"é\"
+2:5 16..18 LT_EQ \"<=\"
+2:8 19..20 IDENT \"x\"
+2:9 20..20 EOF \"\"
+");
+
+        let (code, out, err) = run_cli(&["tokens", "-"], "1 \"open");
+        assert_eq!(code, 1);
+        assert_eq!(out.lines().count(), 3);
+        assert!(err.starts_with("error[E0006]: unterminated string literal\n --> <stdin>:1:3\n"));
+
+        assert_eq!(run_cli(&["tokens"], "").0, 2);
+    }
+
+    #[test]
+    fn test_fmt() {
+        assert_eq!(run_cli(&["fmt", "-"], "let x=1;if(x){x}"), (0, String::from("let x = 1;\nif (x) {\n    x\n}\n"), String::new()));
+
+        let (code, out, err) = run_cli(&["fmt", "-"], "let = 1;");
+        assert_eq!((code, out.as_str()), (1, ""));
+        assert!(err.starts_with("error[E0001]"), "{}", err);
+
+        let path = std::env::temp_dir().join(format!("interpeter-fmt-{}.mk", std::process::id()));
+        std::fs::write(&path, "puts( 1 )").unwrap();
+        let path = path.to_str().unwrap();
+        assert_eq!(run_cli(&["fmt", "--write", path], ""), (0, String::new(), String::new()));
+        assert_eq!(std::fs::read_to_string(path).unwrap(), "puts(1);\n");
+        std::fs::remove_file(path).unwrap();
+
+        assert_eq!(run_cli(&["fmt"], "").0, 2);
+        assert_eq!(run_cli(&["fmt", "--write", "-"], "").0, 2);
+        assert_eq!(run_cli(&["fmt", "a.mk", "b.mk"], "").0, 2);
+    }
+
+    #[test]
+    fn test_ast() {
+        let (code, json, err) = run_cli(&["ast", "-"], "if (argc() == 1) { argv(0) } else { missing }");
+        assert_eq!(code, 0, "{}", err);
+        assert!(json.starts_with("{\"type\":\"Program\",\"span\":{\"start\":0,\"end\":45,"), "{}", json);
+        assert_eq!(run_cli(&["run-ast", "-", "hi"], &json), (0, String::new(), String::new()));
+        assert_eq!(run_cli(&["run-ast", "-"], &json).0, 1);
+
+        let (code, sexp, err) = run_cli(&["ast", "--sexp", "-"], "1 + 2");
+        assert_eq!(code, 0, "{}", err);
+        assert_eq!(sexp, "(program@0..5:1:1 (expr@0..5:1:1 (infix@0..5:1:1 + 1@0..1:1:1 2@4..5:1:5)))\n");
+        assert_eq!(run_cli(&["--vm", "run-ast", "-"], "(program (expr (infix * 6 7)))"), (0, String::new(), String::new()));
+
+        let (code, _, err) = run_cli(&["run-ast", "-"], "(program (expr (infix + 1 true)))");
+        assert_eq!((code, err.as_str()), (1, "error[E0100]: type mismatch: INTEGER + BOOLEAN\n"));
+        let (code, _, err) = run_cli(&["run-ast", "-"], "(program (expr)");
+        assert_eq!(code, 1);
+        assert!(err.starts_with("error[E0200]: unclosed `(`\n --> <stdin>:1:1\n"), "{}", err);
+        let (code, _, err) = run_cli(&["ast", "-"], "let = 1;");
+        assert_eq!(code, 1);
+        assert!(err.starts_with("error[E0001]"), "{}", err);
+
+        assert_eq!(run_cli(&["ast"], "").0, 2);
+        assert_eq!(run_cli(&["ast", "--sexp"], "").0, 2);
+        assert_eq!(run_cli(&["run-ast"], "").0, 2);
+    }
+
+    #[test]
+    fn test_vm_engine() {
+        assert_eq!(run_cli(&["--vm", "-e", "let f = fn(x) { x * 2 }; f(21)"], ""), (0, String::from("42\n"), String::new()));
+        assert_eq!(run_cli(&["--vm", "-e", "argv(0)", "x"], ""), (0, String::from("x\n"), String::new()));
+        assert_eq!(run_cli(&["--vm"], "1;\n").0, 0);
+
+        let (code, _, err) = run_cli(&["--vm", "-"], "1;\n2 + true;\n");
+        assert_eq!(code, 1);
+        assert!(err.starts_with("error[E0100]: type mismatch: INTEGER + BOOLEAN\n --> <stdin>:2:1\n"));
+    }
+}
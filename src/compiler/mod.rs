@@ -0,0 +1,1330 @@
+use std::collections::{HashMap, HashSet};
+use std::rc::Rc;
+
+use crate::ast::{self, Node};
+use crate::builtins;
+use crate::diagnostic::Diagnostic;
+use crate::evaluator;
+use crate::object::{self, Object};
+use crate::token::Span;
+
+/// A single bytecode instruction. Operands follow the opcode byte big-endian,
+/// with the widths given by `Opcode::operand_widths`.
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum Opcode {
+    Constant,
+    Pop,
+    Add,
+    Sub,
+    Mul,
+    Div,
+    True,
+    False,
+    Null,
+    Equal,
+    NotEqual,
+    GreaterThan,
+    LessThan,
+    Minus,
+    Bang,
+    JumpNotTruthy,
+    Jump,
+    GetGlobal,
+    SetGlobal,
+    GetLocal,
+    SetLocal,
+    GetBuiltin,
+    GetFree,
+    CurrentClosure,
+    Array,
+    Hash,
+    Index,
+    Call,
+    ReturnValue,
+    Return,
+    Closure,
+    Mod,
+    GreaterEqual,
+    LessEqual,
+    Interpolate,
+    Range,
+    RangeInclusive,
+    Iterate,
+    IterNext,
+    Dup,
+    AssignGlobal,
+    LoadCell,
+    StoreCell,
+    GetPath,
+    SetPath,
+    Match,
+    NoMatch,
+}
+
+const OPCODES: [Opcode; 47] = [
+    Opcode::Constant,
+    Opcode::Pop,
+    Opcode::Add,
+    Opcode::Sub,
+    Opcode::Mul,
+    Opcode::Div,
+    Opcode::True,
+    Opcode::False,
+    Opcode::Null,
+    Opcode::Equal,
+    Opcode::NotEqual,
+    Opcode::GreaterThan,
+    Opcode::LessThan,
+    Opcode::Minus,
+    Opcode::Bang,
+    Opcode::JumpNotTruthy,
+    Opcode::Jump,
+    Opcode::GetGlobal,
+    Opcode::SetGlobal,
+    Opcode::GetLocal,
+    Opcode::SetLocal,
+    Opcode::GetBuiltin,
+    Opcode::GetFree,
+    Opcode::CurrentClosure,
+    Opcode::Array,
+    Opcode::Hash,
+    Opcode::Index,
+    Opcode::Call,
+    Opcode::ReturnValue,
+    Opcode::Return,
+    Opcode::Closure,
+    Opcode::Mod,
+    Opcode::GreaterEqual,
+    Opcode::LessEqual,
+    Opcode::Interpolate,
+    Opcode::Range,
+    Opcode::RangeInclusive,
+    Opcode::Iterate,
+    Opcode::IterNext,
+    Opcode::Dup,
+    Opcode::AssignGlobal,
+    Opcode::LoadCell,
+    Opcode::StoreCell,
+    Opcode::GetPath,
+    Opcode::SetPath,
+    Opcode::Match,
+    Opcode::NoMatch,
+];
+
+impl Opcode {
+    pub fn from_byte(byte: u8) -> Option<Opcode> {
+        OPCODES.get(byte as usize).copied()
+    }
+
+    pub fn operand_widths(&self) -> &'static [usize] {
+        match self {
+            Opcode::Constant | Opcode::JumpNotTruthy | Opcode::Jump | Opcode::GetGlobal | Opcode::SetGlobal
+            | Opcode::Array | Opcode::Hash | Opcode::Interpolate | Opcode::IterNext | Opcode::AssignGlobal => &[2],
+            Opcode::GetLocal | Opcode::SetLocal | Opcode::GetBuiltin | Opcode::GetFree | Opcode::Call
+            | Opcode::GetPath | Opcode::SetPath => &[1],
+            Opcode::Closure => &[2, 1],
+            Opcode::Match => &[2, 2],
+            _ => &[],
+        }
+    }
+
+    /// The opcode evaluating the infix `operator`.
+    pub fn for_operator(operator: &str) -> Option<Opcode> {
+        OPCODES.iter().copied().find(|op| op.operator() == Some(operator))
+    }
+
+    /// The infix operator this opcode evaluates, as written in source.
+    pub fn operator(&self) -> Option<&'static str> {
+        match self {
+            Opcode::Add => Some("+"),
+            Opcode::Sub => Some("-"),
+            Opcode::Mul => Some("*"),
+            Opcode::Div => Some("/"),
+            Opcode::Equal => Some("=="),
+            Opcode::NotEqual => Some("!="),
+            Opcode::GreaterThan => Some(">"),
+            Opcode::LessThan => Some("<"),
+            Opcode::Mod => Some("%"),
+            Opcode::GreaterEqual => Some(">="),
+            Opcode::LessEqual => Some("<="),
+            Opcode::Range => Some(".."),
+            Opcode::RangeInclusive => Some("..="),
+            _ => None,
+        }
+    }
+}
+
+/// Encodes one instruction. Operands must fit their widths, which
+/// `Compiler` checks before emitting.
+pub fn make(op: Opcode, operands: &[usize]) -> Vec<u8> {
+    let mut instruction = vec![op as u8];
+    for (operand, width) in operands.iter().zip(op.operand_widths()) {
+        match width {
+            2 => instruction.extend_from_slice(&(*operand as u16).to_be_bytes()),
+            _ => instruction.push(*operand as u8),
+        }
+    }
+    instruction
+}
+
+pub fn read_u16(instructions: &[u8], offset: usize) -> usize {
+    u16::from_be_bytes([instructions[offset], instructions[offset + 1]]) as usize
+}
+
+/// Human readable listing of `instructions`, one instruction per line.
+pub fn disassemble(instructions: &[u8]) -> String {
+    let mut out = String::new();
+    let mut offset = 0;
+    while offset < instructions.len() {
+        let op = match Opcode::from_byte(instructions[offset]) {
+            Some(op) => op,
+            None => {
+                out.push_str(&format!("{:04} unknown opcode {}\n", offset, instructions[offset]));
+                offset += 1;
+                continue;
+            }
+        };
+        out.push_str(&format!("{:04} {:?}", offset, op));
+        offset += 1;
+        for width in op.operand_widths() {
+            let operand = match width {
+                2 => read_u16(instructions, offset),
+                _ => instructions[offset] as usize,
+            };
+            out.push_str(&format!(" {}", operand));
+            offset += width;
+        }
+        out.push('\n');
+    }
+    out
+}
+
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum SymbolScope {
+    Global,
+    Local,
+    Builtin,
+    Free,
+    /// The function currently being compiled, referring to itself by the name
+    /// it was bound to with `let`.
+    Function,
+}
+
+/// A resolved name. A local with `cell` set is both captured by a closure and
+/// assigned to, so its slot holds an `object::Cell` that the closures share.
+/// One with `constant` set was bound with `const`.
+#[derive(Debug, PartialEq, Clone)]
+pub struct Symbol {
+    pub name: String,
+    pub scope: SymbolScope,
+    pub index: usize,
+    pub cell: bool,
+    pub constant: bool,
+}
+
+/// Names visible while compiling one function body (or the top level), with
+/// the slot each one lives in.
+#[derive(Default)]
+pub struct SymbolTable {
+    outer: Option<Box<SymbolTable>>,
+    store: HashMap<String, Symbol>,
+    num_definitions: usize,
+    free_symbols: Vec<Symbol>,
+    /// Names whose locals in this table live in cells.
+    cells: HashSet<String>,
+    /// Names bound by a `let`, `const` or `for` compiled so far in this scope.
+    declared: HashSet<String>,
+    /// Slots of the locals that live in cells, including those of blocks
+    /// already left.
+    cell_slots: Vec<usize>,
+    /// For each block being compiled, such as a `match` arm, the bindings and
+    /// declared names of the scope around it, restored when it ends.
+    blocks: Vec<(HashMap<String, Symbol>, HashSet<String>)>,
+}
+
+impl SymbolTable {
+    pub fn new() -> Self {
+        SymbolTable::default()
+    }
+
+    pub fn new_enclosed(outer: SymbolTable) -> Self {
+        SymbolTable { outer: Some(Box::new(outer)), ..SymbolTable::default() }
+    }
+
+    /// Binds `name` in this table. Defining a name again in the same scope
+    /// reuses its slot, matching `let` rebinding in the tree-walker. Inside a
+    /// block, a name from around it gets a new slot instead.
+    pub fn define(&mut self, name: &str) -> Symbol {
+        let scope = if self.outer.is_none() { SymbolScope::Global } else { SymbolScope::Local };
+        if let Some(symbol) = self.store.get(name) {
+            if symbol.scope == scope && (self.blocks.is_empty() || self.declared.contains(name)) {
+                return symbol.clone();
+            }
+        }
+        let cell = scope == SymbolScope::Local && self.cells.contains(name);
+        let symbol = Symbol { name: name.to_string(), scope, index: self.num_definitions, cell, constant: false };
+        if cell {
+            self.cell_slots.push(symbol.index);
+        }
+        self.num_definitions += 1;
+        self.store.insert(name.to_string(), symbol.clone());
+        symbol
+    }
+
+    /// Starts a scope whose bindings hide those around it until `leave_block`.
+    /// Its names still get slots of the enclosing function or the globals.
+    pub fn enter_block(&mut self) {
+        let declared = std::mem::take(&mut self.declared);
+        self.blocks.push((self.store.clone(), declared));
+    }
+
+    pub fn leave_block(&mut self) {
+        (self.store, self.declared) = self.blocks.pop().unwrap();
+    }
+
+    /// Binds `name` in the outermost table, the one holding the globals.
+    pub fn define_global(&mut self, name: &str) -> Symbol {
+        match self.outer.as_mut() {
+            Some(outer) => outer.define_global(name),
+            None => self.define(name),
+        }
+    }
+
+    pub fn define_builtin(&mut self, index: usize, name: &str) -> Symbol {
+        let symbol = Symbol { name: name.to_string(), scope: SymbolScope::Builtin, index, cell: false, constant: false };
+        self.store.insert(name.to_string(), symbol.clone());
+        symbol
+    }
+
+    pub fn define_function_name(&mut self, name: &str) -> Symbol {
+        let symbol = Symbol { name: name.to_string(), scope: SymbolScope::Function, index: 0, cell: false, constant: false };
+        self.store.insert(name.to_string(), symbol.clone());
+        symbol
+    }
+
+    fn define_free(&mut self, original: Symbol) -> Symbol {
+        let symbol = Symbol { scope: SymbolScope::Free, index: self.free_symbols.len(), ..original.clone() };
+        self.free_symbols.push(original);
+        self.store.insert(symbol.name.clone(), symbol.clone());
+        symbol
+    }
+
+    /// Looks `name` up through the enclosing tables. Locals of an enclosing
+    /// function are captured as free variables of this one.
+    pub fn resolve(&mut self, name: &str) -> Option<Symbol> {
+        if let Some(symbol) = self.store.get(name) {
+            return Some(symbol.clone());
+        }
+        let symbol = self.outer.as_mut()?.resolve(name)?;
+        match symbol.scope {
+            SymbolScope::Global | SymbolScope::Builtin => Some(symbol),
+            _ => Some(self.define_free(symbol)),
+        }
+    }
+}
+
+/// The output of the compiler: the top-level instructions and the constants
+/// pool they index into. `globals` names each global slot for error messages.
+pub struct Bytecode {
+    pub main: object::CompiledFunction,
+    pub constants: Vec<Rc<Box<dyn Object>>>,
+    pub globals: Vec<String>,
+}
+
+#[derive(Clone, Copy)]
+struct EmittedInstruction {
+    opcode: Opcode,
+    position: usize,
+}
+
+/// A loop being compiled: where `continue` jumps back to, and the `break`
+/// jumps to point past its end once that is known.
+struct Loop {
+    start: usize,
+    breaks: Vec<usize>,
+}
+
+#[derive(Default)]
+struct CompilationScope {
+    instructions: Vec<u8>,
+    spans: Vec<(usize, Span)>,
+    last: Option<EmittedInstruction>,
+    previous: Option<EmittedInstruction>,
+    loops: Vec<Loop>,
+}
+
+/// Lowers an `ast::Program` to `Bytecode` for the `vm`.
+///
+/// Top-level `let` and `const` names are declared before compiling, so
+/// functions may call globals defined further down as long as they run after
+/// the definition, as in the tree-walker. Any other name not bound where it
+/// is used is taken to be a global, so using it is an error only once it
+/// runs. A program over a limit of the bytecode format, such as a function
+/// with more than 256 locals, is a compile error.
+pub struct Compiler {
+    constants: Vec<Rc<Box<dyn Object>>>,
+    symbol_table: SymbolTable,
+    scopes: Vec<CompilationScope>,
+    globals: Vec<String>,
+}
+
+impl Compiler {
+    pub fn new() -> Self {
+        let mut symbol_table = SymbolTable::new();
+        for (i, (name, _)) in builtins::BUILTINS.iter().enumerate() {
+            symbol_table.define_builtin(i, name);
+        }
+        Compiler {
+            constants: Vec::new(),
+            symbol_table,
+            scopes: vec![CompilationScope::default()],
+            globals: Vec::new(),
+        }
+    }
+
+    pub fn compile_program(&mut self, program: &ast::Program) -> Result<(), Box<Diagnostic>> {
+        for statement in program.statements.iter() {
+            if let Some(let_statement) = statement.as_any().downcast_ref::<ast::LetStatement>() {
+                self.define(&let_statement.name.value);
+            }
+        }
+        for statement in program.statements.iter() {
+            self.compile(statement.as_node())?;
+        }
+        // A program ending in `let` or `const` evaluates to null, not to the
+        // value of the last expression statement before it.
+        if let Some(last) = program.statements.last() {
+            if let ast::NodeType::LetStatement | ast::NodeType::ConstStatement = last.node_type() {
+                self.emit(Opcode::Null, &[])?;
+                self.emit(Opcode::Pop, &[])?;
+            }
+        }
+        Ok(())
+    }
+
+    pub fn bytecode(mut self) -> Bytecode {
+        let scope = self.scopes.pop().unwrap();
+        Bytecode {
+            main: object::CompiledFunction {
+                instructions: scope.instructions.into(),
+                spans: scope.spans.into(),
+                num_locals: 0,
+                num_parameters: 0,
+                cells: Rc::new([]),
+            },
+            constants: self.constants,
+            globals: self.globals,
+        }
+    }
+
+    /// Compiles `node`, pointing an error from emitting one of its
+    /// instructions at it.
+    fn compile(&mut self, node: &dyn Node) -> Result<(), Box<Diagnostic>> {
+        self.compile_node(node).map_err(|mut error| {
+            error.span = error.span.or(Some(node.span()));
+            error
+        })
+    }
+
+    fn compile_node(&mut self, node: &dyn Node) -> Result<(), Box<Diagnostic>> {
+        match node.node_type() {
+            ast::NodeType::Program => self.compile_program(node.as_any().downcast_ref::<ast::Program>().unwrap())?,
+            ast::NodeType::ExpressionStatement => {
+                self.compile(node.as_any().downcast_ref::<ast::ExpressionStatement>().unwrap().expression.as_node())?;
+                self.emit(Opcode::Pop, &[])?;
+            }
+            ast::NodeType::BlockStatement => {
+                self.compile_block(node)?;
+                self.emit(Opcode::Pop, &[])?;
+            }
+            ast::NodeType::LetStatement | ast::NodeType::ConstStatement => {
+                let let_statement = node.as_any().downcast_ref::<ast::LetStatement>().unwrap();
+                let constant = node.node_type() == ast::NodeType::ConstStatement;
+                let symbol = match let_statement.value.as_any().downcast_ref::<ast::FunctionLiteral>() {
+                    // Bound first, for a function that assigns to its own name.
+                    Some(function) => {
+                        let symbol = self.declare(&let_statement.name.value, constant, let_statement.span)?;
+                        self.compile_function(function, Some(&let_statement.name.value))?;
+                        symbol
+                    }
+                    None => {
+                        self.compile(let_statement.value.as_node())?;
+                        self.declare(&let_statement.name.value, constant, let_statement.span)?
+                    }
+                };
+                self.store_symbol(&symbol)?;
+            }
+            ast::NodeType::ReturnStatement => {
+                self.compile(node.as_any().downcast_ref::<ast::ReturnStatement>().unwrap().return_value.as_node())?;
+                self.emit(Opcode::ReturnValue, &[])?;
+            }
+            ast::NodeType::IntegerLiteral => {
+                let value = node.as_any().downcast_ref::<ast::IntegerLiteral>().unwrap().value;
+                let index = self.add_constant(Rc::new(Box::new(object::Integer{value})));
+                self.emit(Opcode::Constant, &[index])?;
+            }
+            ast::NodeType::FloatLiteral => {
+                let value = node.as_any().downcast_ref::<ast::FloatLiteral>().unwrap().value;
+                let index = self.add_constant(Rc::new(Box::new(object::Float{value})));
+                self.emit(Opcode::Constant, &[index])?;
+            }
+            ast::NodeType::StringLiteral => {
+                let value = node.as_any().downcast_ref::<ast::StringLiteral>().unwrap().value.clone();
+                let index = self.add_constant(Rc::new(Box::new(object::StringValue{value})));
+                self.emit(Opcode::Constant, &[index])?;
+            }
+            ast::NodeType::Boolean => {
+                match node.as_any().downcast_ref::<ast::Boolean>().unwrap().value {
+                    true => self.emit(Opcode::True, &[])?,
+                    false => self.emit(Opcode::False, &[])?,
+                };
+            }
+            ast::NodeType::PrefixExpression => {
+                let prefix = node.as_any().downcast_ref::<ast::PrefixExpression>().unwrap();
+                self.compile(prefix.right.as_node())?;
+                match prefix.operator.as_str() {
+                    "!" => self.emit_at(Opcode::Bang, &[], node.span())?,
+                    "-" => self.emit_at(Opcode::Minus, &[], node.span())?,
+                    operator => return Err(Box::new(Diagnostic::error("E0101", &format!("unknown operator: {}", operator)).with_span(node.span()))),
+                };
+            }
+            ast::NodeType::InfixExpression => {
+                let infix = node.as_any().downcast_ref::<ast::InfixExpression>().unwrap();
+                if infix.operator == "&&" || infix.operator == "||" {
+                    return self.compile_logical(infix);
+                }
+                self.compile(infix.left.as_node())?;
+                self.compile(infix.right.as_node())?;
+                self.compile_operator(&infix.operator, node.span())?;
+            }
+            ast::NodeType::IfExpression => {
+                let if_expression = node.as_any().downcast_ref::<ast::IfExpression>().unwrap();
+                self.compile(if_expression.condition.as_node())?;
+                let jump_not_truthy = self.emit(Opcode::JumpNotTruthy, &[0])?;
+                self.compile_block(if_expression.consequence.as_node())?;
+                let jump = self.emit(Opcode::Jump, &[0])?;
+                self.patch_jump(jump_not_truthy)?;
+                match &if_expression.alternative {
+                    Some(alternative) => self.compile_block(alternative.as_node())?,
+                    None => {
+                        self.emit(Opcode::Null, &[])?;
+                    }
+                }
+                self.patch_jump(jump)?;
+            }
+            ast::NodeType::Identifier => {
+                let name = &node.as_any().downcast_ref::<ast::Identifier>().unwrap().value;
+                let symbol = self.resolve(name);
+                self.load_symbol(&symbol, node.span())?;
+            }
+            ast::NodeType::FunctionLiteral => self.compile_function(node.as_any().downcast_ref::<ast::FunctionLiteral>().unwrap(), None)?,
+            ast::NodeType::CallExpression => {
+                let call = node.as_any().downcast_ref::<ast::CallExpression>().unwrap();
+                self.compile(call.function.as_node())?;
+                for argument in call.arguments.iter() {
+                    self.compile(argument.as_node())?;
+                }
+                self.emit_at(Opcode::Call, &[call.arguments.len()], node.span())?;
+            }
+            ast::NodeType::InterpolatedString => {
+                let string = node.as_any().downcast_ref::<ast::InterpolatedString>().unwrap();
+                for part in string.parts.iter() {
+                    match part {
+                        ast::InterpolationPart::Text(text) => {
+                            let index = self.add_constant(Rc::new(Box::new(object::StringValue{value: text.clone()})));
+                            self.emit(Opcode::Constant, &[index])?;
+                        }
+                        ast::InterpolationPart::Expression(expression) => self.compile(expression.as_node())?,
+                    }
+                }
+                self.emit(Opcode::Interpolate, &[string.parts.len()])?;
+            }
+            ast::NodeType::ArrayLiteral => {
+                let array = node.as_any().downcast_ref::<ast::ArrayLiteral>().unwrap();
+                for element in array.elements.iter() {
+                    self.compile(element.as_node())?;
+                }
+                self.emit(Opcode::Array, &[array.elements.len()])?;
+            }
+            ast::NodeType::HashLiteral => {
+                let hash = node.as_any().downcast_ref::<ast::HashLiteral>().unwrap();
+                for (key, value) in hash.pairs.iter() {
+                    self.compile(key.as_node())?;
+                    self.compile(value.as_node())?;
+                }
+                self.emit_at(Opcode::Hash, &[hash.pairs.len() * 2], node.span())?;
+            }
+            ast::NodeType::IndexExpression => {
+                let index = node.as_any().downcast_ref::<ast::IndexExpression>().unwrap();
+                self.compile(index.left.as_node())?;
+                self.compile(index.index.as_node())?;
+                self.emit_at(Opcode::Index, &[], node.span())?;
+            }
+            ast::NodeType::WhileStatement => {
+                let statement = node.as_any().downcast_ref::<ast::WhileStatement>().unwrap();
+                let start = self.current_scope().instructions.len();
+                self.compile(statement.condition.as_node())?;
+                let jump_not_truthy = self.emit(Opcode::JumpNotTruthy, &[0])?;
+                self.current_scope().loops.push(Loop { start, breaks: Vec::new() });
+                let body = self.compile_block(statement.body.as_node());
+                let tail = self.current_scope().loops.pop().unwrap();
+                body?;
+                self.emit(Opcode::Pop, &[])?;
+                self.emit(Opcode::Jump, &[start])?;
+                self.patch_jump(jump_not_truthy)?;
+                for position in tail.breaks {
+                    self.patch_jump(position)?;
+                }
+                // Like a `let`, the loop evaluates to null when it ends a block.
+                self.emit(Opcode::Null, &[])?;
+                self.emit(Opcode::Pop, &[])?;
+            }
+            ast::NodeType::ForStatement => {
+                // The iterator stays on the stack for the whole loop. `IterNext`
+                // pushes the next element, or jumps past the body when there is
+                // none, where the iterator is popped again.
+                let statement = node.as_any().downcast_ref::<ast::ForStatement>().unwrap();
+                self.compile(statement.iterable.as_node())?;
+                self.emit_at(Opcode::Iterate, &[], statement.iterable.span())?;
+                let start = self.emit(Opcode::IterNext, &[0])?;
+                let symbol = self.declare(&statement.variable.value, false, statement.span)?;
+                self.store_symbol(&symbol)?;
+                self.current_scope().loops.push(Loop { start, breaks: Vec::new() });
+                let body = self.compile_block(statement.body.as_node());
+                let tail = self.current_scope().loops.pop().unwrap();
+                body?;
+                self.emit(Opcode::Pop, &[])?;
+                self.emit(Opcode::Jump, &[start])?;
+                self.patch_jump(start)?;
+                for position in tail.breaks {
+                    self.patch_jump(position)?;
+                }
+                self.emit(Opcode::Pop, &[])?;
+                self.emit(Opcode::Null, &[])?;
+                self.emit(Opcode::Pop, &[])?;
+            }
+            ast::NodeType::AssignExpression => self.compile_assignment(node.as_any().downcast_ref::<ast::AssignExpression>().unwrap())?,
+            ast::NodeType::MatchExpression => {
+                // The subject stays on the stack while arms are tried. `Match`
+                // pushes the values the pattern binds, or jumps to the next arm;
+                // the subject is popped before the body runs.
+                let expression = node.as_any().downcast_ref::<ast::MatchExpression>().unwrap();
+                self.compile(expression.subject.as_node())?;
+                let mut ends = Vec::new();
+                for arm in expression.arms.iter() {
+                    let pattern = self.add_constant(Rc::new(Box::new(object::Pattern::new(arm.pattern.as_node()))));
+                    let next = self.emit(Opcode::Match, &[0, pattern])?;
+                    // The arm's names live in slots of their own, which a later
+                    // arm or the code after the match does not see.
+                    self.symbol_table.enter_block();
+                    let mut symbols = Vec::new();
+                    for name in ast::pattern_bindings(arm.pattern.as_node()).unwrap() {
+                        symbols.push(self.declare(&name.value, false, name.span)?);
+                    }
+                    for symbol in symbols.iter().rev() {
+                        self.store_symbol(symbol)?;
+                    }
+                    let guard = match &arm.guard {
+                        Some(guard) => {
+                            self.compile(guard.as_node())?;
+                            Some(self.emit(Opcode::JumpNotTruthy, &[0])?)
+                        }
+                        None => None,
+                    };
+                    self.emit(Opcode::Pop, &[])?;
+                    self.compile_block(arm.body.as_node())?;
+                    self.symbol_table.leave_block();
+                    ends.push(self.emit(Opcode::Jump, &[0])?);
+                    if let Some(guard) = guard {
+                        self.patch_jump(guard)?;
+                    }
+                    self.patch_jump(next)?;
+                }
+                self.emit_at(Opcode::NoMatch, &[], expression.subject.span())?;
+                for end in ends {
+                    self.patch_jump(end)?;
+                }
+            }
+            ast::NodeType::BreakStatement | ast::NodeType::ContinueStatement => {
+                let keyword = node.token_literal();
+                let scope = self.current_scope();
+                let position = scope.instructions.len();
+                match scope.loops.last_mut() {
+                    Some(innermost) if node.node_type() == ast::NodeType::BreakStatement => innermost.breaks.push(position),
+                    Some(innermost) => {
+                        let start = innermost.start;
+                        self.emit(Opcode::Jump, &[start])?;
+                        return Ok(());
+                    }
+                    None => return Err(Box::new(Diagnostic::error("E0012", &format!("`{}` outside of a loop", keyword)).with_span(node.span()))),
+                }
+                self.emit(Opcode::Jump, &[0])?;
+            }
+            ast::NodeType::MatchArm | ast::NodeType::RestPattern => unreachable!("only compiled as part of a match"),
+            ast::NodeType::Statement | ast::NodeType::Expression => unreachable!("abstract node types are never constructed"),
+        }
+        Ok(())
+    }
+
+    /// Compiles a block so that it leaves exactly one value on the stack: the
+    /// value of its last expression statement, or null.
+    fn compile_block(&mut self, node: &dyn Node) -> Result<(), Box<Diagnostic>> {
+        let block = node.as_any().downcast_ref::<ast::BlockStatement>().unwrap();
+        let start = self.current_scope().instructions.len();
+        for statement in block.statements.iter() {
+            self.compile(statement.as_node())?;
+        }
+        let produced_value = self.current_scope().instructions.len() > start;
+        match self.current_scope().last {
+            Some(last) if produced_value && last.opcode == Opcode::Pop => self.remove_last_pop(),
+            _ => {
+                self.emit(Opcode::Null, &[])?;
+            }
+        }
+        Ok(())
+    }
+
+    fn compile_operator(&mut self, operator: &str, span: Span) -> Result<(), Box<Diagnostic>> {
+        match Opcode::for_operator(operator) {
+            Some(op) => {
+                self.emit_at(op, &[], span)?;
+                Ok(())
+            }
+            None => Err(Box::new(Diagnostic::error("E0101", &format!("unknown operator: {}", operator)).with_span(span))),
+        }
+    }
+
+    /// Compiles an assignment in the order the tree-walker evaluates it,
+    /// leaving the assigned value on the stack. An element is replaced with
+    /// `SetPath`, which leaves the value below the updated collection that is
+    /// then stored in the variable.
+    fn compile_assignment(&mut self, assign: &ast::AssignExpression) -> Result<(), Box<Diagnostic>> {
+        let (name, path) = ast::place(assign.target.as_node()).unwrap();
+        let compound = assign.operator.strip_suffix('=').filter(|operator| !operator.is_empty());
+        let symbol = self.resolve(&name.value);
+        if symbol.scope == SymbolScope::Builtin {
+            let message = format!("cannot assign to builtin `{}`", name.value);
+            return Err(Box::new(Diagnostic::error("E0113", &message).with_span(assign.span)));
+        }
+        if symbol.constant {
+            let message = format!("cannot assign to constant `{}`", name.value);
+            return Err(Box::new(Diagnostic::error("E0114", &message).with_span(assign.span)));
+        }
+        if compound.is_some() || !path.is_empty() {
+            self.load_symbol(&symbol, name.span)?;
+        }
+        for index in path.iter() {
+            self.compile(*index)?;
+        }
+        if compound.is_some() && !path.is_empty() {
+            self.emit_at(Opcode::GetPath, &[path.len()], assign.span)?;
+        }
+        self.compile(assign.value.as_node())?;
+        if let Some(operator) = compound {
+            self.compile_operator(operator, assign.span)?;
+        }
+        match path.is_empty() {
+            true => self.emit(Opcode::Dup, &[])?,
+            false => self.emit_at(Opcode::SetPath, &[path.len()], assign.span)?,
+        };
+        match symbol.scope {
+            SymbolScope::Global => {
+                self.emit_at(Opcode::AssignGlobal, &[symbol.index], assign.span)?;
+            }
+            _ => self.store_symbol(&symbol)?,
+        }
+        Ok(())
+    }
+
+    /// Compiles `&&` and `||` with a jump over the right side when the left
+    /// one decides the result. Either way the result is a boolean.
+    fn compile_logical(&mut self, infix: &ast::InfixExpression) -> Result<(), Box<Diagnostic>> {
+        self.compile(infix.left.as_node())?;
+        let jump_not_truthy = self.emit(Opcode::JumpNotTruthy, &[0])?;
+        if infix.operator == "&&" {
+            self.compile_truthiness(infix.right.as_node())?;
+            let jump = self.emit(Opcode::Jump, &[0])?;
+            self.patch_jump(jump_not_truthy)?;
+            self.emit(Opcode::False, &[])?;
+            self.patch_jump(jump)?;
+        } else {
+            self.emit(Opcode::True, &[])?;
+            let jump = self.emit(Opcode::Jump, &[0])?;
+            self.patch_jump(jump_not_truthy)?;
+            self.compile_truthiness(infix.right.as_node())?;
+            self.patch_jump(jump)?;
+        }
+        Ok(())
+    }
+
+    /// Compiles `node` and converts its value to a boolean with a double `!`.
+    fn compile_truthiness(&mut self, node: &dyn Node) -> Result<(), Box<Diagnostic>> {
+        self.compile(node)?;
+        self.emit(Opcode::Bang, &[])?;
+        self.emit(Opcode::Bang, &[])?;
+        Ok(())
+    }
+
+    fn compile_function(&mut self, function: &ast::FunctionLiteral, name: Option<&str>) -> Result<(), Box<Diagnostic>> {
+        let (mut assigned, mut captured) = (HashSet::new(), HashSet::new());
+        scan_names(function.body.as_node(), false, &mut assigned, &mut captured);
+        self.enter_scope();
+        self.symbol_table.cells = assigned.intersection(&captured).cloned().collect();
+        // A function that assigns to its own name must see the binding the
+        // assignment changes, not the closure being called.
+        if let Some(name) = name.filter(|name| !assigned.contains(*name)) {
+            self.symbol_table.define_function_name(name);
+        }
+        for parameter in function.parameters.iter() {
+            self.symbol_table.define(&parameter.value);
+        }
+        if let Err(error) = self.compile_block(function.body.as_node()) {
+            self.leave_scope();
+            return Err(error);
+        }
+        self.emit(Opcode::ReturnValue, &[])?;
+
+        let num_locals = self.symbol_table.num_definitions;
+        let mut cells = self.symbol_table.cell_slots.clone();
+        cells.sort_unstable();
+        let (scope, free_symbols) = self.leave_scope();
+        // Captured cells are passed on as they are, not unwrapped.
+        for symbol in free_symbols.iter() {
+            self.load_symbol(&Symbol { cell: false, ..symbol.clone() }, function.span)?;
+        }
+        let compiled = object::CompiledFunction {
+            instructions: scope.instructions.into(),
+            spans: scope.spans.into(),
+            num_locals,
+            num_parameters: function.parameters.len(),
+            cells: cells.into(),
+        };
+        let index = self.add_constant(Rc::new(Box::new(compiled)));
+        self.emit(Opcode::Closure, &[index, free_symbols.len()])?;
+        Ok(())
+    }
+
+    fn define(&mut self, name: &str) -> Symbol {
+        let symbol = self.symbol_table.define(name);
+        self.add_global(&symbol);
+        symbol
+    }
+
+    /// Looks `name` up. A name bound nowhere gets a global slot of its own,
+    /// which is an E0102 at runtime unless a global of that name is set by
+    /// then, as in the tree-walker.
+    fn resolve(&mut self, name: &str) -> Symbol {
+        match self.symbol_table.resolve(name) {
+            Some(symbol) => symbol,
+            None => {
+                let symbol = self.symbol_table.define_global(name);
+                self.add_global(&symbol);
+                symbol
+            }
+        }
+    }
+
+    fn add_global(&mut self, symbol: &Symbol) {
+        if symbol.scope == SymbolScope::Global && symbol.index == self.globals.len() {
+            self.globals.push(symbol.name.clone());
+        }
+    }
+
+    /// Binds the name of a `let`, `const`, `for` or pattern in the current scope, by
+    /// the rules of `Environment::set` and `Environment::set_const`. A `const`
+    /// in a loop body is rejected, as `Environment::eval_program` does.
+    fn declare(&mut self, name: &str, constant: bool, span: Span) -> Result<Symbol, Box<Diagnostic>> {
+        let table = &self.symbol_table;
+        let existing = table.store.get(name).filter(|_| table.declared.contains(name));
+        let error = match existing.map(|symbol| symbol.constant) {
+            Some(true) => Diagnostic::error("E0115", &format!("cannot redeclare constant `{}`", name)),
+            Some(false) if constant => Diagnostic::error("E0115", &format!("`{}` is already declared", name)),
+            _ if constant && !self.scopes.last().unwrap().loops.is_empty() => evaluator::const_in_loop_error(name),
+            _ => {
+                let mut symbol = self.define(name);
+                if constant {
+                    symbol.constant = true;
+                    self.symbol_table.store.insert(name.to_string(), symbol.clone());
+                }
+                self.symbol_table.declared.insert(name.to_string());
+                return Ok(symbol);
+            }
+        };
+        Err(Box::new(error.with_span(span)))
+    }
+
+    fn load_symbol(&mut self, symbol: &Symbol, span: Span) -> Result<(), Box<Diagnostic>> {
+        match symbol.scope {
+            SymbolScope::Global => self.emit_at(Opcode::GetGlobal, &[symbol.index], span)?,
+            SymbolScope::Local => self.emit(Opcode::GetLocal, &[symbol.index])?,
+            SymbolScope::Builtin => self.emit(Opcode::GetBuiltin, &[symbol.index])?,
+            SymbolScope::Free => self.emit(Opcode::GetFree, &[symbol.index])?,
+            SymbolScope::Function => self.emit(Opcode::CurrentClosure, &[])?,
+        };
+        if symbol.cell {
+            self.emit(Opcode::LoadCell, &[])?;
+        }
+        Ok(())
+    }
+
+    /// Pops the value on top of the stack into the slot of `symbol`.
+    fn store_symbol(&mut self, symbol: &Symbol) -> Result<(), Box<Diagnostic>> {
+        match (symbol.scope, symbol.cell) {
+            (SymbolScope::Global, _) => {
+                self.emit(Opcode::SetGlobal, &[symbol.index])?;
+            }
+            (SymbolScope::Local, false) => {
+                self.emit(Opcode::SetLocal, &[symbol.index])?;
+            }
+            (SymbolScope::Local, true) => {
+                self.emit(Opcode::GetLocal, &[symbol.index])?;
+                self.emit(Opcode::StoreCell, &[])?;
+            }
+            // Only cells are assigned through a closure, see `scan_names`.
+            (_, _) => {
+                self.emit(Opcode::GetFree, &[symbol.index])?;
+                self.emit(Opcode::StoreCell, &[])?;
+            }
+        }
+        Ok(())
+    }
+
+    fn add_constant(&mut self, obj: Rc<Box<dyn Object>>) -> usize {
+        self.constants.push(obj);
+        self.constants.len() - 1
+    }
+
+    fn current_scope(&mut self) -> &mut CompilationScope {
+        self.scopes.last_mut().unwrap()
+    }
+
+    fn emit(&mut self, op: Opcode, operands: &[usize]) -> Result<usize, Box<Diagnostic>> {
+        for (index, (operand, width)) in operands.iter().zip(op.operand_widths()).enumerate() {
+            if *operand >> (8 * width) != 0 {
+                return Err(Box::new(too_wide(op, index)));
+            }
+        }
+        let instruction = make(op, operands);
+        let scope = self.current_scope();
+        let position = scope.instructions.len();
+        scope.instructions.extend_from_slice(&instruction);
+        scope.previous = scope.last;
+        scope.last = Some(EmittedInstruction { opcode: op, position });
+        Ok(position)
+    }
+
+    /// Emits an instruction that can fail at runtime, remembering `span` so
+    /// the `vm` can point its error at the source.
+    fn emit_at(&mut self, op: Opcode, operands: &[usize], span: Span) -> Result<usize, Box<Diagnostic>> {
+        let position = self.emit(op, operands)?;
+        self.current_scope().spans.push((position, span));
+        Ok(position)
+    }
+
+    fn remove_last_pop(&mut self) {
+        let scope = self.current_scope();
+        let last = scope.last.unwrap();
+        scope.instructions.truncate(last.position);
+        scope.last = scope.previous;
+    }
+
+    /// Points the jump at `position` to the next instruction to be emitted.
+    fn patch_jump(&mut self, position: usize) -> Result<(), Box<Diagnostic>> {
+        let scope = self.current_scope();
+        let target = u16::try_from(scope.instructions.len()).map_err(|_| Box::new(too_wide(Opcode::Jump, 0)))?;
+        scope.instructions[position + 1..position + 3].copy_from_slice(&target.to_be_bytes());
+        Ok(())
+    }
+
+    fn enter_scope(&mut self) {
+        self.scopes.push(CompilationScope::default());
+        let outer = std::mem::take(&mut self.symbol_table);
+        self.symbol_table = SymbolTable::new_enclosed(outer);
+    }
+
+    fn leave_scope(&mut self) -> (CompilationScope, Vec<Symbol>) {
+        let scope = self.scopes.pop().unwrap();
+        let outer = self.symbol_table.outer.take().unwrap();
+        let inner = std::mem::replace(&mut self.symbol_table, *outer);
+        (scope, inner.free_symbols)
+    }
+}
+
+/// The error for an operand of `op` that does not fit its width: a program
+/// over one of the limits of the bytecode format.
+fn too_wide(op: Opcode, operand: usize) -> Diagnostic {
+    let message = match (op, operand) {
+        (Opcode::Constant, _) | (Opcode::Closure, 0) | (Opcode::Match, 1) => "too many constants: a program can have at most 65536",
+        (Opcode::Jump | Opcode::JumpNotTruthy | Opcode::IterNext | Opcode::Match, _) => "too much code: a jump can reach at most 65535 bytes into a function",
+        (Opcode::GetGlobal | Opcode::SetGlobal | Opcode::AssignGlobal, _) => "too many global variables: a program can have at most 65536",
+        (Opcode::GetLocal | Opcode::SetLocal, _) => "too many local variables: a function can have at most 256",
+        (Opcode::GetFree | Opcode::Closure, _) => "too many free variables: a function can capture at most 255",
+        (Opcode::Call, _) => "too many arguments: a call can pass at most 255",
+        (Opcode::Array, _) => "too many elements: an array literal can have at most 65535",
+        (Opcode::Hash, _) => "too many pairs: a hash literal can have at most 32767",
+        (Opcode::Interpolate, _) => "too many parts: an interpolated string can have at most 65535",
+        (Opcode::GetPath | Opcode::SetPath, _) => "too many indexes: an assignment can index at most 255 levels deep",
+        _ => unreachable!("{:?} has no operand {}", op, operand),
+    };
+    Diagnostic::error("E0117", message)
+}
+
+/// Collects the names assigned to anywhere below `node` and the names used
+/// inside function literals below it. A local in both is shared with a
+/// closure that may change it or see it change, so it must live in a cell.
+/// Shadowing is ignored, which only puts more locals in cells than needed.
+fn scan_names(node: &dyn Node, in_function: bool, assigned: &mut HashSet<String>, captured: &mut HashSet<String>) {
+    match node.node_type() {
+        ast::NodeType::Identifier if in_function => {
+            captured.insert(node.as_any().downcast_ref::<ast::Identifier>().unwrap().value.clone());
+        }
+        ast::NodeType::AssignExpression => {
+            let target = node.as_any().downcast_ref::<ast::AssignExpression>().unwrap().target.as_node();
+            if let Some((name, _)) = ast::place(target) {
+                assigned.insert(name.value.clone());
+            }
+        }
+        _ => {}
+    }
+    let in_function = in_function || node.node_type() == ast::NodeType::FunctionLiteral;
+    let children = match node.as_any().downcast_ref::<ast::InterpolatedString>() {
+        Some(string) => string.parts.iter()
+            .filter_map(|part| match part {
+                ast::InterpolationPart::Expression(expression) => Some(expression.as_node()),
+                ast::InterpolationPart::Text(_) => None,
+            })
+            .collect(),
+        None => ast::children(node),
+    };
+    for child in children {
+        scan_names(child, in_function, assigned, captured);
+    }
+}
+
+impl Default for Compiler {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::lexer::Lexer;
+    use crate::parser::Parser;
+    use crate::token::{Token, TokenType};
+
+    fn compile(input: &str) -> Bytecode {
+        let mut parser = Parser::new(Lexer::new(input.to_string()));
+        let program = parser.parse_program();
+        assert!(parser.errors.is_empty(), "parser errors: {:?}", parser.errors);
+        let mut compiler = Compiler::new();
+        compiler.compile_program(&program).unwrap();
+        compiler.bytecode()
+    }
+
+    fn listing(instructions: &[Vec<u8>]) -> String {
+        disassemble(&instructions.concat())
+    }
+
+    #[test]
+    fn test_make_and_disassemble() {
+        assert_eq!(make(Opcode::Constant, &[65534]), vec![Opcode::Constant as u8, 255, 254]);
+        assert_eq!(make(Opcode::Closure, &[65535, 255]), vec![Opcode::Closure as u8, 255, 255, 255]);
+        let instructions = [make(Opcode::Add, &[]), make(Opcode::GetLocal, &[1]), make(Opcode::Constant, &[2]), make(Opcode::Closure, &[65535, 255])];
+        assert_eq!(listing(&instructions), "0000 Add\n0001 GetLocal 1\n0003 Constant 2\n0006 Closure 65535 255\n");
+        for (i, op) in OPCODES.iter().enumerate() {
+            assert_eq!(Opcode::from_byte(i as u8), Some(*op));
+        }
+    }
+
+    #[test]
+    fn test_arithmetic_and_constants() {
+        let bytecode = compile("1 + 2; -3 < 4");
+        assert_eq!(disassemble(&bytecode.main.instructions), listing(&[
+            make(Opcode::Constant, &[0]),
+            make(Opcode::Constant, &[1]),
+            make(Opcode::Add, &[]),
+            make(Opcode::Pop, &[]),
+            make(Opcode::Constant, &[2]),
+            make(Opcode::Minus, &[]),
+            make(Opcode::Constant, &[3]),
+            make(Opcode::LessThan, &[]),
+            make(Opcode::Pop, &[]),
+        ]));
+        let constants: Vec<String> = bytecode.constants.iter().map(|c| c.inspect()).collect();
+        assert_eq!(constants, vec!["1", "2", "3", "4"]);
+    }
+
+    #[test]
+    fn test_conditionals_patch_jumps() {
+        let bytecode = compile("if (true) { 10 }; 3333;");
+        assert_eq!(disassemble(&bytecode.main.instructions), listing(&[
+            make(Opcode::True, &[]),
+            make(Opcode::JumpNotTruthy, &[10]),
+            make(Opcode::Constant, &[0]),
+            make(Opcode::Jump, &[11]),
+            make(Opcode::Null, &[]),
+            make(Opcode::Pop, &[]),
+            make(Opcode::Constant, &[1]),
+            make(Opcode::Pop, &[]),
+        ]));
+
+        let bytecode = compile("if (true) { let x = 1; } else { 20 }");
+        assert_eq!(disassemble(&bytecode.main.instructions), listing(&[
+            make(Opcode::True, &[]),
+            make(Opcode::JumpNotTruthy, &[14]),
+            make(Opcode::Constant, &[0]),
+            make(Opcode::SetGlobal, &[0]),
+            make(Opcode::Null, &[]),
+            make(Opcode::Jump, &[17]),
+            make(Opcode::Constant, &[1]),
+            make(Opcode::Pop, &[]),
+        ]));
+    }
+
+    #[test]
+    fn test_logical_operators_short_circuit() {
+        let bytecode = compile("true && false; true || false;");
+        assert_eq!(disassemble(&bytecode.main.instructions), listing(&[
+            make(Opcode::True, &[]),
+            make(Opcode::JumpNotTruthy, &[10]),
+            make(Opcode::False, &[]),
+            make(Opcode::Bang, &[]),
+            make(Opcode::Bang, &[]),
+            make(Opcode::Jump, &[11]),
+            make(Opcode::False, &[]),
+            make(Opcode::Pop, &[]),
+            make(Opcode::True, &[]),
+            make(Opcode::JumpNotTruthy, &[20]),
+            make(Opcode::True, &[]),
+            make(Opcode::Jump, &[23]),
+            make(Opcode::False, &[]),
+            make(Opcode::Bang, &[]),
+            make(Opcode::Bang, &[]),
+            make(Opcode::Pop, &[]),
+        ]));
+
+        let bytecode = compile("1 % 2 <= 3 >= 4");
+        assert_eq!(disassemble(&bytecode.main.instructions), listing(&[
+            make(Opcode::Constant, &[0]),
+            make(Opcode::Constant, &[1]),
+            make(Opcode::Mod, &[]),
+            make(Opcode::Constant, &[2]),
+            make(Opcode::LessEqual, &[]),
+            make(Opcode::Constant, &[3]),
+            make(Opcode::GreaterEqual, &[]),
+            make(Opcode::Pop, &[]),
+        ]));
+    }
+
+    #[test]
+    fn test_while_loops() {
+        let bytecode = compile("while (true) { if (false) { continue; } break; }");
+        assert_eq!(disassemble(&bytecode.main.instructions), listing(&[
+            make(Opcode::True, &[]),
+            make(Opcode::JumpNotTruthy, &[25]),
+            make(Opcode::False, &[]),
+            make(Opcode::JumpNotTruthy, &[15]),
+            make(Opcode::Jump, &[0]),
+            make(Opcode::Null, &[]),
+            make(Opcode::Jump, &[16]),
+            make(Opcode::Null, &[]),
+            make(Opcode::Pop, &[]),
+            make(Opcode::Jump, &[25]),
+            make(Opcode::Null, &[]),
+            make(Opcode::Pop, &[]),
+            make(Opcode::Jump, &[0]),
+            make(Opcode::Null, &[]),
+            make(Opcode::Pop, &[]),
+        ]));
+
+        let program = ast::Program {
+            statements: vec![Box::new(ast::BreakStatement { token: Token::new(TokenType::BREAK, "break"), span: Span::default() })],
+            span: Span::default(),
+        };
+        let error = Compiler::new().compile_program(&program).unwrap_err();
+        assert_eq!(error.code, "E0012");
+    }
+
+    #[test]
+    fn test_for_loops() {
+        let bytecode = compile("for (x in 1..=3) { x }");
+        assert_eq!(disassemble(&bytecode.main.instructions), listing(&[
+            make(Opcode::Constant, &[0]),
+            make(Opcode::Constant, &[1]),
+            make(Opcode::RangeInclusive, &[]),
+            make(Opcode::Iterate, &[]),
+            make(Opcode::IterNext, &[21]),
+            make(Opcode::SetGlobal, &[0]),
+            make(Opcode::GetGlobal, &[0]),
+            make(Opcode::Pop, &[]),
+            make(Opcode::Jump, &[8]),
+            make(Opcode::Pop, &[]),
+            make(Opcode::Null, &[]),
+            make(Opcode::Pop, &[]),
+        ]));
+        assert_eq!(bytecode.globals, vec!["x"]);
+    }
+
+    #[test]
+    fn test_global_slots_are_declared_up_front() {
+        let bytecode = compile("let f = fn() { g }; let g = 1; let f = 2;");
+        assert_eq!(bytecode.globals, vec!["f", "g"]);
+        let function = bytecode.constants[0].as_any().downcast_ref::<object::CompiledFunction>().unwrap();
+        assert_eq!(disassemble(&function.instructions), listing(&[
+            make(Opcode::GetGlobal, &[1]),
+            make(Opcode::ReturnValue, &[]),
+        ]));
+    }
+
+    #[test]
+    fn test_locals_free_variables_and_recursion() {
+        let bytecode = compile("fn(a) { let b = a; fn(c) { a + b + c } }");
+        let inner = bytecode.constants[0].as_any().downcast_ref::<object::CompiledFunction>().unwrap();
+        assert_eq!(disassemble(&inner.instructions), listing(&[
+            make(Opcode::GetFree, &[0]),
+            make(Opcode::GetFree, &[1]),
+            make(Opcode::Add, &[]),
+            make(Opcode::GetLocal, &[0]),
+            make(Opcode::Add, &[]),
+            make(Opcode::ReturnValue, &[]),
+        ]));
+        let outer = bytecode.constants[1].as_any().downcast_ref::<object::CompiledFunction>().unwrap();
+        assert_eq!((outer.num_parameters, outer.num_locals), (1, 2));
+        assert_eq!(disassemble(&outer.instructions), listing(&[
+            make(Opcode::GetLocal, &[0]),
+            make(Opcode::SetLocal, &[1]),
+            make(Opcode::GetLocal, &[0]),
+            make(Opcode::GetLocal, &[1]),
+            make(Opcode::Closure, &[0, 2]),
+            make(Opcode::ReturnValue, &[]),
+        ]));
+
+        let bytecode = compile("let wrapper = fn() { let countdown = fn(x) { countdown(x - 1) }; countdown(1) };");
+        let countdown = bytecode.constants[1].as_any().downcast_ref::<object::CompiledFunction>().unwrap();
+        assert!(disassemble(&countdown.instructions).starts_with("0000 CurrentClosure\n"));
+    }
+
+    #[test]
+    fn test_builtins_and_undefined_names() {
+        let bytecode = compile("len([]); push([], 1);");
+        assert!(disassemble(&bytecode.main.instructions).starts_with("0000 GetBuiltin 0\n0002 Array 0\n0005 Call 1\n0007 Pop\n0008 GetBuiltin 4\n"));
+
+        // An unknown name gets a global slot, which is an error only once it runs.
+        let bytecode = compile("fn() { missing; let missing = 1; missing = 2 }; let missing = 3;");
+        assert_eq!(bytecode.globals, vec!["missing"]);
+        let function = bytecode.constants[2].as_any().downcast_ref::<object::CompiledFunction>().unwrap();
+        assert!(disassemble(&function.instructions).starts_with("0000 GetGlobal 0\n0003 Pop\n0004 Constant 0\n0007 SetLocal 0\n"));
+    }
+    #[test]
+    fn test_operand_limits() {
+        fn lets(n: usize) -> String {
+            (0..n).map(|i| format!("let v{} = 0; ", i)).collect()
+        }
+        // Builds a program needing `n` of what the limit is on.
+        type Program = fn(usize) -> String;
+        let tests: [(Program, usize, &str); 5] = [
+            (|n| format!("fn() {{ {}v0 }}", lets(n)), 256, "too many local variables: a function can have at most 256"),
+            (|n| (0..n).map(|i| format!("{}; ", i)).collect(), 65536, "too many constants: a program can have at most 65536"),
+            (|n| format!("len({});", vec!["true"; n].join(", ")), 255, "too many arguments: a call can pass at most 255"),
+            (|n| format!("fn() {{ {}fn() {{ [{}] }} }}", lets(n), (0..n).map(|i| format!("v{}", i)).collect::<Vec<_>>().join(", ")), 255, "too many free variables: a function can capture at most 255"),
+            (|n| format!("if (true) {{ {}}}", "true; ".repeat(n)), 32764, "too much code: a jump can reach at most 65535 bytes into a function"),
+        ];
+        for (input, limit, message) in tests {
+            let mut parser = Parser::new(Lexer::new(input(limit)));
+            let program = parser.parse_program();
+            assert!(Compiler::new().compile_program(&program).is_ok(), "{}", message);
+            let mut parser = Parser::new(Lexer::new(input(limit + 1)));
+            let program = parser.parse_program();
+            let error = Compiler::new().compile_program(&program).unwrap_err();
+            assert_eq!((error.code, error.message.as_str()), ("E0117", message));
+            assert!(error.span.is_some(), "{}", message);
+        }
+    }
+
+    #[test]
+    fn test_assignment() {
+        let bytecode = compile("let x = 1; x += 2;");
+        assert_eq!(disassemble(&bytecode.main.instructions), listing(&[
+            make(Opcode::Constant, &[0]),
+            make(Opcode::SetGlobal, &[0]),
+            make(Opcode::GetGlobal, &[0]),
+            make(Opcode::Constant, &[1]),
+            make(Opcode::Add, &[]),
+            make(Opcode::Dup, &[]),
+            make(Opcode::AssignGlobal, &[0]),
+            make(Opcode::Pop, &[]),
+        ]));
+
+        // `n` is assigned inside a closure that captures it, so it lives in a cell.
+        let bytecode = compile("fn() { let n = 0; fn() { n = n + 1 } }");
+        let inner = bytecode.constants[2].as_any().downcast_ref::<object::CompiledFunction>().unwrap();
+        assert!(inner.cells.is_empty());
+        assert_eq!(disassemble(&inner.instructions), listing(&[
+            make(Opcode::GetFree, &[0]),
+            make(Opcode::LoadCell, &[]),
+            make(Opcode::Constant, &[1]),
+            make(Opcode::Add, &[]),
+            make(Opcode::Dup, &[]),
+            make(Opcode::GetFree, &[0]),
+            make(Opcode::StoreCell, &[]),
+            make(Opcode::ReturnValue, &[]),
+        ]));
+        let outer = bytecode.constants[3].as_any().downcast_ref::<object::CompiledFunction>().unwrap();
+        assert_eq!(&*outer.cells, &[0]);
+        assert!(disassemble(&outer.instructions).contains("GetLocal 0\n0005 StoreCell\n0006 GetLocal 0\n0008 Closure 2 1\n"));
+
+        let mut parser = Parser::new(Lexer::new("fn() { len = 1 }".to_string()));
+        let program = parser.parse_program();
+        let error = Compiler::new().compile_program(&program).unwrap_err();
+        assert_eq!((error.code, error.message.as_str()), ("E0113", "cannot assign to builtin `len`"));
+    }
+
+    #[test]
+    fn test_constants() {
+        let bytecode = compile("let f = fn() { x }; const x = 1; f()");
+        assert_eq!(bytecode.globals, vec!["f", "x"]);
+
+        let tests = [
+            ("const x = 1; x = 2;", "E0114", "cannot assign to constant `x`", Span::new(13, 18, 1, 14)),
+            ("fn() { const n = 1; fn() { n += 1 } }", "E0114", "cannot assign to constant `n`", Span::new(27, 33, 1, 28)),
+            ("const x = 1; let x = 2;", "E0115", "cannot redeclare constant `x`", Span::new(13, 23, 1, 14)),
+            ("let x = 1; const x = 2;", "E0115", "`x` is already declared", Span::new(11, 23, 1, 12)),
+            ("while (true) { const k = 1; }", "E0115", "cannot redeclare constant `k`", Span::new(15, 27, 1, 16)),
+        ];
+        for (input, code, message, span) in tests {
+            let mut parser = Parser::new(Lexer::new(input.to_string()));
+            let program = parser.parse_program();
+            let error = Compiler::new().compile_program(&program).unwrap_err();
+            assert_eq!((error.code, error.message.as_str(), error.span), (code, message, Some(span)), "{}", input);
+        }
+    }
+
+    #[test]
+    fn test_match_expressions() {
+        let bytecode = compile("let x = [true]; match (x) { [y] if y => 1, _ => 2 }");
+        assert!(bytecode.constants[0].as_any().downcast_ref::<object::Pattern>().is_some());
+        assert_eq!(bytecode.globals, vec!["x", "y"]);
+        let bytecode = compile("let f = fn(x) { match (x) { [y] if y => 1, _ => 2 } };");
+        let function = bytecode.constants[4].as_any().downcast_ref::<object::CompiledFunction>().unwrap();
+        assert_eq!(function.num_locals, 2);
+        assert_eq!(disassemble(&function.instructions), listing(&[
+            make(Opcode::GetLocal, &[0]),
+            make(Opcode::Match, &[21, 0]),
+            make(Opcode::SetLocal, &[1]),
+            make(Opcode::GetLocal, &[1]),
+            make(Opcode::JumpNotTruthy, &[21]),
+            make(Opcode::Pop, &[]),
+            make(Opcode::Constant, &[1]),
+            make(Opcode::Jump, &[34]),
+            make(Opcode::Match, &[33, 2]),
+            make(Opcode::Pop, &[]),
+            make(Opcode::Constant, &[3]),
+            make(Opcode::Jump, &[34]),
+            make(Opcode::NoMatch, &[]),
+            make(Opcode::ReturnValue, &[]),
+        ]));
+    }
+
+}
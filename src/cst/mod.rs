@@ -0,0 +1,241 @@
+use std::iter::Peekable;
+
+use crate::ast::{self, Node, NodeType};
+use crate::diagnostic::Diagnostic;
+use crate::lexer::Lexer;
+use crate::parser::Parser;
+use crate::token::{Span, TokenType, Trivia, TriviaKind};
+
+/// A token as written in the source, with the trivia around it.
+///
+/// A token's trailing trivia is whatever follows it on the same line; the
+/// newline and everything after it lead the next token. Trivia at the end of
+/// the input leads the `EOF` token.
+#[derive(Debug, PartialEq, Clone)]
+pub struct SyntaxToken {
+    pub kind: TokenType,
+    /// The exact source text of the token, e.g. `"a\n"` with its quotes and
+    /// escape rather than the string's value.
+    pub text: String,
+    pub span: Span,
+    pub leading: Vec<Trivia>,
+    pub trailing: Vec<Trivia>,
+}
+
+impl SyntaxToken {
+    fn write(&self, out: &mut String) {
+        for trivia in self.leading.iter() {
+            out.push_str(&trivia.text);
+        }
+        out.push_str(&self.text);
+        for trivia in self.trailing.iter() {
+            out.push_str(&trivia.text);
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Clone)]
+pub enum SyntaxElement {
+    Node(SyntaxNode),
+    Token(SyntaxToken),
+}
+
+/// A node of the lossless syntax tree. It mirrors an `ast` node, holding the
+/// tokens of that node which belong to none of its children.
+#[derive(Debug, PartialEq, Clone)]
+pub struct SyntaxNode {
+    pub kind: NodeType,
+    pub span: Span,
+    pub children: Vec<SyntaxElement>,
+}
+
+impl SyntaxNode {
+    /// The source text covered by the node, trivia included. For the root
+    /// this is the whole input, byte for byte.
+    pub fn text(&self) -> String {
+        let mut out = String::new();
+        for token in self.tokens() {
+            token.write(&mut out);
+        }
+        out
+    }
+
+    /// The tokens below this node, in source order.
+    pub fn tokens(&self) -> Vec<&SyntaxToken> {
+        let mut tokens = Vec::new();
+        for child in self.children.iter() {
+            match child {
+                SyntaxElement::Node(node) => tokens.extend(node.tokens()),
+                SyntaxElement::Token(token) => tokens.push(token),
+            }
+        }
+        tokens
+    }
+}
+
+/// Lexes `source` into tokens that keep all of its trivia, ending with `EOF`.
+pub fn lex(source: &str) -> Vec<SyntaxToken> {
+    let mut lexer = Lexer::with_trivia(source.to_string());
+    let mut tokens: Vec<SyntaxToken> = Vec::new();
+    while let Some(token) = lexer.next() {
+        let mut leading = lexer.take_trivia();
+        if let Some(previous) = tokens.last_mut() {
+            let same_line = leading.iter().position(|t| t.kind == TriviaKind::Newline).unwrap_or(leading.len());
+            let rest = leading.split_off(same_line);
+            previous.trailing = leading;
+            leading = rest;
+        }
+        tokens.push(SyntaxToken {
+            kind: token.type_,
+            text: source[token.span.start..token.span.end].to_string(),
+            span: token.span,
+            leading,
+            trailing: Vec::new(),
+        });
+    }
+    tokens
+}
+
+/// Parses `source` into a lossless syntax tree, along with the parser's
+/// diagnostics. The tree keeps every token even when there are errors; tokens
+/// the parser could not place in a node belong to the nearest enclosing one.
+pub fn parse(source: &str) -> (SyntaxNode, Vec<Diagnostic>) {
+    let mut parser = Parser::new(Lexer::new(source.to_string()));
+    let program = parser.parse_program();
+    let mut tokens = lex(source).into_iter().peekable();
+    let root = build(&program, usize::MAX, &mut tokens);
+    (root, parser.errors)
+}
+
+/// Builds the node for `node` from the tokens starting before `end`.
+fn build<I: Iterator<Item = SyntaxToken>>(node: &dyn Node, end: usize, tokens: &mut Peekable<I>) -> SyntaxNode {
+    let nodes = ast::children(node);
+    let mut next = 0;
+    let mut children = Vec::new();
+    while let Some(token) = tokens.peek() {
+        let start = token.span.start;
+        if start >= end {
+            break;
+        }
+        while next < nodes.len() && nodes[next].span().end <= start {
+            next += 1;
+        }
+        match nodes.get(next) {
+            Some(child) if child.span().start <= start => {
+                children.push(SyntaxElement::Node(build(*child, child.span().end, tokens)));
+                next += 1;
+            }
+            _ => children.push(SyntaxElement::Token(tokens.next().unwrap())),
+        }
+    }
+    SyntaxNode {
+        kind: node.node_type(),
+        span: node.span(),
+        children,
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn kinds(node: &SyntaxNode) -> Vec<String> {
+        node.children.iter().map(|child| match child {
+            SyntaxElement::Node(node) => format!("{:?}", node.kind),
+            SyntaxElement::Token(token) => token.text.clone(),
+        }).collect()
+    }
+
+    fn node(element: &SyntaxElement) -> &SyntaxNode {
+        match element {
+            SyntaxElement::Node(node) => node,
+            SyntaxElement::Token(token) => panic!("expected a node, got token {:?}", token.text),
+        }
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let sources = [
+            "",
+            "   \n\t",
+            "let x = 1;",
+            "/// Adds.\nlet add = fn(a, b) { // sum\n  a + /* inline */ b\n};\r\n\r\nadd(1, 2); // done\n",
+            "let s = \"a\\n${ x  +  1 }\"; r#\"raw\"# \"\"\"\n  triple\n\"\"\"",
+            "if (a <= b) { [1, 2][0] } else { {\"k\": 0x_FF}[\"k\"] }\n/* tail /* nested */ */",
+            // Malformed input is kept as it is.
+            "let = ; 12abc € \"open",
+            "fn(x { x",
+            "1 /* never closed",
+        ];
+        for source in sources {
+            let (root, _) = parse(source);
+            assert_eq!(root.text(), source);
+            let tokens: Vec<SyntaxToken> = lex(source);
+            assert_eq!(tokens.last().unwrap().kind, TokenType::EOF);
+            let mut text = String::new();
+            tokens.iter().for_each(|token| token.write(&mut text));
+            assert_eq!(text, source);
+        }
+    }
+
+    #[test]
+    fn test_random_input_round_trips() {
+        let fragments = [
+            "let", "fn", "if", "x", "1", "0x", "\"s\"", "\"${", "}", "{", "(", ")", "[", "]", "+", "=", ";",
+            " ", "\n", "\r\n", "\t", "// c", "/// d", "/*", "*/", "\"", "r#\"", "é", "€",
+        ];
+        let mut seed: u64 = 0x9E3779B97F4A7C15;
+        for _ in 0..1000 {
+            let mut source = String::new();
+            for _ in 0..(seed % 20) {
+                seed ^= seed << 13;
+                seed ^= seed >> 7;
+                seed ^= seed << 17;
+                source.push_str(fragments[(seed % fragments.len() as u64) as usize]);
+            }
+            assert_eq!(parse(&source).0.text(), source);
+        }
+    }
+
+    #[test]
+    fn test_trivia_attachment() {
+        let tokens = lex("// head\nlet x = 1; // tail\n\n/* next */ x");
+        let trivia = |list: &Vec<Trivia>| list.iter().map(|t| (t.kind, t.text.clone())).collect::<Vec<_>>();
+        assert_eq!(trivia(&tokens[0].leading), vec![
+            (TriviaKind::LineComment, String::from("// head")),
+            (TriviaKind::Newline, String::from("\n")),
+        ]);
+        assert_eq!(trivia(&tokens[0].trailing), vec![(TriviaKind::Whitespace, String::from(" "))]);
+        assert_eq!(tokens[4].text, ";");
+        assert_eq!(trivia(&tokens[4].trailing), vec![
+            (TriviaKind::Whitespace, String::from(" ")),
+            (TriviaKind::LineComment, String::from("// tail")),
+        ]);
+        assert_eq!(trivia(&tokens[5].leading), vec![
+            (TriviaKind::Newline, String::from("\n")),
+            (TriviaKind::Newline, String::from("\n")),
+            (TriviaKind::BlockComment, String::from("/* next */")),
+            (TriviaKind::Whitespace, String::from(" ")),
+        ]);
+        assert_eq!(tokens[5].leading[2].span, Span::new(28, 38, 4, 1));
+    }
+
+    #[test]
+    fn test_tree_mirrors_ast() {
+        let (root, errors) = parse("let add = fn(a, b) { a + b };\nadd(1, 2 * 3);");
+        assert!(errors.is_empty());
+        assert_eq!(kinds(&root), vec!["LetStatement", "ExpressionStatement", ""]);
+
+        let statement = node(&root.children[0]);
+        assert_eq!(kinds(statement), vec!["let", "Identifier", "=", "FunctionLiteral", ";"]);
+        let function = node(&statement.children[3]);
+        assert_eq!(kinds(function), vec!["fn", "(", "Identifier", ",", "Identifier", ")", "BlockStatement"]);
+        let body = node(&function.children[6]);
+        assert_eq!(kinds(body), vec!["{", "ExpressionStatement", "}"]);
+        assert_eq!(body.text(), "{ a + b }");
+
+        let call = node(&node(&root.children[1]).children[0]);
+        assert_eq!(call.kind, NodeType::CallExpression);
+        assert_eq!(kinds(call), vec!["Identifier", "(", "IntegerLiteral", ",", "InfixExpression", ")"]);
+    }
+}
@@ -0,0 +1,217 @@
+use std::fmt::Display;
+
+use crate::token::Span;
+
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum Severity {
+    Error,
+    Warning,
+    Note,
+}
+
+impl Display for Severity {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Severity::Error => write!(f, "error"),
+            Severity::Warning => write!(f, "warning"),
+            Severity::Note => write!(f, "note"),
+        }
+    }
+}
+
+/// A secondary location attached to a diagnostic, e.g. where a block was opened.
+#[derive(Debug, PartialEq, Clone)]
+pub struct Label {
+    pub span: Span,
+    pub message: String,
+}
+
+/// A problem found while parsing or evaluating a program.
+///
+/// `span` is `None` for runtime errors raised before the evaluator knows which
+/// node they belong to; `Environment::eval` fills it in on the way out.
+#[derive(Debug, PartialEq, Clone)]
+pub struct Diagnostic {
+    pub severity: Severity,
+    pub code: &'static str,
+    pub message: String,
+    pub span: Option<Span>,
+    pub labels: Vec<Label>,
+    pub help: Option<String>,
+}
+
+impl Diagnostic {
+    pub fn error(code: &'static str, message: &str) -> Diagnostic {
+        Diagnostic {
+            severity: Severity::Error,
+            code,
+            message: message.to_string(),
+            span: None,
+            labels: Vec::new(),
+            help: None,
+        }
+    }
+
+    pub fn with_span(mut self, span: Span) -> Diagnostic {
+        self.span = Some(span);
+        self
+    }
+
+    pub fn with_label(mut self, span: Span, message: &str) -> Diagnostic {
+        self.labels.push(Label { span, message: message.to_string() });
+        self
+    }
+
+    pub fn with_help(mut self, help: &str) -> Diagnostic {
+        self.help = Some(help.to_string());
+        self
+    }
+
+    /// Renders the diagnostic against the source it was produced from, showing
+    /// each referenced line with the primary span underlined by `^` and
+    /// secondary labels underlined by `-`. Spans that fall outside `source`
+    /// (e.g. from code entered on an earlier REPL line) or that were never
+    /// set print no snippet.
+    pub fn render(&self, source: &str) -> String {
+        self.render_origin(None, source)
+    }
+
+    /// Like `render`, but names `file` in the location line.
+    pub fn render_in(&self, file: &str, source: &str) -> String {
+        self.render_origin(Some(file), source)
+    }
+
+    fn render_origin(&self, file: Option<&str>, source: &str) -> String {
+        let mut out = format!("{}[{}]: {}\n", self.severity, self.code, self.message);
+        let span = match self.span {
+            Some(span) if span.line > 0 && span.end <= source.len() => span,
+            _ => {
+                if let Some(help) = &self.help {
+                    out.push_str(&format!("  = help: {}\n", help));
+                }
+                return out;
+            }
+        };
+
+        let mut marks: Vec<(Span, char, &str)> = vec![(span, '^', "")];
+        for label in &self.labels {
+            marks.push((label.span, '-', label.message.as_str()));
+        }
+        marks.sort_by_key(|(s, _, _)| s.start);
+        let gutter = marks.iter().map(|(s, _, _)| s.line).max().unwrap_or(1).to_string().len();
+
+        let origin = match file {
+            Some(file) => format!("{}:", file),
+            None => String::new(),
+        };
+        out.push_str(&format!("{:>w$}--> {}{}:{}\n", "", origin, span.line, span.column, w = gutter));
+        out.push_str(&format!("{:>w$} |\n", "", w = gutter));
+        let mut last_line = 0;
+        for (s, mark, message) in marks {
+            let (line_start, text) = source_line(source, s);
+            if s.line != last_line {
+                out.push_str(&format!("{:>w$} | {}\n", s.line, text, w = gutter));
+                last_line = s.line;
+            }
+            // Columns count chars, so indent and underline one mark per char.
+            let indent: String = text.chars().take(s.column.saturating_sub(1)).map(|c| if c == '\t' { '\t' } else { ' ' }).collect();
+            let line_end = line_start + text.len();
+            let width = source.get(s.start.min(line_end)..s.end.min(line_end)).map_or(0, |t| t.chars().count()).max(1);
+            out.push_str(&format!("{:>w$} | {}{}", "", indent, mark.to_string().repeat(width), w = gutter));
+            if !message.is_empty() {
+                out.push_str(&format!(" {}", message));
+            }
+            out.push('\n');
+        }
+        if let Some(help) = &self.help {
+            out.push_str(&format!("{:>w$} |\n", "", w = gutter));
+            out.push_str(&format!("{:>w$} = help: {}\n", "", help, w = gutter));
+        }
+        out
+    }
+}
+
+impl Display for Diagnostic {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.message)
+    }
+}
+
+/// The full line of `source` that `span` starts on, without its line break,
+/// and the byte offset where that line begins.
+fn source_line(source: &str, span: Span) -> (usize, &str) {
+    let offset = span.start.min(source.len());
+    let start = source.get(..offset).and_then(|before| before.rfind('\n')).map_or(0, |i| i + 1);
+    let rest = source.get(start..).unwrap_or("");
+    (start, rest.split('\n').next().unwrap_or("").trim_end_matches('\r'))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_render_underlines_span() {
+        let source = "let x = 1;\nlet = 5;\n";
+        let diagnostic = Diagnostic::error("E0001", "expected next token to be IDENT, got ASSIGN instead")
+            .with_span(Span::new(15, 16, 2, 5))
+            .with_help("give the binding a name, e.g. `let y = 5;`");
+        assert_eq!(
+            diagnostic.render(source),
+            "error[E0001]: expected next token to be IDENT, got ASSIGN instead
+ --> 2:5
+  |
+2 | let = 5;
+  |     ^
+  |
+  = help: give the binding a name, e.g. `let y = 5;`
+"
+        );
+    }
+
+    #[test]
+    fn test_render_secondary_labels() {
+        let source = "if (x) {\n  1 + true\n";
+        let diagnostic = Diagnostic::error("E0004", "expected RBRACE to close block, got EOF instead")
+            .with_span(Span::new(15, 19, 2, 7))
+            .with_label(Span::new(7, 8, 1, 8), "block opened here");
+        assert_eq!(
+            diagnostic.render(source),
+            "error[E0004]: expected RBRACE to close block, got EOF instead
+ --> 2:7
+  |
+1 | if (x) {
+  |        - block opened here
+2 |   1 + true
+  |       ^^^^
+"
+        );
+    }
+
+    #[test]
+    fn test_render_counts_columns_in_chars() {
+        let source = "let 名前 = \"héllo\" + 1;";
+        let diagnostic = Diagnostic::error("E0100", "type mismatch: STRING + INTEGER").with_span(Span::new(13, 25, 1, 10));
+        assert_eq!(
+            diagnostic.render(source),
+            "error[E0100]: type mismatch: STRING + INTEGER
+ --> 1:10
+  |
+1 | let 名前 = \"héllo\" + 1;
+  |          ^^^^^^^^^^^
+"
+        );
+    }
+
+    #[test]
+    fn test_render_span_outside_source() {
+        let diagnostic = Diagnostic::error("E0102", "identifier not found: x").with_span(Span::new(40, 41, 3, 2));
+        assert_eq!(diagnostic.render("x"), "error[E0102]: identifier not found: x\n");
+    }
+
+    #[test]
+    fn test_render_without_span() {
+        let diagnostic = Diagnostic::error("E0100", "type mismatch: INTEGER + BOOLEAN");
+        assert_eq!(diagnostic.render(""), "error[E0100]: type mismatch: INTEGER + BOOLEAN\n");
+    }
+}
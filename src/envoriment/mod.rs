@@ -1,54 +1,118 @@
-use std::{collections::HashMap, rc::Rc};
+use std::cell::RefCell;
+use std::{collections::{HashMap, HashSet}, rc::Rc};
 use crate::builtins;
 use crate::object;
 
+/// The bindings of one scope, by name.
+type Store = Rc<RefCell<HashMap<String, Rc<Box<dyn object::Object>>>>>;
 
+/// A scope in the running program. Cloning an `Environment` yields another
+/// handle to the same frame, so a function that captured its defining scope
+/// sees bindings added to that scope later, including its own `let` binding.
 #[derive( Clone)]
 pub struct Environment {
-    pub store: HashMap<String, Rc<Box<dyn object::Object>>>,
-    pub outer: Option< HashMap<String, Rc<Box<dyn object::Object>>>>,
-    pub builtins: HashMap<String, object::Builtin>,
+    pub store: Store,
+    /// Names in `store` bound with `const`.
+    pub constants: Rc<RefCell<HashSet<String>>>,
+    pub outer: Option<Box<Environment>>,
+    pub builtins: Rc<HashMap<String, object::Builtin>>,
+    /// How many function calls deep the code running in this frame is.
+    pub depth: usize,
 }
 
 
 
 impl Environment {
     pub fn new() -> Self {
-      let mut x =  Environment {
-            store: HashMap::new(),
+        let builtins = builtins::BUILTINS
+            .iter()
+            .map(|(name, func)| (name.to_string(), object::Builtin{func: *func}))
+            .collect();
+        Environment {
+            store: Rc::new(RefCell::new(HashMap::new())),
+            constants: Rc::new(RefCell::new(HashSet::new())),
             outer: None,
-            builtins: HashMap::new(),
-        };
-        x.builtins.insert("len".to_string(), object::Builtin{func: builtins::len});
-        x
+            builtins: Rc::new(builtins),
+            depth: 0,
+        }
     }
 
     pub fn get(&self, name: &str) -> Option<Rc<Box<dyn object::Object>>> {
-        // println!("get: {}", name);
-        match self.store.get(name) {
+        match self.store.borrow().get(name) {
             Some(obj) => Some(obj.clone()),
             None =>  match &self.outer {
-                Some(outer) => match outer.get(name) {
-                    Some(obj) => Some(obj.clone()),
-                    None => None
-                },
+                Some(outer) => outer.get(name),
                 None => None
             }
         }
     }
 
-    pub fn set(&mut self, name: &str, val: Rc<Box<dyn object::Object>>) {
-        self.store.insert(name.to_string(), val);
+    /// Binds `name` in this frame, replacing an earlier `let` of it there.
+    /// A constant cannot be redeclared: returns `false`, changing nothing, if
+    /// `name` is one in this frame. Outer frames are only shadowed.
+    pub fn set(&mut self, name: &str, val: Rc<Box<dyn object::Object>>) -> bool {
+        if self.constants.borrow().contains(name) {
+            return false;
+        }
+        self.store.borrow_mut().insert(name.to_string(), val);
+        true
+    }
+
+    /// Binds `name` in this frame as a constant. Returns `false`, changing
+    /// nothing, if this frame already binds `name` in any way.
+    pub fn set_const(&mut self, name: &str, val: Rc<Box<dyn object::Object>>) -> bool {
+        if self.store.borrow().contains_key(name) {
+            return false;
+        }
+        self.store.borrow_mut().insert(name.to_string(), val);
+        self.constants.borrow_mut().insert(name.to_string());
+        true
+    }
+
+    /// Whether the nearest frame that binds `name` holds it as a constant.
+    pub fn is_constant(&self, name: &str) -> bool {
+        if self.store.borrow().contains_key(name) {
+            return self.constants.borrow().contains(name);
+        }
+        self.outer.as_ref().is_some_and(|outer| outer.is_constant(name))
+    }
+
+    /// Replaces the value of `name` in the nearest frame that binds it.
+    /// Returns `false`, changing nothing, if no frame does or that binding
+    /// is a constant.
+    pub fn assign(&self, name: &str, val: Rc<Box<dyn object::Object>>) -> bool {
+        if let Some(slot) = self.store.borrow_mut().get_mut(name) {
+            if self.constants.borrow().contains(name) {
+                return false;
+            }
+            *slot = val;
+            return true;
+        }
+        match &self.outer {
+            Some(outer) => outer.assign(name, val),
+            None => false,
+        }
     }
 
-    pub fn new_enclosed_environment(outer: HashMap<String, Rc<Box<dyn object::Object>>>) -> Self {
-        let mut env = Environment::new();
-        env.outer = Some(outer);
-        env
+    /// Creates a new, empty frame whose parent is `outer`.
+    pub fn new_enclosed_environment(outer: &Environment) -> Self {
+        Environment {
+            store: Rc::new(RefCell::new(HashMap::new())),
+            constants: Rc::new(RefCell::new(HashSet::new())),
+            outer: Some(Box::new(outer.clone())),
+            builtins: outer.builtins.clone(),
+            depth: outer.depth,
+        }
     }
     
 }
 
+impl Default for Environment {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl object::Object for Environment {
     
     fn as_any(&self) -> &dyn std::any::Any {
@@ -61,10 +125,10 @@ impl object::Object for Environment {
     fn inspect(&self) -> String {
         let mut out = String::new();
         out.push_str("ENVIRONMENT\n");
-        for (k, v) in self.store.iter() {
+        for (k, v) in self.store.borrow().iter() {
             out.push_str(&format!("{}: {}\n", k, v.inspect()));
         }
         out
     }
 
-}
\ No newline at end of file
+}
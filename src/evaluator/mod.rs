@@ -5,88 +5,145 @@ use crate::ast::Statement;
 use crate::envoriment::Environment;
 use crate::object::{self, Object};
 use crate::ast::Node;
+use crate::diagnostic::Diagnostic;
+
+/// Deepest nesting of function calls, so that runaway recursion is an
+/// error rather than a crash of the host.
+const MAX_CALL_DEPTH: usize = 1000;
+
+/// Native stack for a thread running the tree-walker. A call takes tens of
+/// kilobytes of it in a debug build, so the default is too small to reach
+/// `MAX_CALL_DEPTH`.
+pub const STACK_SIZE: usize = 256 << 20;
 
 impl Environment {
     pub fn eval(&mut self ,node: &dyn Node) -> Rc<Box<dyn object::Object>> {
+        let result = self.eval_node(node);
+        self.locate_error(result, node)
+    }
+
+    fn eval_node(&mut self ,node: &dyn Node) -> Rc<Box<dyn object::Object>> {
         match node.node_type() {
             ast::NodeType::Program => self.eval_program(&node.as_any().downcast_ref::<ast::Program>().unwrap().statements),
             ast::NodeType::ExpressionStatement => self.eval(node.as_any().downcast_ref::<ast::ExpressionStatement>().unwrap().expression.as_node()),
             ast::NodeType::IntegerLiteral =>Rc::new(Box::new(object::Integer{value:node.as_any().downcast_ref::<ast::IntegerLiteral>().unwrap().value})),
-            ast::NodeType::Boolean => self.bool_to_boolean_object(Some(node.as_any().downcast_ref::<ast::Boolean>().unwrap().value)),
+            ast::NodeType::FloatLiteral => Rc::new(Box::new(object::Float{value: node.as_any().downcast_ref::<ast::FloatLiteral>().unwrap().value})),
+            ast::NodeType::Boolean => bool_to_boolean_object(Some(node.as_any().downcast_ref::<ast::Boolean>().unwrap().value)),
             ast::NodeType::PrefixExpression => {
           
                 let right = self.eval(node.as_any().downcast_ref::<ast::PrefixExpression>().unwrap().right.as_node());
-                if self.is_error(right.clone()) {
+                if is_error(right.clone()) {
                     return right;
                 }
-                return self.eval_perfix_expresion(&node.as_any().downcast_ref::<ast::PrefixExpression>().unwrap().operator, right);
+                eval_perfix_expresion(&node.as_any().downcast_ref::<ast::PrefixExpression>().unwrap().operator, right)
 
             },
             ast::NodeType::InfixExpression => {
                 let left = self.eval(node.as_any().downcast_ref::<ast::InfixExpression>().unwrap().left.as_node());
-                if self.is_error(left.clone()) {
+                if is_error(left.clone()) {
                     return left;
                 }
+                let operator = node.as_any().downcast_ref::<ast::InfixExpression>().unwrap().operator.as_str();
+                // `&&` and `||` only evaluate the right side when the left one does not decide the result.
+                match (operator, is_truthy(left.clone())) {
+                    ("&&", false) => return bool_to_boolean_object(Some(false)),
+                    ("||", true) => return bool_to_boolean_object(Some(true)),
+                    _ => {}
+                }
                 let right = self.eval(node.as_any().downcast_ref::<ast::InfixExpression>().unwrap().right.as_node());
-                if self.is_error(right.clone()) {
+                if is_error(right.clone()) {
                     return right;
                 }
                 
-                return self.eval_infix_expression(&node.as_any().downcast_ref::<ast::InfixExpression>().unwrap().operator, left, right);
+                if operator == "&&" || operator == "||" {
+                    return bool_to_boolean_object(Some(is_truthy(right)));
+                }
+                eval_infix_expression(operator, left, right)
                 
             },
             ast::NodeType::BlockStatement => self.eval_block_statements(&node.as_any().downcast_ref::<ast::BlockStatement>().unwrap().statements),
             ast::NodeType::IfExpression => self.eval_if_expression(node.as_any().downcast_ref::<ast::IfExpression>().unwrap().as_node()),
             ast::NodeType::ReturnStatement => {
                 let value = self.eval(node.as_any().downcast_ref::<ast::ReturnStatement>().unwrap().return_value.as_node());
-                if self.is_error(value.clone()) {
+                if is_error(value.clone()) {
                     return value;
                 }
-                return Rc::new(Box::new(object::Return{value}));
+                Rc::new(Box::new(object::Return{value}))
             }
-            ast::NodeType::LetStatement =>{
+            ast::NodeType::LetStatement | ast::NodeType::ConstStatement =>{
                 let value = self.eval(node.as_any().downcast_ref::<ast::LetStatement>().unwrap().value.as_node());
-                if self.is_error(value.clone()) {
+                if is_error(value.clone()) {
                     return value;
                 }
 
-                self.set(node.as_any().downcast_ref::<ast::LetStatement>().unwrap().name.value.as_str(), value);
+                let name = node.as_any().downcast_ref::<ast::LetStatement>().unwrap().name.value.as_str();
+                let bound = match node.node_type() {
+                    ast::NodeType::ConstStatement => self.set_const(name, value),
+                    _ => self.set(name, value),
+                };
+                if !bound {
+                    return self.redeclaration(name);
+                }
 
-                return Rc::new(Box::new(object::Null{}));
+                Rc::new(Box::new(object::Null{}))
             }
             ast::NodeType::Identifier => {
-                let value = self.get(node.as_any().downcast_ref::<ast::Identifier>().unwrap().value.as_str());
-                if value.is_some() {
-                    return value.unwrap();
+                if let Some(value) = self.get(node.as_any().downcast_ref::<ast::Identifier>().unwrap().value.as_str()) {
+                    return value;
                 }
-                let builtin = self.builtins.get(node.as_any().downcast_ref::<ast::Identifier>().unwrap().value.as_str());
-                if builtin.is_some() {
-                    return Rc::new(Box::new(object::Builtin{func: builtin.unwrap().func.clone()}));
+                if let Some(builtin) = self.builtins.get(node.as_any().downcast_ref::<ast::Identifier>().unwrap().value.as_str()) {
+                    return Rc::new(Box::new(object::Builtin{func: builtin.func}));
                 }
 
 
-                return self.new_error(&format!("identifier not found: {}", node.as_any().downcast_ref::<ast::Identifier>().unwrap().value));
+                new_error("E0102", &format!("identifier not found: {}", node.as_any().downcast_ref::<ast::Identifier>().unwrap().value))
             }
             ast::NodeType::FunctionLiteral => {
                 let parameters = Rc::new(Box::new(node.as_any().downcast_ref::<ast::FunctionLiteral>().unwrap().parameters.clone()));
                 let body = node.as_any().downcast_ref::<ast::FunctionLiteral>().unwrap().body.clone();
-                return Rc::new(Box::new(object::Function{parameters, body, env: self.clone()}));
+                Rc::new(Box::new(object::Function{parameters, body, env: self.clone()}))
             }
             ast::NodeType::CallExpression =>{
                 let function = self.eval(node.as_any().downcast_ref::<ast::CallExpression>().unwrap().function.as_node());
-                if self.is_error(function.clone()) {
+                if is_error(function.clone()) {
                     return function;
                 }
                 let args = self.eval_expressions(&node.as_any().downcast_ref::<ast::CallExpression>().unwrap().arguments);
 
-                if args.len() == 1 && self.is_error(args[0].clone()) {
+                if args.len() == 1 && is_error(args[0].clone()) {
                     return args[0].clone();
                 }
 
-                return self.apply_function(function, &args);
+                self.apply_function(function, &args)
           
             }
+            ast::NodeType::ArrayLiteral => {
+                let elements = self.eval_expressions(&node.as_any().downcast_ref::<ast::ArrayLiteral>().unwrap().elements);
+                if elements.len() == 1 && is_error(elements[0].clone()) {
+                    return elements[0].clone();
+                }
+                Rc::new(Box::new(object::Array{elements}))
+            }
+            ast::NodeType::IndexExpression => {
+                let left = self.eval(node.as_any().downcast_ref::<ast::IndexExpression>().unwrap().left.as_node());
+                if is_error(left.clone()) {
+                    return left;
+                }
+                let index = self.eval(node.as_any().downcast_ref::<ast::IndexExpression>().unwrap().index.as_node());
+                if is_error(index.clone()) {
+                    return index;
+                }
+                eval_index_expression(left, index)
+            }
+            ast::NodeType::HashLiteral => self.eval_hash_literal(node.as_any().downcast_ref::<ast::HashLiteral>().unwrap()),
             ast::NodeType::StringLiteral => Rc::new(Box::new(object::StringValue{value: node.as_any().downcast_ref::<ast::StringLiteral>().unwrap().value.clone()})),
+            ast::NodeType::InterpolatedString => self.eval_interpolated_string(node.as_any().downcast_ref::<ast::InterpolatedString>().unwrap()),
+            ast::NodeType::WhileStatement => self.eval_while_statement(node.as_any().downcast_ref::<ast::WhileStatement>().unwrap()),
+            ast::NodeType::ForStatement => self.eval_for_statement(node.as_any().downcast_ref::<ast::ForStatement>().unwrap()),
+            ast::NodeType::AssignExpression => self.eval_assign_expression(node.as_any().downcast_ref::<ast::AssignExpression>().unwrap()),
+            ast::NodeType::MatchExpression => self.eval_match_expression(node.as_any().downcast_ref::<ast::MatchExpression>().unwrap()),
+            ast::NodeType::BreakStatement => Rc::new(Box::new(object::Break{})),
+            ast::NodeType::ContinueStatement => Rc::new(Box::new(object::Continue{})),
             _ => panic!("Not implemented yet")
             
         }
@@ -98,19 +155,26 @@ impl Environment {
             Some(x) => x,
             None => match obj.as_any().downcast_ref::<object::Builtin>() {
                 Some(x) => return (x.func)(args.clone()),
-                None => return self.new_error(&format!("not a function: {}", obj.object_type())),
+                None => return new_error("E0103", &format!("not a function: {}", obj.object_type())),
             
                 
             }
         };
+        if function.parameters.len() != args.len() {
+            return new_error("E0104", &format!("wrong number of arguments: want={}, got={}", function.parameters.len(), args.len()));
+        }
+        if self.depth >= MAX_CALL_DEPTH {
+            return new_error("E0107", "stack overflow");
+        }
         let mut extended_env = self.extend_function_env(function, args);
         let evaluated = extended_env.eval(function.body.as_node());
-        return self.unwrap_return_value(evaluated);
+        self.unwrap_return_value(evaluated)
 
     }
 
-    fn extend_function_env(&self, function: &object::Function, args: &Vec<Rc<Box<dyn object::Object>>>) -> Environment {
-        let mut env = Environment::new_enclosed_environment(function.env.store.clone());
+    fn extend_function_env(&self, function: &object::Function, args: &[Rc<Box<dyn object::Object>>]) -> Environment {
+        let mut env = Environment::new_enclosed_environment(&function.env);
+        env.depth = self.depth + 1;
         for (i, param) in function.parameters.iter().enumerate() {
             env.set(param.string().as_str(), args[i].clone());
         }
@@ -120,9 +184,10 @@ impl Environment {
         match obj.object_type() {
             object::ObjectType::RETURN => {
                 let return_value = obj.as_any().downcast_ref::<object::Return>().unwrap();
-                return return_value.value.clone();
+                return_value.value.clone()
             },
-            _ => return obj,
+            object::ObjectType::BREAK | object::ObjectType::CONTINUE => stray_loop_control(obj),
+            _ => obj,
         }
     }
 
@@ -132,39 +197,50 @@ impl Environment {
         
         for e in args.iter() {
             let evaluated = self.eval(e.as_node());
-            if self.is_error(evaluated.clone()) {
+            if is_error(evaluated.clone()) {
                 return vec![evaluated];
             }
             result.push(evaluated);
         }
 
-        return result;
+        result
     }
   
-    fn  new_error(&self ,message: &str) -> Rc<Box<dyn object::Object>> {
-        Rc::new(Box::new(object::Error{message: message.to_string()}))
+    /// Gives an error that does not yet know where it came from the span of
+    /// `node`, the innermost node it propagated out of.
+    fn locate_error(&self, obj: Rc<Box<dyn object::Object>>, node: &dyn Node) -> Rc<Box<dyn object::Object>> {
+        match obj.as_any().downcast_ref::<object::Error>() {
+            Some(err) if err.diagnostic.span.is_none() => {
+                Rc::new(Box::new(object::Error{diagnostic: err.diagnostic.clone().with_span(node.span())}))
+            }
+            _ => obj,
+        }
     }
 
-    fn is_error(&self, obj: Rc<Box<dyn object::Object>>) -> bool {
-        obj.object_type() == object::ObjectType::ERROR
-    }
     fn eval_block_statements(&mut self, statements: &Vec<Box<dyn Statement>>) -> Rc<Box<dyn object::Object>>{
         let mut result: Rc<Box<dyn object::Object>> = Rc::new(Box::new(object::Null{}));
         for statement in statements.iter() {
             result = self.eval(statement.as_node());
             match result.object_type() {
-               object::ObjectType::RETURN | object::ObjectType::ERROR => return result,
+               object::ObjectType::RETURN | object::ObjectType::ERROR
+               | object::ObjectType::BREAK | object::ObjectType::CONTINUE => return result,
                _ => continue,
                
            }
         }
-        return result;
+        result
 
 
         
     }
     
     pub  fn eval_program(&mut self ,statements: &Vec<Box<dyn Statement>>) -> Rc<Box<dyn object::Object>> {
+        // Rejected before anything runs, like the compiler does, rather than
+        // on the pass that declares it again.
+        if let Some(statement) = statements.iter().find_map(|statement| const_in_loop(statement.as_node(), false)) {
+            let name = &statement.as_any().downcast_ref::<ast::LetStatement>().unwrap().name.value;
+            return Rc::new(Box::new(object::Error{diagnostic: const_in_loop_error(name).with_span(statement.span())}));
+        }
         let mut result: Rc<Box<dyn object::Object>> = Rc::new(Box::new(object::Null{}));
         for statement in statements.iter() {
             // println!("{:?}", statement);
@@ -173,20 +249,171 @@ impl Environment {
             result = match result.object_type() {
                 object::ObjectType::RETURN =>return  result.as_any().downcast_ref::<object::Return>().unwrap().value(),
                 object::ObjectType::ERROR => return result,
+                object::ObjectType::BREAK | object::ObjectType::CONTINUE => return stray_loop_control(result),
                 _ => result,
             };
            
         }
-        return result;
+        result
     }
     
+    /// Runs the body while the condition is truthy. `break` and `continue`
+    /// come back from the body as signals; `return` and errors pass through.
+    fn eval_while_statement(&mut self, node: &ast::WhileStatement) -> Rc<Box<dyn object::Object>> {
+        loop {
+            let condition = self.eval(node.condition.as_node());
+            if is_error(condition.clone()) {
+                return condition;
+            }
+            if !is_truthy(condition) {
+                break;
+            }
+            let result = self.eval(node.body.as_node());
+            match result.object_type() {
+                object::ObjectType::RETURN | object::ObjectType::ERROR => return result,
+                object::ObjectType::BREAK => break,
+                _ => continue,
+            }
+        }
+        Rc::new(Box::new(object::Null{}))
+    }
+
+    /// Binds each element of the iterable to the loop variable in turn and
+    /// runs the body, handling its signals like `eval_while_statement`.
+    fn eval_for_statement(&mut self, node: &ast::ForStatement) -> Rc<Box<dyn object::Object>> {
+        let iterable = self.eval(node.iterable.as_node());
+        if is_error(iterable.clone()) {
+            return iterable;
+        }
+        let elements = match iterate(iterable) {
+            Ok(elements) => elements,
+            Err(err) => return self.locate_error(err, node.iterable.as_node()),
+        };
+        for element in elements {
+            if !self.set(&node.variable.value, element) {
+                return self.redeclaration(&node.variable.value);
+            }
+            let result = self.eval(node.body.as_node());
+            match result.object_type() {
+                object::ObjectType::RETURN | object::ObjectType::ERROR => return result,
+                object::ObjectType::BREAK => break,
+                _ => continue,
+            }
+        }
+        Rc::new(Box::new(object::Null{}))
+    }
+
+    /// Assigns to the nearest binding of the target's variable. Collections
+    /// are values, so assigning to an element rebinds the variable to an
+    /// updated copy. The variable and indexes are evaluated first, then the
+    /// current value for a compound operator, then the right-hand side.
+    fn eval_assign_expression(&mut self, node: &ast::AssignExpression) -> Rc<Box<dyn object::Object>> {
+        let (name, path) = ast::place(node.target.as_node()).unwrap();
+        if self.is_constant(&name.value) {
+            return assign_to_constant(&name.value);
+        }
+        let compound = node.operator.strip_suffix('=').filter(|operator| !operator.is_empty());
+        let root = match compound.is_some() || !path.is_empty() {
+            true if self.get(&name.value).is_none() && self.builtins.contains_key(&name.value) => return assign_to_builtin(&name.value),
+            true => {
+                let root = self.eval(name);
+                if is_error(root.clone()) {
+                    return root;
+                }
+                Some(root)
+            }
+            false => None,
+        };
+        let mut indexes = Vec::new();
+        for index in path {
+            let index = self.eval(index);
+            if is_error(index.clone()) {
+                return index;
+            }
+            indexes.push(index);
+        }
+        let current = match (compound, &root) {
+            (Some(_), Some(root)) => {
+                let current = eval_index_path(root.clone(), &indexes);
+                if is_error(current.clone()) {
+                    return current;
+                }
+                Some(current)
+            }
+            _ => None,
+        };
+        let mut value = self.eval(node.value.as_node());
+        if is_error(value.clone()) {
+            return value;
+        }
+        if let (Some(operator), Some(current)) = (compound, current) {
+            value = eval_infix_expression(operator, current, value);
+            if is_error(value.clone()) {
+                return value;
+            }
+        }
+        let updated = match root {
+            Some(root) => eval_path_assignment(root, &indexes, value.clone()),
+            None => value.clone(),
+        };
+        if is_error(updated.clone()) {
+            return updated;
+        }
+        if !self.assign(&name.value, updated) {
+            return match self.builtins.contains_key(&name.value) {
+                true => assign_to_builtin(&name.value),
+                false => new_error("E0102", &format!("identifier not found: {}", name.value)),
+            };
+        }
+        value
+    }
+
+    /// Runs the body of the first arm whose pattern matches the subject and
+    /// whose guard holds. Each arm gets a scope of its own for the names its
+    /// pattern binds, dropped again when the pattern or the guard fails.
+    fn eval_match_expression(&mut self, node: &ast::MatchExpression) -> Rc<Box<dyn object::Object>> {
+        let subject = self.eval(node.subject.as_node());
+        if is_error(subject.clone()) {
+            return subject;
+        }
+        for arm in node.arms.iter() {
+            let mut bound = Vec::new();
+            if !object::Pattern::new(arm.pattern.as_node()).matches(&subject, &mut bound) {
+                continue;
+            }
+            let mut arm_env = Environment::new_enclosed_environment(self);
+            for (name, value) in ast::pattern_bindings(arm.pattern.as_node()).unwrap().iter().zip(bound) {
+                arm_env.set(&name.value, value);
+            }
+            if let Some(guard) = &arm.guard {
+                let condition = arm_env.eval(guard.as_node());
+                if is_error(condition.clone()) {
+                    return condition;
+                }
+                if !is_truthy(condition) {
+                    continue;
+                }
+            }
+            return arm_env.eval(arm.body.as_node());
+        }
+        self.locate_error(unmatched(&subject), node.subject.as_node())
+    }
+
+    /// The error for a declaration of `name` that this frame refuses.
+    fn redeclaration(&self, name: &str) -> Rc<Box<dyn object::Object>> {
+        match self.constants.borrow().contains(name) {
+            true => new_error("E0115", &format!("cannot redeclare constant `{}`", name)),
+            false => new_error("E0115", &format!("`{}` is already declared", name)),
+        }
+    }
+
     fn eval_if_expression(&mut self, node: &dyn Node) -> Rc<Box<dyn object::Object>>{
         let condition = self.eval(node.as_any().downcast_ref::<ast::IfExpression>().unwrap().condition.as_node());
-        if self.is_error(condition.clone()) {
+        if is_error(condition.clone()) {
             return condition;
         }
-        if self.is_truthy(condition) {
-            return self.eval(node.as_any().downcast_ref::<ast::IfExpression>().unwrap().consequence.as_node());
+        if is_truthy(condition) {
+            self.eval(node.as_any().downcast_ref::<ast::IfExpression>().unwrap().consequence.as_node())
         } else {
             match node.as_any().downcast_ref::<ast::IfExpression>().unwrap().alternative  {
                 Some(_) => self.eval(node.as_any().downcast_ref::<ast::IfExpression>().unwrap().alternative.as_ref().unwrap().as_node()),
@@ -197,134 +424,392 @@ impl Environment {
         
     }
     
-    fn is_truthy (&self, obj: Rc<Box<dyn object::Object>>) -> bool {
-        match obj.object_type() {
-            object::ObjectType::BOOLEAN => {
-                let boolean = obj.as_any().downcast_ref::<object::Boolean>().unwrap();
-                return boolean.value;
-            },
-            object::ObjectType::NULL => false,
-            _ => true,
+    /// Splices the `inspect` form of each `${...}` value into the text.
+    fn eval_interpolated_string(&mut self, node: &ast::InterpolatedString) -> Rc<Box<dyn object::Object>> {
+        let mut value = String::new();
+        for part in node.parts.iter() {
+            match part {
+                ast::InterpolationPart::Text(text) => value.push_str(text),
+                ast::InterpolationPart::Expression(expression) => {
+                    let evaluated = self.eval(expression.as_node());
+                    if is_error(evaluated.clone()) {
+                        return evaluated;
+                    }
+                    value.push_str(&evaluated.inspect());
+                }
+            }
         }
+        Rc::new(Box::new(object::StringValue{value}))
     }
 
-    fn eval_infix_expression(&self, operator: &str, left: Rc<Box<dyn object::Object>>, right: Rc<Box<dyn object::Object>>) ->Rc< Box<dyn object::Object>> {
-        match (left.object_type(), right.object_type()) {
-            (object::ObjectType::INTEGER, object::ObjectType::INTEGER) => {
-                let left_value = left.as_any().downcast_ref::<object::Integer>().unwrap();
-                let right_value = right.as_any().downcast_ref::<object::Integer>().unwrap();
-                return self.eval_integer_infix_expression(operator, left_value, right_value);
-            },
-            (object::ObjectType::BOOLEAN, object::ObjectType::BOOLEAN) => {
-                let left_value = left.as_any().downcast_ref::<object::Boolean>().unwrap();
-                let right_value = right.as_any().downcast_ref::<object::Boolean>().unwrap();
-                return self.eval_boolean_infix_expression(operator, left_value, right_value);
-            },
-            (object::ObjectType::STRING, object::ObjectType::STRING) => {
-                let left_value = left.as_any().downcast_ref::<object::StringValue>().unwrap();
-                let right_value = right.as_any().downcast_ref::<object::StringValue>().unwrap();
-                return self.eval_string_infix_expression(operator, left_value, right_value);
-            },
-
-            (object::ObjectType::INTEGER , _)=>{
-                return  self.new_error(&format!("type mismatch: {} {} {}", left.object_type(), operator, right.object_type()));
+    fn eval_hash_literal(&mut self, node: &ast::HashLiteral) -> Rc<Box<dyn object::Object>> {
+        let mut hash = object::Hash::default();
+        for (key_node, value_node) in node.pairs.iter() {
+            let key = self.eval(key_node.as_node());
+            if is_error(key.clone()) {
+                return key;
             }
-            (object::ObjectType::BOOLEAN , _)=>{
-                return  self.new_error(&format!("type mismatch: {} {} {}", left.object_type(), operator, right.object_type()));
+            if key.hash_key().is_none() {
+                let err = new_error("E0106", &format!("unusable as hash key: {}", key.object_type()));
+                return self.locate_error(err, key_node.as_node());
             }
-            _ => self.new_error(&format!("unknown operator: {} {} {}", left.object_type(), operator, right.object_type()))
+            let value = self.eval(value_node.as_node());
+            if is_error(value.clone()) {
+                return value;
+            }
+            hash.insert(key, value);
         }
+        Rc::new(Box::new(hash))
     }
+}
 
-    fn eval_string_infix_expression(&self, operator: &str, left: &object::StringValue, right: &object::StringValue) -> Rc<Box<dyn object::Object>> {
-        match operator {
-            "+" => {
-                let left_value = left.value.clone();
-                let right_value = right.value.clone();
-                return Rc::new(Box::new(object::StringValue{value: left_value + right_value.as_str()}));
-            },
-            _ => self.new_error(&format!("unknown operator: {} {} {}", left.object_type(), operator, right.object_type()))
+// The operator and indexing rules below do not depend on an environment and
+// are shared with the bytecode `vm`, so both engines agree on every result.
+
+pub(crate) fn new_error(code: &'static str, message: &str) -> Rc<Box<dyn object::Object>> {
+    Rc::new(Box::new(object::Error{diagnostic: Diagnostic::error(code, message)}))
+}
+
+/// The first `const` directly in the body of a loop below `node`. A function
+/// inside the loop is a scope of its own, so its `const`s do not count.
+fn const_in_loop(node: &dyn Node, in_loop: bool) -> Option<&dyn Node> {
+    let any = node.as_any();
+    match node.node_type() {
+        ast::NodeType::ConstStatement if in_loop => Some(node),
+        ast::NodeType::FunctionLiteral => const_in_loop(any.downcast_ref::<ast::FunctionLiteral>().unwrap().body.as_node(), false),
+        ast::NodeType::WhileStatement => {
+            let statement = any.downcast_ref::<ast::WhileStatement>().unwrap();
+            const_in_loop(statement.condition.as_node(), in_loop).or_else(|| const_in_loop(statement.body.as_node(), true))
         }
+        ast::NodeType::ForStatement => {
+            let statement = any.downcast_ref::<ast::ForStatement>().unwrap();
+            const_in_loop(statement.iterable.as_node(), in_loop).or_else(|| const_in_loop(statement.body.as_node(), true))
+        }
+        _ => ast::children(node).into_iter().find_map(|child| const_in_loop(child, in_loop)),
     }
+}
+
+/// The error for a `const` in a loop body. The body is not a scope of its
+/// own, so the `const` would be declared again on the next pass.
+pub(crate) fn const_in_loop_error(name: &str) -> Diagnostic {
+    Diagnostic::error("E0115", &format!("cannot redeclare constant `{}`", name))
+        .with_help("a loop body runs its `const` on every pass; declare it before the loop")
+}
+
+/// The parser rejects `break` and `continue` outside of a loop, but a tree
+/// built some other way may still let one escape its function.
+fn stray_loop_control(signal: Rc<Box<dyn object::Object>>) -> Rc<Box<dyn object::Object>> {
+    new_error("E0012", &format!("`{}` outside of a loop", signal.inspect()))
+}
 
-    fn eval_integer_infix_expression(&self, operator: &str, left: &object::Integer, right:  &object::Integer) -> Rc<Box<dyn object::Object>> {
-        let right_value = right.value;
-        let left_value = left.value;
-        match operator {
-            "+" => Rc::new(Box::new(object::Integer{value: left_value + right_value})),
-            "-" => Rc::new(Box::new(object::Integer{value: left_value - right_value})),
-            "*" => Rc::new(Box::new(object::Integer{value: left_value * right_value})),
-            "/" => Rc::new(Box::new(object::Integer{value: left_value / right_value})),
-            "<" => self.bool_to_boolean_object(Some(left_value < right_value)),
-            ">" => self.bool_to_boolean_object(Some(left_value > right_value)),
-            "==" => self.bool_to_boolean_object(Some(left_value == right_value)),
-            "!=" => self.bool_to_boolean_object(Some(left_value != right_value)),
-            _ => self.new_error( &format!("unknown operator: {} {} {}", left.object_type(), operator, right.object_type()))
+/// The elements `for` visits in `obj`, or an error if it is not iterable.
+pub(crate) fn iterate(obj: Rc<Box<dyn object::Object>>) -> Result<object::ObjectIterator, Rc<Box<dyn object::Object>>> {
+    obj.iterate().ok_or_else(|| new_error("E0111", &format!("not iterable: {}", obj.object_type())))
+}
+
+pub(crate) fn is_error(obj: Rc<Box<dyn object::Object>>) -> bool {
+    obj.object_type() == object::ObjectType::ERROR
+}
+
+pub(crate) fn is_truthy (obj: Rc<Box<dyn object::Object>>) -> bool {
+    match obj.object_type() {
+        object::ObjectType::BOOLEAN => {
+            let boolean = obj.as_any().downcast_ref::<object::Boolean>().unwrap();
+            boolean.value
+        },
+        object::ObjectType::NULL => false,
+        _ => true,
+    }
+}
+
+pub(crate) fn eval_infix_expression(operator: &str, left: Rc<Box<dyn object::Object>>, right: Rc<Box<dyn object::Object>>) ->Rc< Box<dyn object::Object>> {
+    match (left.object_type(), right.object_type()) {
+        (object::ObjectType::INTEGER, object::ObjectType::INTEGER) => {
+            let left_value = left.as_any().downcast_ref::<object::Integer>().unwrap();
+            let right_value = right.as_any().downcast_ref::<object::Integer>().unwrap();
+            eval_integer_infix_expression(operator, left_value, right_value)
+        },
+        (object::ObjectType::FLOAT, object::ObjectType::FLOAT)
+        | (object::ObjectType::INTEGER, object::ObjectType::FLOAT)
+        | (object::ObjectType::FLOAT, object::ObjectType::INTEGER) => {
+            eval_float_infix_expression(operator, as_float(&left), as_float(&right))
+        },
+        (object::ObjectType::BOOLEAN, object::ObjectType::BOOLEAN) => {
+            let left_value = left.as_any().downcast_ref::<object::Boolean>().unwrap();
+            let right_value = right.as_any().downcast_ref::<object::Boolean>().unwrap();
+            eval_boolean_infix_expression(operator, left_value, right_value)
+        },
+        (object::ObjectType::STRING, object::ObjectType::STRING) => {
+            let left_value = left.as_any().downcast_ref::<object::StringValue>().unwrap();
+            let right_value = right.as_any().downcast_ref::<object::StringValue>().unwrap();
+            eval_string_infix_expression(operator, left_value, right_value)
+        },
+
+        (object::ObjectType::INTEGER , _)=>{
+            new_error("E0100", &format!("type mismatch: {} {} {}", left.object_type(), operator, right.object_type()))
         }
+        (object::ObjectType::FLOAT , _)=>{
+            new_error("E0100", &format!("type mismatch: {} {} {}", left.object_type(), operator, right.object_type()))
+        }
+        (object::ObjectType::BOOLEAN , _)=>{
+            new_error("E0100", &format!("type mismatch: {} {} {}", left.object_type(), operator, right.object_type()))
+        }
+        _ => new_error("E0101", &format!("unknown operator: {} {} {}", left.object_type(), operator, right.object_type()))
     }
+}
 
-    fn eval_boolean_infix_expression(&self,operator: &str, left: &object::Boolean, right: &object::Boolean) -> Rc<Box<dyn object::Object>> {
-        let right_value = right.value;
-        let left_value = left.value;
-        match operator {
-            "==" => self.bool_to_boolean_object(Some(left_value == right_value)),
-            "!=" => self.bool_to_boolean_object(Some(left_value != right_value)),
-            _ => self.new_error( &format!("unknown operator: {} {} {}", left.object_type(), operator, right.object_type()))
+/// Indexes into an array, string or hash. Negative array and string indexes
+/// count back from the end, so `arr[-1]` is the last element; an index out of
+/// range or a key missing from the hash yields null. Strings index by Unicode
+/// scalar value, like `len`, and yield one-character strings.
+pub(crate) fn eval_index_expression(left: Rc<Box<dyn object::Object>>, index: Rc<Box<dyn object::Object>>) -> Rc<Box<dyn object::Object>> {
+    match (left.object_type(), index.object_type()) {
+        (object::ObjectType::ARRAY, object::ObjectType::INTEGER) => {
+            let array = left.as_any().downcast_ref::<object::Array>().unwrap();
+            let index = index.as_any().downcast_ref::<object::Integer>().unwrap().value;
+            let len = array.elements.len() as i64;
+            let position = if index < 0 { index + len } else { index };
+            if position < 0 || position >= len {
+                return Rc::new(Box::new(object::Null{}));
+            }
+            array.elements[position as usize].clone()
+        }
+        (object::ObjectType::STRING, object::ObjectType::INTEGER) => {
+            let string = &left.as_any().downcast_ref::<object::StringValue>().unwrap().value;
+            let index = index.as_any().downcast_ref::<object::Integer>().unwrap().value;
+            let position = if index < 0 { string.chars().count() as i64 + index } else { index };
+            match usize::try_from(position).ok().and_then(|p| string.chars().nth(p)) {
+                Some(ch) => Rc::new(Box::new(object::StringValue{value: ch.to_string()})),
+                None => Rc::new(Box::new(object::Null{})),
+            }
         }
+        (object::ObjectType::HASH, _) => {
+            let hash = left.as_any().downcast_ref::<object::Hash>().unwrap();
+            let key = match index.hash_key() {
+                Some(key) => key,
+                None => return new_error("E0106", &format!("unusable as hash key: {}", index.object_type())),
+            };
+            match hash.get(&key) {
+                Some(pair) => pair.value.clone(),
+                None => Rc::new(Box::new(object::Null{})),
+            }
+        }
+        _ => new_error("E0101", &format!("index operator not supported: {}[{}]", left.object_type(), index.object_type())),
     }
+}
 
+pub(crate) fn assign_to_builtin(name: &str) -> Rc<Box<dyn object::Object>> {
+    new_error("E0113", &format!("cannot assign to builtin `{}`", name))
+}
 
+pub(crate) fn unmatched(subject: &Rc<Box<dyn object::Object>>) -> Rc<Box<dyn object::Object>> {
+    new_error("E0116", &format!("unmatched value: {}", subject.inspect()))
+}
 
-    pub fn bool_to_boolean_object(&self, input: Option<bool>) -> Rc<Box<dyn object::Object>> {
-        match input {
-            Some(true) => Rc::new(Box::new(object::Boolean{value: true})),
-            Some(false) => Rc::new(Box::new(object::Boolean{value: false})),
-            None => Rc::new(Box::new(object::Null{})),
-        }
-            
-    }
+pub(crate) fn assign_to_constant(name: &str) -> Rc<Box<dyn object::Object>> {
+    new_error("E0114", &format!("cannot assign to constant `{}`", name))
+}
 
-    pub fn eval_perfix_expresion(&self, operator: &str, right: Rc<Box<dyn object::Object>>) -> Rc<Box<dyn object::Object>> {
-        match operator {
-            "!" => self.eval_bang_operator_expression(right),
-            "-" => self.eval_minus_prefix_operator_expression(right),
-            _ => self.new_error(&format!("unknown operator: {}{}", operator, right.object_type())),
+/// The element at `path` inside `root`, i.e. `root[path[0]][path[1]]...`.
+pub(crate) fn eval_index_path(root: Rc<Box<dyn object::Object>>, path: &[Rc<Box<dyn object::Object>>]) -> Rc<Box<dyn object::Object>> {
+    let mut current = root;
+    for index in path {
+        current = eval_index_expression(current, index.clone());
+        if is_error(current.clone()) {
+            break;
         }
     }
+    current
+}
 
-    fn eval_bang_operator_expression(&self, right: Rc<Box<dyn object::Object>>) -> Rc<Box<dyn object::Object>> {
-        match right.object_type() {
-            object::ObjectType::BOOLEAN => {
-                let boolean = right.as_any().downcast_ref::<object::Boolean>().unwrap();
-                return self.bool_to_boolean_object(Some(!boolean.value));
+/// A copy of `root` with the element at `path` replaced by `value`, copying
+/// each collection on the way down.
+pub(crate) fn eval_path_assignment(root: Rc<Box<dyn object::Object>>, path: &[Rc<Box<dyn object::Object>>], value: Rc<Box<dyn object::Object>>) -> Rc<Box<dyn object::Object>> {
+    let (index, rest) = match path.split_first() {
+        Some(first) => first,
+        None => return value,
+    };
+    let element = match rest.is_empty() {
+        true => value,
+        false => {
+            let inner = eval_index_expression(root.clone(), index.clone());
+            if is_error(inner.clone()) {
+                return inner;
             }
-            object::ObjectType::NULL => self.bool_to_boolean_object(None),
-            _ => self.bool_to_boolean_object(Some(false)),
+            let updated = eval_path_assignment(inner, rest, value);
+            if is_error(updated.clone()) {
+                return updated;
+            }
+            updated
         }
-    }
+    };
+    eval_index_assignment(root, index.clone(), element)
+}
 
-    fn eval_minus_prefix_operator_expression(&self ,right: Rc<Box<dyn object::Object>>) -> Rc<Box<dyn object::Object>> {
-        match right.object_type() {
-            object::ObjectType::INTEGER => {
-                let value = right.as_any().downcast_ref::<object::Integer>().unwrap();
-                return Rc::new(Box::new(object::Integer{value: -value.value}));
+/// A copy of an array or hash with one element replaced. Array indexes count
+/// back from the end when negative, as for `eval_index_expression`, but must
+/// name an existing element; a hash gains the key if it is missing.
+pub(crate) fn eval_index_assignment(left: Rc<Box<dyn object::Object>>, index: Rc<Box<dyn object::Object>>, value: Rc<Box<dyn object::Object>>) -> Rc<Box<dyn object::Object>> {
+    match (left.object_type(), index.object_type()) {
+        (object::ObjectType::ARRAY, object::ObjectType::INTEGER) => {
+            let array = left.as_any().downcast_ref::<object::Array>().unwrap();
+            let index = index.as_any().downcast_ref::<object::Integer>().unwrap().value;
+            let len = array.elements.len() as i64;
+            let position = if index < 0 { index + len } else { index };
+            if position < 0 || position >= len {
+                return new_error("E0112", &format!("index out of range: {} (length {})", index, len));
             }
-            _ => self.new_error(&format!("unknown operator: -{}", right.object_type())),
+            let mut elements = array.elements.clone();
+            elements[position as usize] = value;
+            Rc::new(Box::new(object::Array{elements}))
         }
+        (object::ObjectType::HASH, _) => {
+            let hash = left.as_any().downcast_ref::<object::Hash>().unwrap();
+            match hash.with(index.clone(), value) {
+                Some(hash) => Rc::new(Box::new(hash)),
+                None => new_error("E0106", &format!("unusable as hash key: {}", index.object_type())),
+            }
+        }
+        _ => new_error("E0101", &format!("index assignment not supported: {}[{}]", left.object_type(), index.object_type())),
     }
+}
 
-   
-   
+fn eval_string_infix_expression(operator: &str, left: &object::StringValue, right: &object::StringValue) -> Rc<Box<dyn object::Object>> {
+    match operator {
+        "+" => {
+            let left_value = left.value.clone();
+            let right_value = right.value.clone();
+            Rc::new(Box::new(object::StringValue{value: left_value + right_value.as_str()}))
+        },
+        _ => new_error("E0101", &format!("unknown operator: {} {} {}", left.object_type(), operator, right.object_type()))
+    }
+}
 
+fn eval_integer_infix_expression(operator: &str, left: &object::Integer, right:  &object::Integer) -> Rc<Box<dyn object::Object>> {
+    let right_value = right.value;
+    let left_value = left.value;
+    match operator {
+        "+" => checked_integer(left_value.checked_add(right_value), left_value, operator, right_value),
+        "-" => checked_integer(left_value.checked_sub(right_value), left_value, operator, right_value),
+        "*" => checked_integer(left_value.checked_mul(right_value), left_value, operator, right_value),
+        "/" if right_value == 0 => new_error("E0108", &format!("division by zero: {} / 0", left_value)),
+        "/" => checked_integer(left_value.checked_div(right_value), left_value, operator, right_value),
+        "%" if right_value == 0 => new_error("E0108", &format!("division by zero: {} % 0", left_value)),
+        "%" => checked_integer(left_value.checked_rem(right_value), left_value, operator, right_value),
+        "<" => bool_to_boolean_object(Some(left_value < right_value)),
+        ">" => bool_to_boolean_object(Some(left_value > right_value)),
+        "<=" => bool_to_boolean_object(Some(left_value <= right_value)),
+        ">=" => bool_to_boolean_object(Some(left_value >= right_value)),
+        "==" => bool_to_boolean_object(Some(left_value == right_value)),
+        "!=" => bool_to_boolean_object(Some(left_value != right_value)),
+        ".." => Rc::new(Box::new(object::Range{start: left_value, end: right_value, inclusive: false})),
+        "..=" => Rc::new(Box::new(object::Range{start: left_value, end: right_value, inclusive: true})),
+        _ => new_error("E0101", &format!("unknown operator: {} {} {}", left.object_type(), operator, right.object_type()))
+    }
+}
+
+/// The numeric value of an INTEGER or FLOAT. Integers beyond 2^53 lose
+/// precision, as in any mixed integer/float arithmetic.
+fn as_float(obj: &Rc<Box<dyn object::Object>>) -> f64 {
+    match obj.as_any().downcast_ref::<object::Integer>() {
+        Some(integer) => integer.value as f64,
+        None => obj.as_any().downcast_ref::<object::Float>().unwrap().value,
+    }
+}
+
+/// Arithmetic and comparison where at least one side is a FLOAT. Division by
+/// zero is an error, as it is for integers, rather than producing infinity.
+fn eval_float_infix_expression(operator: &str, left: f64, right: f64) -> Rc<Box<dyn object::Object>> {
+    match operator {
+        "+" => Rc::new(Box::new(object::Float{value: left + right})),
+        "-" => Rc::new(Box::new(object::Float{value: left - right})),
+        "*" => Rc::new(Box::new(object::Float{value: left * right})),
+        "/" if right == 0.0 => new_error("E0108", &format!("division by zero: {:?} / {:?}", left, right)),
+        "/" => Rc::new(Box::new(object::Float{value: left / right})),
+        "%" if right == 0.0 => new_error("E0108", &format!("division by zero: {:?} % {:?}", left, right)),
+        "%" => Rc::new(Box::new(object::Float{value: left % right})),
+        "<" => bool_to_boolean_object(Some(left < right)),
+        ">" => bool_to_boolean_object(Some(left > right)),
+        "<=" => bool_to_boolean_object(Some(left <= right)),
+        ">=" => bool_to_boolean_object(Some(left >= right)),
+        "==" => bool_to_boolean_object(Some(left == right)),
+        "!=" => bool_to_boolean_object(Some(left != right)),
+        _ => new_error("E0101", &format!("unknown operator: FLOAT {} FLOAT", operator)),
+    }
+}
+
+/// Wraps the result of checked integer arithmetic, reporting overflow as an error.
+fn checked_integer(result: Option<i64>, left: i64, operator: &str, right: i64) -> Rc<Box<dyn object::Object>> {
+    match result {
+        Some(value) => Rc::new(Box::new(object::Integer{value})),
+        None => new_error("E0109", &format!("integer overflow: {} {} {}", left, operator, right)),
+    }
+}
+
+fn eval_boolean_infix_expression(operator: &str, left: &object::Boolean, right: &object::Boolean) -> Rc<Box<dyn object::Object>> {
+    let right_value = right.value;
+    let left_value = left.value;
+    match operator {
+        "==" => bool_to_boolean_object(Some(left_value == right_value)),
+        "!=" => bool_to_boolean_object(Some(left_value != right_value)),
+        _ => new_error("E0101", &format!("unknown operator: {} {} {}", left.object_type(), operator, right.object_type()))
+    }
+}
 
- }
-#[cfg(test)]
 
+
+pub fn bool_to_boolean_object(input: Option<bool>) -> Rc<Box<dyn object::Object>> {
+    match input {
+        Some(true) => Rc::new(Box::new(object::Boolean{value: true})),
+        Some(false) => Rc::new(Box::new(object::Boolean{value: false})),
+        None => Rc::new(Box::new(object::Null{})),
+    }
+        
+}
+
+pub fn eval_perfix_expresion(operator: &str, right: Rc<Box<dyn object::Object>>) -> Rc<Box<dyn object::Object>> {
+    match operator {
+        "!" => eval_bang_operator_expression(right),
+        "-" => eval_minus_prefix_operator_expression(right),
+        _ => new_error("E0101", &format!("unknown operator: {}{}", operator, right.object_type())),
+    }
+}
+
+fn eval_bang_operator_expression(right: Rc<Box<dyn object::Object>>) -> Rc<Box<dyn object::Object>> {
+    match right.object_type() {
+        object::ObjectType::BOOLEAN => {
+            let boolean = right.as_any().downcast_ref::<object::Boolean>().unwrap();
+            bool_to_boolean_object(Some(!boolean.value))
+        }
+        object::ObjectType::NULL => bool_to_boolean_object(None),
+        _ => bool_to_boolean_object(Some(false)),
+    }
+}
+
+fn eval_minus_prefix_operator_expression(right: Rc<Box<dyn object::Object>>) -> Rc<Box<dyn object::Object>> {
+    match right.object_type() {
+        object::ObjectType::INTEGER => {
+            let value = right.as_any().downcast_ref::<object::Integer>().unwrap().value;
+            match value.checked_neg() {
+                Some(value) => Rc::new(Box::new(object::Integer{value})),
+                None => new_error("E0109", &format!("integer overflow: -({})", value)),
+            }
+        }
+        object::ObjectType::FLOAT => {
+            let value = right.as_any().downcast_ref::<object::Float>().unwrap().value;
+            Rc::new(Box::new(object::Float{value: -value}))
+        }
+        _ => new_error("E0101", &format!("unknown operator: -{}", right.object_type())),
+    }
+}
+
+#[cfg(test)]
 mod test {
     use super::*;
     use crate::lexer::Lexer;
     use crate::parser::Parser;
     use crate::object;
+    use crate::token::{Span, Token, TokenType};
 
   
     #[test]
@@ -345,6 +830,10 @@ mod test {
             ("3 * 3 * 3 + 10", 37),
             ("3 * (3 * 3) + 10", 37),
             ("(5 + 10 * 2 + 15 / 3) * 2 + -10", 50),
+            ("1_000_000", 1000000),
+            ("0xFF + 0o17 + 0b1010", 280),
+            ("0x7fff_ffff_ffff_ffff", i64::MAX),
+            ("let x1 = 2; let y2 = x1 * 3; y2", 6),
         ];
         for (input, expected) in tests {
             let evaluated = test_eval(input);
@@ -356,7 +845,7 @@ mod test {
         let mut p = Parser::new(l);
         let program = p.parse_program();
         let mut  env = Environment::new();
-         return  env.eval(&program);
+        env.eval(&program)
 
 
     }
@@ -369,7 +858,6 @@ mod test {
     }
     
     #[test]
-
     fn test_eval_boolean_expression (){
         let tests = vec![
             ("true", true),
@@ -404,7 +892,6 @@ mod test {
     }
 
     #[test]
-
     fn test_bang_operator(){
         let tests = vec![
             ("!true", false),
@@ -508,7 +995,7 @@ mod test {
             let evaluated = test_eval(input);
 
             let err = evaluated.as_any().downcast_ref::<object::Error>().unwrap();
-            assert_eq!(err.message, expected);
+            assert_eq!(err.diagnostic.message, expected);
         }
     }
 
@@ -526,7 +1013,6 @@ mod test {
         }
     }
     #[test]
-
     fn test_function_object(){
         let input = "fn(x) { x + 2; };";
         let evaluated = test_eval(input);
@@ -536,7 +1022,6 @@ mod test {
         assert_eq!(fn_obj.body.string(), "(x + 2)");
     }
     #[test]
-
     fn test_function_application(){
         let tests = vec![
             ("let identity = fn(x) { x; }; identity(5);", 5),
@@ -579,14 +1064,13 @@ mod test {
         assert_eq!(str_obj.value, "Hello World!");
     }
     #[test]
-
     fn test_builtin_functions(){
         let tests = vec![
             (r#"len("")"#, 0),
             (r#"len("four")"#, 4),
             (r#"len("hello world")"#, 11),
             // (r#"len(1)"#, "argument to `len` not supported, got INTEGER"),
-            // (r#"len("one", "two")"#, "wrong number of arguments. got=2, want=1"),
+            // (r#"len("one", "two")"#, "wrong number of arguments: want=1, got=2"),
         ];
         for (input, expected) in tests {
             let evaluated = test_eval(input);
@@ -598,4 +1082,582 @@ mod test {
             }
         }
     }
+    #[test]
+    fn test_error_diagnostics(){
+        let tests = vec![
+            ("let a = 1;\na + true;", "E0100", Span::new(11, 19, 2, 1)),
+            ("let f = fn(x) { -x };\nf(true);", "E0101", Span::new(16, 18, 1, 17)),
+            ("1 + foo", "E0102", Span::new(4, 7, 1, 5)),
+            ("5(1)", "E0103", Span::new(0, 4, 1, 1)),
+        ];
+        for (input, code, span) in tests {
+            let evaluated = test_eval(input);
+            let err = evaluated.as_any().downcast_ref::<object::Error>().unwrap();
+            assert_eq!(err.diagnostic.code, code);
+            assert_eq!(err.diagnostic.span, Some(span));
+        }
+    }
+
+    #[test]
+    fn test_checked_integer_arithmetic(){
+        let tests = vec![
+            ("1 / 0", "E0108", "division by zero: 1 / 0"),
+            ("let zero = 0; 5 / zero", "E0108", "division by zero: 5 / 0"),
+            ("9223372036854775807 + 1", "E0109", "integer overflow: 9223372036854775807 + 1"),
+            ("-9223372036854775807 - 2", "E0109", "integer overflow: -9223372036854775807 - 2"),
+            ("4611686018427387904 * 2", "E0109", "integer overflow: 4611686018427387904 * 2"),
+            ("let min = -9223372036854775807 - 1; min / -1", "E0109", "integer overflow: -9223372036854775808 / -1"),
+            ("let min = -9223372036854775807 - 1; -min", "E0109", "integer overflow: -(-9223372036854775808)"),
+        ];
+        for (input, code, message) in tests {
+            let evaluated = test_eval(input);
+            let err = evaluated.as_any().downcast_ref::<object::Error>().unwrap();
+            assert_eq!((err.diagnostic.code, err.diagnostic.message.as_str()), (code, message), "input: {}", input);
+        }
+        test_integer_object(test_eval("-9223372036854775807 - 1 + 1"), -9223372036854775807);
+        test_integer_object(test_eval("-7 / 2"), -3);
+    }
+
+    #[test]
+    fn test_recursive_functions(){
+        let tests = vec![
+            ("let fib = fn(n) { if (n < 2) { return n; } fib(n - 1) + fib(n - 2) }; fib(15);", 610),
+            (
+                r#"
+                let is_even = fn(n) { if (n == 0) { true } else { is_odd(n - 1) } };
+                let is_odd = fn(n) { if (n == 0) { false } else { is_even(n - 1) } };
+                if (is_even(10)) { 1 } else { 0 }
+                "#,
+                1,
+            ),
+            (
+                r#"
+                let outer = fn() {
+                    let countdown = fn(n) { if (n == 0) { 0 } else { 1 + countdown(n - 1) } };
+                    countdown(5)
+                };
+                outer();
+                "#,
+                5,
+            ),
+        ];
+        for (input, expected) in tests {
+            test_integer_object(test_eval(input), expected);
+        }
+    }
+    #[test]
+    fn test_nested_closures(){
+        let tests = vec![
+            ("let add = fn(a) { fn(b) { fn(c) { a + b + c } } }; add(1)(2)(3);", 6),
+            ("let a = 1; let f = fn() { fn() { fn() { a * 10 } } }; f()()();", 10),
+            (
+                r#"
+                let make_counter = fn(start) { fn(step) { start + step } };
+                let from_ten = make_counter(10);
+                let from_hundred = make_counter(100);
+                from_ten(1) + from_hundred(2);
+                "#,
+                113,
+            ),
+            ("let x = 1; let f = fn(x) { x }; f(5) + x;", 6),
+        ];
+        for (input, expected) in tests {
+            test_integer_object(test_eval(input), expected);
+        }
+    }
+    #[test]
+    fn test_wrong_number_of_arguments(){
+        let evaluated = test_eval("let add = fn(a, b) { a + b }; add(1);");
+        let err = evaluated.as_any().downcast_ref::<object::Error>().unwrap();
+        assert_eq!(err.diagnostic.message, "wrong number of arguments: want=2, got=1");
+    }
+
+    #[test]
+    fn test_array_literals(){
+        let evaluated = test_eval("[1, 2 * 2, 3 + 3]");
+        let array = evaluated.as_any().downcast_ref::<object::Array>().unwrap();
+        assert_eq!(array.elements.len(), 3);
+        test_integer_object(array.elements[0].clone(), 1);
+        test_integer_object(array.elements[1].clone(), 4);
+        test_integer_object(array.elements[2].clone(), 6);
+        assert_eq!(evaluated.inspect(), "[1, 4, 6]");
+    }
+    #[test]
+    fn test_array_index_expressions(){
+        let tests = vec![
+            ("[1, 2, 3][0]", Some(1)),
+            ("[1, 2, 3][1]", Some(2)),
+            ("[1, 2, 3][2]", Some(3)),
+            ("let i = 0; [1][i];", Some(1)),
+            ("[1, 2, 3][1 + 1];", Some(3)),
+            ("let myArray = [1, 2, 3]; myArray[2];", Some(3)),
+            ("let myArray = [1, 2, 3]; myArray[0] + myArray[1] + myArray[2];", Some(6)),
+            ("let myArray = [1, 2, 3]; let i = myArray[0]; myArray[i]", Some(2)),
+            ("[1, 2, 3][-1]", Some(3)),
+            ("[1, 2, 3][-3]", Some(1)),
+            ("[1, 2, 3][3]", None),
+            ("[1, 2, 3][-4]", None),
+            ("[][0]", None),
+        ];
+        for (input, expected) in tests {
+            let evaluated = test_eval(input);
+            match expected {
+                Some(x) => test_integer_object(evaluated, x),
+                None => test_null_object(evaluated),
+            }
+        }
+    }
+    #[test]
+    fn test_array_builtins(){
+        let tests = vec![
+            ("len([1, 2, 3])", "3"),
+            ("len([])", "0"),
+            ("first([1, 2, 3])", "1"),
+            ("first([])", "null"),
+            ("last([1, 2, 3])", "3"),
+            ("last([])", "null"),
+            ("rest([1, 2, 3])", "[2, 3]"),
+            ("rest(rest(rest([1, 2, 3])))", "[]"),
+            ("rest([])", "null"),
+            ("push([], 1)", "[1]"),
+            ("let a = [1]; let b = push(a, 2); a", "[1]"),
+            ("let a = [1]; let b = push(a, 2); b", "[1, 2]"),
+            (
+                r#"
+                let map = fn(arr, f) {
+                    let iter = fn(arr, accumulated) {
+                        if (len(arr) == 0) { accumulated } else { iter(rest(arr), push(accumulated, f(first(arr)))) }
+                    };
+                    iter(arr, [])
+                };
+                map([1, 2, 3], fn(x) { x * 2 })
+                "#,
+                "[2, 4, 6]",
+            ),
+        ];
+        for (input, expected) in tests {
+            assert_eq!(test_eval(input).inspect(), expected);
+        }
+    }
+    #[test]
+    fn test_builtin_errors(){
+        let tests = vec![
+            ("len(1)", "argument to `len` not supported, got INTEGER"),
+            (r#"len("one", "two")"#, "wrong number of arguments: want=1, got=2"),
+            ("first(1)", "argument to `first` not supported, got INTEGER"),
+            ("push([1])", "wrong number of arguments: want=2, got=1"),
+            ("1[0]", "index operator not supported: INTEGER[INTEGER]"),
+        ];
+        for (input, expected) in tests {
+            let evaluated = test_eval(input);
+            let err = evaluated.as_any().downcast_ref::<object::Error>().unwrap();
+            assert_eq!(err.diagnostic.message, expected);
+        }
+    }
+
+    #[test]
+    fn test_hash_literals(){
+        let input = r#"
+        let two = "two";
+        {
+            "one": 10 - 9,
+            two: 1 + 1,
+            "thr" + "ee": 6 / 2,
+            4: 4,
+            true: 5,
+            false: 6
+        }
+        "#;
+        let evaluated = test_eval(input);
+        let hash = evaluated.as_any().downcast_ref::<object::Hash>().unwrap();
+        let expected = vec![
+            (object::HashKey::String(String::from("one")), 1),
+            (object::HashKey::String(String::from("two")), 2),
+            (object::HashKey::String(String::from("three")), 3),
+            (object::HashKey::Integer(4), 4),
+            (object::HashKey::Boolean(true), 5),
+            (object::HashKey::Boolean(false), 6),
+        ];
+        assert_eq!(hash.pairs.len(), expected.len());
+        for (key, value) in expected {
+            test_integer_object(hash.get(&key).unwrap().value.clone(), value);
+        }
+        assert_eq!(evaluated.inspect(), "{one: 1, two: 2, three: 3, 4: 4, true: 5, false: 6}");
+    }
+    #[test]
+    fn test_hash_index_expressions(){
+        let tests = vec![
+            (r#"{"foo": 5}["foo"]"#, Some(5)),
+            (r#"{"foo": 5}["bar"]"#, None),
+            (r#"let key = "foo"; {"foo": 5}[key]"#, Some(5)),
+            (r#"{}["foo"]"#, None),
+            ("{5: 5}[5]", Some(5)),
+            ("{true: 5}[true]", Some(5)),
+            ("{false: 5}[false]", Some(5)),
+            (r#"{1: 5}["1"]"#, None),
+            ("{1: 1, 1: 2}[1]", Some(2)),
+        ];
+        for (input, expected) in tests {
+            let evaluated = test_eval(input);
+            match expected {
+                Some(x) => test_integer_object(evaluated, x),
+                None => test_null_object(evaluated),
+            }
+        }
+    }
+    #[test]
+    fn test_hash_builtins(){
+        let tests = vec![
+            (r#"keys({"a": 1, 2: true})"#, "[a, 2]"),
+            (r#"values({"a": 1, 2: true})"#, "[1, true]"),
+            (r#"has_key({"a": 1}, "a")"#, "true"),
+            (r#"has_key({"a": 1}, "b")"#, "false"),
+            (r#"delete({"a": 1, "b": 2}, "a")"#, "{b: 2}"),
+            (r#"let h = {"a": 1}; let g = delete(h, "a"); h"#, "{a: 1}"),
+            (r#"delete({"a": 1}, "z")"#, "{a: 1}"),
+        ];
+        for (input, expected) in tests {
+            assert_eq!(test_eval(input).inspect(), expected);
+        }
+    }
+    #[test]
+    fn test_comparison_and_logical_operators(){
+        let tests = vec![
+            ("1 <= 2", "true"),
+            ("2 <= 2", "true"),
+            ("3 <= 2", "false"),
+            ("1 >= 2", "false"),
+            ("2 >= 2", "true"),
+            ("2.5 >= 2", "true"),
+            ("7 % 3", "1"),
+            ("-7 % 3", "-1"),
+            ("7.5 % 2", "1.5"),
+            ("1 + 10 % 4 * 2", "5"),
+            ("true && false", "false"),
+            ("true && 1", "true"),
+            ("0 || false", "true"),
+            ("false || false", "false"),
+            ("1 < 2 && 2 < 3", "true"),
+            ("false && undefined", "false"),
+            ("true || undefined", "true"),
+            ("false && 1 / 0", "false"),
+            ("let calls = fn() { puts(\"called\"); true }; true || calls()", "true"),
+            ("true && undefined", "identifier not found: undefined"),
+            ("7 % 0", "division by zero: 7 % 0"),
+            ("1.5 % 0", "division by zero: 1.5 % 0.0"),
+            ("let min = -9223372036854775807 - 1; min % -1", "integer overflow: -9223372036854775808 % -1"),
+            ("\"a\" <= \"b\"", "unknown operator: STRING <= STRING"),
+        ];
+        for (input, expected) in tests {
+            let evaluated = test_eval(input);
+            match evaluated.as_any().downcast_ref::<object::Error>() {
+                Some(error) => assert_eq!(error.diagnostic.message, expected, "{}", input),
+                None => assert_eq!(evaluated.inspect(), expected, "{}", input),
+            }
+        }
+    }
+    #[test]
+    fn test_while_loops(){
+        let tests = vec![
+            ("let i = 0; while (i < 5) { let i = i + 1; } i", "5"),
+            ("while (false) { 1 }", "null"),
+            ("let i = 0; while (true) { let i = i + 1; if (i == 3) { break; } } i", "3"),
+            ("let i = 0; let odd = 0; while (i < 6) { let i = i + 1; if (i % 2 == 0) { continue } let odd = odd + 1; } odd", "3"),
+            ("let f = fn() { while (true) { return 7; } }; f()", "7"),
+            ("let n = 0; let i = 0; while (i < 3) { let i = i + 1; let j = 0; while (true) { let j = j + 1; if (j > i) { break } let n = n + 1; } } n", "6"),
+            ("let f = fn(n) { let acc = []; while (len(acc) < n) { let acc = push(acc, len(acc)); } acc }; f(3)", "[0, 1, 2]"),
+            ("while (missing) { 1 }", "identifier not found: missing"),
+            ("let i = 0; while (i < 3) { let i = i + 1; i + true }", "type mismatch: INTEGER + BOOLEAN"),
+        ];
+        for (input, expected) in tests {
+            let evaluated = test_eval(input);
+            match evaluated.as_any().downcast_ref::<object::Error>() {
+                Some(error) => assert_eq!(error.diagnostic.message, expected, "{}", input),
+                None => assert_eq!(evaluated.inspect(), expected, "{}", input),
+            }
+        }
+    }
+
+    #[test]
+    fn test_for_loops(){
+        let tests = vec![
+            ("let total = 0; for (x in [1, 2, 3]) { let total = total + x; } total", "6"),
+            ("let total = 0; for (i in 0..10) { let total = total + i; } total", "45"),
+            ("let total = 0; for (i in 1..=10) { let total = total + i; } total", "55"),
+            ("let n = 0; for (i in 5..2) { let n = n + 1; } n", "0"),
+            ("let s = \"\"; for (c in \"h\u{e9}y\") { let s = c + s; } s", "y\u{e9}h"),
+            ("let out = []; for (k in {\"b\": 1, \"a\": 2}) { let out = push(out, k); } out", "[b, a]"),
+            ("let out = []; for (i in 0..10) { if (i % 3 != 0) { continue; } if (i > 7) { break; } let out = push(out, i); } out", "[0, 3, 6]"),
+            ("let f = fn(xs) { for (x in xs) { if (x > 1) { return x; } } -1 }; [f([0, 2, 3]), f([])]", "[2, -1]"),
+            ("for (x in [1]) { x }", "null"),
+            ("for (x in [7, 8]) { } x", "8"),
+            ("[0..3, 1..=1, -2..-5]", "[0..3, 1..=1, -2..-5]"),
+            ("for (x in 5) { x }", "not iterable: INTEGER"),
+            ("for (x in fn() { 1 }) { x }", "not iterable: FUNCTION"),
+            ("1.5..2", "unknown operator: FLOAT .. FLOAT"),
+            ("1..true", "type mismatch: INTEGER .. BOOLEAN"),
+        ];
+        for (input, expected) in tests {
+            let evaluated = test_eval(input);
+            match evaluated.as_any().downcast_ref::<object::Error>() {
+                Some(error) => assert_eq!(error.diagnostic.message, expected, "{}", input),
+                None => assert_eq!(evaluated.inspect(), expected, "{}", input),
+            }
+        }
+
+        let evaluated = test_eval("let xs = [1, 2];\nfor (x in len(xs)) { x }");
+        let error = evaluated.as_any().downcast_ref::<object::Error>().unwrap();
+        assert_eq!((error.diagnostic.code, error.diagnostic.span), ("E0111", Some(Span::new(27, 34, 2, 11))));
+    }
+
+    #[test]
+    fn test_assignment(){
+        let tests = vec![
+            ("let x = 1; x = 2; x", "2"),
+            ("let x = 1; x = 5", "5"),
+            ("let x = 10; x += 2; x -= 4; x *= 3; x /= 2; x", "12"),
+            ("let s = \"a\"; s += \"b\"; s", "ab"),
+            ("let a = 1; let b = 2; a = b = 7; [a, b]", "[7, 7]"),
+            ("let i = 0; while (i < 5) { i += 1; } i", "5"),
+            ("let counter = fn() { let n = 0; fn() { n += 1 } }; let c = counter(); c(); c(); c()", "3"),
+            ("let total = 0; let add = fn(x) { total += x; }; add(2); add(3); total", "5"),
+            ("let f = fn(x) { x = x * 2; x }; let y = 4; [f(y), y]", "[8, 4]"),
+            ("let xs = [1, 2, 3]; xs[1] = 9; xs", "[1, 9, 3]"),
+            ("let xs = [1, 2, 3]; let ys = xs; xs[0] += 10; [xs, ys]", "[[11, 2, 3], [1, 2, 3]]"),
+            ("let h = {\"a\": 1}; h[\"a\"] += 1; h[\"b\"] = 5; [h[\"a\"], h[\"b\"]]", "[2, 5]"),
+            ("let m = [[1, 2], [3, 4]]; m[0][1] = 7; m", "[[1, 7], [3, 4]]"),
+            ("let i = 0; let xs = [0, 0]; xs[i] = i = 1; [xs, i]", "[[1, 0], 1]"),
+            ("x = 1", "identifier not found: x"),
+            ("x += 1", "identifier not found: x"),
+            ("len = 1", "cannot assign to builtin `len`"),
+            ("let xs = [1, 2, 3]; xs[5] = 1", "index out of range: 5 (length 3)"),
+            ("let xs = [1, 2, 3]; xs[-1] = 0; xs", "[1, 2, 0]"),
+            ("let xs = [1, 2, 3]; xs[-4] = 1", "index out of range: -4 (length 3)"),
+            ("let s = \"abc\"; s[0] = \"x\"", "index assignment not supported: STRING[INTEGER]"),
+            ("let h = {}; h[[1]] = 1", "unusable as hash key: ARRAY"),
+            ("let x = 1; x += true", "type mismatch: INTEGER + BOOLEAN"),
+        ];
+        for (input, expected) in tests {
+            let evaluated = test_eval(input);
+            match evaluated.as_any().downcast_ref::<object::Error>() {
+                Some(error) => assert_eq!(error.diagnostic.message, expected, "{}", input),
+                None => assert_eq!(evaluated.inspect(), expected, "{}", input),
+            }
+        }
+    }
+
+    #[test]
+    fn test_constants(){
+        let tests = vec![
+            ("const x = 5; x * 2", "10"),
+            ("const x = 5;", "null"),
+            ("let x = 1; let x = x + 1; x", "2"),
+            ("const x = 5; let f = fn() { let x = 6; x }; [f(), x]", "[6, 5]"),
+            ("const x = 5; let f = fn(x) { x = x + 1; x }; [f(1), x]", "[2, 5]"),
+            ("const fact = fn(n) { if (n < 2) { 1 } else { n * fact(n - 1) } }; fact(5)", "120"),
+            ("let f = fn() { const n = 1; n }; f() + f()", "2"),
+            ("const x = 5; x = 6", "cannot assign to constant `x`"),
+            ("const x = 5; x += 1", "cannot assign to constant `x`"),
+            ("const xs = [1, 2]; xs[0] = 3", "cannot assign to constant `xs`"),
+            ("const x = 5; let f = fn() { x = 6 }; f()", "cannot assign to constant `x`"),
+            ("const x = 5; let x = 6;", "cannot redeclare constant `x`"),
+            ("const x = 5; const x = 6;", "cannot redeclare constant `x`"),
+            ("let x = 5; const x = 6;", "`x` is already declared"),
+            ("const x = 5; for (x in [1]) { }", "cannot redeclare constant `x`"),
+            ("let i = 0; while (i < 2) { i += 1; const k = i; } k", "cannot redeclare constant `k`"),
+            ("let i = 0; while (i < 1) { i += 1; const k = i; } i", "cannot redeclare constant `k`"),
+            ("for (x in []) { const k = x; }", "cannot redeclare constant `k`"),
+            ("for (x in [1, 2]) { let f = fn() { const k = x; k }; f() }", "null"),
+        ];
+        for (input, expected) in tests {
+            let evaluated = test_eval(input);
+            match evaluated.as_any().downcast_ref::<object::Error>() {
+                Some(error) => assert_eq!(error.diagnostic.message, expected, "{}", input),
+                None => assert_eq!(evaluated.inspect(), expected, "{}", input),
+            }
+        }
+
+        let evaluated = test_eval("const limit = 1;\nlet limit = 2;");
+        let error = evaluated.as_any().downcast_ref::<object::Error>().unwrap();
+        assert_eq!((error.diagnostic.code, error.diagnostic.span), ("E0115", Some(Span::new(17, 31, 2, 1))));
+
+        let evaluated = test_eval("if (false) { while (true) { const k = 1; } }");
+        let error = evaluated.as_any().downcast_ref::<object::Error>().unwrap();
+        assert_eq!((error.diagnostic.code, error.diagnostic.span), ("E0115", Some(Span::new(28, 40, 1, 29))));
+        assert!(error.diagnostic.help.is_some());
+    }
+
+    #[test]
+    fn test_call_depth_limit() {
+        let evaluated = std::thread::Builder::new().stack_size(STACK_SIZE).spawn(|| {
+            let evaluated = test_eval("let f = fn(n) { f(n + 1) + 1 }; f(0)");
+            let error = evaluated.as_any().downcast_ref::<object::Error>().unwrap();
+            (error.diagnostic.code, error.diagnostic.message.clone())
+        }).unwrap().join().unwrap();
+        assert_eq!(evaluated, ("E0107", "stack overflow".to_string()));
+        assert_eq!(test_eval("let f = fn(n) { if (n == 0) { 0 } else { f(n - 1) + 1 } }; f(100)").inspect(), "100");
+    }
+
+    #[test]
+    fn test_match_expressions(){
+        let tests = vec![
+            ("match (2) { 1 => \"one\", 2 => \"two\", _ => \"many\" }", "two"),
+            ("match (7) { 1 => \"one\", _ => \"many\" }", "many"),
+            ("match (-1) { -1 => \"minus one\", n => n }", "minus one"),
+            ("match (2.0) { 2 => \"int\", _ => \"other\" }", "int"),
+            ("match (\"1\") { 1 => \"int\", \"1\" => \"string\" }", "string"),
+            ("match (true) { false => 0, true => 1 }", "1"),
+            ("match (5) { n if n > 10 => \"big\", n => n * 2 }", "10"),
+            ("match ([1, 2, 3]) { [] => 0, [a] => a, [a, b] => a + b, [a, ..rest] => rest }", "[2, 3]"),
+            ("match ([1, 2]) { [_, _, ..] => \"long\", [..] => \"short\" }", "long"),
+            ("match ([[1, 2], 3]) { [[a, b], c] => a + b + c }", "6"),
+            ("match ({\"x\": 1, \"y\": 2}) { {\"x\": 0} => \"origin\", {\"y\": y, \"x\": x} => [x, y] }", "[1, 2]"),
+            ("match ({1: [true]}) { {1: [b]} if b => \"yes\", _ => \"no\" }", "yes"),
+            ("match ({}) { {\"x\": x} => x, _ => \"missing\" }", "missing"),
+            ("match (3) { n => { let m = n * n; m + 1 } }", "10"),
+            ("let x = 1; match (2) { x => x }; x", "1"),
+            ("let n = 10; match (1) { n if n > 5 => 0, _ => n }", "10"),
+            ("let x = 0; match ([1, 2]) { [a, b] => { x = a + b; let a = 5; } }; [x, a]", "identifier not found: a"),
+            ("let x = 0; match ([1, 2]) { [a, b] => { x = a + b; } }; x", "3"),
+            ("let f = fn(xs) { match (xs) { [] => 0, [x, ..rest] => x + f(rest) } }; f([1, 2, 3])", "6"),
+            ("let n = 0; for (x in [1, 2, 3]) { match (x) { 2 => { break; }, _ => { n += x } } } n", "1"),
+            ("match (1) { }", "unmatched value: 1"),
+            ("match ([1, \"a\"]) { [_] => 1, [1, 2] => 2 }", "unmatched value: [1, a]"),
+            ("match (1) { n if n > 1 => n }", "unmatched value: 1"),
+            ("match (1) { x if y => 1 }", "identifier not found: y"),
+            ("const n = 1; match (2) { n => n }", "2"),
+            ("const n = 1; match (2) { n => { n = 3; n } }; n", "1"),
+            ("match (2) { n => { const n = 3; n } }", "`n` is already declared"),
+        ];
+        for (input, expected) in tests {
+            let evaluated = test_eval(input);
+            match evaluated.as_any().downcast_ref::<object::Error>() {
+                Some(error) => assert_eq!(error.diagnostic.message, expected, "{}", input),
+                None => assert_eq!(evaluated.inspect(), expected, "{}", input),
+            }
+        }
+
+        let evaluated = test_eval("let x = 1;\nmatch (x + 1) { 1 => 0 }");
+        let error = evaluated.as_any().downcast_ref::<object::Error>().unwrap();
+        assert_eq!((error.diagnostic.code, error.diagnostic.span), ("E0116", Some(Span::new(18, 23, 2, 8))));
+    }
+
+    #[test]
+    fn test_loop_control_escaping_a_function(){
+        let program = ast::Program {
+            statements: vec![Box::new(ast::BreakStatement { token: Token::new(TokenType::BREAK, "break"), span: Span::default() })],
+            span: Span::default(),
+        };
+        let evaluated = Environment::new().eval(&program);
+        let error = evaluated.as_any().downcast_ref::<object::Error>().unwrap();
+        assert_eq!((error.diagnostic.code, error.diagnostic.message.as_str()), ("E0012", "`break` outside of a loop"));
+    }
+
+    #[test]
+    fn test_float_arithmetic(){
+        let tests = vec![
+            ("3.14", "3.14"),
+            (".5 + 1", "1.5"),
+            ("1e-9", "1e-9"),
+            ("2 * 1.5", "3.0"),
+            ("10 / 4.0", "2.5"),
+            ("-2.5 - 1", "-3.5"),
+            ("0.1 + 0.2", "0.30000000000000004"),
+            ("1.5 < 2", "true"),
+            ("2 > 1.5", "true"),
+            ("1 == 1.0", "true"),
+            ("0.5 != .5", "false"),
+            ("1e300 * 1e300", "inf"),
+            ("int(3.9)", "3"),
+            ("int(-3.9)", "-3"),
+            (r#"int(" 42 ")"#, "42"),
+            ("float(2)", "2.0"),
+            (r#"float("2.5e1")"#, "25.0"),
+            ("floor(-2.5)", "-3"),
+            ("ceil(2.1)", "3"),
+            ("round(2.5)", "3"),
+            ("round(-2.5)", "-3"),
+            ("floor(7)", "7"),
+        ];
+        for (input, expected) in tests {
+            assert_eq!(test_eval(input).inspect(), expected, "input: {}", input);
+        }
+
+        // inspect() output lexes back to the same value.
+        for input in ["0.1 + 0.2", "1.0 / 3", "1e-9 * 3", "123456789.0 * 1e20", "-0.0"] {
+            let printed = test_eval(input).inspect();
+            assert_eq!(test_eval(&format!("{} == {}", printed, input)).inspect(), "true", "{} printed as {}", input, printed);
+        }
+
+        let tests = vec![
+            ("1.5 / 0", "division by zero: 1.5 / 0.0"),
+            ("1.5 + true", "type mismatch: FLOAT + BOOLEAN"),
+            (r#""a" + 1.5"#, "unknown operator: STRING + FLOAT"),
+            ("let h = {1.5: 1};", "unusable as hash key: FLOAT"),
+            ("int(1e300)", "cannot convert 1e300 to INTEGER in `int`"),
+            ("round(1e300 * 1e300 - 1e300 * 1e300)", "cannot convert NaN to INTEGER in `round`"),
+            (r#"int("4.5")"#, "cannot convert \"4.5\" to INTEGER in `int`"),
+            ("floor(true)", "argument to `floor` not supported, got BOOLEAN"),
+        ];
+        for (input, expected) in tests {
+            let evaluated = test_eval(input);
+            let err = evaluated.as_any().downcast_ref::<object::Error>().unwrap_or_else(|| panic!("no error for {}", input));
+            assert_eq!(err.diagnostic.message, expected);
+        }
+    }
+
+    #[test]
+    fn test_unicode_strings(){
+        let tests = vec![
+            (r#"len("héllo")"#, "5"),
+            (r#"len("日本語")"#, "3"),
+            (r#"len("😀")"#, "1"),
+            (r#""héllo"[1]"#, "é"),
+            (r#""日本語"[-1]"#, "語"),
+            (r#""日本語"[3]"#, "null"),
+            (r#""abc"[-4]"#, "null"),
+            (r#"let 名前 = "Zoë"; let café = " à "; 名前 + café + "ok""#, "Zoë à ok"),
+        ];
+        for (input, expected) in tests {
+            assert_eq!(test_eval(input).inspect(), expected, "input: {}", input);
+        }
+    }
+
+    #[test]
+    fn test_string_interpolation(){
+        let tests = vec![
+            (r#"let name = "Ada"; let age = 36; "hello ${name}, you are ${age + 1}""#, "hello Ada, you are 37"),
+            (r#""${[1, "two", 3.5]} ${true} ${if (false) { 1 }}""#, "[1, two, 3.5] true null"),
+            (r#"let f = fn(x) { "<${x}>" }; "${f("${1}${2}")}""#, "<12>"),
+            (r#""""
+line ${1 + 1}
+""""#, "line 2\n"),
+            (r#""${"}"} \${x}""#, "} ${x}"),
+            (r#""a ${missing} b""#, "identifier not found: missing"),
+            (r#""${1 / 0}""#, "division by zero: 1 / 0"),
+        ];
+        for (input, expected) in tests {
+            let evaluated = test_eval(input);
+            match evaluated.as_any().downcast_ref::<object::Error>() {
+                Some(error) => assert_eq!(error.diagnostic.message, expected, "{}", input),
+                None => assert_eq!(evaluated.inspect(), expected, "{}", input),
+            }
+        }
+    }
+
+    #[test]
+    fn test_unhashable_keys(){
+        let tests = vec![
+            (r#"{"name": "Monkey"}[fn(x) { x }];"#, "unusable as hash key: FUNCTION"),
+            ("let h = {[1]: 2};", "unusable as hash key: ARRAY"),
+            ("has_key({}, {})", "unusable as hash key: HASH"),
+            ("keys([])", "argument to `keys` not supported, got ARRAY"),
+        ];
+        for (input, expected) in tests {
+            let evaluated = test_eval(input);
+            let err = evaluated.as_any().downcast_ref::<object::Error>().unwrap();
+            assert_eq!(err.diagnostic.message, expected);
+        }
+    }
+
 }
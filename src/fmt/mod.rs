@@ -0,0 +1,726 @@
+use std::collections::{BTreeMap, HashMap};
+
+use crate::ast::{self, Node, NodeType};
+use crate::cst;
+use crate::diagnostic::Diagnostic;
+use crate::lexer::Lexer;
+use crate::parser::{self, Parser, Precedence};
+use crate::token::{TokenType, Trivia, TriviaKind};
+
+/// Lines are kept within this many columns where wrapping call arguments
+/// allows it.
+pub const MAX_WIDTH: usize = 100;
+const INDENT: &str = "    ";
+
+/// Formats `source`, keeping its comments and single blank lines between
+/// statements. Fails with the parser's diagnostics if it does not parse.
+pub fn format_source(source: &str) -> Result<String, Vec<Diagnostic>> {
+    let mut parser = Parser::new(Lexer::new(source.to_string()));
+    let program = parser.parse_program();
+    if !parser.errors.is_empty() {
+        return Err(parser.errors);
+    }
+    let mut formatter = Formatter::new(Some(source));
+    Ok(formatter.program(&program, source.len()))
+}
+
+/// Formats a program that has no source text, such as one built in code.
+pub fn format_program(program: &ast::Program) -> String {
+    Formatter::new(None).program(program, usize::MAX)
+}
+
+/// The comments and line breaks around one token of the source.
+#[derive(Clone, Default)]
+struct TokenTrivia {
+    leading: Vec<Comment>,
+    trailing: Vec<Comment>,
+    /// Whether a blank line separates this token from the previous one.
+    blank_line_before: bool,
+}
+
+#[derive(Clone)]
+struct Comment {
+    text: String,
+    is_line: bool,
+    /// Whether a blank line follows the comment, before the next comment or
+    /// the token.
+    blank_line_after: bool,
+}
+
+struct Formatter<'a> {
+    source: Option<&'a str>,
+    precedences: HashMap<TokenType, Precedence>,
+    /// Trivia by the byte offset of its token. Comments are removed as they
+    /// are printed, so each is printed once.
+    trivia: BTreeMap<usize, TokenTrivia>,
+    /// Set while trying a call on one line, so that an outer call wraps
+    /// before the calls nested in it.
+    flat: bool,
+}
+
+impl<'a> Formatter<'a> {
+    fn new(source: Option<&'a str>) -> Formatter<'a> {
+        let mut trivia = BTreeMap::new();
+        for token in source.map(cst::lex).unwrap_or_default() {
+            let comments = |list: &Vec<Trivia>| {
+                let mut comments: Vec<Comment> = Vec::new();
+                let mut newlines = 0;
+                for trivia in list.iter() {
+                    match trivia.kind {
+                        TriviaKind::Whitespace => {}
+                        TriviaKind::Newline => newlines += 1,
+                        kind => {
+                            if let Some(last) = comments.last_mut() {
+                                last.blank_line_after = newlines >= 2;
+                            }
+                            comments.push(Comment { text: trivia.text.clone(), is_line: kind != TriviaKind::BlockComment, blank_line_after: false });
+                            newlines = 0;
+                        }
+                    }
+                }
+                if let Some(last) = comments.last_mut() {
+                    last.blank_line_after = newlines >= 2;
+                }
+                comments
+            };
+            let newlines = token.leading.iter()
+                .take_while(|t| matches!(t.kind, TriviaKind::Whitespace | TriviaKind::Newline))
+                .filter(|t| t.kind == TriviaKind::Newline)
+                .count();
+            trivia.insert(token.span.start, TokenTrivia {
+                leading: comments(&token.leading),
+                trailing: comments(&token.trailing),
+                blank_line_before: newlines >= 2,
+            });
+        }
+        Formatter {
+            source,
+            precedences: parser::precedences(),
+            trivia,
+            flat: false,
+        }
+    }
+
+    fn program(&mut self, program: &ast::Program, end: usize) -> String {
+        let mut out = self.statements(&program.statements, 0, end, false);
+        let trailing = self.trivia.remove(&end).unwrap_or_default();
+        if !trailing.leading.is_empty() {
+            if trailing.blank_line_before && !out.is_empty() {
+                out.push('\n');
+            }
+            let count = trailing.leading.len();
+            for (i, comment) in trailing.leading.into_iter().enumerate() {
+                out.push_str(&comment.text);
+                out.push('\n');
+                if comment.blank_line_after && i + 1 < count {
+                    out.push('\n');
+                }
+            }
+        }
+        out
+    }
+
+    /// Formats statements one per line at `indent`, each followed by a
+    /// newline. `end` is the offset of the token closing the list; in a block
+    /// the last expression statement is its value and has no `;`.
+    fn statements(&mut self, statements: &[Box<dyn ast::Statement>], indent: usize, end: usize, in_block: bool) -> String {
+        let prefix = INDENT.repeat(indent);
+        let mut lines: Vec<(String, String, String)> = Vec::new();
+        let mut open_if = Vec::new();
+        for (i, statement) in statements.iter().enumerate() {
+            let start = statement.span().start;
+            let next = statements.get(i + 1).map(|s| s.span().start).unwrap_or(end);
+            let leading = self.trivia.get_mut(&start).map(|t| (std::mem::take(&mut t.leading), t.blank_line_before));
+            let (leading, blank_line_before) = leading.unwrap_or_default();
+            let mut head = String::new();
+            if i > 0 && blank_line_before {
+                head.push('\n');
+            }
+            for comment in leading {
+                head.push_str(&prefix);
+                head.push_str(&comment.text);
+                head.push('\n');
+                if comment.blank_line_after {
+                    head.push('\n');
+                }
+            }
+            let is_value = in_block && i + 1 == statements.len();
+            let mut text = self.statement(statement.as_node(), indent);
+            if statement.node_type() == NodeType::ExpressionStatement && !is_value {
+                match is_block_expression(statement.as_node()) {
+                    true => open_if.push(i),
+                    false => text.push(';'),
+                }
+            }
+            let mut comments = String::new();
+            self.push_remaining_comments(&mut comments, start, next, &prefix);
+            lines.push((head, text, comments));
+        }
+        // An `if` or `match` statement only needs a `;` when the next statement would
+        // otherwise continue it as an operand, e.g. `(a)` or `-1`.
+        for i in open_if {
+            if lines.get(i + 1).is_some_and(|(_, text, _)| text.starts_with(['(', '[', '-'])) {
+                lines[i].1.push(';');
+            }
+        }
+        let mut out = String::new();
+        for (head, text, comments) in lines {
+            out.push_str(&head);
+            out.push_str(&prefix);
+            out.push_str(&text);
+            out.push_str(&comments);
+            out.push('\n');
+        }
+        out
+    }
+
+    /// Appends the comments of the tokens in `start..end` not printed yet to
+    /// the end of the line, the line comments last since they end it.
+    fn push_remaining_comments(&mut self, out: &mut String, start: usize, end: usize, prefix: &str) {
+        let mut comments = Vec::new();
+        for (_, trivia) in self.trivia.range_mut(start..end) {
+            comments.append(&mut trivia.leading);
+            comments.append(&mut trivia.trailing);
+        }
+        comments.sort_by_key(|c| c.is_line);
+        let mut line_comment = false;
+        for comment in comments {
+            if line_comment {
+                out.push('\n');
+                out.push_str(prefix);
+            } else {
+                out.push(' ');
+            }
+            out.push_str(&comment.text);
+            line_comment = comment.is_line;
+        }
+    }
+
+    /// Formats a statement. An expression statement is left without its `;`,
+    /// which depends on where the statement is.
+    fn statement(&mut self, node: &dyn Node, indent: usize) -> String {
+        let column = indent * INDENT.len();
+        match node.node_type() {
+            NodeType::LetStatement | NodeType::ConstStatement => {
+                let statement = node.as_any().downcast_ref::<ast::LetStatement>().unwrap();
+                let head = format!("{} {} = ", statement.token.literal, statement.name.value);
+                let value = self.expression(statement.value.as_node(), indent, column + head.len());
+                format!("{}{};", head, value)
+            }
+            NodeType::ReturnStatement => {
+                let statement = node.as_any().downcast_ref::<ast::ReturnStatement>().unwrap();
+                let value = self.expression(statement.return_value.as_node(), indent, column + "return ".len());
+                format!("return {};", value)
+            }
+            NodeType::ExpressionStatement => {
+                let expression = node.as_any().downcast_ref::<ast::ExpressionStatement>().unwrap().expression.as_node();
+                match starts_with_hash_literal(expression) {
+                    true => format!("({})", self.expression(expression, indent, column + 1)),
+                    false => self.expression(expression, indent, column),
+                }
+            }
+            NodeType::BlockStatement => self.block(node, indent),
+            NodeType::WhileStatement => {
+                let statement = node.as_any().downcast_ref::<ast::WhileStatement>().unwrap();
+                let condition = self.expression(statement.condition.as_node(), indent, column + "while (".len());
+                format!("while ({}) {}", condition, self.block(statement.body.as_node(), indent))
+            }
+            NodeType::ForStatement => {
+                let statement = node.as_any().downcast_ref::<ast::ForStatement>().unwrap();
+                let head = format!("for ({} in ", statement.variable.value);
+                let iterable = self.expression(statement.iterable.as_node(), indent, column + head.len());
+                format!("{}{}) {}", head, iterable, self.block(statement.body.as_node(), indent))
+            }
+            NodeType::BreakStatement => String::from("break;"),
+            NodeType::ContinueStatement => String::from("continue;"),
+            _ => unreachable!("not a statement: {:?}", node.node_type()),
+        }
+    }
+
+    /// Formats a `{ ... }` block whose first line starts at `indent`.
+    fn block(&mut self, node: &dyn Node, indent: usize) -> String {
+        let block = node.as_any().downcast_ref::<ast::BlockStatement>().unwrap();
+        let close = block.span.end.saturating_sub(1);
+        let mut out = String::from("{");
+        let mut open = self.trivia.get_mut(&block.span.start).map(|t| std::mem::take(&mut t.trailing)).unwrap_or_default();
+        let mut closing = self.trivia.get_mut(&close).map(|t| std::mem::take(&mut t.leading)).unwrap_or_default();
+        if block.statements.is_empty() && open.is_empty() && closing.is_empty() {
+            out.push('}');
+            return out;
+        }
+        open.sort_by_key(|c| c.is_line);
+        for comment in open.iter() {
+            out.push(' ');
+            out.push_str(&comment.text);
+        }
+        out.push('\n');
+        // A block starts new lines, so the calls in it may wrap.
+        let flat = std::mem::replace(&mut self.flat, false);
+        let body = self.statements(&block.statements, indent + 1, close, true);
+        self.flat = flat;
+        out.push_str(&body);
+        let prefix = INDENT.repeat(indent + 1);
+        for comment in closing.drain(..) {
+            out.push_str(&prefix);
+            out.push_str(&comment.text);
+            out.push('\n');
+        }
+        out.push_str(&INDENT.repeat(indent));
+        out.push('}');
+        out
+    }
+
+    /// Formats an expression starting at `column` of a line indented by
+    /// `indent` levels.
+    fn expression(&mut self, node: &dyn Node, indent: usize, column: usize) -> String {
+        let any = node.as_any();
+        match node.node_type() {
+            NodeType::Identifier => any.downcast_ref::<ast::Identifier>().unwrap().value.clone(),
+            NodeType::IntegerLiteral => any.downcast_ref::<ast::IntegerLiteral>().unwrap().token.literal.clone(),
+            NodeType::FloatLiteral => any.downcast_ref::<ast::FloatLiteral>().unwrap().token.literal.clone(),
+            NodeType::Boolean => any.downcast_ref::<ast::Boolean>().unwrap().value.to_string(),
+            NodeType::StringLiteral => {
+                let string = any.downcast_ref::<ast::StringLiteral>().unwrap();
+                match self.source {
+                    Some(source) => source[string.span.start..string.span.end].to_string(),
+                    None => format!("\"{}\"", escape(&string.value)),
+                }
+            }
+            NodeType::InterpolatedString => {
+                let string = any.downcast_ref::<ast::InterpolatedString>().unwrap();
+                let mut out = String::from("\"");
+                for part in string.parts.iter() {
+                    match part {
+                        ast::InterpolationPart::Text(text) => out.push_str(&escape(text)),
+                        ast::InterpolationPart::Expression(expression) => {
+                            let column = advance(column, &out) + 2;
+                            out.push_str("${");
+                            out.push_str(&self.expression(expression.as_node(), indent, column));
+                            out.push('}');
+                        }
+                    }
+                }
+                out.push('"');
+                out
+            }
+            NodeType::PrefixExpression => {
+                let prefix = any.downcast_ref::<ast::PrefixExpression>().unwrap();
+                let right = self.operand(prefix.right.as_node(), Precedence::PREFIX, false, indent, column + prefix.operator.len());
+                format!("{}{}", prefix.operator, right)
+            }
+            NodeType::InfixExpression => {
+                let infix = any.downcast_ref::<ast::InfixExpression>().unwrap();
+                let precedence = self.precedence(node);
+                // Ranges read as one word: `0..n`.
+                let space = if precedence == Precedence::RANGE { "" } else { " " };
+                let left = self.operand(infix.left.as_node(), precedence, false, indent, column);
+                let column = advance(column, &left) + infix.operator.len() + 2 * space.len();
+                let right = self.operand(infix.right.as_node(), precedence, true, indent, column);
+                format!("{}{}{}{}{}", left, space, infix.operator, space, right)
+            }
+            NodeType::AssignExpression => {
+                // Assignment groups to the right, so the value never needs parentheses.
+                let assign = any.downcast_ref::<ast::AssignExpression>().unwrap();
+                let target = self.expression(assign.target.as_node(), indent, column);
+                let column = advance(column, &target) + assign.operator.len() + 2;
+                format!("{} {} {}", target, assign.operator, self.expression(assign.value.as_node(), indent, column))
+            }
+            NodeType::IfExpression => {
+                let if_expression = any.downcast_ref::<ast::IfExpression>().unwrap();
+                let condition = self.expression(if_expression.condition.as_node(), indent, column + 4);
+                let mut out = format!("if ({}) {}", condition, self.block(if_expression.consequence.as_node(), indent));
+                if let Some(alternative) = &if_expression.alternative {
+                    out.push_str(" else ");
+                    out.push_str(&self.block(alternative.as_node(), indent));
+                }
+                out
+            }
+            NodeType::MatchExpression => {
+                let expression = any.downcast_ref::<ast::MatchExpression>().unwrap();
+                let subject = self.expression(expression.subject.as_node(), indent, column + "match (".len());
+                let mut out = format!("match ({}) {{", subject);
+                if expression.arms.is_empty() {
+                    out.push('}');
+                    return out;
+                }
+                out.push('\n');
+                let prefix = INDENT.repeat(indent + 1);
+                for arm in expression.arms.iter() {
+                    out.push_str(&prefix);
+                    out.push_str(&self.match_arm(arm, indent + 1));
+                    out.push('\n');
+                }
+                out.push_str(&INDENT.repeat(indent));
+                out.push('}');
+                out
+            }
+            NodeType::RestPattern => any.downcast_ref::<ast::RestPattern>().unwrap().string(),
+            NodeType::FunctionLiteral => {
+                let function = any.downcast_ref::<ast::FunctionLiteral>().unwrap();
+                let parameters: Vec<&str> = function.parameters.iter().map(|p| p.value.as_str()).collect();
+                format!("fn({}) {}", parameters.join(", "), self.block(function.body.as_node(), indent))
+            }
+            NodeType::CallExpression => {
+                let call = any.downcast_ref::<ast::CallExpression>().unwrap();
+                let function = self.operand(call.function.as_node(), Precedence::CALL, false, indent, column);
+                let arguments: Vec<&dyn Node> = call.arguments.iter().map(|a| a.as_node()).collect();
+                let column = advance(column, &function);
+                format!("{}{}", function, self.arguments(&arguments, indent, column))
+            }
+            NodeType::IndexExpression => {
+                let index = any.downcast_ref::<ast::IndexExpression>().unwrap();
+                let left = self.operand(index.left.as_node(), Precedence::CALL, false, indent, column);
+                let column = advance(column, &left) + 1;
+                format!("{}[{}]", left, self.expression(index.index.as_node(), indent, column))
+            }
+            NodeType::ArrayLiteral => {
+                let array = any.downcast_ref::<ast::ArrayLiteral>().unwrap();
+                let mut out = String::from("[");
+                for (i, element) in array.elements.iter().enumerate() {
+                    if i > 0 {
+                        out.push_str(", ");
+                    }
+                    let column = advance(column, &out);
+                    out.push_str(&self.expression(element.as_node(), indent, column));
+                }
+                out.push(']');
+                out
+            }
+            NodeType::HashLiteral => {
+                let hash = any.downcast_ref::<ast::HashLiteral>().unwrap();
+                let mut out = String::from("{");
+                for (i, (key, value)) in hash.pairs.iter().enumerate() {
+                    if i > 0 {
+                        out.push_str(", ");
+                    }
+                    let column = advance(column, &out);
+                    out.push_str(&self.expression(key.as_node(), indent, column));
+                    out.push_str(": ");
+                    let column = advance(column, &out);
+                    out.push_str(&self.expression(value.as_node(), indent, column));
+                }
+                out.push('}');
+                out
+            }
+            _ => unreachable!("not an expression: {:?}", node.node_type()),
+        }
+    }
+
+    /// Formats a `match` arm on a line indented by `indent` levels. An
+    /// expression body ends with a `,`; a block body ends the arm itself.
+    fn match_arm(&mut self, arm: &ast::MatchArm, indent: usize) -> String {
+        let column = indent * INDENT.len();
+        let mut out = self.expression(arm.pattern.as_node(), indent, column);
+        if let Some(guard) = &arm.guard {
+            out.push_str(" if ");
+            let column = advance(column, &out);
+            out.push_str(&self.expression(guard.as_node(), indent, column));
+        }
+        out.push_str(" => ");
+        if arm.braced() {
+            out.push_str(&self.block(arm.body.as_node(), indent));
+            return out;
+        }
+        let block = arm.body.as_any().downcast_ref::<ast::BlockStatement>().unwrap();
+        let statement = block.statements[0].as_any().downcast_ref::<ast::ExpressionStatement>().unwrap();
+        let column = advance(column, &out);
+        let body = match starts_with_hash_literal(statement.expression.as_node()) {
+            true => format!("({})", self.expression(statement.expression.as_node(), indent, column + 1)),
+            false => self.expression(statement.expression.as_node(), indent, column),
+        };
+        out.push_str(&body);
+        out.push(',');
+        out
+    }
+
+    /// Formats call arguments on one line if they fit, or else one per line
+    /// indented below the call.
+    fn arguments(&mut self, arguments: &[&dyn Node], indent: usize, column: usize) -> String {
+        let before = self.trivia.clone();
+        let nested = std::mem::replace(&mut self.flat, true);
+        let mut flat = String::from("(");
+        for (i, argument) in arguments.iter().enumerate() {
+            if i > 0 {
+                flat.push_str(", ");
+            }
+            let column = advance(column, &flat);
+            flat.push_str(&self.expression(*argument, indent, column));
+        }
+        flat.push(')');
+        self.flat = nested;
+        let first_line = flat.split('\n').next().unwrap_or("");
+        if nested || arguments.is_empty() || column + first_line.chars().count() <= MAX_WIDTH {
+            return flat;
+        }
+        self.trivia = before;
+        let prefix = INDENT.repeat(indent + 1);
+        let mut out = String::from("(\n");
+        for (i, argument) in arguments.iter().enumerate() {
+            out.push_str(&prefix);
+            out.push_str(&self.expression(*argument, indent + 1, prefix.len()));
+            if i + 1 < arguments.len() {
+                out.push(',');
+            }
+            out.push('\n');
+        }
+        out.push_str(&INDENT.repeat(indent));
+        out.push(')');
+        out
+    }
+
+    /// Formats the operand of an operator binding with `precedence`, in
+    /// parentheses only if the parser would otherwise group it differently.
+    /// Infix operators associate to the left, so a right operand of equal
+    /// precedence needs them too.
+    fn operand(&mut self, node: &dyn Node, precedence: Precedence, right: bool, indent: usize, column: usize) -> String {
+        let own = self.precedence(node);
+        if own < precedence || (right && own == precedence) {
+            format!("({})", self.expression(node, indent, column + 1))
+        } else {
+            self.expression(node, indent, column)
+        }
+    }
+
+    /// How tightly the expression binds: the precedence of its operator, or
+    /// the highest for literals and other expressions that cannot be split.
+    fn precedence(&self, node: &dyn Node) -> Precedence {
+        match node.node_type() {
+            NodeType::InfixExpression => {
+                let infix = node.as_any().downcast_ref::<ast::InfixExpression>().unwrap();
+                self.precedences.get(&infix.token.type_).copied().unwrap_or(Precedence::LOWEST)
+            }
+            NodeType::AssignExpression => Precedence::ASSIGN,
+            NodeType::PrefixExpression => Precedence::PREFIX,
+            NodeType::CallExpression => Precedence::CALL,
+            _ => Precedence::INDEX,
+        }
+    }
+}
+
+fn is_block_expression(statement: &dyn Node) -> bool {
+    let expression = statement.as_any().downcast_ref::<ast::ExpressionStatement>().unwrap();
+    matches!(expression.expression.node_type(), NodeType::IfExpression | NodeType::MatchExpression)
+}
+
+/// Whether an expression statement would begin with a `{` that the parser
+/// could take for a block: a hash literal whose first key is not a single
+/// token.
+fn starts_with_hash_literal(node: &dyn Node) -> bool {
+    let any = node.as_any();
+    match node.node_type() {
+        NodeType::HashLiteral => match any.downcast_ref::<ast::HashLiteral>().unwrap().pairs.first() {
+            Some((key, _)) => !matches!(
+                key.node_type(),
+                NodeType::Identifier | NodeType::IntegerLiteral | NodeType::FloatLiteral | NodeType::Boolean
+                | NodeType::StringLiteral | NodeType::InterpolatedString
+            ),
+            None => false,
+        },
+        NodeType::InfixExpression => starts_with_hash_literal(any.downcast_ref::<ast::InfixExpression>().unwrap().left.as_node()),
+        NodeType::CallExpression => starts_with_hash_literal(any.downcast_ref::<ast::CallExpression>().unwrap().function.as_node()),
+        NodeType::IndexExpression => starts_with_hash_literal(any.downcast_ref::<ast::IndexExpression>().unwrap().left.as_node()),
+        _ => false,
+    }
+}
+
+/// The column after writing `text` starting at `column`.
+fn advance(column: usize, text: &str) -> usize {
+    match text.rfind('\n') {
+        Some(newline) => text[newline + 1..].chars().count(),
+        None => column + text.chars().count(),
+    }
+}
+
+/// Writes `value` as the inside of a `"..."` string literal.
+fn escape(value: &str) -> String {
+    let mut out = String::new();
+    let mut chars = value.chars().peekable();
+    while let Some(ch) = chars.next() {
+        match ch {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\t' => out.push_str("\\t"),
+            '\r' => out.push_str("\\r"),
+            '\0' => out.push_str("\\0"),
+            '$' if chars.peek() == Some(&'{') => out.push_str("\\$"),
+            ch => out.push(ch),
+        }
+    }
+    out
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::test_corpus;
+
+    fn format(source: &str) -> String {
+        match format_source(source) {
+            Ok(formatted) => formatted,
+            Err(errors) => panic!("parser errors in {:?}: {:?}", source, errors),
+        }
+    }
+
+    fn ast_string(source: &str) -> String {
+        let mut parser = Parser::new(Lexer::new(source.to_string()));
+        parser.parse_program().string()
+    }
+
+    #[test]
+    fn test_minimal_parentheses() {
+        let tests = vec![
+            ("(5 + (x * 2))", "5 + x * 2;\n"),
+            ("(a + b) * c", "(a + b) * c;\n"),
+            ("a - (b - c); (a - b) - c", "a - (b - c);\na - b - c;\n"),
+            ("-(a + b); -(-a); !(a == b)", "-(a + b);\n--a;\n!(a == b);\n"),
+            ("(a || b) && c; a || (b && c)", "(a || b) && c;\na || b && c;\n"),
+            ("(f)(1)[0]; (a + b)(1); (-a)[0]", "f(1)[0];\n(a + b)(1);\n(-a)[0];\n"),
+            ("a % (b * c) <= (d >= e)", "a % (b * c) <= (d >= e);\n"),
+            ("({a + 1: 2})[3]; {\"k\": 1}[\"k\"]", "({a + 1: 2}[3]);\n{\"k\": 1}[\"k\"];\n"),
+        ];
+        for (input, expected) in tests {
+            assert_eq!(format(input), expected, "input: {}", input);
+        }
+    }
+
+    #[test]
+    fn test_canonical_layout() {
+        let input = "let   add=fn(a,b){a+b};if(add(1,2)>2){puts(\"yes\");}else{}\nlet s = r\"raw\" + \"\"\"\nx\"\"\" + \"t ${ 1+2 }\";";
+        let expected = "\
+let add = fn(a, b) {
+    a + b
+};
+if (add(1, 2) > 2) {
+    puts(\"yes\")
+} else {}
+let s = r\"raw\" + \"\"\"
+x\"\"\" + \"t ${1 + 2}\";
+";
+        assert_eq!(format(input), expected);
+
+        // An `if` statement keeps its `;` only where the next line would
+        // otherwise be parsed as its operand.
+        let input = "if (a) { 1 }; -1; if (b) { 2 }; f(); if (c) { 3 }; (-d)[0]";
+        assert_eq!(format(input), "if (a) {\n    1\n};\n-1;\nif (b) {\n    2\n}\nf();\nif (c) {\n    3\n};\n(-d)[0];\n");
+
+        let input = "for(i in 0 ..n+1){ for (c in (x) ..=(-1)) { continue } }";
+        assert_eq!(format(input), "for (i in 0..n + 1) {\n    for (c in x..=-1) {\n        continue;\n    }\n}\n");
+
+        let input = "const   limit=10;x+=1;xs[i]=y=(1+2);(a=b)+1";
+        assert_eq!(format(input), "const limit = 10;\nx += 1;\nxs[i] = y = 1 + 2;\n(a = b) + 1;\n");
+
+        let input = "let y=match(x){[a,..rest] if a>0=>rest,{\"k\":-1}=>{puts(1);2},_=>{\"e\":1}};match(y){};(-z)[0]";
+        let expected = "\
+let y = match (x) {
+    [a, ..rest] if a > 0 => rest,
+    {\"k\": -1} => {
+        puts(1);
+        2
+    }
+    _ => {\"e\": 1},
+};
+match (y) {};
+(-z)[0];
+";
+        assert_eq!(format(input), expected);
+    }
+
+    #[test]
+    fn test_keeps_comments_and_blank_lines() {
+        let input = "\
+// Header.
+
+/// Doubles.
+let double = fn(x) { // why
+    // inside
+    x * 2  /* note */ // value
+
+
+};   // after
+let y = double(/* arg */ 2);
+// footer
+";
+        let expected = "\
+// Header.
+
+/// Doubles.
+let double = fn(x) { // why
+    // inside
+    x * 2 /* note */ // value
+}; // after
+let y = double(2); /* arg */
+// footer
+";
+        assert_eq!(format(input), expected);
+    }
+
+    #[test]
+    fn test_wraps_long_call_arguments() {
+        let input = "let result = combine(firstArgumentWithLongName, secondArgumentWithLongName, [1, 2, 3, 4], inner(a, b));";
+        let expected = "\
+let result = combine(
+    firstArgumentWithLongName,
+    secondArgumentWithLongName,
+    [1, 2, 3, 4],
+    inner(a, b)
+);
+";
+        assert_eq!(format(input), expected);
+
+        let input = "map(xs, fn(x) { combine(firstArgumentWithLongName, secondArgumentWithLongName, thirdArgumentWithLongName, fourth) })";
+        let expected = "\
+map(xs, fn(x) {
+    combine(
+        firstArgumentWithLongName,
+        secondArgumentWithLongName,
+        thirdArgumentWithLongName,
+        fourth
+    )
+});
+";
+        assert_eq!(format(input), expected);
+
+        let input = format!("let f = fn() {{ outer(short, nested({}, {}), last) }};", "a".repeat(50), "b".repeat(40));
+        let expected = format!("\
+let f = fn() {{
+    outer(
+        short,
+        nested(
+            {},
+            {}
+        ),
+        last
+    )
+}};
+", "a".repeat(50), "b".repeat(40));
+        assert_eq!(format(&input), expected);
+        assert!(format(&input).lines().all(|line| line.chars().count() <= MAX_WIDTH));
+    }
+
+    #[test]
+    fn test_format_program_without_source() {
+        let mut parser = Parser::new(Lexer::new("let s = r\"a\\n\" + \"${x}\\${\";".to_string()));
+        let program = parser.parse_program();
+        assert_eq!(format_program(&program), "let s = \"a\\\\n\" + \"${x}\\${\";\n");
+    }
+
+    #[test]
+    fn test_idempotent_over_corpus() {
+        let corpus = [
+            "let add = fn(a,b){a+b}; add(1,2)",
+            "// c\nlet x = 1; /* d */\n\n\n// e\nx",
+            "if (a) { if (b) { c } else { d } } else { e }",
+            "let h = {\"a\": [1, {\"b\": fn(x) { x }}], 2: true}; h[\"a\"][1][\"b\"](3)",
+            "puts(\"${a}-${b + c}\", r#\"x\"#, \"\"\"\n  y\n\"\"\")",
+            "{ let a = 1; { a } }",
+            "let f = fn() { return if (x) { 1 } else { 2 }; };",
+            "long(aaaaaaaaaaaaaaaaaaaaaaaaa, bbbbbbbbbbbbbbbbbbbbbbbbbbbb, cccccccccccccccccccccccc, fn(x) { x + dddddddddd })",
+        ];
+        for source in corpus.iter().chain(test_corpus::PROGRAMS) {
+            let once = format(source);
+            assert_eq!(format(&once), once, "not idempotent for {:?}", source);
+            assert_eq!(ast_string(&once), ast_string(source), "meaning changed for {:?}", source);
+        }
+    }
+}
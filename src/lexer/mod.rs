@@ -1,119 +1,712 @@
-use crate::token::{Token, TokenType};
-use std::char;
+use std::collections::HashMap;
+use std::rc::Rc;
+
+use crate::diagnostic::Diagnostic;
+use crate::token::{Span, Token, TokenType, Trivia, TriviaKind};
+
+/// Splits source text into tokens.
+///
+/// The lexer walks the input one Unicode scalar value at a time. Span offsets
+/// are byte offsets into the input, while columns count characters, so a
+/// column matches what an editor shows for the line.
 #[derive(Debug, Clone)]
 pub struct Lexer {
-    input: String,
-    position: usize, // current position in input (points to current char)
-    read_position: usize, // current reading position in input (after current char)
-    ch: u8, // current char under examination
+    /// The whole source, shared with the lexers of the `${...}` inside it.
+    input: Rc<str>,
+    /// Byte offset where this lexer's part of `input` ends.
+    end: usize,
+    position: usize, // byte offset of the current char
+    read_position: usize, // byte offset just after the current char
+    ch: char, // current char under examination, '\0' at the end of input
+    line: usize, // line of the current char, starting at 1
+    column: usize, // column of the current char in chars, starting at 1
+    /// Problems found in the input so far, such as unterminated strings. The
+    /// lexer still returns a best-effort token for them so parsing can go on.
+    pub errors: Vec<Diagnostic>,
+    /// `///` doc comments, keyed by the byte offset of the token they precede.
+    doc_comments: HashMap<usize, String>,
+    /// The parts of interpolated strings, keyed by the byte offset of their
+    /// `TEMPLATE` token.
+    interpolations: HashMap<usize, Vec<StringPart>>,
+    /// Trivia skipped since the last `take_trivia`, when keeping it.
+    trivia: Option<Vec<Trivia>>,
+    /// How many `${...}` this lexer is nested in.
+    interpolation_depth: usize,
+    /// Set once the iterator has yielded `EOF`.
+    finished: bool,
+}
+
+/// Deepest nesting of `${...}` inside strings inside `${...}`, so that
+/// pathological input cannot overflow the stack.
+const MAX_INTERPOLATION_DEPTH: usize = 32;
+
+/// A piece of an interpolated string: literal text, or a lexer over the
+/// expression inside a `${...}`.
+#[derive(Debug, Clone)]
+pub enum StringPart {
+    Text(String),
+    Code(Lexer),
 }
 impl Lexer {
     pub fn new (input: String) -> Lexer {
         let mut l = Lexer {
-            input,
+            end: input.len(),
+            input: input.into(),
             position: 0,
             read_position: 0,
-            ch: 0,
+            ch: '\0',
+            line: 1,
+            column: 0,
+            errors: Vec::new(),
+            doc_comments: HashMap::new(),
+            interpolations: HashMap::new(),
+            trivia: None,
+            interpolation_depth: 0,
+            finished: false,
         };
         l.read_char();
         l
     }
+
+    /// A lexer that keeps the whitespace and comments it skips, for
+    /// `take_trivia` to hand out.
+    pub fn with_trivia(input: String) -> Lexer {
+        let mut l = Lexer::new(input);
+        l.trivia = Some(Vec::new());
+        l
+    }
+
+    /// Removes and returns the trivia skipped so far, in source order. This
+    /// is always empty unless the lexer was made with `with_trivia`.
+    pub fn take_trivia(&mut self) -> Vec<Trivia> {
+        self.trivia.as_mut().map(std::mem::take).unwrap_or_default()
+    }
+
+    /// A lexer over `input[start..end]` that starts at the given line and
+    /// column, so its spans point into the whole of `input`.
+    fn slice(input: &Rc<str>, start: usize, end: usize, line: usize, column: usize) -> Lexer {
+        let mut l = Lexer::new(String::new());
+        l.input = input.clone();
+        l.end = end;
+        l.position = start;
+        l.read_position = start;
+        l.ch = '\0';
+        l.line = line;
+        l.column = column - 1;
+        l.read_char();
+        l
+    }
     pub fn read_char(&mut self) {
-        if self.read_position >= self.input.len() {
-            self.ch = 0;
+        if self.ch == '\n' {
+            self.line += 1;
+            self.column = 1;
         } else {
-            self.ch = self.input.as_bytes()[self.read_position];
+            self.column += 1;
         }
         self.position = self.read_position;
-        self.read_position += 1;
-    }
-    pub fn peak_char(&self) -> u8 {
-        if self.read_position >= self.input.len() {
-            0
-        } else {
-            self.input.as_bytes()[self.read_position]
+        match self.input[..self.end].get(self.read_position..).and_then(|rest| rest.chars().next()) {
+            Some(ch) => {
+                self.ch = ch;
+                self.read_position += ch.len_utf8();
+            }
+            None => {
+                self.ch = '\0';
+                self.read_position += 1;
+            }
         }
     }
+    pub fn peak_char(&self) -> char {
+        self.input[..self.end].get(self.read_position..).and_then(|rest| rest.chars().next()).unwrap_or('\0')
+    }
     pub fn next_token(&mut self) -> Token {
-        self.skip_whitespace();
+        self.skip_trivia();
+        let start = self.offset();
+        let line = self.line;
+        let column = self.column;
+        let mut tok = self.read_token();
+        tok.span = Span::new(start, self.offset(), line, column);
+        tok
+    }
+
+    /// Byte offset of the current char, clamped to the end of the input.
+    fn offset(&self) -> usize {
+        self.position.min(self.end)
+    }
+
+    fn read_token(&mut self) -> Token {
         let tok = match self.ch {
-            b'=' => {
-                if self.peak_char() == b'=' {
+            '=' => {
+                if self.peak_char() == '=' {
                     self.read_char();
                     Token::new(TokenType::EQ, "==")
+                } else if self.peak_char() == '>' {
+                    self.read_char();
+                    Token::new(TokenType::FAT_ARROW, "=>")
                 } else {
-                    Token::new(TokenType::ASSIGN, char::from_u32(self.ch as u32).unwrap().to_string().as_str())
+                    Token::new(TokenType::ASSIGN, self.ch.to_string().as_str())
                 }
             }
-            b';' => Token::new(TokenType::SEMICOLON, char::from_u32(self.ch as u32).unwrap().to_string().as_str()),
-            b'(' => Token::new(TokenType::LPAREN, char::from_u32(self.ch as u32).unwrap().to_string().as_str()),
-            b')' => Token::new(TokenType::RPAREN, char::from_u32(self.ch as u32).unwrap().to_string().as_str()),
-            b',' => Token::new(TokenType::COMMA, char::from_u32(self.ch as u32).unwrap().to_string().as_str()),
-            b'+' => Token::new(TokenType::PLUS, char::from_u32(self.ch as u32).unwrap().to_string().as_str()),
-            b'{' => Token::new(TokenType::LBRACE, char::from_u32(self.ch as u32).unwrap().to_string().as_str()),
-            b'}' => Token::new(TokenType::RBRACE, char::from_u32(self.ch as u32).unwrap().to_string().as_str()),
-            b'-' => Token::new(TokenType::MINUS, char::from_u32(self.ch as u32).unwrap().to_string().as_str()),
-            b'!' => {
-                if self.peak_char() == b'=' {
+            ';' => Token::new(TokenType::SEMICOLON, self.ch.to_string().as_str()),
+            ':' => Token::new(TokenType::COLON, self.ch.to_string().as_str()),
+            '(' => Token::new(TokenType::LPAREN, self.ch.to_string().as_str()),
+            ')' => Token::new(TokenType::RPAREN, self.ch.to_string().as_str()),
+            ',' => Token::new(TokenType::COMMA, self.ch.to_string().as_str()),
+            '+' if self.peak_char() == '=' => {
+                self.read_char();
+                Token::new(TokenType::PLUS_ASSIGN, "+=")
+            }
+            '+' => Token::new(TokenType::PLUS, self.ch.to_string().as_str()),
+            '{' => Token::new(TokenType::LBRACE, self.ch.to_string().as_str()),
+            '}' => Token::new(TokenType::RBRACE, self.ch.to_string().as_str()),
+            '[' => Token::new(TokenType::LBRACKET, self.ch.to_string().as_str()),
+            ']' => Token::new(TokenType::RBRACKET, self.ch.to_string().as_str()),
+            '-' if self.peak_char() == '=' => {
+                self.read_char();
+                Token::new(TokenType::MINUS_ASSIGN, "-=")
+            }
+            '-' => Token::new(TokenType::MINUS, self.ch.to_string().as_str()),
+            '!' => {
+                if self.peak_char() == '=' {
                     self.read_char();
                     Token::new(TokenType::NOT_EQ, "!=")
                 } else {
-                    Token::new(TokenType::BANG, char::from_u32(self.ch as u32).unwrap().to_string().as_str())
+                    Token::new(TokenType::BANG, self.ch.to_string().as_str())
                 }
             }
-            b'*' => Token::new(TokenType::ASTERISK, char::from_u32(self.ch as u32).unwrap().to_string().as_str()),
-            b'/' => Token::new(TokenType::SLASH, char::from_u32(self.ch as u32).unwrap().to_string().as_str()),
-            b'<' => Token::new(TokenType::LT, char::from_u32(self.ch as u32).unwrap().to_string().as_str()),
-            b'>' => Token::new(TokenType::GT, char::from_u32(self.ch as u32).unwrap().to_string().as_str()),
-            0 => Token::new(TokenType::EOF, ""),
-            b'a'..=b'z' | b'A'..=b'Z' | b'_' => {
+            '*' if self.peak_char() == '=' => {
+                self.read_char();
+                Token::new(TokenType::ASTERISK_ASSIGN, "*=")
+            }
+            '*' => Token::new(TokenType::ASTERISK, self.ch.to_string().as_str()),
+            '/' if self.peak_char() == '=' => {
+                self.read_char();
+                Token::new(TokenType::SLASH_ASSIGN, "/=")
+            }
+            '/' => Token::new(TokenType::SLASH, self.ch.to_string().as_str()),
+            '%' => Token::new(TokenType::PERCENT, self.ch.to_string().as_str()),
+            '<' => {
+                if self.peak_char() == '=' {
+                    self.read_char();
+                    Token::new(TokenType::LT_EQ, "<=")
+                } else {
+                    Token::new(TokenType::LT, self.ch.to_string().as_str())
+                }
+            }
+            '>' => {
+                if self.peak_char() == '=' {
+                    self.read_char();
+                    Token::new(TokenType::GT_EQ, ">=")
+                } else {
+                    Token::new(TokenType::GT, self.ch.to_string().as_str())
+                }
+            }
+            '&' if self.peak_char() == '&' => {
+                self.read_char();
+                Token::new(TokenType::AND, "&&")
+            }
+            '|' if self.peak_char() == '|' => {
+                self.read_char();
+                Token::new(TokenType::OR, "||")
+            }
+            '\0' => Token::new(TokenType::EOF, ""),
+            'r' if self.raw_string_follows() => return self.read_raw_string(),
+            ch if is_identifier_start(ch) => {
                 let mut ident = String::new();
-                while self.ch.is_ascii_alphabetic() || self.ch == b'_' {
-                    ident.push(char::from_u32(self.ch as u32).unwrap());
+                while is_identifier_continue(self.ch) {
+                    ident.push(self.ch);
                     self.read_char();
                 }
                 match ident.as_str() {
                     "fn" => return Token::new(TokenType::FUNCTION, ident.as_str()),
                     "let" => return Token::new(TokenType::LET, ident.as_str()),
+                    "const" => return Token::new(TokenType::CONST, ident.as_str()),
                     "true" => return Token::new(TokenType::TRUE, ident.as_str()),
                     "false" => return Token::new(TokenType::FALSE, ident.as_str()),
                     "if" => return Token::new(TokenType::IF, ident.as_str()),
                     "else" => return Token::new(TokenType::ELSE, ident.as_str()),
                     "return" => return Token::new(TokenType::RETURN, ident.as_str()),
+                    "while" => return Token::new(TokenType::WHILE, ident.as_str()),
+                    "break" => return Token::new(TokenType::BREAK, ident.as_str()),
+                    "continue" => return Token::new(TokenType::CONTINUE, ident.as_str()),
+                    "for" => return Token::new(TokenType::FOR, ident.as_str()),
+                    "in" => return Token::new(TokenType::IN, ident.as_str()),
+                    "match" => return Token::new(TokenType::MATCH, ident.as_str()),
                     _ => return Token::new(TokenType::IDENT, ident.as_str()),
                     
                 }                   
                 
             },
-            b'0'..=b'9' => {
-                let mut number = String::new();
-                while self.ch.is_ascii_digit() {
-                    number.push(char::from_u32(self.ch as u32).unwrap());
+            '.' if self.peak_char() == '.' => {
+                self.read_char();
+                if self.peak_char() == '=' {
                     self.read_char();
+                    Token::new(TokenType::DOT_DOT_EQ, "..=")
+                } else {
+                    Token::new(TokenType::DOT_DOT, "..")
                 }
-                return Token::new(TokenType::INT, number.as_str());
-            },
-            b'"' => {
-                let mut string = String::new();
+            }
+            '0'..='9' => return self.read_number(),
+            '.' if self.peak_char().is_ascii_digit() => return self.read_number(),
+            '"' => return self.read_string(),
+            _ => Token::new(TokenType::ILLEGAL, self.ch.to_string().as_str()),
+        };
+        self.read_char();
+        tok
+    }
+    /// Reads an integer or float literal: `42`, `1_000`, `0xFF`, `0o17`,
+    /// `0b1010`, `3.14`, `.5`, `1e-9`, `2.5E+3`. A `.` only continues the
+    /// number when a digit follows it, and `e` only when a digit or a signed
+    /// digit does. Digits may be separated by `_`.
+    ///
+    /// A literal running straight into letters (`12abc`) or with no or
+    /// invalid digits after a prefix (`0x`, `0b12`) is reported, and the
+    /// valid part is returned so parsing can continue.
+    fn read_number(&mut self) -> Token {
+        let start = self.position;
+        let (line, column) = (self.line, self.column);
+        if let Some(radix) = self.radix_prefix() {
+            return self.read_radix_number(radix, start, line, column);
+        }
+        let mut is_float = false;
+        self.skip_digits();
+        if self.ch == '.' && self.peak_char().is_ascii_digit() {
+            is_float = true;
+            self.read_char();
+            self.skip_digits();
+        }
+        if (self.ch == 'e' || self.ch == 'E') && self.exponent_follows() {
+            is_float = true;
+            self.read_char();
+            if self.ch == '+' || self.ch == '-' {
                 self.read_char();
-                while self.ch != b'"' && self.ch != 0 {
-                    string.push(char::from_u32(self.ch as u32).unwrap());
-                    self.read_char();
+            }
+            self.skip_digits();
+        }
+        let literal = self.input[start..self.offset()].to_string();
+        if is_identifier_continue(self.ch) {
+            let suffix_start = self.position;
+            while is_identifier_continue(self.ch) {
+                self.read_char();
+            }
+            let suffix = &self.input[suffix_start..self.offset()];
+            self.errors.push(
+                Diagnostic::error("E0009", &format!("invalid suffix `{}` on number literal `{}`", suffix, literal))
+                    .with_span(Span::new(start, self.offset(), line, column))
+                    .with_help("separate the number from the name that follows it with a space or an operator"),
+            );
+        }
+        match is_float {
+            true => Token::new(TokenType::FLOAT, &literal),
+            false => Token::new(TokenType::INT, &literal),
+        }
+    }
+
+    fn skip_digits(&mut self) {
+        while self.ch.is_ascii_digit() || self.ch == '_' {
+            self.read_char();
+        }
+    }
+
+    /// The radix named by a `0x`, `0o` or `0b` prefix at the current char.
+    fn radix_prefix(&self) -> Option<u32> {
+        match self.rest().get(..2) {
+            Some("0x") => Some(16),
+            Some("0o") => Some(8),
+            Some("0b") => Some(2),
+            _ => None,
+        }
+    }
+
+    fn read_radix_number(&mut self, radix: u32, start: usize, line: usize, column: usize) -> Token {
+        let name = match radix {
+            16 => "hexadecimal",
+            8 => "octal",
+            _ => "binary",
+        };
+        self.skip(2);
+        let mut valid_end = None;
+        let mut has_digits = false;
+        while is_identifier_continue(self.ch) {
+            if self.ch.is_digit(radix) {
+                has_digits = true;
+            } else if self.ch != '_' && valid_end.is_none() {
+                valid_end = Some(self.position);
+                let message = format!("invalid digit `{}` in {} literal", self.ch, name);
+                self.errors.push(
+                    Diagnostic::error("E0009", &message).with_span(Span::new(self.position, self.position + self.ch.len_utf8(), self.line, self.column)),
+                );
+            }
+            self.read_char();
+        }
+        let end = valid_end.unwrap_or(self.offset());
+        if !has_digits {
+            let prefix = &self.input[start..start + 2];
+            self.errors.push(
+                Diagnostic::error("E0009", &format!("missing digits after `{}`", prefix))
+                    .with_span(Span::new(start, self.offset(), line, column))
+                    .with_help(&format!("write at least one {} digit, e.g. `{}1`", name, prefix)),
+            );
+            return Token::new(TokenType::INT, "0");
+        }
+        Token::new(TokenType::INT, &self.input[start..end])
+    }
+
+    fn exponent_follows(&self) -> bool {
+        let bytes = self.input[..self.end].as_bytes();
+        match bytes.get(self.read_position) {
+            Some(b'+') | Some(b'-') => bytes.get(self.read_position + 1).is_some_and(|b| b.is_ascii_digit()),
+            Some(b) => b.is_ascii_digit(),
+            None => false,
+        }
+    }
+
+    fn at_end(&self) -> bool {
+        self.position >= self.end
+    }
+
+    fn rest(&self) -> &str {
+        self.input[..self.end].get(self.position..).unwrap_or("")
+    }
+
+    /// Reads a `"..."` or `"""..."""` string, processing escapes. Triple-quoted
+    /// strings drop a line break directly after the opening quotes, so their
+    /// text can start on the next line. One containing `${...}` becomes a
+    /// `TEMPLATE` token whose literal is its source text; its parts are kept
+    /// for `take_interpolation`.
+    fn read_string(&mut self) -> Token {
+        let (start, line, column) = (self.position, self.line, self.column);
+        let delimiter = if self.rest().starts_with("\"\"\"") { "\"\"\"" } else { "\"" };
+        self.skip(delimiter.len());
+        if delimiter.len() == 3 && self.rest().starts_with('\n') {
+            self.read_char();
+        } else if delimiter.len() == 3 && self.rest().starts_with("\r\n") {
+            self.skip(2);
+        }
+        let mut value = String::new();
+        let mut parts = Vec::new();
+        loop {
+            if self.at_end() {
+                self.unterminated(start, delimiter, line, column);
+                break;
+            }
+            if self.rest().starts_with(delimiter) {
+                self.skip(delimiter.len());
+                break;
+            }
+            if self.ch == '\\' {
+                self.read_escape(&mut value);
+                continue;
+            }
+            if self.rest().starts_with("${") {
+                parts.push(StringPart::Text(std::mem::take(&mut value)));
+                match self.read_interpolation() {
+                    Some(code) => parts.push(StringPart::Code(code)),
+                    None if self.at_end() => break,
+                    None => {}
+                }
+                continue;
+            }
+            value.push(self.ch);
+            self.read_char();
+        }
+        if parts.is_empty() {
+            return Token::new(TokenType::STRING, &value);
+        }
+        parts.push(StringPart::Text(value));
+        self.interpolations.insert(start, parts);
+        Token::new(TokenType::TEMPLATE, &self.input[start..self.offset()])
+    }
+
+    /// Reads a `${...}` and returns a lexer over the expression inside it.
+    /// The closing `}` is found by lexing ahead from here with a second lexer
+    /// over the same input, so braces and strings within the expression are
+    /// skipped over. Returns `None` after reporting an
+    /// unterminated or too deeply nested `${`.
+    fn read_interpolation(&mut self) -> Option<Lexer> {
+        let (start, line, column) = (self.position, self.line, self.column);
+        self.skip(2);
+        if self.interpolation_depth >= MAX_INTERPOLATION_DEPTH {
+            let message = format!("string interpolation nested too deeply (more than {} levels)", MAX_INTERPOLATION_DEPTH);
+            self.errors.push(Diagnostic::error("E0010", &message).with_span(Span::new(start, start + 2, line, column)));
+            // Skip to the matching `}` by counting braces alone, without lexing.
+            let mut depth = 0;
+            while !(self.at_end() || self.ch == '}' && depth == 0) {
+                match self.ch {
+                    '{' => depth += 1,
+                    '}' => depth -= 1,
+                    _ => {}
                 }
                 self.read_char();
-                return Token::new(TokenType::STRING, string.as_str());
-            },
-            _ => Token::new(TokenType::ILLEGAL, char::from_u32(self.ch as u32).unwrap().to_string().as_str()),
+            }
+            self.read_char();
+            return None;
+        }
+        let (code_start, code_line, code_column) = (self.position, self.line, self.column);
+        let mut scan = Lexer::slice(&self.input, code_start, self.end, code_line, code_column);
+        scan.interpolation_depth = self.interpolation_depth + 1;
+        let mut depth = 0;
+        let code_end = loop {
+            let tok = scan.next_token();
+            match tok.type_ {
+                TokenType::LBRACE => depth += 1,
+                TokenType::RBRACE if depth == 0 => break tok.span.start,
+                TokenType::RBRACE => depth -= 1,
+                TokenType::EOF => {
+                    self.errors.push(
+                        Diagnostic::error("E0010", "unterminated `${` in string literal")
+                            .with_span(Span::new(start, start + 2, line, column))
+                            .with_help("add a closing `}` after the expression"),
+                    );
+                    while !self.at_end() {
+                        self.read_char();
+                    }
+                    return None;
+                }
+                _ => {}
+            }
         };
+        self.position = scan.position;
+        self.read_position = scan.read_position;
+        self.ch = scan.ch;
+        self.line = scan.line;
+        self.column = scan.column;
+        let mut code = Lexer::slice(&self.input, code_start, code_end, code_line, code_column);
+        code.interpolation_depth = self.interpolation_depth + 1;
+        Some(code)
+    }
+
+    /// Reads the escape sequence at the current `\\`, pushing what it stands
+    /// for onto `value`. An invalid escape is reported and kept verbatim.
+    fn read_escape(&mut self, value: &mut String) {
+        let (start, line, column) = (self.position, self.line, self.column);
         self.read_char();
-        tok
+        let kind = self.ch;
+        let escaped = match kind {
+            'n' => Some('\n'),
+            't' => Some('\t'),
+            'r' => Some('\r'),
+            '0' => Some('\0'),
+            '\\' => Some('\\'),
+            '"' => Some('"'),
+            '$' => Some('$'),
+            'u' => self.read_unicode_escape(),
+            _ if self.at_end() => return,
+            _ => None,
+        };
+        let end = self.read_position.min(self.end);
+        let text = &self.input[start..end];
+        match escaped {
+            Some(ch) => value.push(ch),
+            None => {
+                let help = if kind == 'u' {
+                    "write a code point as `\\u{1F600}`, with 1 to 6 hex digits"
+                } else {
+                    "valid escapes are \\n \\t \\r \\0 \\\\ \\\" \\$ and \\u{...}"
+                };
+                self.errors.push(
+                    Diagnostic::error("E0007", &format!("invalid escape sequence `{}`", text))
+                        .with_span(Span::new(start, end, line, column))
+                        .with_help(help),
+                );
+                value.push_str(text);
+            }
+        }
+        self.read_char();
+    }
+
+    /// Reads the `{1F600}` part of a `\\u{1F600}` escape, leaving the current
+    /// char on the last char consumed.
+    fn read_unicode_escape(&mut self) -> Option<char> {
+        if self.peak_char() != '{' {
+            return None;
+        }
+        self.read_char();
+        let mut digits = String::new();
+        while self.peak_char().is_ascii_hexdigit() && digits.len() < 6 {
+            self.read_char();
+            digits.push(self.ch);
+        }
+        if self.peak_char() != '}' {
+            return None;
+        }
+        self.read_char();
+        u32::from_str_radix(&digits, 16).ok().and_then(char::from_u32)
+    }
+
+    /// Whether the current `r` starts a raw string: `r"` or `r#...#"`.
+    fn raw_string_follows(&self) -> bool {
+        self.rest()[1..].trim_start_matches('#').starts_with('"')
+    }
+
+    /// Reads `r"..."` or `r#"..."#`, where escapes are not processed. Each `#`
+    /// after the `r` must be matched after the closing quote, so `r#"a "quote""#`
+    /// may contain bare quotes.
+    fn read_raw_string(&mut self) -> Token {
+        let (start, line, column) = (self.position, self.line, self.column);
+        let hashes = self.rest()[1..].len() - self.rest()[1..].trim_start_matches('#').len();
+        let opening = self.rest()[..hashes + 2].to_string();
+        let closing = format!("\"{}", "#".repeat(hashes));
+        self.skip(hashes + 2);
+        let value = match self.rest().find(&closing) {
+            Some(len) => {
+                let value = self.rest()[..len].to_string();
+                self.skip(len + closing.len());
+                value
+            }
+            None => {
+                let value = self.rest().to_string();
+                self.skip(value.len());
+                self.unterminated(start, &opening, line, column);
+                value
+            }
+        };
+        Token::new(TokenType::STRING, &value)
+    }
+
+    /// Advances past the next `bytes` bytes of input, which must end on a char
+    /// boundary.
+    fn skip(&mut self, bytes: usize) {
+        let target = self.position + bytes;
+        while self.position < target && !self.at_end() {
+            self.read_char();
+        }
+    }
+
+    fn unterminated(&mut self, start: usize, opening: &str, line: usize, column: usize) {
+        let closing: String = opening.trim_start_matches('r').chars().rev().collect();
+        self.errors.push(
+            Diagnostic::error("E0006", "unterminated string literal")
+                .with_span(Span::new(start, start + opening.len(), line, column))
+                .with_help(&format!("add a closing `{}`", closing)),
+        );
+    }
+
+    /// Removes and returns the doc comment written directly above the token
+    /// starting at byte `offset`, with consecutive `///` lines joined by `\n`.
+    pub fn take_doc_comment(&mut self, offset: usize) -> Option<String> {
+        self.doc_comments.remove(&offset)
+    }
+
+    /// Removes and returns the parts of the `TEMPLATE` token starting at byte
+    /// `offset`.
+    pub fn take_interpolation(&mut self, offset: usize) -> Option<Vec<StringPart>> {
+        self.interpolations.remove(&offset)
+    }
+
+    /// Skips whitespace and comments up to the next token. `//` runs to the
+    /// end of the line and `/* */` may nest; `///` (but not `////`) is a doc
+    /// comment, remembered for the token that follows it.
+    fn skip_trivia(&mut self) {
+        let mut doc = Vec::new();
+        loop {
+            let (start, line, column) = (self.position, self.line, self.column);
+            let kind = if self.ch == '\n' || self.rest().starts_with("\r\n") {
+                self.skip(if self.ch == '\n' { 1 } else { 2 });
+                TriviaKind::Newline
+            } else if self.ch == ' ' || self.ch == '\t' || self.ch == '\r' {
+                while self.ch == ' ' || self.ch == '\t' || (self.ch == '\r' && self.peak_char() != '\n') {
+                    self.read_char();
+                }
+                TriviaKind::Whitespace
+            } else if self.rest().starts_with("//") {
+                let comment = self.rest().split('\n').next().unwrap_or("").trim_end_matches('\r');
+                let is_doc = comment.starts_with("///") && !comment.starts_with("////");
+                if is_doc {
+                    let text = &comment[3..];
+                    doc.push(text.strip_prefix(' ').unwrap_or(text).to_string());
+                }
+                self.skip(comment.len());
+                if is_doc { TriviaKind::DocComment } else { TriviaKind::LineComment }
+            } else if self.rest().starts_with("/*") {
+                self.skip_block_comment();
+                TriviaKind::BlockComment
+            } else {
+                break;
+            };
+            let end = self.offset();
+            if let Some(trivia) = self.trivia.as_mut() {
+                let text = self.input[start..end].to_string();
+                trivia.push(Trivia { kind, text, span: Span::new(start, end, line, column) });
+            }
+        }
+        if !doc.is_empty() {
+            self.doc_comments.insert(self.offset(), doc.join("\n"));
+        }
+    }
+
+    fn skip_block_comment(&mut self) {
+        let (start, line, column) = (self.position, self.line, self.column);
+        self.skip(2);
+        let mut depth = 1;
+        while depth > 0 {
+            if self.at_end() {
+                self.errors.push(
+                    Diagnostic::error("E0008", "unterminated block comment")
+                        .with_span(Span::new(start, start + 2, line, column))
+                        .with_help("close each `/*` with a matching `*/`"),
+                );
+                return;
+            }
+            if self.rest().starts_with("/*") {
+                depth += 1;
+                self.skip(2);
+            } else if self.rest().starts_with("*/") {
+                depth -= 1;
+                self.skip(2);
+            } else {
+                self.read_char();
+            }
+        }
     }
+
     pub fn skip_whitespace(&mut self) {
-        while self.ch == b' ' || self.ch == b'\t' || self.ch == b'\n' || self.ch == b'\r' {
+        while self.ch == ' ' || self.ch == '\t' || self.ch == '\n' || self.ch == '\r' {
             self.read_char();
         }
     }
-} 
+}
+
+/// The problems `tokenize` found in its input.
+#[derive(Debug, PartialEq, Clone)]
+pub struct LexError {
+    pub errors: Vec<Diagnostic>,
+}
+
+/// Lexes all of `input`, ending with the `EOF` token. Fails if the lexer
+/// reported any problem, such as an unterminated string; characters that
+/// start no token come back as `ILLEGAL` tokens for the parser to reject.
+pub fn tokenize(input: &str) -> Result<Vec<Token>, LexError> {
+    let mut lexer = Lexer::new(input.to_string());
+    let tokens: Vec<Token> = lexer.by_ref().collect();
+    match lexer.errors.is_empty() {
+        true => Ok(tokens),
+        false => Err(LexError { errors: lexer.errors }),
+    }
+}
+
+/// Yields every token up to and including `EOF`, then stops.
+impl Iterator for Lexer {
+    type Item = Token;
+
+    fn next(&mut self) -> Option<Token> {
+        if self.finished {
+            return None;
+        }
+        let tok = self.next_token();
+        self.finished = tok.type_ == TokenType::EOF;
+        Some(tok)
+    }
+}
+
+/// Identifiers follow UAX #31: they start with a character from XID_Start
+/// or `_`, and continue with characters from XID_Continue.
+fn is_identifier_start(ch: char) -> bool {
+    ch == '_' || unicode_ident::is_xid_start(ch)
+}
+
+fn is_identifier_continue(ch: char) -> bool {
+    unicode_ident::is_xid_continue(ch)
+}
 
 
 
@@ -149,6 +742,149 @@ mod test {
         }
     }
 
+    #[test]
+    fn test_comparison_and_logical_operators(){
+        let input = "a <= b >= c % d && e || f < g & h | i";
+        let tests = vec![
+            Token::new(TokenType::IDENT, "a"),
+            Token::new(TokenType::LT_EQ, "<="),
+            Token::new(TokenType::IDENT, "b"),
+            Token::new(TokenType::GT_EQ, ">="),
+            Token::new(TokenType::IDENT, "c"),
+            Token::new(TokenType::PERCENT, "%"),
+            Token::new(TokenType::IDENT, "d"),
+            Token::new(TokenType::AND, "&&"),
+            Token::new(TokenType::IDENT, "e"),
+            Token::new(TokenType::OR, "||"),
+            Token::new(TokenType::IDENT, "f"),
+            Token::new(TokenType::LT, "<"),
+            Token::new(TokenType::IDENT, "g"),
+            Token::new(TokenType::ILLEGAL, "&"),
+            Token::new(TokenType::IDENT, "h"),
+            Token::new(TokenType::ILLEGAL, "|"),
+            Token::new(TokenType::IDENT, "i"),
+            Token::new(TokenType::EOF, ""),
+        ];
+        let mut l = Lexer::new(input.to_string());
+        for tt in tests {
+            let tok = l.next_token();
+            assert_eq!((tok.type_, tok.literal), (tt.type_, tt.literal));
+        }
+    }
+    #[test]
+    fn test_loop_keywords(){
+        let types: Vec<TokenType> = Lexer::new("while breaks break continue for in inner".to_string()).map(|tok| tok.type_).collect();
+        assert_eq!(types, vec![
+            TokenType::WHILE, TokenType::IDENT, TokenType::BREAK, TokenType::CONTINUE, TokenType::FOR, TokenType::IN, TokenType::IDENT, TokenType::EOF,
+        ]);
+    }
+    #[test]
+    fn test_const_keyword(){
+        let types: Vec<TokenType> = Lexer::new("const constant let".to_string()).map(|tok| tok.type_).collect();
+        assert_eq!(types, vec![TokenType::CONST, TokenType::IDENT, TokenType::LET, TokenType::EOF]);
+    }
+    #[test]
+    fn test_assignment_operators(){
+        let tokens: Vec<(TokenType, String)> = Lexer::new("x = 1 += -= *= /= == + = - /".to_string())
+            .map(|tok| (tok.type_, tok.literal))
+            .collect();
+        let expected = [
+            (TokenType::IDENT, "x"), (TokenType::ASSIGN, "="), (TokenType::INT, "1"), (TokenType::PLUS_ASSIGN, "+="),
+            (TokenType::MINUS_ASSIGN, "-="), (TokenType::ASTERISK_ASSIGN, "*="), (TokenType::SLASH_ASSIGN, "/="),
+            (TokenType::EQ, "=="), (TokenType::PLUS, "+"), (TokenType::ASSIGN, "="), (TokenType::MINUS, "-"),
+            (TokenType::SLASH, "/"), (TokenType::EOF, ""),
+        ];
+        assert_eq!(tokens, expected.map(|(type_, literal)| (type_, literal.to_string())));
+    }
+    #[test]
+    fn test_match_tokens(){
+        let types: Vec<TokenType> = Lexer::new("match (x) { _ => 1, [a, ..] => 2 } matches ==> >=".to_string()).map(|tok| tok.type_).collect();
+        assert_eq!(types, vec![
+            TokenType::MATCH, TokenType::LPAREN, TokenType::IDENT, TokenType::RPAREN, TokenType::LBRACE,
+            TokenType::IDENT, TokenType::FAT_ARROW, TokenType::INT, TokenType::COMMA,
+            TokenType::LBRACKET, TokenType::IDENT, TokenType::COMMA, TokenType::DOT_DOT, TokenType::RBRACKET, TokenType::FAT_ARROW, TokenType::INT,
+            TokenType::RBRACE, TokenType::IDENT, TokenType::EQ, TokenType::GT, TokenType::GT_EQ, TokenType::EOF,
+        ]);
+    }
+    #[test]
+    fn test_range_operators(){
+        let tokens: Vec<(TokenType, String, usize, usize)> = Lexer::new("0..10 1..=n 1.5..x[0]..y".to_string())
+            .map(|tok| (tok.type_, tok.literal, tok.span.start, tok.span.end))
+            .collect();
+        assert_eq!(tokens, vec![
+            (TokenType::INT, String::from("0"), 0, 1),
+            (TokenType::DOT_DOT, String::from(".."), 1, 3),
+            (TokenType::INT, String::from("10"), 3, 5),
+            (TokenType::INT, String::from("1"), 6, 7),
+            (TokenType::DOT_DOT_EQ, String::from("..="), 7, 10),
+            (TokenType::IDENT, String::from("n"), 10, 11),
+            (TokenType::FLOAT, String::from("1.5"), 12, 15),
+            (TokenType::DOT_DOT, String::from(".."), 15, 17),
+            (TokenType::IDENT, String::from("x"), 17, 18),
+            (TokenType::LBRACKET, String::from("["), 18, 19),
+            (TokenType::INT, String::from("0"), 19, 20),
+            (TokenType::RBRACKET, String::from("]"), 20, 21),
+            (TokenType::DOT_DOT, String::from(".."), 21, 23),
+            (TokenType::IDENT, String::from("y"), 23, 24),
+            (TokenType::EOF, String::new(), 24, 24),
+        ]);
+    }
+    #[test]
+    fn test_iterator_and_tokenize(){
+        let types: Vec<TokenType> = Lexer::new("let x = 1;".to_string()).map(|tok| tok.type_).collect();
+        assert_eq!(types, vec![TokenType::LET, TokenType::IDENT, TokenType::ASSIGN, TokenType::INT, TokenType::SEMICOLON, TokenType::EOF]);
+
+        let mut l = Lexer::new(String::new());
+        assert_eq!(l.next().map(|tok| tok.type_), Some(TokenType::EOF));
+        assert_eq!(l.next(), None);
+        assert_eq!(l.next(), None);
+
+        let tokens = tokenize("a @ b").unwrap();
+        assert_eq!(tokens.len(), 4);
+        assert_eq!(tokens[1], Token::with_span(TokenType::ILLEGAL, "@", Span::new(2, 3, 1, 3)));
+        assert_eq!(tokens[1].to_string(), "1:3 2..3 ILLEGAL \"@\"");
+
+        let error = tokenize("\"a\\q\" 0x").unwrap_err();
+        let codes: Vec<&str> = error.errors.iter().map(|e| e.code).collect();
+        assert_eq!(codes, vec!["E0007", "E0009"]);
+    }
+    #[test]
+    fn test_interpolated_strings(){
+        let input = r#""hi ${name}, ${ {"}": 1}["}"] }!" "\${x}""#;
+        let mut l = Lexer::new(input.to_string());
+        let tok = l.next_token();
+        assert_eq!((tok.type_, tok.literal.as_str()), (TokenType::TEMPLATE, r#""hi ${name}, ${ {"}": 1}["}"] }!""#));
+        let parts = l.take_interpolation(0).unwrap();
+        assert_eq!(parts.len(), 5);
+        assert!(matches!(&parts[0], StringPart::Text(text) if text == "hi "));
+        assert!(matches!(&parts[2], StringPart::Text(text) if text == ", "));
+        assert!(matches!(&parts[4], StringPart::Text(text) if text == "!"));
+        let code: Vec<Token> = match &parts[1] {
+            StringPart::Code(code) => code.clone().collect(),
+            _ => panic!("expected code"),
+        };
+        assert_eq!(code, vec![
+            Token::with_span(TokenType::IDENT, "name", Span::new(6, 10, 1, 7)),
+            Token::with_span(TokenType::EOF, "", Span::new(10, 10, 1, 11)),
+        ]);
+        let types: Vec<TokenType> = match &parts[3] {
+            StringPart::Code(code) => code.clone().map(|tok| tok.type_).collect(),
+            _ => panic!("expected code"),
+        };
+        assert_eq!(types, vec![
+            TokenType::LBRACE, TokenType::STRING, TokenType::COLON, TokenType::INT, TokenType::RBRACE,
+            TokenType::LBRACKET, TokenType::STRING, TokenType::RBRACKET, TokenType::EOF,
+        ]);
+        assert_eq!(l.next_token(), Token::with_span(TokenType::STRING, "${x}", Span::new(34, 41, 1, 35)));
+        assert_eq!(l.next_token().type_, TokenType::EOF);
+        assert!(l.errors.is_empty());
+
+        let mut l = Lexer::new("\"a ${x\" + 1".to_string());
+        assert_eq!(l.next_token().type_, TokenType::TEMPLATE);
+        assert_eq!(l.next_token().type_, TokenType::EOF);
+        assert_eq!(l.errors.len(), 1);
+        assert_eq!((l.errors[0].code, l.errors[0].span), ("E0010", Some(Span::new(3, 5, 1, 4))));
+    }
     #[test]
     fn test_next_token2(){
         let input = "let five = 5;
@@ -212,7 +948,7 @@ mod test {
             x + y;
         };
         let result = add(five, ten);
-        !-/*5;
+        !-/ *5;
         5 < 10 > 5;
         ";
         let tests = vec![
@@ -284,7 +1020,7 @@ mod test {
             x + y;
         };
         let result = add(five, ten);
-        !-/*5;
+        !-/ *5;
         5 < 10 > 5;
         if (5 < 10) {
             return true;
@@ -377,7 +1113,7 @@ mod test {
             x + y;
         };
         let result = add(five, ten);
-        !-/*5;
+        !-/ *5;
         5 < 10 > 5;
         if (5 < 10) {
             return true;
@@ -474,6 +1210,235 @@ mod test {
             assert_eq!(tok.literal, tt.literal);
         }
     }
+    #[test]
+    fn test_brackets_and_colon (){
+        let input = "[1, 2][0] {\"a\": 1}";
+        let tests = vec![
+            Token::new(TokenType::LBRACKET, "["),
+            Token::new(TokenType::INT, "1"),
+            Token::new(TokenType::COMMA, ","),
+            Token::new(TokenType::INT, "2"),
+            Token::new(TokenType::RBRACKET, "]"),
+            Token::new(TokenType::LBRACKET, "["),
+            Token::new(TokenType::INT, "0"),
+            Token::new(TokenType::RBRACKET, "]"),
+            Token::new(TokenType::LBRACE, "{"),
+            Token::new(TokenType::STRING, "a"),
+            Token::new(TokenType::COLON, ":"),
+            Token::new(TokenType::INT, "1"),
+            Token::new(TokenType::RBRACE, "}"),
+            Token::new(TokenType::EOF, ""),
+        ];
+        let mut l = Lexer::new(input.to_string());
+        for tt in tests {
+            let tok = l.next_token();
+            assert_eq!(tok.type_, tt.type_);
+            assert_eq!(tok.literal, tt.literal);
+        }
+    }
+    #[test]
+    fn test_float_literals (){
+        let input = "3.14 .5 1e-9 2.5E+3 7e2 10 1.foo 1..2 x.5";
+        let tests = vec![
+            Token::new(TokenType::FLOAT, "3.14"),
+            Token::new(TokenType::FLOAT, ".5"),
+            Token::new(TokenType::FLOAT, "1e-9"),
+            Token::new(TokenType::FLOAT, "2.5E+3"),
+            Token::new(TokenType::FLOAT, "7e2"),
+            Token::new(TokenType::INT, "10"),
+            Token::new(TokenType::INT, "1"),
+            Token::new(TokenType::ILLEGAL, "."),
+            Token::new(TokenType::IDENT, "foo"),
+            Token::new(TokenType::INT, "1"),
+            Token::new(TokenType::DOT_DOT, ".."),
+            Token::new(TokenType::INT, "2"),
+            Token::new(TokenType::IDENT, "x"),
+            Token::new(TokenType::FLOAT, ".5"),
+            Token::new(TokenType::EOF, ""),
+        ];
+        let mut l = Lexer::new(input.to_string());
+        for tt in tests {
+            let tok = l.next_token();
+            assert_eq!(tok.type_, tt.type_);
+            assert_eq!(tok.literal, tt.literal);
+        }
+    }
+    #[test]
+    fn test_number_literals (){
+        let input = "x1 _2 1_000_000 0xFF 0o17 0b1010 0x_dead_BEEF 1_000.5e1_0";
+        let tests = vec![
+            Token::new(TokenType::IDENT, "x1"),
+            Token::new(TokenType::IDENT, "_2"),
+            Token::new(TokenType::INT, "1_000_000"),
+            Token::new(TokenType::INT, "0xFF"),
+            Token::new(TokenType::INT, "0o17"),
+            Token::new(TokenType::INT, "0b1010"),
+            Token::new(TokenType::INT, "0x_dead_BEEF"),
+            Token::new(TokenType::FLOAT, "1_000.5e1_0"),
+            Token::new(TokenType::EOF, ""),
+        ];
+        let mut l = Lexer::new(input.to_string());
+        for tt in tests {
+            let tok = l.next_token();
+            assert_eq!((tok.type_, tok.literal), (tt.type_, tt.literal));
+        }
+        assert!(l.errors.is_empty());
+    }
+    #[test]
+    fn test_malformed_number_literals (){
+        let tests = vec![
+            ("12abc", "12", "invalid suffix `abc` on number literal `12`", Span::new(0, 5, 1, 1)),
+            ("3e", "3", "invalid suffix `e` on number literal `3`", Span::new(0, 2, 1, 1)),
+            ("0x", "0", "missing digits after `0x`", Span::new(0, 2, 1, 1)),
+            ("0b_", "0", "missing digits after `0b`", Span::new(0, 3, 1, 1)),
+            ("0b102", "0b10", "invalid digit `2` in binary literal", Span::new(4, 5, 1, 5)),
+            ("0o78", "0o7", "invalid digit `8` in octal literal", Span::new(3, 4, 1, 4)),
+            ("0xFG", "0xF", "invalid digit `G` in hexadecimal literal", Span::new(3, 4, 1, 4)),
+        ];
+        for (input, literal, message, span) in tests {
+            let mut l = Lexer::new(input.to_string());
+            let tok = l.next_token();
+            assert_eq!((tok.type_, tok.literal.as_str()), (TokenType::INT, literal), "{}", input);
+            assert_eq!(l.next_token().type_, TokenType::EOF, "{}", input);
+            assert_eq!(l.errors.len(), 1, "{}", input);
+            assert_eq!(l.errors[0].code, "E0009");
+            assert_eq!(l.errors[0].message, message);
+            assert_eq!(l.errors[0].span, Some(span));
+        }
+    }
+    #[test]
+    fn test_unicode_input (){
+        let input = "let café = \"héllo 世界\";\nnaïve_1 + ü € x";
+        let tests = vec![
+            (TokenType::LET, "let", Span::new(0, 3, 1, 1)),
+            (TokenType::IDENT, "café", Span::new(4, 9, 1, 5)),
+            (TokenType::ASSIGN, "=", Span::new(10, 11, 1, 10)),
+            (TokenType::STRING, "héllo 世界", Span::new(12, 27, 1, 12)),
+            (TokenType::SEMICOLON, ";", Span::new(27, 28, 1, 22)),
+            (TokenType::IDENT, "naïve_1", Span::new(29, 37, 2, 1)),
+            (TokenType::PLUS, "+", Span::new(38, 39, 2, 9)),
+            (TokenType::IDENT, "ü", Span::new(40, 42, 2, 11)),
+            (TokenType::ILLEGAL, "€", Span::new(43, 46, 2, 13)),
+            (TokenType::IDENT, "x", Span::new(47, 48, 2, 15)),
+            (TokenType::EOF, "", Span::new(48, 48, 2, 16)),
+        ];
+        let mut l = Lexer::new(input.to_string());
+        for (type_, literal, span) in tests {
+            let tok = l.next_token();
+            assert_eq!((tok.type_, tok.literal.as_str(), tok.span), (type_, literal, span));
+        }
+    }
+    #[test]
+    fn test_string_escapes_and_raw_strings (){
+        let tests = vec![
+            (r#""a\nb\tc""#, "a\nb\tc"),
+            (r#""say \"hi\" \\ bye""#, "say \"hi\" \\ bye"),
+            (r#""\u{1F600} \u{e9}\r\0""#, "😀 é\r\0"),
+            (r#"r"C:\path\n""#, "C:\\path\\n"),
+            (r##"r#"a "quoted" word"#"##, "a \"quoted\" word"),
+            (r###"r##"ends with "# inside"##"###, "ends with \"# inside"),
+            ("\"\"\"\nline one\n  \"two\"\n\"\"\"", "line one\n  \"two\"\n"),
+            ("\"\"\"tab\\there\"\"\"", "tab\there"),
+            ("\"\"", ""),
+        ];
+        for (input, expected) in tests {
+            let mut l = Lexer::new(input.to_string());
+            let tok = l.next_token();
+            assert_eq!((tok.type_, tok.literal.as_str()), (TokenType::STRING, expected), "input: {}", input);
+            assert_eq!(tok.span, Span::new(0, input.len(), 1, 1), "input: {}", input);
+            assert_eq!(l.next_token().type_, TokenType::EOF);
+            assert!(l.errors.is_empty(), "input: {}", input);
+        }
+
+        let mut l = Lexer::new("rest r#x".to_string());
+        assert_eq!(l.next_token().literal, "rest");
+        assert_eq!(l.next_token().literal, "r");
+        assert_eq!(l.next_token().type_, TokenType::ILLEGAL);
+    }
+    #[test]
+    fn test_string_errors (){
+        let tests = vec![
+            ("x = \"open", "E0006", "unterminated string literal", Span::new(4, 5, 1, 5), "open"),
+            ("r#\"open\"", "E0006", "unterminated string literal", Span::new(0, 3, 1, 1), "open\""),
+            ("\"\"\"\nopen", "E0006", "unterminated string literal", Span::new(0, 3, 1, 1), "open"),
+            ("\"a\\qb\"", "E0007", "invalid escape sequence `\\q`", Span::new(2, 4, 1, 3), "a\\qb"),
+            ("\"\\u{110000}\"", "E0007", "invalid escape sequence `\\u{110000}`", Span::new(1, 11, 1, 2), "\\u{110000}"),
+            ("\"\\u{}\"", "E0007", "invalid escape sequence `\\u{}`", Span::new(1, 5, 1, 2), "\\u{}"),
+            ("\"\\u41\"", "E0007", "invalid escape sequence `\\u`", Span::new(1, 3, 1, 2), "\\u41"),
+            ("\"é\\", "E0006", "unterminated string literal", Span::new(0, 1, 1, 1), "é"),
+        ];
+        for (input, code, message, span, literal) in tests {
+            let mut l = Lexer::new(input.to_string());
+            let mut tok = l.next_token();
+            while tok.type_ != TokenType::STRING {
+                tok = l.next_token();
+            }
+            assert_eq!(tok.literal, literal, "input: {}", input);
+            assert_eq!(l.errors.len(), 1, "input: {}", input);
+            let error = &l.errors[0];
+            assert_eq!((error.code, error.message.as_str(), error.span), (code, message, Some(span)), "input: {}", input);
+        }
+    }
+    #[test]
+    fn test_comments (){
+        let input = "1 // line comment\n/* block /* nested */ still comment */ 2 / 3 /**/ //\n4 //// not doc";
+        let tests = vec![
+            (TokenType::INT, "1"),
+            (TokenType::INT, "2"),
+            (TokenType::SLASH, "/"),
+            (TokenType::INT, "3"),
+            (TokenType::INT, "4"),
+            (TokenType::EOF, ""),
+        ];
+        let mut l = Lexer::new(input.to_string());
+        for (type_, literal) in tests {
+            let tok = l.next_token();
+            assert_eq!((tok.type_, tok.literal.as_str()), (type_, literal));
+        }
+        assert!(l.errors.is_empty());
+
+        let mut l = Lexer::new("1 /* open /* nested */".to_string());
+        l.next_token();
+        assert_eq!(l.next_token().type_, TokenType::EOF);
+        assert_eq!(l.errors.len(), 1);
+        assert_eq!((l.errors[0].code, l.errors[0].span), ("E0008", Some(Span::new(2, 4, 1, 3))));
+    }
+    #[test]
+    fn test_doc_comments (){
+        let input = "/// Adds two numbers.\n///\n///Returns their sum.\n// plain\nlet add = 1;\n//// four slashes\nlet x = 2;";
+        let mut l = Lexer::new(input.to_string());
+        let tok = l.next_token();
+        assert_eq!(tok.type_, TokenType::LET);
+        assert_eq!(l.take_doc_comment(tok.span.start), Some(String::from("Adds two numbers.\n\nReturns their sum.")));
+        assert_eq!(l.take_doc_comment(tok.span.start), None);
+        for _ in 0..4 {
+            l.next_token();
+        }
+        let tok = l.next_token();
+        assert_eq!(tok.type_, TokenType::LET);
+        assert_eq!(l.take_doc_comment(tok.span.start), None);
+    }
+    #[test]
+    fn test_token_spans (){
+        let input = "let five = 5;\n  five == \"ab\"";
+        let tests = vec![
+            (TokenType::LET, Span::new(0, 3, 1, 1)),
+            (TokenType::IDENT, Span::new(4, 8, 1, 5)),
+            (TokenType::ASSIGN, Span::new(9, 10, 1, 10)),
+            (TokenType::INT, Span::new(11, 12, 1, 12)),
+            (TokenType::SEMICOLON, Span::new(12, 13, 1, 13)),
+            (TokenType::IDENT, Span::new(16, 20, 2, 3)),
+            (TokenType::EQ, Span::new(21, 23, 2, 8)),
+            (TokenType::STRING, Span::new(24, 28, 2, 11)),
+            (TokenType::EOF, Span::new(28, 28, 2, 15)),
+        ];
+        let mut l = Lexer::new(input.to_string());
+        for (type_, span) in tests {
+            let tok = l.next_token();
+            assert_eq!(tok.type_, type_);
+            assert_eq!(tok.span, span);
+        }
+    }
     
 
     
@@ -6,4 +6,13 @@ pub mod parser;
 pub mod object;
 pub mod evaluator;
 pub mod envoriment;
-pub mod builtins;
\ No newline at end of file
+pub mod builtins;
+pub mod diagnostic;
+pub mod cli;
+pub mod compiler;
+pub mod vm;
+pub mod cst;
+pub mod fmt;
+pub mod serialize;
+#[cfg(test)]
+mod test_corpus;
\ No newline at end of file
@@ -1,6 +1,19 @@
-use interpeter::repl;
+use std::io::IsTerminal;
+
+use interpeter::builtins::Output;
+use interpeter::{cli, evaluator};
 
 fn main() {
-    println!("Feel Free to type in commands");
-    repl::start(&mut std::io::stdin().lock(), &mut std::io::stdout());
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    let code = std::thread::Builder::new()
+        .stack_size(evaluator::STACK_SIZE)
+        .spawn(move || {
+            let stdin = std::io::stdin();
+            let interactive = stdin.is_terminal();
+            cli::run(&args, &mut stdin.lock(), interactive, &mut Output::new(std::io::stdout()), &mut std::io::stderr())
+        })
+        .unwrap()
+        .join()
+        .unwrap();
+    std::process::exit(code);
 }
@@ -1,14 +1,20 @@
-use std::{fmt::Display, any, rc::Rc};
+use std::{cell::RefCell, collections::HashMap, fmt::Display, any, rc::Rc};
 use crate::ast;
 use crate::ast::Statement;
 use crate::envoriment::Environment;
+use crate::diagnostic::Diagnostic;
+use crate::token::Span;
 use ast::Node;
+#[allow(non_camel_case_types)]
 #[derive(Debug, PartialEq, Clone)]
 pub enum ObjectType {
     INTEGER,
+    FLOAT,
     BOOLEAN,
     NULL,
     RETURN,
+    BREAK,
+    CONTINUE,
     ERROR,
 
     ENVIRONMENT,
@@ -16,21 +22,40 @@ pub enum ObjectType {
 
     STRING,
     BUILTIN,
+    ARRAY,
+    HASH,
+    RANGE,
+    ITERATOR,
+    CELL,
+    PATTERN,
 
+    COMPILED_FUNCTION,
+    CLOSURE,
 }
     
 impl Display for ObjectType  {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
             ObjectType::INTEGER => write!(f, "INTEGER"),
+            ObjectType::FLOAT => write!(f, "FLOAT"),
             ObjectType::BOOLEAN => write!(f, "BOOLEAN"),
             ObjectType::NULL => write!(f, "NULL"),
             ObjectType::RETURN => write!(f, "RETURN"),
+            ObjectType::BREAK => write!(f, "BREAK"),
+            ObjectType::CONTINUE => write!(f, "CONTINUE"),
             ObjectType::ERROR => write!(f, "ERROR"),
             ObjectType::ENVIRONMENT => write!(f, "ENVIRONMENT"),
             ObjectType::FUNCTION => write!(f, "FUNCTION"),
             ObjectType::STRING => write!(f, "STRING"),
             ObjectType::BUILTIN => write!(f, "BUILTIN"),
+            ObjectType::ARRAY => write!(f, "ARRAY"),
+            ObjectType::HASH => write!(f, "HASH"),
+            ObjectType::RANGE => write!(f, "RANGE"),
+            ObjectType::ITERATOR => write!(f, "ITERATOR"),
+            ObjectType::CELL => write!(f, "CELL"),
+            ObjectType::PATTERN => write!(f, "PATTERN"),
+            ObjectType::COMPILED_FUNCTION => write!(f, "COMPILED_FUNCTION"),
+            ObjectType::CLOSURE => write!(f, "CLOSURE"),
         }
     }   
 }
@@ -41,7 +66,29 @@ pub trait Object  {
     fn object_type(&self) -> ObjectType;
     fn inspect(&self) -> String;
     fn as_any(&self) -> &dyn any::Any;
+    /// Key under which this value is stored in a hash, or `None` if the value
+    /// cannot be used as a hash key.
+    fn hash_key(&self) -> Option<HashKey> {
+        None
+    }
+    /// The elements a `for` loop visits, or `None` if the value cannot be
+    /// iterated. The iterator owns what it needs, so it may outlive `self`.
+    fn iterate(&self) -> Option<ObjectIterator> {
+        None
+    }
+
+}
+
+/// Elements produced by `Object::iterate`.
+pub type ObjectIterator = Box<dyn Iterator<Item = Rc<Box<dyn Object>>>>;
 
+/// Identity of a hashable value. Two objects with equal `HashKey`s refer to
+/// the same hash entry, so `1` and `"1"` are different keys.
+#[derive(Debug, PartialEq, Eq, Hash, Clone)]
+pub enum HashKey {
+    Integer(i64),
+    String(String),
+    Boolean(bool),
 }
 
 #[derive(Debug, PartialEq, Clone)]
@@ -59,6 +106,30 @@ impl Object for Integer {
     fn as_any(&self) -> &dyn any::Any {
         self
     }
+    fn hash_key(&self) -> Option<HashKey> {
+        Some(HashKey::Integer(self.value))
+    }
+}
+
+/// A 64-bit float. Floats are not hashable, since keys that compare equal
+/// must hash the same and `NaN` equals nothing.
+#[derive(Debug, PartialEq, Clone)]
+pub struct Float {
+    pub value: f64
+}
+
+impl Object for Float {
+    fn object_type(&self) -> ObjectType {
+        ObjectType::FLOAT
+    }
+    /// Formats the shortest text that parses back to the same value, always
+    /// with a `.` or exponent so it reads back as a float (`1.0`, `1e-9`).
+    fn inspect(&self) -> String {
+        format!("{:?}", self.value)
+    }
+    fn as_any(&self) -> &dyn any::Any {
+        self
+    }
 }
 
 #[derive(Debug, PartialEq, Clone)]
@@ -76,6 +147,9 @@ impl Object for Boolean {
     fn as_any(&self) -> &dyn any::Any {
         self
     }
+    fn hash_key(&self) -> Option<HashKey> {
+        Some(HashKey::Boolean(self.value))
+    }
 }
 
 
@@ -87,7 +161,7 @@ impl Object for Null {
         ObjectType::NULL
     }
     fn inspect(&self) -> String {
-        format!("null")
+        "null".to_string()
     }
     fn as_any(&self) -> &dyn any::Any {
         self
@@ -104,7 +178,7 @@ impl Object for Return {
         ObjectType::RETURN
     }
     fn inspect(&self) -> String {
-        format!("{}", self.value.inspect())
+        self.value.inspect()
     }
     fn as_any(&self) -> &dyn any::Any {
         self
@@ -118,9 +192,39 @@ impl Return  {
     }
 }
 
+/// Signal left by `break`, carried out of blocks up to the enclosing loop.
+pub struct Break {}
+
+impl Object for Break {
+    fn object_type(&self) -> ObjectType {
+        ObjectType::BREAK
+    }
+    fn inspect(&self) -> String {
+        "break".to_string()
+    }
+    fn as_any(&self) -> &dyn any::Any {
+        self
+    }
+}
+
+/// Signal left by `continue`, carried out of blocks up to the enclosing loop.
+pub struct Continue {}
+
+impl Object for Continue {
+    fn object_type(&self) -> ObjectType {
+        ObjectType::CONTINUE
+    }
+    fn inspect(&self) -> String {
+        "continue".to_string()
+    }
+    fn as_any(&self) -> &dyn any::Any {
+        self
+    }
+}
+
 
 pub struct Error {
-    pub message: String
+    pub diagnostic: Diagnostic
 }
 
 impl Object for Error {
@@ -128,7 +232,7 @@ impl Object for Error {
         ObjectType::ERROR
     }
     fn inspect(&self) -> String {
-        format!("ERROR: {}", self.message)
+        format!("ERROR: {}", self.diagnostic.message)
     }
     fn as_any(&self) -> &dyn any::Any {
         self
@@ -153,7 +257,7 @@ impl Object for Function {
             params.push(p.string());
         }
         out.push_str("fn");
-        out.push_str("(");
+        out.push('(');
         out.push_str(&params.join(", "));
         out.push_str(") {\n");
         out.push_str(&self.body.string());
@@ -175,17 +279,27 @@ impl Object for StringValue {
         ObjectType::STRING
     }
     fn inspect(&self) -> String {
-        format!("{}", self.value)
+        self.value.clone()
     }
     fn as_any(&self) -> &dyn any::Any {
         self
     }
+    fn hash_key(&self) -> Option<HashKey> {
+        Some(HashKey::String(self.value.clone()))
+    }
+    /// Each Unicode scalar value as a one-character string, like indexing.
+    fn iterate(&self) -> Option<ObjectIterator> {
+        let chars: Vec<char> = self.value.chars().collect();
+        Some(Box::new(chars.into_iter().map(|ch| Rc::new(Box::new(StringValue{value: ch.to_string()}) as Box<dyn Object>))))
+    }
     
 }
 
-#[derive(Debug, PartialEq, Clone)]
+pub type BuiltinFunction = fn(Vec<Rc<Box<dyn Object>>>) -> Rc<Box<dyn Object>>;
+
+#[derive(Debug, Clone)]
 pub struct Builtin {
-    pub func: fn(Vec<Rc<Box<dyn Object>>>) -> Rc<Box<dyn Object>>
+    pub func: BuiltinFunction,
 }
 
 impl Object for Builtin {
@@ -193,10 +307,368 @@ impl Object for Builtin {
         ObjectType::BUILTIN
     }
     fn inspect(&self) -> String {
-        format!("builtin function")
+        "builtin function".to_string()
     }
     fn as_any(&self) -> &dyn any::Any {
         self
     }
     
-}
\ No newline at end of file
+}
+
+pub struct Array {
+    pub elements: Vec<Rc<Box<dyn Object>>>,
+}
+
+impl Object for Array {
+    fn object_type(&self) -> ObjectType {
+        ObjectType::ARRAY
+    }
+    fn inspect(&self) -> String {
+        let mut out = String::new();
+        let mut elements = Vec::new();
+        for e in self.elements.iter() {
+            elements.push(e.inspect());
+        }
+        out.push('[');
+        out.push_str(&elements.join(", "));
+        out.push(']');
+        out
+    }
+    fn as_any(&self) -> &dyn any::Any {
+        self
+    }
+    fn iterate(&self) -> Option<ObjectIterator> {
+        Some(Box::new(self.elements.clone().into_iter()))
+    }
+    
+}
+
+pub struct HashPair {
+    pub key: Rc<Box<dyn Object>>,
+    pub value: Rc<Box<dyn Object>>,
+}
+
+/// A hash map object. Entries keep the order they were inserted in, which is
+/// the order `inspect`, `keys` and `values` report them in.
+#[derive(Default)]
+pub struct Hash {
+    pub pairs: Vec<HashPair>,
+    index: HashMap<HashKey, usize>,
+}
+
+impl Hash {
+    pub fn get(&self, key: &HashKey) -> Option<&HashPair> {
+        self.index.get(key).map(|i| &self.pairs[*i])
+    }
+
+    /// Inserts or replaces the entry for `key`. Returns `false`, leaving the
+    /// hash unchanged, when `key` is not hashable.
+    pub fn insert(&mut self, key: Rc<Box<dyn Object>>, value: Rc<Box<dyn Object>>) -> bool {
+        let hash_key = match key.hash_key() {
+            Some(hash_key) => hash_key,
+            None => return false,
+        };
+        match self.index.get(&hash_key) {
+            Some(i) => self.pairs[*i].value = value,
+            None => {
+                self.index.insert(hash_key, self.pairs.len());
+                self.pairs.push(HashPair{key, value});
+            }
+        }
+        true
+    }
+
+    /// Copy of this hash with the entry for `key` set to `value`, or `None`
+    /// when `key` is not hashable.
+    pub fn with(&self, key: Rc<Box<dyn Object>>, value: Rc<Box<dyn Object>>) -> Option<Hash> {
+        let mut hash = Hash::default();
+        for pair in self.pairs.iter() {
+            hash.insert(pair.key.clone(), pair.value.clone());
+        }
+        hash.insert(key, value).then_some(hash)
+    }
+
+    /// Copy of this hash without the entry for `key`.
+    pub fn without(&self, key: &HashKey) -> Hash {
+        let mut hash = Hash::default();
+        for pair in self.pairs.iter() {
+            if pair.key.hash_key().as_ref() != Some(key) {
+                hash.insert(pair.key.clone(), pair.value.clone());
+            }
+        }
+        hash
+    }
+}
+
+impl Object for Hash {
+    fn object_type(&self) -> ObjectType {
+        ObjectType::HASH
+    }
+    fn inspect(&self) -> String {
+        let mut out = String::new();
+        let mut pairs = Vec::new();
+        for pair in self.pairs.iter() {
+            pairs.push(format!("{}: {}", pair.key.inspect(), pair.value.inspect()));
+        }
+        out.push('{');
+        out.push_str(&pairs.join(", "));
+        out.push('}');
+        out
+    }
+    fn as_any(&self) -> &dyn any::Any {
+        self
+    }
+    /// The keys, in insertion order.
+    fn iterate(&self) -> Option<ObjectIterator> {
+        let keys: Vec<Rc<Box<dyn Object>>> = self.pairs.iter().map(|pair| pair.key.clone()).collect();
+        Some(Box::new(keys.into_iter()))
+    }
+    
+}
+
+/// The integers from `start` up to `end`, written `start..end`, or up to and
+/// including `end`, written `start..=end`.
+#[derive(Debug, PartialEq, Clone)]
+pub struct Range {
+    pub start: i64,
+    pub end: i64,
+    pub inclusive: bool,
+}
+
+impl Object for Range {
+    fn object_type(&self) -> ObjectType {
+        ObjectType::RANGE
+    }
+    fn inspect(&self) -> String {
+        match self.inclusive {
+            true => format!("{}..={}", self.start, self.end),
+            false => format!("{}..{}", self.start, self.end),
+        }
+    }
+    fn as_any(&self) -> &dyn any::Any {
+        self
+    }
+    fn iterate(&self) -> Option<ObjectIterator> {
+        let integer = |value| Rc::new(Box::new(Integer{value}) as Box<dyn Object>);
+        match self.inclusive {
+            true => Some(Box::new((self.start..=self.end).map(integer))),
+            false => Some(Box::new((self.start..self.end).map(integer))),
+        }
+    }
+}
+
+/// A `for` loop's position in the value it walks over. Only the `vm` creates
+/// these; they never become visible to a program.
+pub struct Iter {
+    pub elements: RefCell<ObjectIterator>,
+}
+
+impl Object for Iter {
+    fn object_type(&self) -> ObjectType {
+        ObjectType::ITERATOR
+    }
+    fn inspect(&self) -> String {
+        "iterator".to_string()
+    }
+    fn as_any(&self) -> &dyn any::Any {
+        self
+    }
+}
+
+/// A function body lowered to bytecode by the `compiler`. It lives in the
+/// constants pool; the `vm` wraps it in a `Closure` when the function
+/// expression is evaluated.
+/// `spans` maps the offset of each instruction that can fail at runtime to the
+/// source it was compiled from.
+#[derive(Clone)]
+pub struct CompiledFunction {
+    pub instructions: Rc<[u8]>,
+    pub spans: Rc<[(usize, Span)]>,
+    pub num_locals: usize,
+    pub num_parameters: usize,
+    /// Locals the `vm` puts in a `Cell` on entry, parameters included.
+    pub cells: Rc<[usize]>,
+}
+
+impl Object for CompiledFunction {
+    fn object_type(&self) -> ObjectType {
+        ObjectType::COMPILED_FUNCTION
+    }
+    fn inspect(&self) -> String {
+        format!("compiled function[{} bytes]", self.instructions.len())
+    }
+    fn as_any(&self) -> &dyn any::Any {
+        self
+    }
+
+}
+
+/// A variable shared between a function and the closures it creates, so an
+/// assignment on either side is seen by the other. Only the `vm` creates these.
+pub struct Cell {
+    pub value: RefCell<Rc<Box<dyn Object>>>,
+}
+
+impl Object for Cell {
+    fn object_type(&self) -> ObjectType {
+        ObjectType::CELL
+    }
+    fn inspect(&self) -> String {
+        self.value.borrow().inspect()
+    }
+    fn as_any(&self) -> &dyn any::Any {
+        self
+    }
+}
+
+/// A compiled function together with the free variables it captured.
+pub struct Closure {
+    pub func: CompiledFunction,
+    pub free: Vec<Rc<Box<dyn Object>>>,
+}
+
+impl Object for Closure {
+    fn object_type(&self) -> ObjectType {
+        ObjectType::CLOSURE
+    }
+    fn inspect(&self) -> String {
+        format!("closure[{} params]", self.func.num_parameters)
+    }
+    fn as_any(&self) -> &dyn any::Any {
+        self
+    }
+
+}
+
+/// A `match` pattern, built from a syntax tree that `ast::pattern_bindings`
+/// accepts. The `compiler` stores these in the constants pool.
+pub enum Pattern {
+    /// `_`, which matches anything.
+    Wildcard,
+    /// A name, which matches anything and binds it.
+    Binding,
+    /// Matches values `==` to this one.
+    Literal(Rc<Box<dyn Object>>),
+    /// `rest` is `Some` when the pattern ends in `..`, holding whether the
+    /// remaining elements are bound to a name.
+    Array { elements: Vec<Pattern>, rest: Option<bool> },
+    /// Matches hashes that have all of these keys, whatever else they hold.
+    Hash(Vec<(HashKey, Pattern)>),
+}
+
+impl Pattern {
+    pub fn new(node: &dyn Node) -> Pattern {
+        let any = node.as_any();
+        match node.node_type() {
+            ast::NodeType::Identifier => match any.downcast_ref::<ast::Identifier>().unwrap().value.as_str() {
+                "_" => Pattern::Wildcard,
+                _ => Pattern::Binding,
+            },
+            ast::NodeType::ArrayLiteral => {
+                let mut elements = Vec::new();
+                let mut rest = None;
+                for element in any.downcast_ref::<ast::ArrayLiteral>().unwrap().elements.iter() {
+                    match element.as_any().downcast_ref::<ast::RestPattern>() {
+                        Some(pattern) => rest = Some(pattern.name.as_ref().is_some_and(|name| name.value != "_")),
+                        None => elements.push(Pattern::new(element.as_node())),
+                    }
+                }
+                Pattern::Array { elements, rest }
+            }
+            ast::NodeType::HashLiteral => Pattern::Hash(any.downcast_ref::<ast::HashLiteral>().unwrap().pairs.iter()
+                .map(|(key, value)| {
+                    let key = match Pattern::new(key.as_node()) {
+                        Pattern::Literal(key) => key.hash_key().unwrap(),
+                        _ => unreachable!("hash pattern keys are literals"),
+                    };
+                    (key, Pattern::new(value.as_node()))
+                })
+                .collect()),
+            _ => Pattern::Literal(Rc::new(literal(node))),
+        }
+    }
+
+    /// Whether `value` matches, pushing the values of the pattern's names onto
+    /// `bound` in the order `ast::pattern_bindings` lists them.
+    pub fn matches(&self, value: &Rc<Box<dyn Object>>, bound: &mut Vec<Rc<Box<dyn Object>>>) -> bool {
+        match self {
+            Pattern::Wildcard => true,
+            Pattern::Binding => {
+                bound.push(value.clone());
+                true
+            }
+            Pattern::Literal(literal) => literal_equals(literal, value),
+            Pattern::Array { elements, rest } => {
+                let array = match value.as_any().downcast_ref::<Array>() {
+                    Some(array) => array,
+                    None => return false,
+                };
+                let fits = match rest {
+                    Some(_) => array.elements.len() >= elements.len(),
+                    None => array.elements.len() == elements.len(),
+                };
+                if !fits || !elements.iter().zip(array.elements.iter()).all(|(pattern, element)| pattern.matches(element, bound)) {
+                    return false;
+                }
+                if *rest == Some(true) {
+                    bound.push(Rc::new(Box::new(Array { elements: array.elements[elements.len()..].to_vec() })));
+                }
+                true
+            }
+            Pattern::Hash(pairs) => match value.as_any().downcast_ref::<Hash>() {
+                Some(hash) => pairs.iter().all(|(key, pattern)| hash.get(key).is_some_and(|pair| pattern.matches(&pair.value, bound))),
+                None => false,
+            },
+        }
+    }
+}
+
+impl Object for Pattern {
+    fn object_type(&self) -> ObjectType {
+        ObjectType::PATTERN
+    }
+    fn inspect(&self) -> String {
+        "pattern".to_string()
+    }
+    fn as_any(&self) -> &dyn any::Any {
+        self
+    }
+}
+
+/// The value of a literal pattern.
+fn literal(node: &dyn Node) -> Box<dyn Object> {
+    let any = node.as_any();
+    match node.node_type() {
+        ast::NodeType::IntegerLiteral => Box::new(Integer { value: any.downcast_ref::<ast::IntegerLiteral>().unwrap().value }),
+        ast::NodeType::FloatLiteral => Box::new(Float { value: any.downcast_ref::<ast::FloatLiteral>().unwrap().value }),
+        ast::NodeType::StringLiteral => Box::new(StringValue { value: any.downcast_ref::<ast::StringLiteral>().unwrap().value.clone() }),
+        ast::NodeType::Boolean => Box::new(Boolean { value: any.downcast_ref::<ast::Boolean>().unwrap().value }),
+        ast::NodeType::PrefixExpression => {
+            let value = literal(any.downcast_ref::<ast::PrefixExpression>().unwrap().right.as_node());
+            match value.as_any().downcast_ref::<Integer>() {
+                Some(integer) => Box::new(Integer { value: -integer.value }),
+                None => Box::new(Float { value: -value.as_any().downcast_ref::<Float>().unwrap().value }),
+            }
+        }
+        node_type => unreachable!("not a literal pattern: {:?}", node_type),
+    }
+}
+
+/// `==` on a literal pattern's value and a matched value. Numbers compare by
+/// value whether integer or float; anything else must have the same type.
+fn literal_equals(literal: &Rc<Box<dyn Object>>, value: &Rc<Box<dyn Object>>) -> bool {
+    let number = |object: &Rc<Box<dyn Object>>| match object.as_any().downcast_ref::<Integer>() {
+        Some(integer) => Some(integer.value as f64),
+        None => object.as_any().downcast_ref::<Float>().map(|float| float.value),
+    };
+    if let (Some(left), Some(right)) = (literal.as_any().downcast_ref::<Integer>(), value.as_any().downcast_ref::<Integer>()) {
+        return left.value == right.value;
+    }
+    if let (Some(left), Some(right)) = (number(literal), number(value)) {
+        return left == right;
+    }
+    match (literal.hash_key(), value.hash_key()) {
+        (Some(left), Some(right)) => left == right,
+        _ => false,
+    }
+}
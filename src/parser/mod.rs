@@ -3,10 +3,11 @@
 use std::collections::HashMap;
 use std::rc::Rc;
 
-use crate::token::{Token, self};
+use crate::token::{Span, Token, self};
 use crate::ast;
-use crate::lexer::Lexer;
+use crate::lexer::{Lexer, StringPart};
 use crate::ast::Node;
+use crate::diagnostic::Diagnostic;
 
 
 pub type PrefixParseFn = fn(&mut Parser) -> Option<Box<dyn ast::Expression>>;
@@ -17,25 +18,85 @@ pub struct Parser {
     lexer: Lexer,
     cur_token: Token,
     peek_token: Token,
-  pub  errors: Vec<String>,
+    /// The token after `peek_token`, for the few places one is not enough.
+    second_peek_token: Token,
+  pub  errors: Vec<Diagnostic>,
     precedence: HashMap<token::TokenType, Precedence>,
     perfix_parse_fns: HashMap<token::TokenType, PrefixParseFn>,
     infix_parse_fns: HashMap<token::TokenType, InfixParseFn>,
+    depth: usize,
+    /// How many loops enclose the current token within the current function,
+    /// for rejecting `break` and `continue` anywhere else.
+    loop_depth: usize,
 }
 
-#[derive(Clone,PartialEq,PartialOrd,)]
+/// Deepest expression nesting the parser accepts before giving up, so that
+/// pathological input such as thousands of `(` reports an error instead of
+/// overflowing the stack.
+const MAX_NESTING_DEPTH: usize = 128;
+
+#[derive(Debug,Clone,Copy,PartialEq,PartialOrd,)]
 pub enum Precedence {
     LOWEST,
+    ASSIGN, // = or +=
+    OR, // ||
+    AND, // &&
     EQUALS, // ==
     LESSGREATER, // > or <
+    RANGE, // 0..10
     SUM, // +
     PRODUCT, // *
     PREFIX, // -X or !X
     CALL, // myFunction(X)    
+    INDEX, // array[index]
 }
 
 
 
+/// The binding power of each infix operator token, as used by the parser.
+pub fn precedences() -> HashMap<token::TokenType, Precedence> {
+    let mut map = HashMap::new();
+    map.insert(token::TokenType::ASSIGN, Precedence::ASSIGN);
+    map.insert(token::TokenType::PLUS_ASSIGN, Precedence::ASSIGN);
+    map.insert(token::TokenType::MINUS_ASSIGN, Precedence::ASSIGN);
+    map.insert(token::TokenType::ASTERISK_ASSIGN, Precedence::ASSIGN);
+    map.insert(token::TokenType::SLASH_ASSIGN, Precedence::ASSIGN);
+    map.insert(token::TokenType::OR, Precedence::OR);
+    map.insert(token::TokenType::AND, Precedence::AND);
+    map.insert(token::TokenType::EQ, Precedence::EQUALS);
+    map.insert(token::TokenType::NOT_EQ, Precedence::EQUALS);
+    map.insert(token::TokenType::LT, Precedence::LESSGREATER);
+    map.insert(token::TokenType::GT, Precedence::LESSGREATER);
+    map.insert(token::TokenType::LT_EQ, Precedence::LESSGREATER);
+    map.insert(token::TokenType::GT_EQ, Precedence::LESSGREATER);
+    map.insert(token::TokenType::DOT_DOT, Precedence::RANGE);
+    map.insert(token::TokenType::DOT_DOT_EQ, Precedence::RANGE);
+    map.insert(token::TokenType::PLUS, Precedence::SUM);
+    map.insert(token::TokenType::MINUS, Precedence::SUM);
+    map.insert(token::TokenType::SLASH, Precedence::PRODUCT);
+    map.insert(token::TokenType::ASTERISK, Precedence::PRODUCT);
+    map.insert(token::TokenType::PERCENT, Precedence::PRODUCT);
+    map.insert(token::TokenType::LPAREN, Precedence::CALL);
+    map.insert(token::TokenType::LBRACKET, Precedence::INDEX);
+    map
+}
+
+/// The value of an `INT` literal such as `1_000` or `0xFF`.
+pub fn integer_value(literal: &str) -> Result<i64, std::num::ParseIntError> {
+    let digits = literal.replace('_', "");
+    match digits.get(..2) {
+        Some("0x") => i64::from_str_radix(&digits[2..], 16),
+        Some("0o") => i64::from_str_radix(&digits[2..], 8),
+        Some("0b") => i64::from_str_radix(&digits[2..], 2),
+        _ => digits.parse::<i64>(),
+    }
+}
+
+/// The value of a `FLOAT` literal such as `1.5e3` or `.5`.
+pub fn float_value(literal: &str) -> Result<f64, std::num::ParseFloatError> {
+    literal.replace('_', "").parse::<f64>()
+}
+
 impl Parser {
     
     pub fn new (lexer: Lexer) -> Parser {
@@ -43,14 +104,18 @@ impl Parser {
             lexer,
             cur_token: Token::new(token::TokenType::EOF, ""),
             peek_token: Token::new(token::TokenType::EOF, ""),
+            second_peek_token: Token::new(token::TokenType::EOF, ""),
             errors: Vec::new(),
             precedence: HashMap::new(),
             perfix_parse_fns: HashMap::new(),
             infix_parse_fns: HashMap::new(),
+            depth: 0,
+            loop_depth: 0,
         };
         p.create_precedences_map();
         p.register_prefix(token::TokenType::IDENT, Parser::parse_identifier_expression);
         p.register_prefix(token::TokenType::INT, Parser::parse_integer_literal);
+        p.register_prefix(token::TokenType::FLOAT, Parser::parse_float_literal);
         p.register_prefix(token::TokenType::BANG, Parser::parse_prefix_expression);
         p.register_prefix(token::TokenType::MINUS, Parser::parse_prefix_expression);
         p.register_prefix(token::TokenType::TRUE, Parser::parse_boolean);
@@ -59,6 +124,10 @@ impl Parser {
         p.register_prefix(token::TokenType::IF, Parser::parse_if_expression);
         p.register_prefix(token::TokenType::FUNCTION, Parser::parse_function_literal);
         p.register_prefix(token::TokenType::STRING, Parser::parse_string_literal);
+        p.register_prefix(token::TokenType::TEMPLATE, Parser::parse_interpolated_string);
+        p.register_prefix(token::TokenType::LBRACKET, Parser::parse_array_literal);
+        p.register_prefix(token::TokenType::LBRACE, Parser::parse_hash_literal);
+        p.register_prefix(token::TokenType::MATCH, Parser::parse_match_expression);
 
         p.register_infix(token::TokenType::MINUS, Parser::parse_infix_expression);
         p.register_infix(token::TokenType::PLUS, Parser::parse_infix_expression);
@@ -68,37 +137,43 @@ impl Parser {
         p.register_infix(token::TokenType::NOT_EQ, Parser::parse_infix_expression);
         p.register_infix(token::TokenType::LT, Parser::parse_infix_expression);
         p.register_infix(token::TokenType::GT, Parser::parse_infix_expression);
+        p.register_infix(token::TokenType::LT_EQ, Parser::parse_infix_expression);
+        p.register_infix(token::TokenType::GT_EQ, Parser::parse_infix_expression);
+        p.register_infix(token::TokenType::PERCENT, Parser::parse_infix_expression);
+        p.register_infix(token::TokenType::AND, Parser::parse_infix_expression);
+        p.register_infix(token::TokenType::OR, Parser::parse_infix_expression);
+        p.register_infix(token::TokenType::DOT_DOT, Parser::parse_infix_expression);
+        p.register_infix(token::TokenType::DOT_DOT_EQ, Parser::parse_infix_expression);
+        p.register_infix(token::TokenType::ASSIGN, Parser::parse_assign_expression);
+        p.register_infix(token::TokenType::PLUS_ASSIGN, Parser::parse_assign_expression);
+        p.register_infix(token::TokenType::MINUS_ASSIGN, Parser::parse_assign_expression);
+        p.register_infix(token::TokenType::ASTERISK_ASSIGN, Parser::parse_assign_expression);
+        p.register_infix(token::TokenType::SLASH_ASSIGN, Parser::parse_assign_expression);
         p.register_infix(token::TokenType::LPAREN, Parser::parse_call_expression);
+        p.register_infix(token::TokenType::LBRACKET, Parser::parse_index_expression);
           
 
+        p.next_token();
         p.next_token();
         p.next_token();
         p
     }
     
     fn create_precedences_map (&mut self){
-        self.precedence.insert(token::TokenType::EQ, Precedence::EQUALS);
-        self.precedence.insert(token::TokenType::NOT_EQ, Precedence::EQUALS);
-        self.precedence.insert(token::TokenType::LT, Precedence::LESSGREATER);
-        self.precedence.insert(token::TokenType::GT, Precedence::LESSGREATER);
-        self.precedence.insert(token::TokenType::PLUS, Precedence::SUM);
-        self.precedence.insert(token::TokenType::MINUS, Precedence::SUM);
-        self.precedence.insert(token::TokenType::SLASH, Precedence::PRODUCT);
-        self.precedence.insert(token::TokenType::ASTERISK, Precedence::PRODUCT);
-        self.precedence.insert(token::TokenType::LPAREN, Precedence::CALL);
+        self.precedence = precedences();
     }
 
     fn register_prefix(&mut self, token_type: token::TokenType, parse_fn: fn(&mut Parser) -> Option<Box<dyn ast::Expression>>) {
         self.perfix_parse_fns.insert(token_type, parse_fn);
     }
 
-    pub fn register_infix(&mut self, token_type: token::TokenType, func:fn(&mut Parser,Option<Box<dyn ast::Expression>>) -> Option<Box<dyn ast::Expression>> ) {
+    pub fn register_infix(&mut self, token_type: token::TokenType, func: InfixParseFn) {
         self.infix_parse_fns.insert(token_type, func);
     }
 
     fn peek_precedence(&self) -> Precedence {
         match self.precedence.get(&self.peek_token.type_) {
-            Some(p) => p.clone(),                                                                                                                                                                                                                   
+            Some(p) => *p,                                                                                                                                                                                                                   
             None => Precedence::LOWEST,
         }                                                                                                               
     }
@@ -106,10 +181,46 @@ impl Parser {
     fn parse_string_literal(&mut self) -> Option<Box<dyn ast::Expression>> {
         Some(Box::new(ast::StringLiteral {
             token: self.cur_token.clone(),
+            span: self.cur_token.span,
             value: self.cur_token.literal.clone(),
         }))
     }
 
+    /// Parses each `${...}` of a `TEMPLATE` token with its own parser over
+    /// the lexer the `Lexer` kept for it.
+    fn parse_interpolated_string(&mut self) -> Option<Box<dyn ast::Expression>> {
+        let token = self.cur_token.clone();
+        let mut parts = Vec::new();
+        for part in self.lexer.take_interpolation(token.span.start).unwrap_or_default() {
+            match part {
+                StringPart::Text(text) => parts.push(ast::InterpolationPart::Text(text)),
+                StringPart::Code(lexer) => {
+                    let mut parser = Parser::new(lexer);
+                    parser.depth = self.depth;
+                    parser.loop_depth = self.loop_depth;
+                    if parser.cur_token_is(token::TokenType::EOF) {
+                        self.errors.append(&mut parser.errors);
+                        self.errors.push(Diagnostic::error("E0011", "empty `${}` in string literal").with_span(token.span));
+                        continue;
+                    }
+                    let expression = parser.parse_expression(Precedence::LOWEST);
+                    if expression.is_some() {
+                        parser.expect_peek(token::TokenType::EOF);
+                    }
+                    self.errors.append(&mut parser.errors);
+                    if let Some(expression) = expression {
+                        parts.push(ast::InterpolationPart::Expression(expression));
+                    }
+                }
+            }
+        }
+        Some(Box::new(ast::InterpolatedString {
+            span: token.span,
+            token,
+            parts,
+        }))
+    }
+
     fn parse_infix_expression(&mut self, left: Option<Box<dyn ast::Expression>>) -> Option<Box<dyn ast::Expression>> {
     
          let  token = self.cur_token.clone();
@@ -117,50 +228,140 @@ impl Parser {
        
         let precedence = self.cur_precedence();
         self.next_token();
-       let expresion_right = self.parse_expression(precedence).unwrap();
+       let left = left?;
+       let expresion_right = self.parse_expression(precedence)?;
        let expression = ast::InfixExpression {
            token,
+           span: left.span().merge(&self.cur_token.span),
            operator,
-           left: left.unwrap(),
+           left,
            right: expresion_right,
         };
         Some(Box::new(expression))
     }
 
+    /// Parses `target = value` or a compound assignment. Assignment groups to
+    /// the right, so `a = b = 1` assigns `b = 1` to `a`.
+    fn parse_assign_expression(&mut self, target: Option<Box<dyn ast::Expression>>) -> Option<Box<dyn ast::Expression>> {
+        let token = self.cur_token.clone();
+        let target = target?;
+        if ast::place(target.as_node()).is_none() {
+            self.errors.push(
+                Diagnostic::error("E0013", &format!("invalid left-hand side of `{}`", token.literal))
+                    .with_span(target.span())
+                    .with_help("only a name or an index into one, such as `xs[0]`, can be assigned to"),
+            );
+            return None;
+        }
+        self.next_token();
+        let value = self.parse_expression(Precedence::LOWEST)?;
+        Some(Box::new(ast::AssignExpression {
+            span: target.span().merge(&self.cur_token.span),
+            operator: token.literal.clone(),
+            token,
+            target,
+            value,
+        }))
+    }
+
     fn cur_precedence(&self) -> Precedence {
         match self.precedence.get(&self.cur_token.type_) {
-            Some(p) => p.clone(),
+            Some(p) => *p,
             None => Precedence::LOWEST,
         }
     }
     fn parse_call_expression(&mut self, function: Option<Box<dyn ast::Expression>>) -> Option<Box<dyn ast::Expression>> {
         let token = self.cur_token.clone();
-        let arguments = self.parse_call_arguments();
+        let function = function?;
+        let arguments = self.parse_expression_list(token::TokenType::RPAREN)?;
         let expression = ast::CallExpression {
             token,
-            function: function.unwrap(),
+            span: function.span().merge(&self.cur_token.span),
+            function,
             arguments,
         };
         Some(Box::new(expression))
     }
 
-    fn parse_call_arguments(&mut self) -> Vec<Box<dyn ast::Expression>> {
+    /// Parses comma separated expressions up to and including `end`.
+    fn parse_expression_list(&mut self, end: token::TokenType) -> Option<Vec<Box<dyn ast::Expression>>> {
         let mut args = Vec::new();
-        if self.peek_token_is(token::TokenType::RPAREN) {
+        if self.peek_token_is(end.clone()) {
             self.next_token();
-            return args;
+            return Some(args);
         }
         self.next_token();
-        args.push(self.parse_expression(Precedence::LOWEST).unwrap());
+        args.push(self.parse_expression(Precedence::LOWEST)?);
         while self.peek_token_is(token::TokenType::COMMA) {
             self.next_token();
             self.next_token();
-            args.push(self.parse_expression(Precedence::LOWEST).unwrap());
+            args.push(self.parse_expression(Precedence::LOWEST)?);
         }
-        if !self.expect_peek(token::TokenType::RPAREN) {
-            return Vec::new();
+        if !self.expect_peek(end) {
+            return None;
+        }
+        Some(args)
+    }
+
+    fn parse_array_literal(&mut self) -> Option<Box<dyn ast::Expression>> {
+        let token = self.cur_token.clone();
+        let elements = self.parse_expression_list(token::TokenType::RBRACKET)?;
+        Some(Box::new(ast::ArrayLiteral {
+            span: self.span_from(&token),
+            token,
+            elements,
+        }))
+    }
+
+    fn parse_hash_literal(&mut self) -> Option<Box<dyn ast::Expression>> {
+        let token = self.cur_token.clone();
+        let mut pairs = Vec::new();
+        while !self.peek_token_is(token::TokenType::RBRACE) {
+            self.next_token();
+            let key = self.parse_expression(Precedence::LOWEST)?;
+            if !self.expect_peek(token::TokenType::COLON) {
+                return None;
+            }
+            self.next_token();
+            let value = self.parse_expression(Precedence::LOWEST)?;
+            pairs.push((key, value));
+            if !self.peek_token_is(token::TokenType::RBRACE) && !self.expect_peek(token::TokenType::COMMA) {
+                return None;
+            }
         }
-        args
+        self.next_token();
+        Some(Box::new(ast::HashLiteral {
+            span: self.span_from(&token),
+            token,
+            pairs,
+        }))
+    }
+
+    /// Decides what a `{` at the start of a statement opens. Inside an
+    /// expression `{` is always a hash literal, but as a statement it is a block
+    /// unless it is `{}` or its first token is followed by a `:`, as in
+    /// `{"key": value}`.
+    fn starts_block_statement(&self) -> bool {
+        if self.peek_token_is(token::TokenType::RBRACE) {
+            return false;
+        }
+        self.second_peek_token.type_ != token::TokenType::COLON
+    }
+
+    fn parse_index_expression(&mut self, left: Option<Box<dyn ast::Expression>>) -> Option<Box<dyn ast::Expression>> {
+        let token = self.cur_token.clone();
+        let left = left?;
+        self.next_token();
+        let index = self.parse_expression(Precedence::LOWEST)?;
+        if !self.expect_peek(token::TokenType::RBRACKET) {
+            return None;
+        }
+        Some(Box::new(ast::IndexExpression {
+            token,
+            span: left.span().merge(&self.cur_token.span),
+            left,
+            index,
+        }))
     }
 
 
@@ -169,44 +370,53 @@ impl Parser {
         if !self.expect_peek(token::TokenType::LPAREN) {
             return None;
         }
-        let parameters = self.parse_function_parameters();
+        let parameters = self.parse_function_parameters()?;
         if !self.expect_peek(token::TokenType::LBRACE) {
             return None;
         }
-        let body = self.parse_block_statement().unwrap();
+        let loop_depth = std::mem::replace(&mut self.loop_depth, 0);
+        let body = self.parse_block_statement();
+        self.loop_depth = loop_depth;
         let expression = ast::FunctionLiteral {
+            span: self.span_from(&token),
             token,
             parameters,
-            body: Rc::new(body),
+            body: Rc::new(body?),
         };
         Some(Box::new(expression))
     }
 
-    fn parse_function_parameters(&mut self) -> Vec<ast::Identifier> {
+    fn parse_function_parameters(&mut self) -> Option<Vec<ast::Identifier>> {
         let mut identifiers = Vec::new();
         if self.peek_token_is(token::TokenType::RPAREN) {
             self.next_token();
-            return identifiers;
+            return Some(identifiers);
+        }
+        if !self.expect_peek(token::TokenType::IDENT) {
+            return None;
         }
-        self.next_token();
         let ident = ast::Identifier {
             token: self.cur_token.clone(),
+            span: self.cur_token.span,
             value: self.cur_token.literal.clone(),
         };
         identifiers.push(ident);
         while self.peek_token_is(token::TokenType::COMMA) {
             self.next_token();
-            self.next_token();
+            if !self.expect_peek(token::TokenType::IDENT) {
+                return None;
+            }
             let ident = ast::Identifier {
                 token: self.cur_token.clone(),
+                span: self.cur_token.span,
                 value: self.cur_token.literal.clone(),
             };
             identifiers.push(ident);
         }
         if !self.expect_peek(token::TokenType::RPAREN) {
-            return Vec::new();
+            return None;
         }
-        identifiers
+        Some(identifiers)
     }
 
     fn parse_if_expression(&mut self) -> Option<Box<dyn ast::Expression>> {
@@ -215,41 +425,266 @@ impl Parser {
             return None;
         }
         self.next_token();
-        let condition = self.parse_expression(Precedence::LOWEST);
+        let condition = self.parse_expression(Precedence::LOWEST)?;
         if !self.expect_peek(token::TokenType::RPAREN) {
             return None;
         }
         if !self.expect_peek(token::TokenType::LBRACE) {
             return None;
         }
-        let consequence = self.parse_block_statement();
+        let consequence = self.parse_block_statement()?;
         let mut alternative = None;
         if self.peek_token_is(token::TokenType::ELSE) {
             self.next_token();
             if !self.expect_peek(token::TokenType::LBRACE) {
                 return None;
             }
-            alternative = self.parse_block_statement();
+            alternative = Some(self.parse_block_statement()?);
         }
         Some(Box::new(ast::IfExpression {
+            span: self.span_from(&token),
             token,
-            condition: condition.unwrap(),
-            consequence:   consequence.unwrap(),
+            condition,
+            consequence,
             alternative,
         }))
     }
+
+    fn parse_match_expression(&mut self) -> Option<Box<dyn ast::Expression>> {
+        let token = self.cur_token.clone();
+        if !self.expect_peek(token::TokenType::LPAREN) {
+            return None;
+        }
+        self.next_token();
+        let subject = self.parse_expression(Precedence::LOWEST)?;
+        if !self.expect_peek(token::TokenType::RPAREN) {
+            return None;
+        }
+        if !self.expect_peek(token::TokenType::LBRACE) {
+            return None;
+        }
+        let mut arms = Vec::new();
+        while !self.peek_token_is(token::TokenType::RBRACE) {
+            self.next_token();
+            let arm = self.parse_match_arm()?;
+            // A block body ends itself; an expression body needs a `,`
+            // unless it is the last arm.
+            let braced = arm.braced();
+            arms.push(arm);
+            if self.peek_token_is(token::TokenType::COMMA) {
+                self.next_token();
+            } else if !braced && !self.peek_token_is(token::TokenType::RBRACE) && !self.expect_peek(token::TokenType::COMMA) {
+                return None;
+            }
+        }
+        self.next_token();
+        Some(Box::new(ast::MatchExpression {
+            span: self.span_from(&token),
+            token,
+            subject,
+            arms,
+        }))
+    }
+
+    fn parse_match_arm(&mut self) -> Option<ast::MatchArm> {
+        let start = self.cur_token.clone();
+        let pattern = self.parse_pattern()?;
+        let mut seen: Vec<&str> = Vec::new();
+        for name in ast::pattern_bindings(pattern.as_node()).unwrap_or_default() {
+            if seen.contains(&name.value.as_str()) {
+                self.errors.push(
+                    Diagnostic::error("E0014", &format!("`{}` is bound more than once in this pattern", name.value))
+                        .with_span(name.span),
+                );
+                return None;
+            }
+            seen.push(&name.value);
+        }
+        let mut guard = None;
+        if self.peek_token_is(token::TokenType::IF) {
+            self.next_token();
+            self.next_token();
+            guard = Some(self.parse_expression(Precedence::LOWEST)?);
+        }
+        if !self.expect_peek(token::TokenType::FAT_ARROW) {
+            return None;
+        }
+        let token = self.cur_token.clone();
+        self.next_token();
+        let body: Box<dyn ast::Statement> = if self.cur_token_is(token::TokenType::LBRACE) && self.starts_block_statement() {
+            self.parse_block_statement()?
+        } else {
+            let first = self.cur_token.clone();
+            let expression = self.parse_expression(Precedence::LOWEST)?;
+            let span = expression.span();
+            Box::new(ast::BlockStatement {
+                token: first.clone(),
+                span,
+                statements: vec![Box::new(ast::ExpressionStatement { token: first, span, expression })],
+            })
+        };
+        Some(ast::MatchArm {
+            span: self.span_from(&start),
+            token,
+            pattern,
+            guard,
+            body,
+        })
+    }
+
+    /// Parses a `match` pattern; see `ast::pattern_bindings` for what one
+    /// may contain.
+    fn parse_pattern(&mut self) -> Option<Box<dyn ast::Expression>> {
+        if self.depth >= MAX_NESTING_DEPTH {
+            self.errors.push(
+                Diagnostic::error("E0005", &format!("expression nested too deeply (more than {} levels)", MAX_NESTING_DEPTH))
+                    .with_span(self.cur_token.span),
+            );
+            return None;
+        }
+        self.depth += 1;
+        let pattern = self.parse_pattern_inner();
+        self.depth -= 1;
+        pattern
+    }
+
+    fn parse_pattern_inner(&mut self) -> Option<Box<dyn ast::Expression>> {
+        let token = self.cur_token.clone();
+        match token.type_ {
+            token::TokenType::IDENT => self.parse_identifier_expression(),
+            token::TokenType::STRING => self.parse_string_literal(),
+            token::TokenType::TRUE | token::TokenType::FALSE => self.parse_boolean(),
+            token::TokenType::INT | token::TokenType::FLOAT | token::TokenType::MINUS => self.parse_number_pattern(),
+            token::TokenType::LBRACKET => {
+                let mut elements: Vec<Box<dyn ast::Expression>> = Vec::new();
+                while !self.peek_token_is(token::TokenType::RBRACKET) {
+                    self.next_token();
+                    if self.cur_token_is(token::TokenType::DOT_DOT) {
+                        let rest_token = self.cur_token.clone();
+                        let mut name = None;
+                        if self.peek_token_is(token::TokenType::IDENT) {
+                            self.next_token();
+                            name = Some(ast::Identifier {
+                                token: self.cur_token.clone(),
+                                span: self.cur_token.span,
+                                value: self.cur_token.literal.clone(),
+                            });
+                        }
+                        let span = self.span_from(&rest_token);
+                        elements.push(Box::new(ast::RestPattern { token: rest_token, span, name }));
+                        if !self.peek_token_is(token::TokenType::RBRACKET) {
+                            self.errors.push(
+                                Diagnostic::error("E0014", "`..` must be the last element of an array pattern")
+                                    .with_span(span),
+                            );
+                            return None;
+                        }
+                        break;
+                    }
+                    elements.push(self.parse_pattern()?);
+                    if !self.peek_token_is(token::TokenType::RBRACKET) && !self.expect_peek(token::TokenType::COMMA) {
+                        return None;
+                    }
+                }
+                self.next_token();
+                Some(Box::new(ast::ArrayLiteral {
+                    span: self.span_from(&token),
+                    token,
+                    elements,
+                }))
+            }
+            token::TokenType::LBRACE => {
+                let mut pairs = Vec::new();
+                while !self.peek_token_is(token::TokenType::RBRACE) {
+                    self.next_token();
+                    let key = self.parse_pattern()?;
+                    if !ast::is_key_pattern(key.as_node()) {
+                        self.errors.push(
+                            Diagnostic::error("E0014", "hash pattern keys must be integer, string or boolean literals")
+                                .with_span(key.span()),
+                        );
+                        return None;
+                    }
+                    if !self.expect_peek(token::TokenType::COLON) {
+                        return None;
+                    }
+                    self.next_token();
+                    pairs.push((key, self.parse_pattern()?));
+                    if !self.peek_token_is(token::TokenType::RBRACE) && !self.expect_peek(token::TokenType::COMMA) {
+                        return None;
+                    }
+                }
+                self.next_token();
+                Some(Box::new(ast::HashLiteral {
+                    span: self.span_from(&token),
+                    token,
+                    pairs,
+                }))
+            }
+            _ => {
+                self.errors.push(
+                    Diagnostic::error("E0014", &format!("expected a pattern, got {:?} instead", token.type_))
+                        .with_span(token.span)
+                        .with_help("patterns are `_`, names, literals, and arrays or hashes of patterns"),
+                );
+                None
+            }
+        }
+    }
+
+    /// A number literal in a pattern, possibly negated.
+    fn parse_number_pattern(&mut self) -> Option<Box<dyn ast::Expression>> {
+        if !self.cur_token_is(token::TokenType::MINUS) {
+            return match self.cur_token.type_ {
+                token::TokenType::INT => self.parse_integer_literal(),
+                _ => self.parse_float_literal(),
+            };
+        }
+        let token = self.cur_token.clone();
+        if !self.peek_token_is(token::TokenType::INT) && !self.peek_token_is(token::TokenType::FLOAT) {
+            self.errors.push(
+                Diagnostic::error("E0014", &format!("expected a number after `-` in a pattern, got {:?} instead", self.peek_token.type_))
+                    .with_span(self.peek_token.span),
+            );
+            return None;
+        }
+        self.next_token();
+        let right = self.parse_number_pattern()?;
+        Some(Box::new(ast::PrefixExpression {
+            span: self.span_from(&token),
+            operator: token.literal.clone(),
+            token,
+            right,
+        }))
+    }
+
     fn parse_block_statement(&mut self) -> Option<Box<dyn ast::Statement>> {
         let token = self.cur_token.clone();
         let mut statements = Vec::new();
         self.next_token();
         while !self.cur_token_is(token::TokenType::RBRACE) && !self.cur_token_is(token::TokenType::EOF) {
-            let stmt = self.parse_statement();
-            if stmt.is_some() {
-                statements.push(stmt.unwrap());
+            match self.parse_statement() {
+                Some(stmt) => statements.push(stmt),
+                None => {
+                    self.synchronize();
+                    if self.cur_token_is(token::TokenType::RBRACE) {
+                        break;
+                    }
+                }
             }
             self.next_token();
         }
+        if self.cur_token_is(token::TokenType::EOF) {
+            self.errors.push(
+                Diagnostic::error("E0004", "expected RBRACE to close block, got EOF instead")
+                    .with_span(self.cur_token.span)
+                    .with_label(token.span, "block opened here")
+                    .with_help("add a `}` to close the block"),
+            );
+            return None;
+        }
         Some(Box::new(ast::BlockStatement {
+            span: self.span_from(&token),
             token,
             statements,
         }))
@@ -267,6 +702,7 @@ impl Parser {
     fn parse_boolean(&mut self) -> Option<Box<dyn ast::Expression>> {
         Some(Box::new(ast::Boolean {
             token: self.cur_token.clone(),
+            span: self.cur_token.span,
             value: self.cur_token_is(token::TokenType::TRUE),
         }))
     }
@@ -274,72 +710,113 @@ impl Parser {
     fn parse_identifier_expression(&mut self) -> Option<Box<dyn ast::Expression>> {
         Some(Box::new(ast::Identifier {
             token: self.cur_token.clone(),
+            span: self.cur_token.span,
             value: self.cur_token.literal.clone(),
         }))
     }
 
-    pub fn errors(&self) -> Vec<String> {
+    pub fn errors(&self) -> Vec<Diagnostic> {
         self.errors.clone()
     }
 
     pub fn peek_error(&mut self, t: token::TokenType) {
         let msg = format!("expected next token to be {:?}, got {:?} instead", t, self.peek_token.type_);
-        self.errors.push(msg);
+        self.errors.push(Diagnostic::error("E0001", &msg).with_span(self.peek_token.span));
     }
 
     pub fn next_token(&mut self) {
-        self.cur_token = self.peek_token.clone();
-        self.peek_token = self.lexer.next_token();
+        self.cur_token = std::mem::replace(&mut self.peek_token, self.second_peek_token.clone());
+        self.second_peek_token = self.lexer.next_token();
+        self.errors.append(&mut self.lexer.errors);
     }
 
     pub fn parse_program(&mut self) -> ast::Program {
         let mut program = ast::Program {
             statements: Vec::new(),
+            span: Span::default(),
         };
         while self.cur_token.type_ != token::TokenType::EOF {
-            let stmt = self.parse_statement();
-            match stmt {
-                Some(x) => program.statements.push(x),
-                None => (),
+            match self.parse_statement() {
+                Some(stmt) => program.statements.push(stmt),
+                None => self.synchronize(),
             }
             self.next_token();
         }
+        if let (Some(first), Some(last)) = (program.statements.first(), program.statements.last()) {
+            program.span = first.span().merge(&last.span());
+        }
         program
     } 
 
     fn parse_statement(&mut self) -> Option <Box<dyn ast::Statement>> {
         // println!("parse_statement: {:?}", self.cur_token.type_);
         match self.cur_token.type_ {
-            token::TokenType::LET => self.parse_let_statement(),
+            token::TokenType::LET | token::TokenType::CONST => self.parse_let_statement(),
             token::TokenType::RETURN => self.prase_return_statement(),
+            token::TokenType::WHILE => self.parse_while_statement(),
+            token::TokenType::FOR => self.parse_for_statement(),
+            token::TokenType::BREAK | token::TokenType::CONTINUE => self.parse_loop_control_statement(),
+            token::TokenType::LBRACE if self.starts_block_statement() => {
+                let block = self.parse_block_statement()?;
+                if self.peek_token_is(token::TokenType::SEMICOLON) {
+                    self.next_token();
+                }
+                Some(block)
+            }
             _ => self.parse_expression_statement(), 
         }
     }
 
     fn parse_expression_statement(&mut self) -> Option <Box<dyn ast::Statement>> {
         // println!("parse_expression_statement: {:?}", self.cur_token.type_);
-        let stmt = ast::ExpressionStatement {
-            token: self.cur_token.clone(),
-            expression: self.parse_expression(Precedence::LOWEST).unwrap(),
-        };
+        let token = self.cur_token.clone();
+        let expression = self.parse_expression(Precedence::LOWEST)?;
 
         if self.peek_token_is(token::TokenType::SEMICOLON) {
             self.next_token();
         }
+        let stmt = ast::ExpressionStatement {
+            span: self.span_from(&token),
+            token,
+            expression,
+        };
         Some(Box::new(stmt))
     }
     
     fn parse_integer_literal(&mut self) -> Option <Box<dyn ast::Expression>> {
-        let value = self.cur_token.literal.parse::<i64>();
-       let value = match value {
+       let value = match integer_value(&self.cur_token.literal) {
             Ok(x) => x,
             Err(_) => {
-                self.errors.push(format!("could not parse {:?} as integer", self.cur_token.literal));
+                self.errors.push(
+                    Diagnostic::error("E0003", &format!("could not parse {:?} as integer", self.cur_token.literal))
+                        .with_span(self.cur_token.span)
+                        .with_help(&format!("integer literals must not exceed {}", i64::MAX)),
+                );
                 return None;
             },
        };
         let lit = ast::IntegerLiteral {
             token: self.cur_token.clone(),
+            span: self.cur_token.span,
+            value
+        };
+        Some(Box::new(lit))
+    }
+
+    fn parse_float_literal(&mut self) -> Option <Box<dyn ast::Expression>> {
+        let value = match float_value(&self.cur_token.literal) {
+            Ok(x) => x,
+            Err(_) => {
+                self.errors.push(
+                    Diagnostic::error("E0003", &format!("could not parse {:?} as float", self.cur_token.literal))
+                        .with_span(self.cur_token.span),
+                );
+                return None;
+            },
+        };
+        let lit = ast::FloatLiteral {
+            token: self.cur_token.clone(),
+            span: self.cur_token.span,
             value
         };
         Some(Box::new(lit))
@@ -347,26 +824,40 @@ impl Parser {
 
     fn parse_expression(&mut self, precedence: Precedence) -> Option <Box<dyn ast::Expression>> {
         // println!("parse_expression: {:?}", self.cur_token.type_);
-        let prefix = self.perfix_parse_fns.get(&self.cur_token.type_);
+        if self.depth >= MAX_NESTING_DEPTH {
+            self.errors.push(
+                Diagnostic::error("E0005", &format!("expression nested too deeply (more than {} levels)", MAX_NESTING_DEPTH))
+                    .with_span(self.cur_token.span),
+            );
+            return None;
+        }
+        self.depth += 1;
+        let expression = self.parse_expression_inner(precedence);
+        self.depth -= 1;
+        expression
+    }
+
+    fn parse_expression_inner(&mut self, precedence: Precedence) -> Option <Box<dyn ast::Expression>> {
+        let prefix = self.perfix_parse_fns.get(&self.cur_token.type_).copied();
         let mut left_expression = match prefix {
-            Some(prefix_fn) => prefix_fn(self),
+            Some(prefix_fn) => prefix_fn(self)?,
             None => {
-                // println!("no prefix parse function for {:?} found", self.cur_token.type_);
-                self.errors.push(format!("no prefix parse function for {:?} found", self.cur_token.type_));
+                self.errors.push(
+                    Diagnostic::error("E0002", &format!("no prefix parse function for {:?} found", self.cur_token.type_))
+                        .with_span(self.cur_token.span),
+                );
                 return None;
             },
         };
         while !self.peek_token_is(token::TokenType::SEMICOLON) && precedence < self.peek_precedence() {
-            let copy_self = self.clone();
-            let peek_token = self.peek_token.type_.clone();
-            let infix_fn = match copy_self.infix_parse_fns.get(&peek_token){
+            let infix_fn = match self.infix_parse_fns.get(&self.peek_token.type_).copied() {
                 Some(infix_fn) => infix_fn,
-                None => return left_expression,
+                None => return Some(left_expression),
             };
             self.next_token();
-            left_expression = infix_fn(self, left_expression);
+            left_expression = infix_fn(self, Some(left_expression))?;
         }
-        left_expression
+        Some(left_expression)
     }
     
     pub fn parse_prefix_expression(&mut self) -> Option<Box<dyn ast::Expression>> {
@@ -375,22 +866,26 @@ impl Parser {
         self.next_token();
 
 
+        let right = self.parse_expression(Precedence::PREFIX)?;
         let expresion = ast::PrefixExpression {
+            span: self.span_from(&token),
             token,
             operator,
-            right: self.parse_expression(Precedence::PREFIX).unwrap(),
+            right,
         };
         Some(Box::new(expresion))
     }
 
     fn parse_let_statement(&mut self) -> Option <Box<dyn ast::Statement>> {
         let token = self.cur_token.clone();
+        let doc = self.lexer.take_doc_comment(token.span.start);
 
         if !self.expect_peek(token::TokenType::IDENT) {
             return None;
         }
         let name = ast::Identifier {
             token: self.cur_token.clone(),
+            span: self.cur_token.span,
             value: self.cur_token.literal.clone(),
         };
 
@@ -399,30 +894,145 @@ impl Parser {
         }
         self.next_token();
 
-         let stmt = ast::LetStatement {
-            token,
-            name,
-            value: self.parse_expression(Precedence::LOWEST).unwrap(),
-        };
+        let value = self.parse_expression(Precedence::LOWEST)?;
         if self.peek_token_is(token::TokenType::SEMICOLON) {
             self.next_token();
         };
-   
-     
+        let stmt = ast::LetStatement {
+            span: self.span_from(&token),
+            token,
+            name,
+            value,
+            doc,
+        };
         Some(Box::new(stmt))
     }
     
     fn prase_return_statement(&mut self) -> Option<Box<dyn ast::Statement>> {
         let token = self.cur_token.clone();
         self.next_token();
+        let return_value = self.parse_expression(Precedence::LOWEST)?;
+        if self.peek_token_is(token::TokenType::SEMICOLON) {
+            self.next_token();
+        }
         let stmt = ast::ReturnStatement {
+            span: self.span_from(&token),
             token,
-            return_value: self.parse_expression(Precedence::LOWEST).unwrap(),
+            return_value,
         };
-        while !self.cur_token_is(token::TokenType::SEMICOLON) {
+        Some(Box::new(stmt))
+    }
+
+    fn parse_while_statement(&mut self) -> Option<Box<dyn ast::Statement>> {
+        let token = self.cur_token.clone();
+        if !self.expect_peek(token::TokenType::LPAREN) {
+            return None;
+        }
+        self.next_token();
+        let condition = self.parse_expression(Precedence::LOWEST)?;
+        if !self.expect_peek(token::TokenType::RPAREN) {
+            return None;
+        }
+        if !self.expect_peek(token::TokenType::LBRACE) {
+            return None;
+        }
+        self.loop_depth += 1;
+        let body = self.parse_block_statement();
+        self.loop_depth -= 1;
+        let body = body?;
+        if self.peek_token_is(token::TokenType::SEMICOLON) {
             self.next_token();
         }
-        Some(Box::new(stmt))
+        Some(Box::new(ast::WhileStatement {
+            span: self.span_from(&token),
+            token,
+            condition,
+            body,
+        }))
+    }
+
+    fn parse_for_statement(&mut self) -> Option<Box<dyn ast::Statement>> {
+        let token = self.cur_token.clone();
+        if !self.expect_peek(token::TokenType::LPAREN) {
+            return None;
+        }
+        if !self.expect_peek(token::TokenType::IDENT) {
+            return None;
+        }
+        let variable = ast::Identifier {
+            token: self.cur_token.clone(),
+            span: self.cur_token.span,
+            value: self.cur_token.literal.clone(),
+        };
+        if !self.expect_peek(token::TokenType::IN) {
+            return None;
+        }
+        self.next_token();
+        let iterable = self.parse_expression(Precedence::LOWEST)?;
+        if !self.expect_peek(token::TokenType::RPAREN) {
+            return None;
+        }
+        if !self.expect_peek(token::TokenType::LBRACE) {
+            return None;
+        }
+        self.loop_depth += 1;
+        let body = self.parse_block_statement();
+        self.loop_depth -= 1;
+        let body = body?;
+        if self.peek_token_is(token::TokenType::SEMICOLON) {
+            self.next_token();
+        }
+        Some(Box::new(ast::ForStatement {
+            span: self.span_from(&token),
+            token,
+            variable,
+            iterable,
+            body,
+        }))
+    }
+
+    /// Parses `break` or `continue`, which are only allowed inside a loop of
+    /// the function they appear in.
+    fn parse_loop_control_statement(&mut self) -> Option<Box<dyn ast::Statement>> {
+        let token = self.cur_token.clone();
+        if self.loop_depth == 0 {
+            self.errors.push(
+                Diagnostic::error("E0012", &format!("`{}` outside of a loop", token.literal))
+                    .with_span(token.span)
+                    .with_help("`break` and `continue` can only be used inside a `while` or `for` loop"),
+            );
+            return None;
+        }
+        if self.peek_token_is(token::TokenType::SEMICOLON) {
+            self.next_token();
+        }
+        let span = self.span_from(&token);
+        match token.type_ {
+            token::TokenType::BREAK => Some(Box::new(ast::BreakStatement { token, span })),
+            _ => Some(Box::new(ast::ContinueStatement { token, span })),
+        }
+    }
+
+    /// Skips the rest of a statement that failed to parse so the next one can
+    /// be parsed normally. Stops on a `;`, or just before `let`, `const`,
+    /// `return`, `while`, `for`, `}` or the end of input; the caller then
+    /// advances past the current token.
+    fn synchronize(&mut self) {
+        while !self.cur_token_is(token::TokenType::EOF) {
+            if self.cur_token_is(token::TokenType::SEMICOLON) || self.cur_token_is(token::TokenType::RBRACE) {
+                return;
+            }
+            match self.peek_token.type_ {
+                token::TokenType::LET | token::TokenType::CONST | token::TokenType::RETURN | token::TokenType::WHILE
+                | token::TokenType::FOR | token::TokenType::RBRACE | token::TokenType::EOF => return,
+                _ => self.next_token(),
+            }
+        }
+    }
+
+    /// Span running from `start` up to and including the current token.
+    fn span_from(&self, start: &Token) -> Span {
+        start.span.merge(&self.cur_token.span)
     }
 
     fn cur_token_is(&self, t: token::TokenType) -> bool {
@@ -455,7 +1065,7 @@ mod test {
         Boolean(bool),        
     }
 
-    fn test_literal_expresion(x: &Box<dyn ast::Expression>, expected: Literal){
+    fn test_literal_expresion(x: &dyn ast::Expression, expected: Literal){
         match expected {
             Literal::IntegerLiteral(i) => {
                 match x.as_any().downcast_ref::<ast::IntegerLiteral>() {
@@ -478,9 +1088,9 @@ mod test {
             None => panic!("s not InfixExpression. got={}", x.token_literal()),
         };
 
-        test_literal_expresion(&infix.left, Literal::IntegerLiteral(left));
+        test_literal_expresion(infix.left.as_ref(), Literal::IntegerLiteral(left));
         assert_eq!(infix.operator, operator);
-        test_literal_expresion(&infix.right, Literal::IntegerLiteral(right));
+        test_literal_expresion(infix.right.as_ref(), Literal::IntegerLiteral(right));
     }
 
     fn test_identifier(x:&dyn ast::Expression, expected: String){
@@ -492,7 +1102,7 @@ mod test {
         assert_eq!(ident.token_literal(), expected);
     }
 
-    fn test_let_statement(s: &Box<dyn ast::Statement>, name: &str) {
+    fn test_let_statement(s: &dyn ast::Statement, name: &str) {
         assert_eq!(s.token_literal(), "let");
          let let_stmt = match s.as_any().downcast_ref::<ast::LetStatement>() {
             Some(stmt) => stmt,
@@ -507,7 +1117,7 @@ mod test {
 
     fn check_parser_errors(p: &Parser) {
         let errors = p.errors();
-        if errors.len() == 0 {
+        if errors.is_empty() {
             return;
         }
         println!("parser has {} errors", errors.len());
@@ -517,20 +1127,39 @@ mod test {
         panic!();
     }
     #[test]
-
     fn test_let_statements() {
-        let input = vec![("let x = 5;", "x", 5), ("let y = 10;", "y", 10), ("let foobar = 838383;", "foobar", 838383)];
-        for (_, tt) in input.iter().enumerate() {
+        let input = [("let x = 5;", "x", 5), ("let y = 10;", "y", 10), ("let foobar = 838383;", "foobar", 838383)];
+        for tt in input.iter() {
             let l = Lexer::new(tt.0.to_string());
             let mut p = Parser::new(l);
             let program = p.parse_program();
             check_parser_errors(&p);
             assert_eq!(program.statements.len(), 1);
             let stmt = &program.statements[0];
-            test_let_statement(stmt, tt.1);
+            test_let_statement(stmt.as_ref(), tt.1);
         }
 
     }
+    #[test]
+    fn test_const_statements() {
+        let input = "/// Upper bound.\nconst limit = 10 * 2;\nconst f = fn() { limit }";
+        let l = Lexer::new(input.to_string());
+        let mut p = Parser::new(l);
+        let program = p.parse_program();
+        check_parser_errors(&p);
+        assert_eq!(program.statements.len(), 2);
+        let stmt = program.statements[0].as_any().downcast_ref::<ast::LetStatement>().unwrap();
+        assert_eq!(stmt.node_type(), ast::NodeType::ConstStatement);
+        assert_eq!(stmt.name.value, "limit");
+        assert_eq!(stmt.doc.as_deref(), Some("Upper bound."));
+        assert_eq!(stmt.span, Span::new(17, 38, 2, 1));
+        assert_eq!(program.string(), "const limit = (10 * 2);const f = fn() limit;");
+
+        let mut p = Parser::new(Lexer::new("const = 1; let y = 2;".to_string()));
+        let program = p.parse_program();
+        assert_eq!(p.errors()[0].message, "expected next token to be IDENT, got ASSIGN instead");
+        assert_eq!(program.statements.len(), 1);
+    }
     // #[test]
     // #[should_panic]
     // fn test_2(){
@@ -601,6 +1230,25 @@ mod test {
         assert_eq!(literal.token_literal(), "5");
     }
     #[test]
+    fn test_float_literal_expression() {
+        let input = "2.5e-3;";
+        let l = Lexer::new(input.to_string());
+        let mut p = Parser::new(l);
+        let program = p.parse_program();
+        check_parser_errors(&p);
+        assert_eq!(program.statements.len(), 1);
+        let stmt = &program.statements[0];
+        let literal = match stmt.as_any().downcast_ref::<ast::ExpressionStatement>() {
+            Some(stmt) => match stmt.expression.as_any().downcast_ref::<ast::FloatLiteral>() {
+                Some(literal) => literal,
+                None => panic!("s not FloatLiteral. got={}", stmt.token_literal()),
+            },
+            None => panic!("s not ExpressionStatement. got={}", stmt.token_literal()),
+        };
+        assert_eq!(literal.value, 0.0025);
+        assert_eq!(literal.string(), "2.5e-3");
+    }
+    #[test]
     fn test_perfix_operator() {
         let tests = vec![
             ("!5;", "!", 5),
@@ -695,6 +1343,15 @@ mod test {
             ("a + add(b * c) + d", "((a + add((b * c))) + d)"),
             ("add(a, b, 1, 2 * 3, 4 + 5, add(6, 7 * 8))", "add(a, b, 1, (2 * 3), (4 + 5), add(6, (7 * 8)))"),
             ("add(a + b + c * d / f + g)", "add((((a + b) + ((c * d) / f)) + g))"),
+            ("a * [1, 2, 3, 4][b * c] * d", "((a * ([1, 2, 3, 4][(b * c)])) * d)"),
+            ("add(a * b[2], b[1], 2 * [1, 2][1])", "add((a * (b[2])), (b[1]), (2 * ([1, 2][1])))"),
+            ("a + b % c * d", "(a + ((b % c) * d))"),
+            ("a <= b == c >= d", "((a <= b) == (c >= d))"),
+            ("a || b && c == d", "(a || (b && (c == d)))"),
+            ("a && b || c && d", "((a && b) || (c && d))"),
+            ("!a || -b < c", "((!a) || ((-b) < c))"),
+            ("0..n + 1", "(0 .. (n + 1))"),
+            ("a < 0..=-b * 2", "(a < (0 ..= ((-b) * 2)))"),
            
 
 
@@ -786,9 +1443,8 @@ mod test {
             None => panic!("s not ExpressionStatement. got={}", stmt.token_literal()),
         };
         assert_eq!(consequence.value, "x");
-        match expression.alternative {
-            Some(_) => panic!("expression.alternative was not None. got="),
-            None => {}
+        if expression.alternative.is_some() {
+            panic!("expression.alternative was not None. got=");
         }
             
         }
@@ -967,5 +1623,443 @@ mod test {
         assert_eq!(expression.value, "hello world");
     }
 
+    #[test]
+    fn test_node_spans(){
+        let input = "let x = 1;\nadd(x, 2 * 3);";
+        let l = Lexer::new(input.to_string());
+        let mut p = Parser::new(l);
+        let program = p.parse_program();
+        check_parser_errors(&p);
+        assert_eq!(program.span(), Span::new(0, 25, 1, 1));
+        let let_stmt = program.statements[0].as_any().downcast_ref::<ast::LetStatement>().unwrap();
+        assert_eq!(let_stmt.span, Span::new(0, 10, 1, 1));
+        assert_eq!(let_stmt.name.span, Span::new(4, 5, 1, 5));
+        let stmt = program.statements[1].as_any().downcast_ref::<ast::ExpressionStatement>().unwrap();
+        assert_eq!(stmt.span, Span::new(11, 25, 2, 1));
+        let call = stmt.expression.as_any().downcast_ref::<ast::CallExpression>().unwrap();
+        assert_eq!(call.span, Span::new(11, 24, 2, 1));
+        assert_eq!(call.arguments[1].span(), Span::new(18, 23, 2, 8));
+    }
+
+    #[test]
+    fn test_error_recovery(){
+        let input = "let x = ; let = 5; let y = 3; -); return 7";
+        let l = Lexer::new(input.to_string());
+        let mut p = Parser::new(l);
+        let program = p.parse_program();
+        let messages: Vec<String> = p.errors().iter().map(|e| e.message.clone()).collect();
+        assert_eq!(messages, vec![
+            "no prefix parse function for SEMICOLON found",
+            "expected next token to be IDENT, got ASSIGN instead",
+            "no prefix parse function for RPAREN found",
+        ]);
+        assert_eq!(program.string(), "let y = 3;return 7;");
+    }
+
+    #[test]
+    fn test_error_recovery_in_block(){
+        let input = "let f = fn(a) { let b = ; a * }; let g = 1;";
+        let l = Lexer::new(input.to_string());
+        let mut p = Parser::new(l);
+        let program = p.parse_program();
+        assert_eq!(p.errors().len(), 2);
+        assert_eq!(program.string(), "let f = fn(a) ;let g = 1;");
+    }
+
+    #[test]
+    fn test_doc_comments_on_let_statements(){
+        let input = "/// The answer.\n/// Always 42.\nlet answer = 42; // trailing\n/* note */ let other = /// not attached\n 1;\nlet plain = 2;";
+        let l = Lexer::new(input.to_string());
+        let mut p = Parser::new(l);
+        let program = p.parse_program();
+        check_parser_errors(&p);
+        let docs: Vec<Option<String>> = program.statements.iter()
+            .map(|s| s.as_any().downcast_ref::<ast::LetStatement>().unwrap().doc.clone())
+            .collect();
+        assert_eq!(docs, vec![Some(String::from("The answer.\nAlways 42.")), None, None]);
+    }
+
+    #[test]
+    fn test_interpolated_strings(){
+        let tests = vec![
+            (r#""hello ${name}, you are ${age + 1}""#, r#""hello ${name}, you are ${(age + 1)}""#),
+            (r#""${"in" + "ner ${x}"}\n""#, r#""${(in + "ner ${x}")}\n""#),
+            (r#""\${not} \"q\"""#, r#"${not} "q""#),
+            (r#"f("${-a}")"#, r#"f("${(-a)}")"#),
+        ];
+        for (input, expected) in tests {
+            let mut p = Parser::new(Lexer::new(input.to_string()));
+            let program = p.parse_program();
+            check_parser_errors(&p);
+            assert_eq!(program.string(), expected);
+        }
+
+        let tests = vec![
+            (r#""a ${}""#, "E0011", "empty `${}` in string literal", Span::new(0, 7, 1, 1)),
+            (r#""a ${1 2}""#, "E0001", "expected next token to be EOF, got INT instead", Span::new(7, 8, 1, 8)),
+            ("\"a\n ${x + }\"", "E0002", "no prefix parse function for EOF found", Span::new(10, 10, 2, 8)),
+            (r#""${"\q"}""#, "E0007", "invalid escape sequence `\\q`", Span::new(4, 6, 1, 5)),
+        ];
+        for (input, code, message, span) in tests {
+            let mut p = Parser::new(Lexer::new(input.to_string()));
+            p.parse_program();
+            assert_eq!(p.errors.len(), 1, "{}: {:?}", input, p.errors);
+            assert_eq!((p.errors[0].code, p.errors[0].message.as_str(), p.errors[0].span), (code, message, Some(span)), "{}", input);
+        }
+    }
+
+    #[test]
+    fn test_deeply_nested_interpolation(){
+        let input = format!("{}1{}", "\"${".repeat(100), "}\"".repeat(100));
+        let mut p = Parser::new(Lexer::new(input));
+        p.parse_program();
+        assert_eq!(p.errors.len(), 1, "{:?}", p.errors);
+        assert_eq!(p.errors[0].message, "string interpolation nested too deeply (more than 32 levels)");
+    }
+
+    #[test]
+    fn test_malformed_programs_do_not_panic(){
+        let corpus = vec![
+            "let x = ;",
+            "-)",
+            "let",
+            "let x",
+            "let x =",
+            "return",
+            "return ;",
+            "fn",
+            "fn(",
+            "fn(x,",
+            "fn(x, 1) { x }",
+            "fn(x) {",
+            "fn(x) { x",
+            "if",
+            "if (",
+            "if (x",
+            "if (x) {",
+            "if (x) { 1 } else",
+            "if (x) { 1 } else {",
+            "add(",
+            "add(1,",
+            "add(1 2)",
+            "(((",
+            ")))",
+            "}}}",
+            "1 + + 2",
+            "* 3",
+            "!",
+            "99999999999999999999999",
+            "let 5 = 5;",
+            "; ; ;",
+            "@ # $",
+            "\"unterminated",
+            "1 /* never closed",
+            "12abc",
+            "0x",
+            "0b102",
+            "0x1_0000_0000_0000_0000",
+            "\"${\"",
+            "\"${}\"",
+            "\"${1 2}\"",
+            "\"${ let x = 1; }\"",
+            "let s = \"bad \\q escape\";",
+            "r#\"raw",
+            "\"\"\"never closed\n",
+            "while",
+            "while (x",
+            "while (x) {",
+            "while x { 1 }",
+            "break;",
+            "while (x) { fn() { continue } }",
+            "match",
+            "match x { _ => 1 }",
+            "match (x) { 1 }",
+            "match (x) { 1 => 2 3 => 4 }",
+            "match (x) { f(y) => 1 }",
+            "match (x) { -y => 1 }",
+            "match (x) { [..rest, y] => 1 }",
+            "{ let x = 1;",
+            "match (x) { {k: v} => 1 }",
+        ];
+        for input in corpus {
+            let l = Lexer::new(input.to_string());
+            let mut p = Parser::new(l);
+            p.parse_program();
+            assert!(!p.errors().is_empty(), "expected errors for {:?}", input);
+        }
+    }
+
+    #[test]
+    fn test_random_token_soup_does_not_panic(){
+        let fragments = [
+            "let", "return", "fn", "if", "else", "true", "x", "1", "\"s\"", "while", "break", "continue",
+            "=", "+", "-", "!", "*", "/", "<", ">", "==", "!=", "<=", ">=", "%", "&&", "||", "&",
+            "0x", "0b1", "_", "1_0", "e", ".",
+            ",", ";", "(", ")", "{", "}", "#", "\"", "r#\"", "${", "\\", "//", "/*", "*/", "///",
+            "match", "=>", "..", "[", "]",
+        ];
+        let mut seed: u64 = 0x2545F4914F6CDD1D;
+        for _ in 0..2000 {
+            let mut input = String::new();
+            for _ in 0..(seed % 24) {
+                seed ^= seed << 13;
+                seed ^= seed >> 7;
+                seed ^= seed << 17;
+                input.push_str(fragments[(seed % fragments.len() as u64) as usize]);
+                input.push(' ');
+            }
+            let l = Lexer::new(input);
+            let mut p = Parser::new(l);
+            p.parse_program();
+        }
+    }
+
+    #[test]
+    fn test_deep_nesting_reports_error(){
+        let input = format!("{}1{}", "(".repeat(10_000), ")".repeat(10_000));
+        let l = Lexer::new(input);
+        let mut p = Parser::new(l);
+        p.parse_program();
+        assert!(p.errors()[0].message.starts_with("expression nested too deeply"));
+
+        let input = format!("{}1{}", "(".repeat(100), ")".repeat(100));
+        let l = Lexer::new(input);
+        let mut p = Parser::new(l);
+        p.parse_program();
+        check_parser_errors(&p);
+    }
+
+    #[test]
+    fn test_array_literal_parsing(){
+        let input = "[1, 2 * 2, 3 + 3]";
+        let l = Lexer::new(input.to_string());
+        let mut p = Parser::new(l);
+        let program = p.parse_program();
+        check_parser_errors(&p);
+        let stmt = program.statements[0].as_any().downcast_ref::<ast::ExpressionStatement>().unwrap();
+        let array = match stmt.expression.as_any().downcast_ref::<ast::ArrayLiteral>() {
+            Some(array) => array,
+            None => panic!("s not ArrayLiteral. got={}", stmt.token_literal()),
+        };
+        assert_eq!(array.elements.len(), 3);
+        test_literal_expresion(array.elements[0].as_ref(), Literal::IntegerLiteral(1));
+        test_infix_expression(array.elements[1].as_ref(), 2, String::from("*"), 2);
+        test_infix_expression(array.elements[2].as_ref(), 3, String::from("+"), 3);
+        assert_eq!(array.span, Span::new(0, 17, 1, 1));
+
+        let l = Lexer::new(String::from("[]"));
+        let mut p = Parser::new(l);
+        let program = p.parse_program();
+        check_parser_errors(&p);
+        assert_eq!(program.string(), "[]");
+    }
+
+    #[test]
+    fn test_index_expression_parsing(){
+        let input = "myArray[1 + 1]";
+        let l = Lexer::new(input.to_string());
+        let mut p = Parser::new(l);
+        let program = p.parse_program();
+        check_parser_errors(&p);
+        let stmt = program.statements[0].as_any().downcast_ref::<ast::ExpressionStatement>().unwrap();
+        let index = match stmt.expression.as_any().downcast_ref::<ast::IndexExpression>() {
+            Some(index) => index,
+            None => panic!("s not IndexExpression. got={}", stmt.token_literal()),
+        };
+        test_identifier(index.left.as_ref(), String::from("myArray"));
+        test_infix_expression(index.index.as_ref(), 1, String::from("+"), 1);
+        assert_eq!(index.span, Span::new(0, 14, 1, 1));
+    }
+
+    #[test]
+    fn test_hash_literal_parsing(){
+        let tests = vec![
+            (r#"{"one": 1, "two": 2, "three": 3}"#, "{one: 1, two: 2, three: 3}"),
+            ("{}", "{}"),
+            (r#"{"one": 0 + 1, true: 10 - 8, 3: 15 / 5}"#, "{one: (0 + 1), true: (10 - 8), 3: (15 / 5)}"),
+            (r#"let h = {"a": {"b": 1}}; h["a"]["b"]"#, "let h = {a: {b: 1}};((h[a])[b])"),
+            ("{x: 1};", "{x: 1}"),
+        ];
+        for (input, expected) in tests {
+            let l = Lexer::new(input.to_string());
+            let mut p = Parser::new(l);
+            let program = p.parse_program();
+            check_parser_errors(&p);
+            assert_eq!(program.string(), expected);
+        }
+        let l = Lexer::new(String::from("{}"));
+        let mut p = Parser::new(l);
+        let program = p.parse_program();
+        let stmt = program.statements[0].as_any().downcast_ref::<ast::ExpressionStatement>().unwrap();
+        assert!(stmt.expression.as_any().downcast_ref::<ast::HashLiteral>().is_some());
+    }
+
+    #[test]
+    fn test_while_statement(){
+        let input = "while (x < 10) { if (x == 5) { break; } continue }";
+        let l = Lexer::new(input.to_string());
+        let mut p = Parser::new(l);
+        let program = p.parse_program();
+        check_parser_errors(&p);
+        assert_eq!(program.statements.len(), 1);
+        let stmt = program.statements[0].as_any().downcast_ref::<ast::WhileStatement>().unwrap();
+        assert_eq!(stmt.condition.string(), "(x < 10)");
+        assert_eq!(stmt.span, Span::new(0, 50, 1, 1));
+        let body = stmt.body.as_any().downcast_ref::<ast::BlockStatement>().unwrap();
+        assert_eq!(body.statements.len(), 2);
+        assert_eq!(body.statements[1].node_type(), ast::NodeType::ContinueStatement);
+        assert_eq!(program.string(), "while(x < 10) if(x == 5) break;continue;");
+    }
+
+    #[test]
+    fn test_for_statement(){
+        let input = "for (x in 0..len(xs)) { if (x == 5) { break; } puts(xs[x]); };";
+        let l = Lexer::new(input.to_string());
+        let mut p = Parser::new(l);
+        let program = p.parse_program();
+        check_parser_errors(&p);
+        assert_eq!(program.statements.len(), 1);
+        let stmt = program.statements[0].as_any().downcast_ref::<ast::ForStatement>().unwrap();
+        assert_eq!(stmt.variable.value, "x");
+        assert_eq!(stmt.iterable.string(), "(0 .. len(xs))");
+        assert_eq!(stmt.span, Span::new(0, 62, 1, 1));
+        assert_eq!(program.string(), "for(x in (0 .. len(xs))) if(x == 5) break;puts((xs[x]))");
+
+        for (input, message) in [
+            ("for x in xs { }", "expected next token to be LPAREN, got IDENT instead"),
+            ("for (1 in xs) { }", "expected next token to be IDENT, got INT instead"),
+            ("for (x of xs) { }", "expected next token to be IN, got IDENT instead"),
+        ] {
+            let mut p = Parser::new(Lexer::new(input.to_string()));
+            p.parse_program();
+            assert_eq!(p.errors()[0].message, message, "{}", input);
+        }
+    }
+
+    #[test]
+    fn test_assign_expression(){
+        let tests = vec![
+            ("x = 1", "(x = 1)"),
+            ("x = y = 1", "(x = (y = 1))"),
+            ("a[0] += 1 + 2", "((a[0]) += (1 + 2))"),
+            ("m[i][j] *= 2;", "(((m[i])[j]) *= 2)"),
+            ("n -= 1 == 0", "(n -= (1 == 0))"),
+            ("f(x /= 2)", "f((x /= 2))"),
+        ];
+        for (input, expected) in tests {
+            let l = Lexer::new(input.to_string());
+            let mut p = Parser::new(l);
+            let program = p.parse_program();
+            check_parser_errors(&p);
+            assert_eq!(program.string(), expected);
+        }
+
+        let l = Lexer::new(String::from("count += 1"));
+        let mut p = Parser::new(l);
+        let program = p.parse_program();
+        let stmt = program.statements[0].as_any().downcast_ref::<ast::ExpressionStatement>().unwrap();
+        let assign = stmt.expression.as_any().downcast_ref::<ast::AssignExpression>().unwrap();
+        test_identifier(assign.target.as_ref(), String::from("count"));
+        assert_eq!(assign.operator, "+=");
+        test_literal_expresion(assign.value.as_ref(), Literal::IntegerLiteral(1));
+        assert_eq!(assign.span, Span::new(0, 10, 1, 1));
+
+        for (input, span) in [
+            ("1 = 2", Span::new(0, 1, 1, 1)),
+            ("let y = f() = 1;", Span::new(8, 11, 1, 9)),
+            ("x + y = 1", Span::new(0, 5, 1, 1)),
+        ] {
+            let mut p = Parser::new(Lexer::new(input.to_string()));
+            p.parse_program();
+            let error = &p.errors()[0];
+            assert_eq!((error.code, error.message.as_str(), error.span), ("E0013", "invalid left-hand side of `=`", Some(span)), "{}", input);
+        }
+    }
+
+    #[test]
+    fn test_loop_control_outside_loop(){
+        let input = "break; while (a) { let f = fn() { continue; }; \"${ break }\"; break; }\ncontinue";
+        let l = Lexer::new(input.to_string());
+        let mut p = Parser::new(l);
+        let program = p.parse_program();
+        let errors: Vec<(&str, String, Span)> = p.errors().iter().map(|e| (e.code, e.message.clone(), e.span.unwrap())).collect();
+        assert_eq!(errors, vec![
+            ("E0012", String::from("`break` outside of a loop"), Span::new(0, 5, 1, 1)),
+            ("E0012", String::from("`continue` outside of a loop"), Span::new(34, 42, 1, 35)),
+            ("E0002", String::from("no prefix parse function for BREAK found"), Span::new(51, 56, 1, 52)),
+            ("E0012", String::from("`continue` outside of a loop"), Span::new(70, 78, 2, 1)),
+        ]);
+        assert_eq!(program.statements.len(), 1);
+    }
+
+    #[test]
+    fn test_block_statement_disambiguation(){
+        let input = "{ let a = 1; a }; { x }; { let x = 1; }";
+        let l = Lexer::new(input.to_string());
+        let mut p = Parser::new(l);
+        let program = p.parse_program();
+        check_parser_errors(&p);
+        assert_eq!(program.statements.len(), 3);
+        for stmt in program.statements.iter() {
+            assert!(stmt.as_any().downcast_ref::<ast::BlockStatement>().is_some());
+        }
+
+        for input in ["{1: }", "let h = {1 2};", r#"{"a": 1"#, r#"let h = {"a" 1};"#] {
+            let l = Lexer::new(input.to_string());
+            let mut p = Parser::new(l);
+            p.parse_program();
+            assert!(!p.errors().is_empty(), "expected errors for {:?}", input);
+        }
+    }
+
+
+    #[test]
+    fn test_match_expression(){
+        let tests = vec![
+            ("match (x) { 0 => \"zero\", n if n < 0 => { -n } _ => n }", "match (x) {0 => zero, n if (n < 0) => (-n), _ => n}"),
+            ("match (xs) { [] => 0, [a, ..] => a, [_, ..rest] => rest, }", "match (xs) {[] => 0, [a, ..] => a, [_, ..rest] => rest}"),
+            ("match (h) { {\"k\": [v], 1: true, -2: -1.5} => v }", "match (h) {{k: [v], 1: true, (-2): (-1.5)} => v}"),
+            ("let y = match (f(x)) {};", "let y = match (f(x)) {};"),
+            ("match (x) { _ => {} }", "match (x) {_ => {}}"),
+        ];
+        for (input, expected) in tests {
+            let l = Lexer::new(input.to_string());
+            let mut p = Parser::new(l);
+            let program = p.parse_program();
+            check_parser_errors(&p);
+            assert_eq!(program.string(), expected, "{}", input);
+        }
+
+        let l = Lexer::new(String::from("match (x) { [a, ..b] if a => a, _ => { 0 } }"));
+        let mut p = Parser::new(l);
+        let program = p.parse_program();
+        check_parser_errors(&p);
+        let stmt = program.statements[0].as_any().downcast_ref::<ast::ExpressionStatement>().unwrap();
+        let expression = stmt.expression.as_any().downcast_ref::<ast::MatchExpression>().unwrap();
+        test_identifier(expression.subject.as_ref(), String::from("x"));
+        assert_eq!(expression.span, Span::new(0, 44, 1, 1));
+        let arm = &expression.arms[0];
+        assert_eq!((arm.span, arm.braced()), (Span::new(12, 30, 1, 13), false));
+        let bindings: Vec<&str> = ast::pattern_bindings(arm.pattern.as_node()).unwrap().iter().map(|name| name.value.as_str()).collect();
+        assert_eq!(bindings, vec!["a", "b"]);
+        test_identifier(arm.guard.as_ref().unwrap().as_ref(), String::from("a"));
+        assert!(expression.arms[1].braced());
+
+        for (input, message, span) in [
+            ("match (x) { x + 1 => 1 }", "expected next token to be FAT_ARROW, got PLUS instead", Span::new(14, 15, 1, 15)),
+            ("match (x) { f => 1 2 }", "expected next token to be COMMA, got INT instead", Span::new(19, 20, 1, 20)),
+            ("match (x) { (y) => 1 }", "expected a pattern, got LPAREN instead", Span::new(12, 13, 1, 13)),
+            ("match (x) { [..a, b] => 1 }", "`..` must be the last element of an array pattern", Span::new(13, 16, 1, 14)),
+            ("match (x) { {k: v} => 1 }", "hash pattern keys must be integer, string or boolean literals", Span::new(13, 14, 1, 14)),
+            ("match (x) { [a, {\"b\": a}] => 1 }", "`a` is bound more than once in this pattern", Span::new(22, 23, 1, 23)),
+            ("match (x) { -y => 1 }", "expected a number after `-` in a pattern, got IDENT instead", Span::new(13, 14, 1, 14)),
+        ] {
+            let mut p = Parser::new(Lexer::new(input.to_string()));
+            p.parse_program();
+            let error = &p.errors()[0];
+            assert_eq!((error.message.as_str(), error.span), (message, Some(span)), "{}", input);
+        }
+    }
+
   
 }
@@ -1,5 +1,9 @@
 use std::io::{BufRead, Write};
+use crate::builtins::{self, Output};
+use crate::cli;
+use crate::diagnostic::Diagnostic;
 use crate::envoriment::Environment;
+use crate::object;
 
 use crate::{lexer::Lexer,  parser:: Parser};
 const MONKEY_FACE:&str = r#" 
@@ -18,8 +22,10 @@ const MONKEY_FACE:&str = r#"
 "#;
 const PROMT: &str = ">> ";
 
-pub fn  start<R: BufRead, W: Write>(reader: &mut R, writer: &mut W) {
+pub fn  start<R: BufRead>(reader: &mut R, writer: &mut Output) {
     let mut  env = Environment::new();
+    builtins::set_output(writer.clone());
+    let mut show_tokens = false;
     loop {
         write!(writer, "{}", PROMT).unwrap();
         writer.flush().unwrap();
@@ -29,29 +35,39 @@ pub fn  start<R: BufRead, W: Write>(reader: &mut R, writer: &mut W) {
         if line.trim() == "exit" {
             break;
         }
-        let  l = Lexer::new(line);
+        if line.trim() == ":tokens" {
+            show_tokens = !show_tokens;
+            writeln!(writer, "token dump {}", if show_tokens { "on" } else { "off" }).unwrap();
+            continue;
+        }
+        if show_tokens {
+            // Lexer errors are reported by the parser below.
+            cli::dump_tokens("<repl>", &line, writer, &mut std::io::sink());
+        }
+        let  l = Lexer::new(line.clone());
         let mut parser = Parser::new( l);
         let program = parser.parse_program();
-        if parser.errors.len() != 0 {
-            print_parse_errors(writer, parser.errors);
+        if !parser.errors.is_empty() {
+            print_parse_errors(writer, &line, parser.errors);
             continue;
         }
 
         let evaluated =  env.eval(&program);
 
-      
-            write!(writer, "{}", evaluated.inspect()).unwrap();
-            write!(writer, "\n").unwrap();
+        match evaluated.as_any().downcast_ref::<object::Error>() {
+            Some(err) => write!(writer, "{}", err.diagnostic.render(&line)).unwrap(),
+            None => writeln!(writer, "{}", evaluated.inspect()).unwrap(),
+        }
         
 
        
     }
 }
 
-fn print_parse_errors<W: Write>(writer: &mut W, errors: Vec<String>) {
+fn print_parse_errors<W: Write>(writer: &mut W, source: &str, errors: Vec<Diagnostic>) {
     write!(writer, "{}", MONKEY_FACE).unwrap();
-    write!(writer, "Woops! We ran into some monkey business here!\n").unwrap();
+    writeln!(writer, "Woops! We ran into some monkey business here!").unwrap();
     for error in errors {
-        write!(writer, "\t{}\n", error).unwrap();
+        write!(writer, "{}", error.render(source)).unwrap();
     }
 }
\ No newline at end of file
@@ -0,0 +1,1385 @@
+use std::rc::Rc;
+
+use crate::ast::{self, Node, NodeType};
+use crate::diagnostic::Diagnostic;
+use crate::lexer;
+use crate::parser;
+use crate::token::{Span, Token, TokenType};
+
+/// Every node type that can appear in a tree.
+const NODE_TYPES: [NodeType; 28] = [
+    NodeType::Program,
+    NodeType::Identifier,
+    NodeType::LetStatement,
+    NodeType::ConstStatement,
+    NodeType::ReturnStatement,
+    NodeType::ExpressionStatement,
+    NodeType::IntegerLiteral,
+    NodeType::FloatLiteral,
+    NodeType::PrefixExpression,
+    NodeType::InfixExpression,
+    NodeType::Boolean,
+    NodeType::IfExpression,
+    NodeType::BlockStatement,
+    NodeType::FunctionLiteral,
+    NodeType::CallExpression,
+    NodeType::StringLiteral,
+    NodeType::InterpolatedString,
+    NodeType::ArrayLiteral,
+    NodeType::IndexExpression,
+    NodeType::HashLiteral,
+    NodeType::WhileStatement,
+    NodeType::BreakStatement,
+    NodeType::ContinueStatement,
+    NodeType::ForStatement,
+    NodeType::AssignExpression,
+    NodeType::MatchExpression,
+    NodeType::MatchArm,
+    NodeType::RestPattern,
+];
+
+/// How a field of a node is written in an S-expression.
+#[derive(Debug, PartialEq, Clone, Copy)]
+enum Field {
+    /// A node, or `nil`.
+    Node,
+    /// A string written as a bare symbol, such as an operator.
+    Symbol,
+    /// A string written in quotes, or `nil`.
+    Text,
+    /// Nodes in a parenthesized list.
+    List,
+    /// Nodes making up the rest of the list.
+    Nodes,
+    /// Key-value pairs making up the rest of the list, each as `(key value)`.
+    Pairs,
+    /// Strings and nodes making up the rest of the list.
+    Parts,
+}
+
+type NodeSchema = (NodeType, &'static str, &'static [(&'static str, Field)]);
+
+/// The S-expression head and fields of each node type that is written as a
+/// list. Identifiers, numbers, booleans and strings are written as atoms; a
+/// string literal inside a template is written `(str "...")` to tell it from
+/// the template's text.
+const SCHEMA: &[NodeSchema] = &[
+    (NodeType::Program, "program", &[("statements", Field::Nodes)]),
+    (NodeType::LetStatement, "let", &[("name", Field::Node), ("value", Field::Node), ("doc", Field::Text)]),
+    (NodeType::ConstStatement, "const", &[("name", Field::Node), ("value", Field::Node), ("doc", Field::Text)]),
+    (NodeType::ReturnStatement, "return", &[("return_value", Field::Node)]),
+    (NodeType::ExpressionStatement, "expr", &[("expression", Field::Node)]),
+    (NodeType::PrefixExpression, "prefix", &[("operator", Field::Symbol), ("right", Field::Node)]),
+    (NodeType::InfixExpression, "infix", &[("operator", Field::Symbol), ("left", Field::Node), ("right", Field::Node)]),
+    (NodeType::IfExpression, "if", &[("condition", Field::Node), ("consequence", Field::Node), ("alternative", Field::Node)]),
+    (NodeType::BlockStatement, "block", &[("statements", Field::Nodes)]),
+    (NodeType::FunctionLiteral, "fn", &[("parameters", Field::List), ("body", Field::Node)]),
+    (NodeType::CallExpression, "call", &[("function", Field::Node), ("arguments", Field::Nodes)]),
+    (NodeType::StringLiteral, "str", &[("value", Field::Text)]),
+    (NodeType::InterpolatedString, "template", &[("parts", Field::Parts)]),
+    (NodeType::ArrayLiteral, "array", &[("elements", Field::Nodes)]),
+    (NodeType::IndexExpression, "index", &[("left", Field::Node), ("index", Field::Node)]),
+    (NodeType::HashLiteral, "hash", &[("pairs", Field::Pairs)]),
+    (NodeType::WhileStatement, "while", &[("condition", Field::Node), ("body", Field::Node)]),
+    (NodeType::BreakStatement, "break", &[]),
+    (NodeType::ContinueStatement, "continue", &[]),
+    (NodeType::ForStatement, "for", &[("variable", Field::Node), ("iterable", Field::Node), ("body", Field::Node)]),
+    (NodeType::AssignExpression, "assign", &[("operator", Field::Symbol), ("target", Field::Node), ("value", Field::Node)]),
+    (NodeType::MatchExpression, "match", &[("subject", Field::Node), ("arms", Field::Nodes)]),
+    (NodeType::MatchArm, "arm", &[("pattern", Field::Node), ("guard", Field::Node), ("body", Field::Node)]),
+    (NodeType::RestPattern, "rest", &[("name", Field::Node)]),
+];
+
+/// Serializes `program` to JSON. Every node is an object with its `type`
+/// (the `NodeType` name), its `span` and its fields under the names of the
+/// `ast` struct fields, e.g.
+/// `{"type":"Identifier","span":{"start":4,"end":5,"line":1,"column":5},"value":"x"}`.
+pub fn to_json(program: &ast::Program) -> String {
+    let mut out = String::new();
+    encode(program).write(&mut out);
+    out
+}
+
+/// Reads a program serialized by `to_json`. `span` may be left out of any
+/// node, as may a number's `literal` or `value` (but not both).
+pub fn from_json(text: &str) -> Result<ast::Program, Box<Diagnostic>> {
+    let mut reader = Reader::new(text);
+    let json = reader.json()?;
+    reader.finish()?;
+    decode_program(&json)
+}
+
+/// Serializes `program` to an S-expression such as
+/// `(program (let x (infix + 1 2)))`. With `spans` set, each node's span
+/// follows its head or atom as `@start..end:line:column`.
+pub fn to_sexp(program: &ast::Program, spans: bool) -> String {
+    let mut out = String::new();
+    write_sexp(&encode(program), spans, &mut out);
+    out
+}
+
+/// Reads a program serialized by `to_sexp`, with or without spans.
+pub fn from_sexp(text: &str) -> Result<ast::Program, Box<Diagnostic>> {
+    let mut reader = Reader::new(text);
+    let sexp = reader.sexp()?;
+    reader.finish()?;
+    decode_program(&sexp_node(&sexp)?)
+}
+
+/// A JSON value, along with where it was read from for error messages.
+#[derive(Debug, Clone)]
+struct Json {
+    value: Value,
+    pos: Span,
+}
+
+#[derive(Debug, Clone)]
+enum Value {
+    Null,
+    Bool(bool),
+    /// A number as written, so that integers keep all 64 bits.
+    Number(String),
+    String(String),
+    Array(Vec<Json>),
+    Object(Vec<(String, Json)>),
+}
+
+impl Json {
+    fn new(value: Value) -> Json {
+        Json { value, pos: Span::default() }
+    }
+
+    fn string(value: &str) -> Json {
+        Json::new(Value::String(value.to_string()))
+    }
+
+    fn number(value: impl ToString) -> Json {
+        Json::new(Value::Number(value.to_string()))
+    }
+
+    fn object(fields: Vec<(&str, Json)>) -> Json {
+        Json::new(Value::Object(fields.into_iter().map(|(name, value)| (name.to_string(), value)).collect()))
+    }
+
+    fn nodes<'a>(nodes: impl Iterator<Item = &'a dyn Node>) -> Json {
+        Json::new(Value::Array(nodes.map(encode).collect()))
+    }
+
+    fn is_null(&self) -> bool {
+        matches!(self.value, Value::Null)
+    }
+
+    /// The field `name` of an object, if it has one that is not `null`.
+    fn get(&self, name: &str) -> Option<&Json> {
+        match &self.value {
+            Value::Object(fields) => fields.iter().find(|(field, _)| field == name).map(|(_, value)| value).filter(|v| !v.is_null()),
+            _ => None,
+        }
+    }
+
+    fn field(&self, name: &str) -> Result<&Json, Box<Diagnostic>> {
+        self.get(name).ok_or_else(|| invalid(self.pos, &format!("missing field `{}`", name)))
+    }
+
+    fn describe(&self) -> &'static str {
+        match self.value {
+            Value::Null => "null",
+            Value::Bool(_) => "a boolean",
+            Value::Number(_) => "a number",
+            Value::String(_) => "a string",
+            Value::Array(_) => "an array",
+            Value::Object(_) => "an object",
+        }
+    }
+
+    fn expected(&self, what: &str) -> Box<Diagnostic> {
+        invalid(self.pos, &format!("expected {}, found {}", what, self.describe()))
+    }
+
+    fn as_str(&self) -> Result<&str, Box<Diagnostic>> {
+        match &self.value {
+            Value::String(value) => Ok(value),
+            _ => Err(self.expected("a string")),
+        }
+    }
+
+    fn as_bool(&self) -> Result<bool, Box<Diagnostic>> {
+        match self.value {
+            Value::Bool(value) => Ok(value),
+            _ => Err(self.expected("a boolean")),
+        }
+    }
+
+    fn as_array(&self) -> Result<&[Json], Box<Diagnostic>> {
+        match &self.value {
+            Value::Array(values) => Ok(values),
+            _ => Err(self.expected("an array")),
+        }
+    }
+
+    fn as_number<T: std::str::FromStr>(&self) -> Result<T, Box<Diagnostic>> {
+        match &self.value {
+            Value::Number(number) => number.parse().map_err(|_| invalid(self.pos, &format!("number {} is out of range", number))),
+            _ => Err(self.expected("a number")),
+        }
+    }
+
+    fn write(&self, out: &mut String) {
+        match &self.value {
+            Value::Null => out.push_str("null"),
+            Value::Bool(value) => out.push_str(&value.to_string()),
+            Value::Number(number) => out.push_str(number),
+            Value::String(value) => write_string(value, true, out),
+            Value::Array(values) => {
+                out.push('[');
+                for (i, value) in values.iter().enumerate() {
+                    if i > 0 {
+                        out.push(',');
+                    }
+                    value.write(out);
+                }
+                out.push(']');
+            }
+            Value::Object(fields) => {
+                out.push('{');
+                for (i, (name, value)) in fields.iter().enumerate() {
+                    if i > 0 {
+                        out.push(',');
+                    }
+                    write_string(name, true, out);
+                    out.push(':');
+                    value.write(out);
+                }
+                out.push('}');
+            }
+        }
+    }
+}
+
+/// Writes `value` in double quotes. JSON escapes other control characters
+/// as `\u00XX`; S-expressions only use the escapes of the language.
+fn write_string(value: &str, json: bool, out: &mut String) {
+    out.push('"');
+    for ch in value.chars() {
+        match ch {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\t' => out.push_str("\\t"),
+            '\r' => out.push_str("\\r"),
+            '\0' if !json => out.push_str("\\0"),
+            ch if json && (ch as u32) < 0x20 => out.push_str(&format!("\\u{:04x}", ch as u32)),
+            ch => out.push(ch),
+        }
+    }
+    out.push('"');
+}
+
+fn invalid(pos: Span, message: &str) -> Box<Diagnostic> {
+    Box::new(Diagnostic::error("E0201", &format!("invalid syntax tree: {}", message)).with_span(pos))
+}
+
+fn encode(node: &dyn Node) -> Json {
+    let span = node.span();
+    let mut fields = vec![
+        ("type", Json::string(&format!("{:?}", node.node_type()))),
+        ("span", Json::object(vec![
+            ("start", Json::number(span.start)),
+            ("end", Json::number(span.end)),
+            ("line", Json::number(span.line)),
+            ("column", Json::number(span.column)),
+        ])),
+    ];
+    let any = node.as_any();
+    match node.node_type() {
+        NodeType::Program => {
+            let program = any.downcast_ref::<ast::Program>().unwrap();
+            fields.push(("statements", Json::nodes(program.statements.iter().map(|s| s.as_node()))));
+        }
+        NodeType::LetStatement | NodeType::ConstStatement => {
+            let statement = any.downcast_ref::<ast::LetStatement>().unwrap();
+            fields.push(("name", encode(&statement.name)));
+            fields.push(("value", encode(statement.value.as_node())));
+            fields.push(("doc", statement.doc.as_deref().map_or(Json::new(Value::Null), Json::string)));
+        }
+        NodeType::ReturnStatement => {
+            let statement = any.downcast_ref::<ast::ReturnStatement>().unwrap();
+            fields.push(("return_value", encode(statement.return_value.as_node())));
+        }
+        NodeType::ExpressionStatement => {
+            let statement = any.downcast_ref::<ast::ExpressionStatement>().unwrap();
+            fields.push(("expression", encode(statement.expression.as_node())));
+        }
+        NodeType::Identifier => fields.push(("value", Json::string(&any.downcast_ref::<ast::Identifier>().unwrap().value))),
+        NodeType::IntegerLiteral => {
+            let literal = any.downcast_ref::<ast::IntegerLiteral>().unwrap();
+            fields.push(("literal", Json::string(&literal.token.literal)));
+            fields.push(("value", Json::number(literal.value)));
+        }
+        NodeType::FloatLiteral => {
+            // JSON has no infinity, so such a value is left to the literal.
+            let literal = any.downcast_ref::<ast::FloatLiteral>().unwrap();
+            fields.push(("literal", Json::string(&literal.token.literal)));
+            fields.push(("value", match literal.value.is_finite() {
+                true => Json::number(format!("{:?}", literal.value)),
+                false => Json::new(Value::Null),
+            }));
+        }
+        NodeType::PrefixExpression => {
+            let prefix = any.downcast_ref::<ast::PrefixExpression>().unwrap();
+            fields.push(("operator", Json::string(&prefix.operator)));
+            fields.push(("right", encode(prefix.right.as_node())));
+        }
+        NodeType::InfixExpression => {
+            let infix = any.downcast_ref::<ast::InfixExpression>().unwrap();
+            fields.push(("operator", Json::string(&infix.operator)));
+            fields.push(("left", encode(infix.left.as_node())));
+            fields.push(("right", encode(infix.right.as_node())));
+        }
+        NodeType::Boolean => fields.push(("value", Json::new(Value::Bool(any.downcast_ref::<ast::Boolean>().unwrap().value)))),
+        NodeType::IfExpression => {
+            let if_expression = any.downcast_ref::<ast::IfExpression>().unwrap();
+            fields.push(("condition", encode(if_expression.condition.as_node())));
+            fields.push(("consequence", encode(if_expression.consequence.as_node())));
+            fields.push(("alternative", if_expression.alternative.as_ref().map_or(Json::new(Value::Null), |a| encode(a.as_node()))));
+        }
+        NodeType::BlockStatement => {
+            let block = any.downcast_ref::<ast::BlockStatement>().unwrap();
+            fields.push(("statements", Json::nodes(block.statements.iter().map(|s| s.as_node()))));
+        }
+        NodeType::FunctionLiteral => {
+            let function = any.downcast_ref::<ast::FunctionLiteral>().unwrap();
+            fields.push(("parameters", Json::nodes(function.parameters.iter().map(|p| p as &dyn Node))));
+            fields.push(("body", encode(function.body.as_node())));
+        }
+        NodeType::CallExpression => {
+            let call = any.downcast_ref::<ast::CallExpression>().unwrap();
+            fields.push(("function", encode(call.function.as_node())));
+            fields.push(("arguments", Json::nodes(call.arguments.iter().map(|a| a.as_node()))));
+        }
+        NodeType::StringLiteral => fields.push(("value", Json::string(&any.downcast_ref::<ast::StringLiteral>().unwrap().value))),
+        NodeType::InterpolatedString => {
+            let string = any.downcast_ref::<ast::InterpolatedString>().unwrap();
+            let parts = string.parts.iter().map(|part| match part {
+                ast::InterpolationPart::Text(text) => Json::string(text),
+                ast::InterpolationPart::Expression(expression) => encode(expression.as_node()),
+            });
+            fields.push(("parts", Json::new(Value::Array(parts.collect()))));
+        }
+        NodeType::ArrayLiteral => {
+            let array = any.downcast_ref::<ast::ArrayLiteral>().unwrap();
+            fields.push(("elements", Json::nodes(array.elements.iter().map(|e| e.as_node()))));
+        }
+        NodeType::IndexExpression => {
+            let index = any.downcast_ref::<ast::IndexExpression>().unwrap();
+            fields.push(("left", encode(index.left.as_node())));
+            fields.push(("index", encode(index.index.as_node())));
+        }
+        NodeType::HashLiteral => {
+            let hash = any.downcast_ref::<ast::HashLiteral>().unwrap();
+            let pairs = hash.pairs.iter().map(|(key, value)| Json::new(Value::Array(vec![encode(key.as_node()), encode(value.as_node())])));
+            fields.push(("pairs", Json::new(Value::Array(pairs.collect()))));
+        }
+        NodeType::WhileStatement => {
+            let statement = any.downcast_ref::<ast::WhileStatement>().unwrap();
+            fields.push(("condition", encode(statement.condition.as_node())));
+            fields.push(("body", encode(statement.body.as_node())));
+        }
+        NodeType::ForStatement => {
+            let statement = any.downcast_ref::<ast::ForStatement>().unwrap();
+            fields.push(("variable", encode(&statement.variable)));
+            fields.push(("iterable", encode(statement.iterable.as_node())));
+            fields.push(("body", encode(statement.body.as_node())));
+        }
+        NodeType::AssignExpression => {
+            let assign = any.downcast_ref::<ast::AssignExpression>().unwrap();
+            fields.push(("operator", Json::string(&assign.operator)));
+            fields.push(("target", encode(assign.target.as_node())));
+            fields.push(("value", encode(assign.value.as_node())));
+        }
+        NodeType::MatchExpression => {
+            let expression = any.downcast_ref::<ast::MatchExpression>().unwrap();
+            fields.push(("subject", encode(expression.subject.as_node())));
+            fields.push(("arms", Json::nodes(expression.arms.iter().map(|arm| arm as &dyn Node))));
+        }
+        NodeType::MatchArm => {
+            let arm = any.downcast_ref::<ast::MatchArm>().unwrap();
+            fields.push(("pattern", encode(arm.pattern.as_node())));
+            fields.push(("guard", arm.guard.as_ref().map_or(Json::new(Value::Null), |g| encode(g.as_node()))));
+            fields.push(("body", encode(arm.body.as_node())));
+        }
+        NodeType::RestPattern => {
+            let rest = any.downcast_ref::<ast::RestPattern>().unwrap();
+            fields.push(("name", rest.name.as_ref().map_or(Json::new(Value::Null), |name| encode(name))));
+        }
+        NodeType::BreakStatement | NodeType::ContinueStatement => {}
+        NodeType::Statement | NodeType::Expression => unreachable!("not a concrete node: {:?}", node.node_type()),
+    }
+    Json::object(fields)
+}
+
+fn node_type(json: &Json) -> Result<NodeType, Box<Diagnostic>> {
+    let name = json.field("type")?;
+    let text = name.as_str()?;
+    NODE_TYPES.iter().copied().find(|t| format!("{:?}", t) == text)
+        .ok_or_else(|| invalid(name.pos, &format!("unknown node type {:?}", text)))
+}
+
+fn span(json: &Json) -> Result<Span, Box<Diagnostic>> {
+    match json.get("span") {
+        Some(span) => Ok(Span::new(
+            span.field("start")?.as_number()?,
+            span.field("end")?.as_number()?,
+            span.field("line")?.as_number()?,
+            span.field("column")?.as_number()?,
+        )),
+        None => Ok(Span::default()),
+    }
+}
+
+/// The token the parser would have stored in the node. Tokens are rebuilt
+/// from the node's fields and carry the node's span.
+fn token(json: &Json) -> Result<Token, Box<Diagnostic>> {
+    let span = span(json)?;
+    let (type_, literal) = match node_type(json)? {
+        NodeType::LetStatement => (TokenType::LET, String::from("let")),
+        NodeType::ConstStatement => (TokenType::CONST, String::from("const")),
+        NodeType::ReturnStatement => (TokenType::RETURN, String::from("return")),
+        NodeType::WhileStatement => (TokenType::WHILE, String::from("while")),
+        NodeType::BreakStatement => (TokenType::BREAK, String::from("break")),
+        NodeType::ContinueStatement => (TokenType::CONTINUE, String::from("continue")),
+        NodeType::ForStatement => (TokenType::FOR, String::from("for")),
+        NodeType::Identifier => (TokenType::IDENT, json.field("value")?.as_str()?.to_string()),
+        NodeType::IntegerLiteral => (TokenType::INT, number_literal(json)?),
+        NodeType::FloatLiteral => (TokenType::FLOAT, number_literal(json)?),
+        NodeType::PrefixExpression | NodeType::InfixExpression | NodeType::AssignExpression => {
+            let operator = json.field("operator")?;
+            let token = lexer::Lexer::new(operator.as_str()?.to_string()).next_token();
+            (token.type_, token.literal)
+        }
+        NodeType::Boolean => match json.field("value")?.as_bool()? {
+            true => (TokenType::TRUE, String::from("true")),
+            false => (TokenType::FALSE, String::from("false")),
+        },
+        NodeType::IfExpression => (TokenType::IF, String::from("if")),
+        NodeType::MatchExpression => (TokenType::MATCH, String::from("match")),
+        NodeType::MatchArm => (TokenType::FAT_ARROW, String::from("=>")),
+        NodeType::RestPattern => (TokenType::DOT_DOT, String::from("..")),
+        NodeType::BlockStatement | NodeType::HashLiteral => (TokenType::LBRACE, String::from("{")),
+        NodeType::FunctionLiteral => (TokenType::FUNCTION, String::from("fn")),
+        NodeType::CallExpression => (TokenType::LPAREN, String::from("(")),
+        NodeType::StringLiteral => (TokenType::STRING, json.field("value")?.as_str()?.to_string()),
+        NodeType::ArrayLiteral | NodeType::IndexExpression => (TokenType::LBRACKET, String::from("[")),
+        // The literal of a template is its source text, rebuilt by the caller.
+        NodeType::InterpolatedString => (TokenType::TEMPLATE, String::new()),
+        NodeType::ExpressionStatement => return first_token(json.field("expression")?),
+        other => return Err(invalid(json.pos, &format!("{:?} has no token", other))),
+    };
+    Ok(Token::with_span(type_, &literal, span))
+}
+
+/// The token an expression statement starting with `json` stores: that of
+/// the expression's leftmost part.
+fn first_token(json: &Json) -> Result<Token, Box<Diagnostic>> {
+    match node_type(json)? {
+        NodeType::InfixExpression | NodeType::IndexExpression => first_token(json.field("left")?),
+        NodeType::AssignExpression => first_token(json.field("target")?),
+        NodeType::CallExpression => first_token(json.field("function")?),
+        _ => token(json),
+    }
+}
+
+/// The source text of a number, written out from its value if left out.
+fn number_literal(json: &Json) -> Result<String, Box<Diagnostic>> {
+    match json.get("literal") {
+        Some(literal) => Ok(literal.as_str()?.to_string()),
+        None => match &json.field("value")?.value {
+            Value::Number(number) => Ok(number.clone()),
+            _ => Err(json.field("value")?.expected("a number")),
+        },
+    }
+}
+
+fn decode_program(json: &Json) -> Result<ast::Program, Box<Diagnostic>> {
+    match node_type(json)? {
+        NodeType::Program => Ok(ast::Program {
+            statements: decode_statements(json.field("statements")?)?,
+            span: span(json)?,
+        }),
+        other => Err(invalid(json.pos, &format!("expected a Program, found {:?}", other))),
+    }
+}
+
+fn decode_statements(json: &Json) -> Result<Vec<Box<dyn ast::Statement>>, Box<Diagnostic>> {
+    json.as_array()?.iter().map(decode_statement).collect()
+}
+
+fn decode_statement(json: &Json) -> Result<Box<dyn ast::Statement>, Box<Diagnostic>> {
+    let span = span(json)?;
+    let statement: Box<dyn ast::Statement> = match node_type(json)? {
+        NodeType::LetStatement | NodeType::ConstStatement => Box::new(ast::LetStatement {
+            token: token(json)?,
+            span,
+            name: decode_identifier(json.field("name")?)?,
+            value: decode_expression(json.field("value")?)?,
+            doc: json.get("doc").map(|doc| doc.as_str().map(String::from)).transpose()?,
+        }),
+        NodeType::ReturnStatement => Box::new(ast::ReturnStatement {
+            token: token(json)?,
+            span,
+            return_value: decode_expression(json.field("return_value")?)?,
+        }),
+        NodeType::ExpressionStatement => Box::new(ast::ExpressionStatement {
+            token: token(json)?,
+            span,
+            expression: decode_expression(json.field("expression")?)?,
+        }),
+        NodeType::BlockStatement => Box::new(decode_block(json)?),
+        NodeType::WhileStatement => Box::new(ast::WhileStatement {
+            token: token(json)?,
+            span,
+            condition: decode_expression(json.field("condition")?)?,
+            body: Box::new(decode_block(json.field("body")?)?),
+        }),
+        NodeType::ForStatement => Box::new(ast::ForStatement {
+            token: token(json)?,
+            span,
+            variable: decode_identifier(json.field("variable")?)?,
+            iterable: decode_expression(json.field("iterable")?)?,
+            body: Box::new(decode_block(json.field("body")?)?),
+        }),
+        NodeType::BreakStatement => Box::new(ast::BreakStatement { token: token(json)?, span }),
+        NodeType::ContinueStatement => Box::new(ast::ContinueStatement { token: token(json)?, span }),
+        other => return Err(invalid(json.pos, &format!("expected a statement, found {:?}", other))),
+    };
+    Ok(statement)
+}
+
+fn decode_block(json: &Json) -> Result<ast::BlockStatement, Box<Diagnostic>> {
+    match node_type(json)? {
+        NodeType::BlockStatement => Ok(ast::BlockStatement {
+            token: token(json)?,
+            span: span(json)?,
+            statements: decode_statements(json.field("statements")?)?,
+        }),
+        other => Err(invalid(json.pos, &format!("expected a BlockStatement, found {:?}", other))),
+    }
+}
+
+fn decode_identifier(json: &Json) -> Result<ast::Identifier, Box<Diagnostic>> {
+    match node_type(json)? {
+        NodeType::Identifier => Ok(ast::Identifier {
+            token: token(json)?,
+            span: span(json)?,
+            value: json.field("value")?.as_str()?.to_string(),
+        }),
+        other => Err(invalid(json.pos, &format!("expected an Identifier, found {:?}", other))),
+    }
+}
+
+fn decode_expression(json: &Json) -> Result<Box<dyn ast::Expression>, Box<Diagnostic>> {
+    let span = span(json)?;
+    let expression: Box<dyn ast::Expression> = match node_type(json)? {
+        NodeType::Identifier => Box::new(decode_identifier(json)?),
+        NodeType::IntegerLiteral => {
+            let token = token(json)?;
+            let value = match json.get("value") {
+                Some(value) => value.as_number()?,
+                None => parser::integer_value(&token.literal)
+                    .map_err(|_| invalid(json.pos, &format!("could not parse {:?} as integer", token.literal)))?,
+            };
+            Box::new(ast::IntegerLiteral { token, span, value })
+        }
+        NodeType::FloatLiteral => {
+            let token = token(json)?;
+            let value = match json.get("value") {
+                Some(value) => value.as_number()?,
+                None => parser::float_value(&token.literal)
+                    .map_err(|_| invalid(json.pos, &format!("could not parse {:?} as float", token.literal)))?,
+            };
+            Box::new(ast::FloatLiteral { token, span, value })
+        }
+        NodeType::PrefixExpression => {
+            let operator = json.field("operator")?;
+            if !matches!(operator.as_str()?, "!" | "-") {
+                return Err(invalid(operator.pos, &format!("unknown prefix operator {:?}", operator.as_str()?)));
+            }
+            Box::new(ast::PrefixExpression {
+                token: token(json)?,
+                span,
+                operator: operator.as_str()?.to_string(),
+                right: decode_expression(json.field("right")?)?,
+            })
+        }
+        NodeType::InfixExpression => {
+            let operator = json.field("operator")?;
+            let token = token(json)?;
+            let known = parser::precedences().get(&token.type_).is_some_and(|p| *p != parser::Precedence::ASSIGN)
+                && token.literal == operator.as_str()?;
+            if !known || matches!(token.type_, TokenType::LPAREN | TokenType::LBRACKET) {
+                return Err(invalid(operator.pos, &format!("unknown infix operator {:?}", operator.as_str()?)));
+            }
+            Box::new(ast::InfixExpression {
+                token,
+                span,
+                left: decode_expression(json.field("left")?)?,
+                operator: operator.as_str()?.to_string(),
+                right: decode_expression(json.field("right")?)?,
+            })
+        }
+        NodeType::AssignExpression => {
+            let operator = json.field("operator")?;
+            if !matches!(operator.as_str()?, "=" | "+=" | "-=" | "*=" | "/=") {
+                return Err(invalid(operator.pos, &format!("unknown assignment operator {:?}", operator.as_str()?)));
+            }
+            let target = json.field("target")?;
+            let assign = ast::AssignExpression {
+                token: token(json)?,
+                span,
+                target: decode_expression(target)?,
+                operator: operator.as_str()?.to_string(),
+                value: decode_expression(json.field("value")?)?,
+            };
+            if ast::place(assign.target.as_node()).is_none() {
+                return Err(invalid(target.pos, "invalid assignment target"));
+            }
+            Box::new(assign)
+        }
+        NodeType::Boolean => Box::new(ast::Boolean {
+            token: token(json)?,
+            span,
+            value: json.field("value")?.as_bool()?,
+        }),
+        NodeType::IfExpression => Box::new(ast::IfExpression {
+            token: token(json)?,
+            span,
+            condition: decode_expression(json.field("condition")?)?,
+            consequence: Box::new(decode_block(json.field("consequence")?)?),
+            alternative: match json.get("alternative") {
+                Some(alternative) => Some(Box::new(decode_block(alternative)?)),
+                None => None,
+            },
+        }),
+        NodeType::FunctionLiteral => Box::new(ast::FunctionLiteral {
+            token: token(json)?,
+            span,
+            parameters: json.field("parameters")?.as_array()?.iter().map(decode_identifier).collect::<Result<_, _>>()?,
+            body: Rc::new(Box::new(decode_block(json.field("body")?)?)),
+        }),
+        NodeType::CallExpression => Box::new(ast::CallExpression {
+            token: token(json)?,
+            span,
+            function: decode_expression(json.field("function")?)?,
+            arguments: decode_expressions(json.field("arguments")?)?,
+        }),
+        NodeType::StringLiteral => Box::new(ast::StringLiteral {
+            token: token(json)?,
+            span,
+            value: json.field("value")?.as_str()?.to_string(),
+        }),
+        NodeType::InterpolatedString => {
+            let mut parts = Vec::new();
+            for part in json.field("parts")?.as_array()? {
+                match &part.value {
+                    Value::String(text) => parts.push(ast::InterpolationPart::Text(text.clone())),
+                    _ => parts.push(ast::InterpolationPart::Expression(decode_expression(part)?)),
+                }
+            }
+            let mut string = ast::InterpolatedString { token: token(json)?, span, parts };
+            string.token.literal = string.string();
+            Box::new(string)
+        }
+        NodeType::ArrayLiteral => Box::new(ast::ArrayLiteral {
+            token: token(json)?,
+            span,
+            elements: decode_expressions(json.field("elements")?)?,
+        }),
+        NodeType::IndexExpression => Box::new(ast::IndexExpression {
+            token: token(json)?,
+            span,
+            left: decode_expression(json.field("left")?)?,
+            index: decode_expression(json.field("index")?)?,
+        }),
+        NodeType::HashLiteral => decode_hash(json, decode_expression)?,
+        NodeType::MatchExpression => Box::new(ast::MatchExpression {
+            token: token(json)?,
+            span,
+            subject: decode_expression(json.field("subject")?)?,
+            arms: json.field("arms")?.as_array()?.iter().map(decode_arm).collect::<Result<_, _>>()?,
+        }),
+        other => return Err(invalid(json.pos, &format!("expected an expression, found {:?}", other))),
+    };
+    Ok(expression)
+}
+
+/// Decodes one node of a larger tree, such as `decode_expression`.
+type Decoder = fn(&Json) -> Result<Box<dyn ast::Expression>, Box<Diagnostic>>;
+
+fn decode_hash(json: &Json, decode: Decoder) -> Result<Box<dyn ast::Expression>, Box<Diagnostic>> {
+    let mut pairs = Vec::new();
+    for pair in json.field("pairs")?.as_array()? {
+        match pair.as_array()? {
+            [key, value] => pairs.push((decode(key)?, decode(value)?)),
+            _ => return Err(invalid(pair.pos, "expected a [key, value] pair")),
+        }
+    }
+    Ok(Box::new(ast::HashLiteral { token: token(json)?, span: span(json)?, pairs }))
+}
+
+fn decode_arm(json: &Json) -> Result<ast::MatchArm, Box<Diagnostic>> {
+    match node_type(json)? {
+        NodeType::MatchArm => {
+            let pattern = json.field("pattern")?;
+            let arm = ast::MatchArm {
+                token: token(json)?,
+                span: span(json)?,
+                pattern: decode_pattern(pattern)?,
+                guard: json.get("guard").map(decode_expression).transpose()?,
+                body: Box::new(decode_block(json.field("body")?)?),
+            };
+            // As in the parser, a pattern may bind each name only once.
+            let unique = ast::pattern_bindings(arm.pattern.as_node())
+                .is_some_and(|names| names.iter().enumerate().all(|(i, name)| names[..i].iter().all(|other| other.value != name.value)));
+            if !unique {
+                return Err(invalid(pattern.pos, "invalid match pattern"));
+            }
+            Ok(arm)
+        }
+        other => Err(invalid(json.pos, &format!("expected a MatchArm, found {:?}", other))),
+    }
+}
+
+/// Decodes a match pattern, the only place a `RestPattern` may appear.
+fn decode_pattern(json: &Json) -> Result<Box<dyn ast::Expression>, Box<Diagnostic>> {
+    match node_type(json)? {
+        NodeType::ArrayLiteral => Ok(Box::new(ast::ArrayLiteral {
+            token: token(json)?,
+            span: span(json)?,
+            elements: json.field("elements")?.as_array()?.iter().map(decode_pattern).collect::<Result<_, _>>()?,
+        })),
+        NodeType::HashLiteral => decode_hash(json, decode_pattern),
+        NodeType::RestPattern => Ok(Box::new(ast::RestPattern {
+            token: token(json)?,
+            span: span(json)?,
+            name: json.get("name").map(decode_identifier).transpose()?,
+        })),
+        _ => decode_expression(json),
+    }
+}
+
+fn decode_expressions(json: &Json) -> Result<Vec<Box<dyn ast::Expression>>, Box<Diagnostic>> {
+    json.as_array()?.iter().map(decode_expression).collect()
+}
+
+/// Writes a node produced by `encode` as an S-expression.
+fn write_sexp(json: &Json, spans: bool, out: &mut String) {
+    let node_type = node_type(json).unwrap();
+    let field = |name: &str| json.get(name).unwrap();
+    match node_type {
+        NodeType::Identifier => out.push_str(field("value").as_str().unwrap()),
+        NodeType::IntegerLiteral | NodeType::FloatLiteral => out.push_str(field("literal").as_str().unwrap()),
+        NodeType::Boolean => out.push_str(&field("value").as_bool().unwrap().to_string()),
+        NodeType::StringLiteral => write_string(field("value").as_str().unwrap(), false, out),
+        _ => {
+            let (_, head, fields) = SCHEMA.iter().find(|(t, _, _)| *t == node_type).unwrap();
+            out.push('(');
+            out.push_str(head);
+            write_span(json, spans, out);
+            // Trailing fields that are `nil` are left out.
+            let present = fields.iter().rposition(|(name, _)| json.get(name).is_some()).map_or(0, |i| i + 1);
+            for (name, kind) in fields[..present].iter() {
+                let value = json.get(name);
+                match (kind, value) {
+                    (_, None) => out.push_str(" nil"),
+                    (Field::Node, Some(value)) => {
+                        out.push(' ');
+                        write_sexp(value, spans, out);
+                    }
+                    (Field::Symbol, Some(value)) => {
+                        out.push(' ');
+                        out.push_str(value.as_str().unwrap());
+                    }
+                    (Field::Text, Some(value)) => {
+                        out.push(' ');
+                        write_string(value.as_str().unwrap(), false, out);
+                    }
+                    (Field::List, Some(value)) => {
+                        out.push_str(" (");
+                        for (i, item) in value.as_array().unwrap().iter().enumerate() {
+                            if i > 0 {
+                                out.push(' ');
+                            }
+                            write_sexp(item, spans, out);
+                        }
+                        out.push(')');
+                    }
+                    (Field::Nodes | Field::Pairs | Field::Parts, Some(value)) => {
+                        for item in value.as_array().unwrap() {
+                            out.push(' ');
+                            match (kind, &item.value) {
+                                (Field::Pairs, Value::Array(pair)) => {
+                                    out.push('(');
+                                    write_sexp(&pair[0], spans, out);
+                                    out.push(' ');
+                                    write_sexp(&pair[1], spans, out);
+                                    out.push(')');
+                                }
+                                (Field::Parts, Value::String(text)) => write_string(text, false, out),
+                                (Field::Parts, _) if node_type_is(item, NodeType::StringLiteral) => {
+                                    out.push_str("(str");
+                                    write_span(item, spans, out);
+                                    out.push(' ');
+                                    write_string(item.get("value").unwrap().as_str().unwrap(), false, out);
+                                    out.push(')');
+                                }
+                                _ => write_sexp(item, spans, out),
+                            }
+                        }
+                    }
+                }
+            }
+            out.push(')');
+            return;
+        }
+    }
+    write_span(json, spans, out);
+}
+
+fn node_type_is(json: &Json, node_type: NodeType) -> bool {
+    self::node_type(json).ok() == Some(node_type)
+}
+
+fn write_span(json: &Json, spans: bool, out: &mut String) {
+    if spans {
+        let span = span(json).unwrap();
+        out.push_str(&format!("@{}..{}:{}:{}", span.start, span.end, span.line, span.column));
+    }
+}
+
+/// An S-expression as read from text. `span` is the node span written after
+/// an atom or a list's head; `pos` is where the expression was read from.
+#[derive(Debug)]
+enum Sexp {
+    Symbol { text: String, span: Option<Span>, pos: Span },
+    String { value: String, span: Option<Span>, pos: Span },
+    List { items: Vec<Sexp>, pos: Span },
+}
+
+impl Sexp {
+    fn pos(&self) -> Span {
+        match self {
+            Sexp::Symbol { pos, .. } | Sexp::String { pos, .. } | Sexp::List { pos, .. } => *pos,
+        }
+    }
+
+    fn is_nil(&self) -> bool {
+        matches!(self, Sexp::Symbol { text, .. } if text == "nil")
+    }
+}
+
+/// Builds the JSON object for a node read from an S-expression.
+fn node_object(node_type: NodeType, span: Option<Span>, mut fields: Vec<(&str, Json)>, pos: Span) -> Json {
+    let name = format!("{:?}", node_type);
+    let mut all = vec![("type", Json::string(&name))];
+    if let Some(span) = span {
+        all.push(("span", Json::object(vec![
+            ("start", Json::number(span.start)),
+            ("end", Json::number(span.end)),
+            ("line", Json::number(span.line)),
+            ("column", Json::number(span.column)),
+        ])));
+    }
+    all.append(&mut fields);
+    Json { pos, ..Json::object(all) }
+}
+
+/// Converts an S-expression to the JSON form of the node it stands for.
+fn sexp_node(sexp: &Sexp) -> Result<Json, Box<Diagnostic>> {
+    match sexp {
+        Sexp::Symbol { text, pos, .. } if text == "nil" => Ok(Json { value: Value::Null, pos: *pos }),
+        Sexp::Symbol { text, span, pos } => {
+            let (node_type, field) = match text.as_str() {
+                "true" | "false" => (NodeType::Boolean, ("value", Json::new(Value::Bool(text == "true")))),
+                _ if text.starts_with(|c: char| c.is_ascii_digit() || c == '.') => {
+                    let node_type = match lexer::tokenize(text).ok().as_deref() {
+                        Some([token, _]) if token.type_ == TokenType::INT => NodeType::IntegerLiteral,
+                        Some([token, _]) if token.type_ == TokenType::FLOAT => NodeType::FloatLiteral,
+                        _ => return Err(invalid(*pos, &format!("malformed number {:?}", text))),
+                    };
+                    (node_type, ("literal", Json::string(text)))
+                }
+                _ => (NodeType::Identifier, ("value", Json::string(text))),
+            };
+            Ok(node_object(node_type, *span, vec![field], *pos))
+        }
+        Sexp::String { value, span, pos } => Ok(node_object(NodeType::StringLiteral, *span, vec![("value", Json::string(value))], *pos)),
+        Sexp::List { items, pos } => {
+            let (head, span) = match items.first() {
+                Some(Sexp::Symbol { text, span, .. }) => (text, span),
+                _ => return Err(invalid(*pos, "expected a list starting with a node name, such as `(let ...)`")),
+            };
+            let (node_type, _, fields) = SCHEMA.iter().find(|(_, name, _)| name == head)
+                .ok_or_else(|| invalid(items[0].pos(), &format!("unknown node {:?}", head)))?;
+            let mut values = Vec::new();
+            let mut rest = items[1..].iter();
+            for (name, kind) in fields.iter() {
+                let value = match kind {
+                    Field::Node => match rest.next() {
+                        Some(item) => sexp_node(item)?,
+                        None => Json::new(Value::Null),
+                    },
+                    Field::Symbol | Field::Text => match rest.next() {
+                        Some(item) if item.is_nil() => Json { value: Value::Null, pos: item.pos() },
+                        Some(Sexp::Symbol { text: value, pos, .. } | Sexp::String { value, pos, .. }) => Json { pos: *pos, ..Json::string(value) },
+                        Some(item) => return Err(invalid(item.pos(), &format!("expected the {} as an atom", name))),
+                        None => Json::new(Value::Null),
+                    },
+                    Field::List => match rest.next() {
+                        Some(Sexp::List { items, pos }) => Json { value: Value::Array(items.iter().map(sexp_node).collect::<Result<_, _>>()?), pos: *pos },
+                        Some(item) => return Err(invalid(item.pos(), &format!("expected the {} as a list", name))),
+                        None => Json::new(Value::Null),
+                    },
+                    Field::Nodes => Json::new(Value::Array(rest.by_ref().map(sexp_node).collect::<Result<_, _>>()?)),
+                    Field::Pairs => {
+                        let mut pairs = Vec::new();
+                        for item in rest.by_ref() {
+                            match item {
+                                Sexp::List { items, pos } if items.len() == 2 => pairs.push(Json {
+                                    value: Value::Array(vec![sexp_node(&items[0])?, sexp_node(&items[1])?]),
+                                    pos: *pos,
+                                }),
+                                _ => return Err(invalid(item.pos(), "expected a (key value) pair")),
+                            }
+                        }
+                        Json::new(Value::Array(pairs))
+                    }
+                    Field::Parts => {
+                        let mut parts = Vec::new();
+                        for item in rest.by_ref() {
+                            match item {
+                                Sexp::String { value, pos, .. } => parts.push(Json { pos: *pos, ..Json::string(value) }),
+                                _ => parts.push(sexp_node(item)?),
+                            }
+                        }
+                        Json::new(Value::Array(parts))
+                    }
+                };
+                values.push((*name, value));
+            }
+            if let Some(item) = rest.next() {
+                return Err(invalid(item.pos(), &format!("too many elements in ({} ...)", head)));
+            }
+            Ok(node_object(*node_type, *span, values, *pos))
+        }
+    }
+}
+
+/// Reads JSON and S-expressions, tracking lines and columns like the lexer.
+struct Reader<'a> {
+    text: &'a str,
+    offset: usize,
+    line: usize,
+    column: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn new(text: &'a str) -> Reader<'a> {
+        Reader { text, offset: 0, line: 1, column: 1 }
+    }
+
+    fn peek(&self) -> Option<char> {
+        self.text[self.offset..].chars().next()
+    }
+
+    fn bump(&mut self) -> Option<char> {
+        let ch = self.peek()?;
+        self.offset += ch.len_utf8();
+        if ch == '\n' {
+            self.line += 1;
+            self.column = 1;
+        } else {
+            self.column += 1;
+        }
+        Some(ch)
+    }
+
+    fn here(&self) -> Span {
+        Span::new(self.offset, self.offset, self.line, self.column)
+    }
+
+    /// The span from `start` to the current position.
+    fn since(&self, start: Span) -> Span {
+        Span::new(start.start, self.offset, start.line, start.column)
+    }
+
+    fn error(&self, start: Span, message: &str) -> Box<Diagnostic> {
+        Box::new(Diagnostic::error("E0200", message).with_span(self.since(start)))
+    }
+
+    fn skip_whitespace(&mut self) {
+        while matches!(self.peek(), Some(ch) if ch.is_whitespace()) {
+            self.bump();
+        }
+    }
+
+    fn expect(&mut self, expected: char) -> Result<(), Box<Diagnostic>> {
+        let start = self.here();
+        match self.peek() {
+            Some(ch) if ch == expected => {
+                self.bump();
+                Ok(())
+            }
+            Some(ch) => {
+                self.bump();
+                Err(self.error(start, &format!("expected `{}`, found `{}`", expected, ch)))
+            }
+            None => Err(self.error(start, &format!("expected `{}`, found end of input", expected))),
+        }
+    }
+
+    fn finish(&mut self) -> Result<(), Box<Diagnostic>> {
+        self.skip_whitespace();
+        match self.peek() {
+            None => Ok(()),
+            Some(_) => {
+                let start = self.here();
+                self.bump();
+                Err(self.error(start, "unexpected text after the tree"))
+            }
+        }
+    }
+
+    fn json(&mut self) -> Result<Json, Box<Diagnostic>> {
+        self.skip_whitespace();
+        let start = self.here();
+        let value = match self.peek() {
+            Some('{') => {
+                self.bump();
+                let mut fields = Vec::new();
+                self.skip_whitespace();
+                if self.peek() == Some('}') {
+                    self.bump();
+                } else {
+                    loop {
+                        self.skip_whitespace();
+                        let key_start = self.here();
+                        if self.peek() != Some('"') {
+                            return Err(self.error(key_start, "expected a field name"));
+                        }
+                        let name = self.string(true)?;
+                        self.skip_whitespace();
+                        self.expect(':')?;
+                        fields.push((name, self.json()?));
+                        self.skip_whitespace();
+                        match self.peek() {
+                            Some(',') => self.bump(),
+                            _ => break self.expect('}')?,
+                        };
+                    }
+                }
+                Value::Object(fields)
+            }
+            Some('[') => {
+                self.bump();
+                let mut values = Vec::new();
+                self.skip_whitespace();
+                if self.peek() == Some(']') {
+                    self.bump();
+                } else {
+                    loop {
+                        values.push(self.json()?);
+                        self.skip_whitespace();
+                        match self.peek() {
+                            Some(',') => self.bump(),
+                            _ => break self.expect(']')?,
+                        };
+                    }
+                }
+                Value::Array(values)
+            }
+            Some('"') => Value::String(self.string(true)?),
+            Some(ch) if ch == '-' || ch.is_ascii_digit() => {
+                while matches!(self.peek(), Some('-' | '+' | '.' | 'e' | 'E' | '0'..='9')) {
+                    self.bump();
+                }
+                let number = &self.text[start.start..self.offset];
+                if number.parse::<f64>().is_err() {
+                    return Err(self.error(start, &format!("malformed number {:?}", number)));
+                }
+                Value::Number(number.to_string())
+            }
+            Some(ch) if ch.is_ascii_alphabetic() => {
+                while matches!(self.peek(), Some(ch) if ch.is_ascii_alphabetic()) {
+                    self.bump();
+                }
+                match &self.text[start.start..self.offset] {
+                    "null" => Value::Null,
+                    "true" => Value::Bool(true),
+                    "false" => Value::Bool(false),
+                    word => return Err(self.error(start, &format!("expected a JSON value, found `{}`", word))),
+                }
+            }
+            Some(_) => {
+                self.bump();
+                return Err(self.error(start, "expected a JSON value"));
+            }
+            None => return Err(self.error(start, "expected a JSON value, found end of input")),
+        };
+        Ok(Json { value, pos: self.since(start) })
+    }
+
+    /// Reads a quoted string. JSON allows `\/`, `\b`, `\f` and `\uXXXX`;
+    /// S-expressions allow `\0`.
+    fn string(&mut self, json: bool) -> Result<String, Box<Diagnostic>> {
+        let start = self.here();
+        self.bump();
+        let mut value = String::new();
+        loop {
+            let escape = self.here();
+            match self.bump() {
+                None => return Err(self.error(start, "unterminated string")),
+                Some('"') => return Ok(value),
+                Some('\\') => match self.bump() {
+                    Some('"') => value.push('"'),
+                    Some('\\') => value.push('\\'),
+                    Some('n') => value.push('\n'),
+                    Some('t') => value.push('\t'),
+                    Some('r') => value.push('\r'),
+                    Some('0') if !json => value.push('\0'),
+                    Some('/') if json => value.push('/'),
+                    Some('b') if json => value.push('\u{8}'),
+                    Some('f') if json => value.push('\u{c}'),
+                    Some('u') if json => {
+                        let mut code = self.hex4(escape)?;
+                        if (0xD800..0xDC00).contains(&code) && self.text[self.offset..].starts_with("\\u") {
+                            self.bump();
+                            self.bump();
+                            let low = self.hex4(escape)?;
+                            code = 0x10000 + ((code - 0xD800) << 10) + (low.wrapping_sub(0xDC00) & 0x3FF);
+                        }
+                        match char::from_u32(code) {
+                            Some(ch) => value.push(ch),
+                            None => return Err(self.error(escape, "invalid unicode escape")),
+                        }
+                    }
+                    _ => return Err(self.error(escape, "invalid escape")),
+                },
+                Some(ch) => value.push(ch),
+            }
+        }
+    }
+
+    fn hex4(&mut self, escape: Span) -> Result<u32, Box<Diagnostic>> {
+        let digits = self.text.get(self.offset..self.offset + 4).filter(|d| d.chars().all(|c| c.is_ascii_hexdigit()));
+        match digits {
+            Some(digits) => {
+                let code = u32::from_str_radix(digits, 16).unwrap();
+                (0..4).for_each(|_| { self.bump(); });
+                Ok(code)
+            }
+            None => Err(self.error(escape, "invalid unicode escape")),
+        }
+    }
+
+    fn sexp(&mut self) -> Result<Sexp, Box<Diagnostic>> {
+        self.skip_whitespace();
+        let start = self.here();
+        match self.peek() {
+            Some('(') => {
+                self.bump();
+                let mut items = Vec::new();
+                loop {
+                    self.skip_whitespace();
+                    match self.peek() {
+                        Some(')') => {
+                            self.bump();
+                            return Ok(Sexp::List { items, pos: self.since(start) });
+                        }
+                        None => return Err(self.error(start, "unclosed `(`")),
+                        Some(_) => items.push(self.sexp()?),
+                    }
+                }
+            }
+            Some(')') => {
+                self.bump();
+                Err(self.error(start, "unexpected `)`"))
+            }
+            Some('"') => {
+                let value = self.string(false)?;
+                let span = self.span_suffix()?;
+                Ok(Sexp::String { value, span, pos: self.since(start) })
+            }
+            Some(_) => {
+                while matches!(self.peek(), Some(ch) if !is_delimiter(ch) && ch != '@') {
+                    self.bump();
+                }
+                let text = self.text[start.start..self.offset].to_string();
+                let span = self.span_suffix()?;
+                Ok(Sexp::Symbol { text, span, pos: self.since(start) })
+            }
+            None => Err(self.error(start, "expected an S-expression, found end of input")),
+        }
+    }
+
+    /// Reads an optional `@start..end:line:column` span.
+    fn span_suffix(&mut self) -> Result<Option<Span>, Box<Diagnostic>> {
+        if self.peek() != Some('@') {
+            return Ok(None);
+        }
+        let start = self.here();
+        self.bump();
+        while matches!(self.peek(), Some(ch) if !is_delimiter(ch)) {
+            self.bump();
+        }
+        let text = &self.text[start.start + 1..self.offset];
+        let parse = || {
+            let (range, position) = text.split_once(':')?;
+            let (line, column) = position.split_once(':')?;
+            let (from, to) = range.split_once("..")?;
+            Some(Span::new(from.parse().ok()?, to.parse().ok()?, line.parse().ok()?, column.parse().ok()?))
+        };
+        match parse() {
+            Some(span) => Ok(Some(span)),
+            None => Err(self.error(start, &format!("malformed span {:?}, expected `@start..end:line:column`", text))),
+        }
+    }
+}
+
+fn is_delimiter(ch: char) -> bool {
+    ch.is_whitespace() || matches!(ch, '(' | ')' | '"')
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::envoriment::Environment;
+    use crate::lexer::Lexer;
+    use crate::parser::Parser;
+    use crate::test_corpus;
+
+    fn parse(input: &str) -> ast::Program {
+        let mut parser = Parser::new(Lexer::new(input.to_string()));
+        let program = parser.parse_program();
+        assert!(parser.errors.is_empty(), "parser errors in {:?}: {:?}", input, parser.errors);
+        program
+    }
+
+    fn eval(program: &ast::Program) -> String {
+        Environment::new().eval(program).inspect()
+    }
+
+    fn corpus() -> Vec<&'static str> {
+        let mut corpus = vec![
+            "/// Adds.\nlet add = fn(a, b) { a + b; }; add(0x_FF, 1e3)",
+            "let s = \"tab\\t\\\"q\\\" é \\0\"; \"${s}${\"lit\"} and ${ {\"k\": [1, 2.5]}[\"k\"] }\"",
+            "if (!(1 <= 2) || -3 % 2 == -1) { return 1; } else { { 2 } }",
+            "let f = fn() { if (true) { 1 } }; [f(), 1.5, 1e999, {}][3]",
+        ];
+        corpus.extend(test_corpus::PROGRAMS);
+        corpus
+    }
+
+    #[test]
+    fn test_json_round_trip() {
+        for input in corpus() {
+            let program = parse(input);
+            let json = to_json(&program);
+            let decoded = from_json(&json).unwrap_or_else(|e| panic!("{}\n{}", e.render(&json), json));
+            assert_eq!(to_json(&decoded), json, "program: {}", input);
+            assert_eq!(decoded.string(), program.string(), "program: {}", input);
+            assert_eq!(eval(&decoded), eval(&program), "program: {}", input);
+        }
+    }
+
+    #[test]
+    fn test_sexp_round_trip() {
+        for input in corpus() {
+            let program = parse(input);
+            let sexp = to_sexp(&program, true);
+            let decoded = from_sexp(&sexp).unwrap_or_else(|e| panic!("{}\n{}", e.render(&sexp), sexp));
+            assert_eq!(to_json(&decoded), to_json(&program), "program: {}", input);
+            assert_eq!(eval(&decoded), eval(&program), "program: {}", input);
+
+            let sexp = to_sexp(&program, false);
+            let decoded = from_sexp(&sexp).unwrap_or_else(|e| panic!("{}\n{}", e.render(&sexp), sexp));
+            assert_eq!(to_sexp(&decoded, false), sexp, "program: {}", input);
+            assert_eq!(decoded.string(), program.string(), "program: {}", input);
+        }
+    }
+
+    #[test]
+    fn test_json_form() {
+        let program = parse("let x = -1.5;");
+        assert_eq!(to_json(&program), concat!(
+            r#"{"type":"Program","span":{"start":0,"end":13,"line":1,"column":1},"statements":["#,
+            r#"{"type":"LetStatement","span":{"start":0,"end":13,"line":1,"column":1},"#,
+            r#""name":{"type":"Identifier","span":{"start":4,"end":5,"line":1,"column":5},"value":"x"},"#,
+            r#""value":{"type":"PrefixExpression","span":{"start":8,"end":12,"line":1,"column":9},"operator":"-","#,
+            r#""right":{"type":"FloatLiteral","span":{"start":9,"end":12,"line":1,"column":10},"literal":"1.5","value":1.5}},"#,
+            r#""doc":null}]}"#,
+        ));
+    }
+
+    #[test]
+    fn test_sexp_form() {
+        let program = parse("let f = fn(x) { if (x < 3) { \"a\\n\" } else { f(x - 1) } };\nf(0x10)[\"${x}\"]");
+        assert_eq!(
+            to_sexp(&program, false),
+            "(program (let f (fn (x) (block (expr (if (infix < x 3) (block (expr \"a\\n\")) \
+             (block (expr (call f (infix - x 1))))))))) (expr (index (call f 0x10) (template \"\" x \"\"))))",
+        );
+        let program = parse("const n = 1; n += 2;");
+        assert_eq!(to_sexp(&program, false), "(program (const n 1) (expr (assign += n 2)))");
+        let program = parse("match (x) { [a, ..r] if a => r, _ => { 0 } }");
+        assert_eq!(
+            to_sexp(&program, false),
+            "(program (expr (match x (arm (array a (rest r)) a (block (expr r))) (arm _ nil (block (expr 0))))))",
+        );
+        let program = parse("[true, \"${\"s\"}\"]");
+        assert_eq!(
+            to_sexp(&program, true),
+            "(program@0..16:1:1 (expr@0..16:1:1 (array@0..16:1:1 true@1..5:1:2 (template@7..15:1:8 \"\" (str@10..13:1:11 \"s\") \"\"))))",
+        );
+    }
+
+    #[test]
+    fn test_written_by_hand() {
+        let program = from_sexp("(program\n  (let add (fn (a b) (block (expr (infix + a b)))))\n  (expr (call add 1_000 .5)))").unwrap();
+        assert_eq!(eval(&program), "1000.5");
+        assert_eq!(program.string(), "let add = fn(a, b) (a + b);add(1_000, .5)");
+
+        let program = from_json(r#"{"type": "Program", "statements": [
+            {"type": "ExpressionStatement", "expression": {"type": "HashLiteral", "pairs": [
+                [{"type": "StringLiteral", "value": "\u00e9\ud83d\ude00"}, {"type": "IntegerLiteral", "value": 2}]
+            ]}}
+        ]}"#).unwrap();
+        assert_eq!(eval(&program), "{é😀: 2}");
+    }
+
+    #[test]
+    fn test_errors() {
+        let tests = vec![
+            (from_json("{\"type\": \"Program\",\n \"statements\": [}"), "E0200", "expected a JSON value", Span::new(36, 37, 2, 17)),
+            (from_json("[] 1"), "E0200", "unexpected text after the tree", Span::new(3, 4, 1, 4)),
+            (from_json("{\"type\": \"Nope\"}"), "E0201", "invalid syntax tree: unknown node type \"Nope\"", Span::new(9, 15, 1, 10)),
+            (from_json("{\"type\": \"Program\"}"), "E0201", "invalid syntax tree: missing field `statements`", Span::new(0, 19, 1, 1)),
+            (from_sexp("(program (expr \"open))"), "E0200", "unterminated string", Span::new(15, 22, 1, 16)),
+            (from_sexp("(program (expr 1@2))"), "E0200", "malformed span \"2\", expected `@start..end:line:column`", Span::new(16, 18, 1, 17)),
+            (from_sexp("(program (let x))"), "E0201", "invalid syntax tree: missing field `value`", Span::new(9, 16, 1, 10)),
+            (from_sexp("(program (infix + 1 2))"), "E0201", "invalid syntax tree: expected a statement, found InfixExpression", Span::new(9, 22, 1, 10)),
+            (from_sexp("(program (expr (infix ^ 1 2)))"), "E0201", "invalid syntax tree: unknown infix operator \"^\"", Span::new(22, 23, 1, 23)),
+            (from_sexp("(program (expr (infix = x 2)))"), "E0201", "invalid syntax tree: unknown infix operator \"=\"", Span::new(22, 23, 1, 23)),
+            (from_sexp("(program (expr (assign = 1 2)))"), "E0201", "invalid syntax tree: invalid assignment target", Span::new(25, 26, 1, 26)),
+            (from_sexp("(program (expr (index a)) x)"), "E0201", "invalid syntax tree: missing field `index`", Span::new(15, 24, 1, 16)),
+            (from_sexp("(program (expr (fn x (block))))"), "E0201", "invalid syntax tree: expected the parameters as a list", Span::new(19, 20, 1, 20)),
+            (from_sexp("(program (expr (match x (arm (call f) nil (block)))))"), "E0201", "invalid syntax tree: invalid match pattern", Span::new(29, 37, 1, 30)),
+            (from_sexp("(program (expr (match x (arm (array a a) nil (block)))))"), "E0201", "invalid syntax tree: invalid match pattern", Span::new(29, 40, 1, 30)),
+            (from_sexp("(program (expr (array (rest xs))))"), "E0201", "invalid syntax tree: expected an expression, found RestPattern", Span::new(22, 31, 1, 23)),
+            (from_sexp("(block)"), "E0201", "invalid syntax tree: expected a Program, found BlockStatement", Span::new(0, 7, 1, 1)),
+        ];
+        for (result, code, message, span) in tests {
+            let error = result.err().unwrap();
+            assert_eq!((error.code, error.message.as_str(), error.span), (code, message, Some(span)));
+        }
+    }
+}
@@ -0,0 +1,125 @@
+/// Programs both engines must agree on, which the `vm` tests check. The
+/// formatter and serializer tests use them as a corpus too.
+pub(crate) const PROGRAMS: &[&str] = &[
+    "1 + 2 * 3 - 4 / 2",
+    "0xFF + 0o17 + 0b1010 + 1_000",
+    "[1 <= 2, 2 >= 3, 7 % 3, -7 % 3, 7.5 % 2, 1.0 <= 1]",
+    "[true && false, true && 1, 0 || false, false || false, 1 < 2 && 2 < 3 || false]",
+    "let boom = fn() { 1 / 0 }; [false && boom(), true || boom()]",
+    "let i = 0; let total = 0; while (i < 10) { let i = i + 1; if (i % 2 == 0) { continue; } if (i > 7) { break } let total = total + i; } total",
+    "let f = fn(n) { let i = 0; let acc = []; while (true) { if (i == n) { return acc; } let acc = push(acc, i * i); let i = i + 1; } }; f(4)",
+    "let count = fn(n) { let i = 0; let pairs = 0; while (i < n) { let i = i + 1; let j = 0; while (j < i) { let j = j + 1; let pairs = pairs + 1; } } pairs }; count(4)",
+    "let x = 0; while (x < 3) { let x = x + 1; } ",
+    "while (false) { 1 }",
+    "let i = 0; while (i < 3) { let i = i + 1; i + true }",
+    "let total = 0; for (x in [1, 2, 3]) { let total = total + x; } total",
+    "let out = []; for (i in 0..10) { if (i % 3 != 0) { continue; } if (i > 7) { break; } let out = push(out, i); } out",
+    "let pairs = []; for (i in 1..=3) { for (c in \"ab\") { let pairs = push(pairs, \"${c}${i}\"); } } pairs",
+    "let keys = fn(h) { let out = []; for (k in h) { let out = push(out, k); } out }; keys({\"x\": 1, 2: 3})",
+    "let f = fn(xs) { for (x in xs) { if (x > 1) { return x; } } -1 }; [f(0..5), f([])]",
+    "let count = fn(n) { let total = 0; for (i in 0..n) { for (j in 0..i) { let total = total + 1; } } total }; count(5)",
+    "for (x in [7, 8]) { } [x, 0..3, 1..=1]",
+    "for (x in 5) { x }",
+    "1.5..2",
+    "let min = -9223372036854775807 - 1; min % -1",
+    "7 % 0",
+    "let name = \"Ada\"; let f = fn(x) { \"<${x}>\" }; \"hi ${name}, ${f(1 + 1)} ${[1, 2.5]} ${\"${true}\"}\"",
+    "\"a ${missing} b\"",
+    "-5 + 10; !true; !!5; !null",
+    "1 < 2 == true; 1 > 2; 1 != 1; true == false",
+    "\"foo\" + \"bar\"",
+    "if (1 > 2) { 10 }",
+    "if (1 < 2) { 10 } else { 20 }",
+    "if (false) { 10 } else { let x = 1; }",
+    "let a = 5; let b = a * 2; a + b",
+    "let x = 1; let x = x + 1; x",
+    "5; let x = 1;",
+    "return 10; 9",
+    "if (true) { if (true) { return 1; } return 2; }",
+    "let add = fn(a, b) { a + b }; add(1, add(2, 3))",
+    "let f = fn() { }; f()",
+    "let f = fn() { let x = 1; }; f()",
+    "let f = fn() { return 1; 2 }; f()",
+    "let newAdder = fn(x) { fn(y) { x + y } }; newAdder(2)(3)",
+    "let a = fn(x) { fn(y) { fn(z) { x + y + z } } }; a(1)(2)(3)",
+    "let fib = fn(n) { if (n < 2) { n } else { fib(n - 1) + fib(n - 2) } }; fib(15)",
+    "let isEven = fn(n) { if (n == 0) { true } else { isOdd(n - 1) } }; let isOdd = fn(n) { if (n == 0) { false } else { isEven(n - 1) } }; isEven(10)",
+    "let wrapper = fn() { let countdown = fn(x) { if (x == 0) { 0 } else { countdown(x - 1) } }; countdown(3) }; wrapper()",
+    "[1, 2 * 2, 3 + 3][1]; [1, 2, 3][-1]; [1][5]",
+    "let h = {\"a\": 1, 2: true, false: \"x\"}; [h[\"a\"], h[2], h[false], h[\"missing\"]]",
+    "len(\"four\"); push(rest([1, 2, 3]), 4)",
+    "let h = {\"b\": 2, \"a\": 1}; [keys(h), values(delete(h, \"b\")), has_key(h, \"a\")]",
+    "{ 1; 2 }",
+    "1 + true",
+    "-true",
+    "\"a\" - \"b\"",
+    "true + false; 5",
+    "missing",
+    "let f = fn() { undefinedLater }; let undefinedLater = 1; f()",
+    "let f = fn() { later }; f(); let later = 1;",
+    "if (false) { missing } else { 2 }",
+    "fn() { missing }; 1",
+    "let f = fn() { missing = 1 }; f()",
+    "5()",
+    "fn(a) { a }()",
+    "len(1)",
+    "len(1, 2)",
+    "let h = {[1]: 2};",
+    "let h = {}; h[[]]",
+    "[1][true]",
+    "1 / 0",
+    "\"日本語\"[1] + \"é\"[-1]; len(\"héllo\")",
+    "1.5 * 2 + .5; 1 < 1.5; -2.5",
+    "round(2.5) + int(\"3\") + floor(float(4))",
+    "9223372036854775807 + 1",
+    "let min = -9223372036854775807 - 1; -min",
+    "let x = 10; x += 2; x -= 4; x *= 3; x /= 2; [x, x = 1, x]",
+    "let a = 1; let b = 2; a = b = 7; [a, b]",
+    "let counter = fn() { let n = 0; [fn() { n += 1 }, fn() { n }] }; let c = counter(); c[0](); c[0](); [c[0](), c[1]()]",
+    "let total = 0; let add = fn(x) { total += x; }; add(2); add(3); total",
+    "let f = fn(x) { x = x * 2; x }; let y = 4; [f(y), y]",
+    "let f = fn() { f = 1; 2 }; [f(), f]",
+    "let outer = fn() { let n = 0; let inner = fn() { fn() { n += 1 } }; let g = inner(); g(); g() }; outer()",
+    "let i = 0; let out = []; while (i < 3) { out[0] = i; i += 1; } [out, i]",
+    "let xs = [1, 2, 3]; let ys = xs; xs[0] += 10; xs[-1] = 0; [xs, ys]",
+    "let m = [[1, 2], {\"k\": [3]}]; m[1][\"k\"][0] *= 5; m[0][1] = m; m[1]",
+    "let i = 0; let xs = [0, 0]; xs[i] = i = 1; [xs, i]",
+    "x = 1; let x = 2;",
+    "fn() { y += 1 }()",
+    "len = 1",
+    "let xs = [1, 2, 3]; xs[5] = 1",
+    "let s = \"abc\"; s[0] = \"x\"",
+    "let h = {}; h[[1]] = 1",
+    "const x = 5; let f = fn(x) { let x = x + 1; x }; [f(1), x]",
+    "const fact = fn(n) { if (n < 2) { 1 } else { n * fact(n - 1) } }; fact(5)",
+    "let f = fn() { const n = 1; n }; f() + f()",
+    "const x = 5; x += 1",
+    "const x = 5; let f = fn() { x = 6 }; f()",
+    "let f = fn() { const n = [1]; fn() { n[0] = 2 } }; f()()",
+    "const x = 5; let x = 6;",
+    "let x = 5; const x = 6;",
+    "let i = 0; while (i < 0) { i += 1; const k = i; } i",
+    "let i = 0; while (i < 1) { i += 1; const k = i; } i",
+    "for (x in [1, 2]) { let f = fn() { const k = x; k }; f() }",
+    "let f = fn() { const n = 1; const n = 2; n }; f()",
+    "const x = 5; for (x in [1]) { }",
+    "let i = 0; while (i < 2) { i += 1; const k = i; } k",
+    "let name = fn(n) { match (n) { 0 => \"zero\", -1 => \"minus one\", 2.0 => \"two\", n if n > 9 => \"big\", _ => \"some\" } }; [name(0), name(-1), name(2), name(10), name(5)]",
+    "let sum = fn(xs) { match (xs) { [] => 0, [x, ..rest] => x + sum(rest) } }; sum([1, 2, 3, 4])",
+    "match ([[1, 2], {\"k\": true}]) { [[a, _], {\"k\": false}] => a, [[a, b], {\"k\": k}] if k => [a, b, k] }",
+    "match ([1, 2, 3]) { [first, .._] => first }; [first, 0]",
+    "match ({\"x\": 1}) { {\"x\": x, \"y\": y} => y, {\"x\": x} => { let d = x * 2; d } }",
+    "let f = fn(v) { match (v) { n => fn() { n += 1; n } } }; let g = f(1); g(); g()",
+    "let out = []; for (x in 0..6) { match (x % 3) { 0 => { continue; } 2 if x > 3 => { break; } r => { out = push(out, r); } } } out",
+    "let f = fn() { match (1) { } }; f()",
+    "match (\"a\") { \"b\" => 1 }",
+    "match (2) { n if n > 1 => n + true, _ => 0 }",
+    "match (1 / 0) { _ => 1 }",
+    "const n = 1; match (2) { n => n }",
+    "let x = 1; match (2) { x => x }; x",
+    "let n = 10; match (1) { n if n > 5 => 0, _ => n }",
+    "const x = 1; [match (2) { x => x }, x]",
+    "let x = 0; match ([1, 2]) { [a, b] => { x = a + b; let a = 5; } }; [x, a]",
+    "let g = fn() { let v = 1; let h = match (2) { v => fn() { v } }; [h(), v] }; g()",
+    "let f = fn(x) { let y = 0; match ([x, 1]) { [y, z] if y > z => y, [a, y] => { let z = a + y; fn() { z += 1; z } } } }; [f(5), f(0)()]",
+];
@@ -1,3 +1,5 @@
+use std::fmt;
+
 #[allow(non_camel_case_types)]
 
 #[derive(Debug, PartialEq, Clone,Hash, Eq,)]
@@ -8,48 +10,126 @@ pub enum TokenType {
     // Identifiers + literals
     IDENT, // add, foobar, x, y, ...
     INT, // 1343456
+    FLOAT, // 3.14, .5, 1e-9
     STRING, // "foobar"
+    TEMPLATE, // "hello ${name}"
 
     // Operators
     ASSIGN, // =
+    PLUS_ASSIGN, // +=
+    MINUS_ASSIGN, // -=
+    ASTERISK_ASSIGN, // *=
+    SLASH_ASSIGN, // /=
     PLUS, // +
     MINUS, // -
     BANG, // !
     ASTERISK, // *
     SLASH, // /
+    PERCENT, // %
 
     LT, // <
     GT, // >
+    LT_EQ, // <=
+    GT_EQ, // >=
 
     EQ, // ==
     NOT_EQ, // !=
 
+    AND, // &&
+    OR, // ||
+
+    DOT_DOT, // ..
+    DOT_DOT_EQ, // ..=
+    FAT_ARROW, // =>
+
 
     // Delimiters
 
     COMMA, // ,
     SEMICOLON, // ;
+    COLON, // :
 
     LPAREN, // (
     RPAREN, // )
     LBRACE, // {
     RBRACE, // }
+    LBRACKET, // [
+    RBRACKET, // ]
 
     // Keywords
     FUNCTION, // fn
     LET, // let
+    CONST, // const
     TRUE, // true
     FALSE, // false
     IF, // if
     ELSE, // else
     RETURN, // return
+    WHILE, // while
+    BREAK, // break
+    CONTINUE, // continue
+    FOR, // for
+    IN, // in
+    MATCH, // match
+
+
+}
+/// Location of a piece of source text: the byte range `start..end` plus the
+/// 1-based line and column where it begins.
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Default)]
+pub struct Span {
+    pub start: usize,
+    pub end: usize,
+    pub line: usize,
+    pub column: usize,
+}
+
+impl Span {
+    pub fn new(start: usize, end: usize, line: usize, column: usize) -> Span {
+        Span {
+            start,
+            end,
+            line,
+            column,
+        }
+    }
 
+    /// Smallest span covering both `self` and `other`.
+    pub fn merge(&self, other: &Span) -> Span {
+        let first = if self.start <= other.start { self } else { other };
+        Span {
+            start: first.start,
+            end: self.end.max(other.end),
+            line: first.line,
+            column: first.column,
+        }
+    }
+}
 
+/// What a piece of trivia is.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum TriviaKind {
+    Whitespace, // spaces, tabs and lone `\r`
+    Newline, // `\n` or `\r\n`
+    LineComment, // `// ...`, up to but not including the newline
+    DocComment, // `/// ...`
+    BlockComment, // `/* ... */`, possibly nested
 }
+
+/// Source text between tokens that the parser ignores, kept by a lexer made
+/// with `Lexer::with_trivia`.
+#[derive(Debug, PartialEq, Clone)]
+pub struct Trivia {
+    pub kind: TriviaKind,
+    pub text: String,
+    pub span: Span,
+}
+
 #[derive(Debug, PartialEq, Clone)]
 pub struct Token {
     pub type_: TokenType,
     pub literal: String,
+    pub span: Span,
 }
 
 impl Token {
@@ -57,6 +137,23 @@ impl Token {
         Token {
             type_,
             literal: literal.to_string(),
+            span: Span::default(),
         }
     }   
-}
\ No newline at end of file
+
+    pub fn with_span(type_: TokenType, literal: &str, span: Span) -> Token {
+        Token {
+            type_,
+            literal: literal.to_string(),
+            span,
+        }
+    }
+}
+
+/// One line of a token dump: where the token starts, its byte range, its
+/// type and its literal, e.g. `1:5 4..9 IDENT "five"`.
+impl fmt::Display for Token {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}:{} {}..{} {:?} {:?}", self.span.line, self.span.column, self.span.start, self.span.end, self.type_, self.literal)
+    }
+}
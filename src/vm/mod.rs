@@ -0,0 +1,429 @@
+use std::cell::RefCell;
+use std::rc::Rc;
+
+use crate::ast;
+use crate::builtins;
+use crate::compiler::{self, Bytecode, Compiler, Opcode};
+use crate::diagnostic::Diagnostic;
+use crate::evaluator;
+use crate::object::{self, Object};
+
+/// Compiles and runs `program`, returning the value it evaluates to.
+pub fn run_program(program: &ast::Program) -> Result<Rc<Box<dyn Object>>, Box<Diagnostic>> {
+    let mut compiler = Compiler::new();
+    compiler.compile_program(program)?;
+    let mut vm = Vm::new(compiler.bytecode());
+    vm.run()?;
+    Ok(vm.last_popped())
+}
+
+const STACK_SIZE: usize = 1 << 16;
+const MAX_FRAMES: usize = 10_000;
+
+/// One active function call. Its arguments and locals live on the value stack
+/// starting at `base_pointer`; the closure being called sits just below.
+struct Frame {
+    instructions: Rc<[u8]>,
+    closure: Rc<Box<dyn Object>>,
+    ip: usize,
+    base_pointer: usize,
+}
+
+/// A stack machine executing `Bytecode` from the `compiler`.
+///
+/// Operators, indexing and builtins are the ones the tree-walker uses, so a
+/// program produces the same values and errors on either engine. The
+/// exceptions are the checks the `Compiler` makes before anything runs:
+/// assigning to a builtin or a constant, anywhere in the program, and going
+/// over a limit of the bytecode format.
+pub struct Vm {
+    constants: Vec<Rc<Box<dyn Object>>>,
+    global_names: Vec<String>,
+    globals: Vec<Option<Rc<Box<dyn Object>>>>,
+    stack: Vec<Rc<Box<dyn Object>>>,
+    frames: Vec<Frame>,
+    builtins: Vec<Rc<Box<dyn Object>>>,
+    last_popped: Option<Rc<Box<dyn Object>>>,
+    null: Rc<Box<dyn Object>>,
+    true_value: Rc<Box<dyn Object>>,
+    false_value: Rc<Box<dyn Object>>,
+}
+
+impl Vm {
+    pub fn new(bytecode: Bytecode) -> Self {
+        let main = object::Closure { func: bytecode.main, free: Vec::new() };
+        let main_frame = Frame {
+            instructions: main.func.instructions.clone(),
+            closure: Rc::new(Box::new(main)),
+            ip: 0,
+            base_pointer: 0,
+        };
+        Vm {
+            constants: bytecode.constants,
+            globals: vec![None; bytecode.globals.len()],
+            global_names: bytecode.globals,
+            stack: Vec::with_capacity(256),
+            frames: vec![main_frame],
+            builtins: builtins::BUILTINS.iter().map(|(_, func)| Rc::new(Box::new(object::Builtin{func: *func}) as Box<dyn Object>)).collect(),
+            last_popped: None,
+            null: Rc::new(Box::new(object::Null{})),
+            true_value: Rc::new(Box::new(object::Boolean{value: true})),
+            false_value: Rc::new(Box::new(object::Boolean{value: false})),
+        }
+    }
+
+    /// The value of the last expression statement executed, or null.
+    pub fn last_popped(&self) -> Rc<Box<dyn Object>> {
+        match &self.last_popped {
+            Some(obj) => obj.clone(),
+            None => self.null.clone(),
+        }
+    }
+
+    pub fn run(&mut self) -> Result<(), Box<Diagnostic>> {
+        loop {
+            let frame = self.frames.last_mut().unwrap();
+            let ip = frame.ip;
+            if ip >= frame.instructions.len() {
+                return Ok(());
+            }
+            let op = Opcode::from_byte(frame.instructions[ip]).expect("compiler emitted an unknown opcode");
+            frame.ip += 1;
+
+            match op {
+                Opcode::Constant => {
+                    let index = self.read_u16();
+                    self.push(self.constants[index].clone(), ip)?;
+                }
+                Opcode::Pop => self.last_popped = self.stack.pop(),
+                Opcode::True => self.push(self.true_value.clone(), ip)?,
+                Opcode::False => self.push(self.false_value.clone(), ip)?,
+                Opcode::Null => self.push(self.null.clone(), ip)?,
+                Opcode::Add | Opcode::Sub | Opcode::Mul | Opcode::Div | Opcode::Equal | Opcode::NotEqual
+                | Opcode::GreaterThan | Opcode::LessThan | Opcode::Mod | Opcode::GreaterEqual | Opcode::LessEqual
+                | Opcode::Range | Opcode::RangeInclusive => {
+                    let right = self.pop();
+                    let left = self.pop();
+                    let result = evaluator::eval_infix_expression(op.operator().unwrap(), left, right);
+                    self.push_result(result, ip)?;
+                }
+                Opcode::Minus | Opcode::Bang => {
+                    let right = self.pop();
+                    let operator = if op == Opcode::Minus { "-" } else { "!" };
+                    let result = evaluator::eval_perfix_expresion(operator, right);
+                    self.push_result(result, ip)?;
+                }
+                Opcode::Jump => {
+                    let target = self.read_u16();
+                    self.frames.last_mut().unwrap().ip = target;
+                }
+                Opcode::JumpNotTruthy => {
+                    let target = self.read_u16();
+                    let condition = self.pop();
+                    if !evaluator::is_truthy(condition) {
+                        self.frames.last_mut().unwrap().ip = target;
+                    }
+                }
+                Opcode::GetGlobal => {
+                    let index = self.read_u16();
+                    match self.globals[index].clone() {
+                        Some(value) => self.push(value, ip)?,
+                        None => {
+                            let message = format!("identifier not found: {}", self.global_names[index]);
+                            return Err(self.error_at(ip, Diagnostic::error("E0102", &message)));
+                        }
+                    }
+                }
+                Opcode::SetGlobal => {
+                    let index = self.read_u16();
+                    self.globals[index] = Some(self.pop());
+                }
+                Opcode::AssignGlobal => {
+                    let index = self.read_u16();
+                    if self.globals[index].is_none() {
+                        let message = format!("identifier not found: {}", self.global_names[index]);
+                        return Err(self.error_at(ip, Diagnostic::error("E0102", &message)));
+                    }
+                    self.globals[index] = Some(self.pop());
+                }
+                Opcode::GetLocal => {
+                    let index = self.read_u8();
+                    let base_pointer = self.frames.last().unwrap().base_pointer;
+                    self.push(self.stack[base_pointer + index].clone(), ip)?;
+                }
+                Opcode::SetLocal => {
+                    let index = self.read_u8();
+                    let base_pointer = self.frames.last().unwrap().base_pointer;
+                    self.stack[base_pointer + index] = self.pop();
+                }
+                Opcode::Dup => {
+                    let top = self.stack.last().unwrap().clone();
+                    self.push(top, ip)?;
+                }
+                Opcode::LoadCell => {
+                    let cell = self.pop();
+                    let value = cell.as_any().downcast_ref::<object::Cell>().unwrap().value.borrow().clone();
+                    self.push(value, ip)?;
+                }
+                Opcode::StoreCell => {
+                    let cell = self.pop();
+                    let value = self.pop();
+                    *cell.as_any().downcast_ref::<object::Cell>().unwrap().value.borrow_mut() = value;
+                }
+                Opcode::GetBuiltin => {
+                    let index = self.read_u8();
+                    self.push(self.builtins[index].clone(), ip)?;
+                }
+                Opcode::GetFree => {
+                    let index = self.read_u8();
+                    let closure = self.frames.last().unwrap().closure.clone();
+                    let value = closure.as_any().downcast_ref::<object::Closure>().unwrap().free[index].clone();
+                    self.push(value, ip)?;
+                }
+                Opcode::CurrentClosure => {
+                    let closure = self.frames.last().unwrap().closure.clone();
+                    self.push(closure, ip)?;
+                }
+                Opcode::Array => {
+                    let count = self.read_u16();
+                    let elements = self.stack.split_off(self.stack.len() - count);
+                    self.push(Rc::new(Box::new(object::Array{elements})), ip)?;
+                }
+                Opcode::Interpolate => {
+                    let count = self.read_u16();
+                    let parts = self.stack.split_off(self.stack.len() - count);
+                    let value: String = parts.iter().map(|part| part.inspect()).collect();
+                    self.push(Rc::new(Box::new(object::StringValue{value})), ip)?;
+                }
+                Opcode::Hash => {
+                    let count = self.read_u16();
+                    let items = self.stack.split_off(self.stack.len() - count);
+                    let mut hash = object::Hash::default();
+                    for pair in items.chunks(2) {
+                        if !hash.insert(pair[0].clone(), pair[1].clone()) {
+                            let message = format!("unusable as hash key: {}", pair[0].object_type());
+                            return Err(self.error_at(ip, Diagnostic::error("E0106", &message)));
+                        }
+                    }
+                    self.push(Rc::new(Box::new(hash)), ip)?;
+                }
+                Opcode::Index => {
+                    let index = self.pop();
+                    let left = self.pop();
+                    let result = evaluator::eval_index_expression(left, index);
+                    self.push_result(result, ip)?;
+                }
+                Opcode::GetPath => {
+                    let depth = self.read_u8();
+                    let root = self.stack[self.stack.len() - 1 - depth].clone();
+                    let element = evaluator::eval_index_path(root, &self.stack[self.stack.len() - depth..]);
+                    self.push_result(element, ip)?;
+                }
+                Opcode::SetPath => {
+                    let depth = self.read_u8();
+                    let value = self.pop();
+                    let path = self.stack.split_off(self.stack.len() - depth);
+                    let root = self.pop();
+                    let updated = evaluator::eval_path_assignment(root, &path, value.clone());
+                    if let Some(error) = updated.as_any().downcast_ref::<object::Error>() {
+                        return Err(self.error_at(ip, error.diagnostic.clone()));
+                    }
+                    self.push(value, ip)?;
+                    self.push(updated, ip)?;
+                }
+                Opcode::Iterate => {
+                    let iterable = self.pop();
+                    match evaluator::iterate(iterable) {
+                        Ok(elements) => self.push(Rc::new(Box::new(object::Iter{elements: RefCell::new(elements)})), ip)?,
+                        Err(error) => return self.push_result(error, ip),
+                    }
+                }
+                Opcode::IterNext => {
+                    let target = self.read_u16();
+                    let iterator = self.stack.last().unwrap().clone();
+                    let next = iterator.as_any().downcast_ref::<object::Iter>().unwrap().elements.borrow_mut().next();
+                    match next {
+                        Some(element) => self.push(element, ip)?,
+                        None => self.frames.last_mut().unwrap().ip = target,
+                    }
+                }
+                Opcode::Match => {
+                    let target = self.read_u16();
+                    let index = self.read_u16();
+                    let subject = self.stack.last().unwrap().clone();
+                    let mut bound = Vec::new();
+                    match self.constants[index].as_any().downcast_ref::<object::Pattern>().unwrap().matches(&subject, &mut bound) {
+                        true => {
+                            for value in bound {
+                                self.push(value, ip)?;
+                            }
+                        }
+                        false => self.frames.last_mut().unwrap().ip = target,
+                    }
+                }
+                Opcode::NoMatch => {
+                    let subject = self.pop();
+                    return self.push_result(evaluator::unmatched(&subject), ip);
+                }
+                Opcode::Call => {
+                    let num_args = self.read_u8();
+                    self.call(num_args, ip)?;
+                }
+                Opcode::ReturnValue | Opcode::Return => {
+                    let value = if op == Opcode::ReturnValue { self.pop() } else { self.null.clone() };
+                    let frame = self.frames.pop().unwrap();
+                    if self.frames.is_empty() {
+                        // `return` at the top level ends the program with its value.
+                        self.last_popped = Some(value);
+                        return Ok(());
+                    }
+                    self.stack.truncate(frame.base_pointer - 1);
+                    self.push(value, ip)?;
+                }
+                Opcode::Closure => {
+                    let index = self.read_u16();
+                    let num_free = self.read_u8();
+                    let func = self.constants[index].as_any().downcast_ref::<object::CompiledFunction>().unwrap().clone();
+                    let free = self.stack.split_off(self.stack.len() - num_free);
+                    self.push(Rc::new(Box::new(object::Closure{func, free})), ip)?;
+                }
+            }
+        }
+    }
+
+    fn call(&mut self, num_args: usize, ip: usize) -> Result<(), Box<Diagnostic>> {
+        let callee = self.stack[self.stack.len() - 1 - num_args].clone();
+        if let Some(closure) = callee.as_any().downcast_ref::<object::Closure>() {
+            if num_args != closure.func.num_parameters {
+                let message = format!("wrong number of arguments: want={}, got={}", closure.func.num_parameters, num_args);
+                return Err(self.error_at(ip, Diagnostic::error("E0104", &message)));
+            }
+            if self.frames.len() >= MAX_FRAMES {
+                return Err(self.error_at(ip, Diagnostic::error("E0107", "stack overflow")));
+            }
+            let base_pointer = self.stack.len() - num_args;
+            if base_pointer + closure.func.num_locals > STACK_SIZE {
+                return Err(self.error_at(ip, Diagnostic::error("E0107", "stack overflow")));
+            }
+            let instructions = closure.func.instructions.clone();
+            self.stack.resize(base_pointer + closure.func.num_locals, self.null.clone());
+            for index in closure.func.cells.iter() {
+                let value = self.stack[base_pointer + index].clone();
+                self.stack[base_pointer + index] = Rc::new(Box::new(object::Cell{value: RefCell::new(value)}));
+            }
+            self.frames.push(Frame { instructions, closure: callee, ip: 0, base_pointer });
+            return Ok(());
+        }
+        if let Some(builtin) = callee.as_any().downcast_ref::<object::Builtin>() {
+            let args = self.stack.split_off(self.stack.len() - num_args);
+            self.stack.pop();
+            let result = (builtin.func)(args);
+            return self.push_result(result, ip);
+        }
+        Err(self.error_at(ip, Diagnostic::error("E0103", &format!("not a function: {}", callee.object_type()))))
+    }
+
+    fn push(&mut self, obj: Rc<Box<dyn Object>>, ip: usize) -> Result<(), Box<Diagnostic>> {
+        if self.stack.len() >= STACK_SIZE {
+            return Err(self.error_at(ip, Diagnostic::error("E0107", "stack overflow")));
+        }
+        self.stack.push(obj);
+        Ok(())
+    }
+
+    /// Pushes the result of a shared operator or builtin, turning an error
+    /// object into a `Diagnostic`.
+    fn push_result(&mut self, obj: Rc<Box<dyn Object>>, ip: usize) -> Result<(), Box<Diagnostic>> {
+        if let Some(error) = obj.as_any().downcast_ref::<object::Error>() {
+            return Err(self.error_at(ip, error.diagnostic.clone()));
+        }
+        self.push(obj, ip)
+    }
+
+    fn pop(&mut self) -> Rc<Box<dyn Object>> {
+        self.stack.pop().expect("stack underflow")
+    }
+
+    fn read_u16(&mut self) -> usize {
+        let frame = self.frames.last_mut().unwrap();
+        let value = compiler::read_u16(&frame.instructions, frame.ip);
+        frame.ip += 2;
+        value
+    }
+
+    fn read_u8(&mut self) -> usize {
+        let frame = self.frames.last_mut().unwrap();
+        let value = frame.instructions[frame.ip] as usize;
+        frame.ip += 1;
+        value
+    }
+
+    /// Attaches the span of the instruction at `ip` in the current frame.
+    fn error_at(&self, ip: usize, diagnostic: Diagnostic) -> Box<Diagnostic> {
+        if diagnostic.span.is_some() {
+            return Box::new(diagnostic);
+        }
+        let closure = &self.frames.last().unwrap().closure;
+        let spans = &closure.as_any().downcast_ref::<object::Closure>().unwrap().func.spans;
+        match spans.binary_search_by_key(&ip, |(offset, _)| *offset) {
+            Ok(i) => Box::new(diagnostic.with_span(spans[i].1)),
+            Err(_) => Box::new(diagnostic),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::envoriment::Environment;
+    use crate::lexer::Lexer;
+    use crate::parser::Parser;
+    use crate::test_corpus;
+    use crate::token::Span;
+
+    fn run(input: &str) -> Result<Rc<Box<dyn Object>>, Box<Diagnostic>> {
+        let mut parser = Parser::new(Lexer::new(input.to_string()));
+        let program = parser.parse_program();
+        assert!(parser.errors.is_empty(), "parser errors: {:?}", parser.errors);
+        run_program(&program)
+    }
+
+    fn inspect(input: &str) -> String {
+        match run(input) {
+            Ok(obj) => obj.inspect(),
+            Err(error) => format!("ERROR: {}", error.message),
+        }
+    }
+
+    #[test]
+    fn test_matches_tree_walker() {
+        for input in test_corpus::PROGRAMS {
+            let mut parser = Parser::new(Lexer::new(input.to_string()));
+            let program = parser.parse_program();
+            let expected = Environment::new().eval(&program).inspect();
+            assert_eq!(inspect(input), expected, "program: {}", input);
+        }
+    }
+
+    #[test]
+    fn test_compile_errors() {
+        let error = run("const x = 1; if (false) { x = 2; }").err().unwrap();
+        assert_eq!((error.code, error.message.as_str()), ("E0114", "cannot assign to constant `x`"));
+    }
+
+    #[test]
+    fn test_runtime_errors_carry_spans() {
+        let error = run("let f = fn(x) {\n  x + true\n};\nf(1)").err().unwrap();
+        assert_eq!(error.message, "type mismatch: INTEGER + BOOLEAN");
+        assert_eq!(error.span, Some(Span::new(18, 26, 2, 3)));
+
+        let error = run("len(1, 2)").err().unwrap();
+        assert_eq!(error.span, Some(Span::new(0, 9, 1, 1)));
+    }
+
+    #[test]
+    fn test_deep_recursion_overflows_cleanly() {
+        let error = run("let f = fn(n) { f(n + 1) + 1 }; f(0)").err().unwrap();
+        assert_eq!((error.code, error.message.as_str()), ("E0107", "stack overflow"));
+    }
+}